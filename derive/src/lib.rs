@@ -0,0 +1,124 @@
+// Attribute macros for bgp4_serde.
+//
+// #[wire_codes] goes on an enum used inside a TaggedEnum and lets its
+// variants name their wire code with #[bgp_wire(code = 14)] instead of
+// #[serde(rename = "14")]. Each one is rewritten to that rename, which is
+// what TaggedEnum goes by; everything else passes through untouched. No syn
+// or quote, the enum is only walked as far as the variant attributes.
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+#[proc_macro_attribute]
+pub fn wire_codes(args: TokenStream, item: TokenStream) -> TokenStream {
+    if let Some(tt) = args.into_iter().next() {
+        return compile_error("#[wire_codes] takes no arguments", tt.span());
+    }
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    let is_enum = |tt: &TokenTree| matches!(tt, TokenTree::Ident(i) if i.to_string() == "enum");
+    let is_body = |tt: &TokenTree| matches!(tt, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace);
+    let body = tokens.iter().position(is_enum).and_then(|at| tokens[at..].iter().position(is_body).map(|i| at + i));
+    let body = match body {
+        Some(body) => body,
+        None => return compile_error("#[wire_codes] only applies to enums", Span::call_site()),
+    };
+    let group = match &tokens[body] {
+        TokenTree::Group(group) => group.clone(),
+        _ => unreachable!(),
+    };
+    match rewrite_variants(group.stream()) {
+        Ok(stream) => {
+            let mut rewritten = Group::new(Delimiter::Brace, stream);
+            rewritten.set_span(group.span());
+            tokens[body] = TokenTree::Group(rewritten);
+            tokens.into_iter().collect()
+        },
+        Err((message, span)) => compile_error(&message, span),
+    }
+}
+
+// Swaps each #[bgp_wire(code = N)] among the variants for
+// #[serde(rename = "N")]. Attributes inside variant fields aren't looked at.
+fn rewrite_variants(body: TokenStream) -> Result<TokenStream, (String, Span)> {
+    let mut out = Vec::new();
+    let mut tokens = body.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        let is_pound = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '#');
+        let code = match tokens.peek() {
+            Some(TokenTree::Group(attr)) if is_pound && attr.delimiter() == Delimiter::Bracket => wire_code(attr)?,
+            _ => None,
+        };
+        match code {
+            Some((code, span)) => {
+                tokens.next();
+                out.push(tt);
+                out.push(TokenTree::Group(rename(&code, span)));
+            },
+            None => out.push(tt),
+        }
+    }
+    Ok(out.into_iter().collect())
+}
+
+// The code in a bgp_wire attribute (the part between the brackets), None for
+// any other attribute.
+fn wire_code(attr: &Group) -> Result<Option<(String, Span)>, (String, Span)> {
+    let mut inner = attr.stream().into_iter();
+    match inner.next() {
+        Some(TokenTree::Ident(name)) if name.to_string() == "bgp_wire" => {},
+        _ => return Ok(None),
+    }
+    let usage = || (String::from("expected #[bgp_wire(code = <integer>)]"), attr.span());
+    let args = match (inner.next(), inner.next()) {
+        (Some(TokenTree::Group(args)), None) if args.delimiter() == Delimiter::Parenthesis => args,
+        _ => return Err(usage()),
+    };
+    let args: Vec<TokenTree> = args.stream().into_iter().collect();
+    match args.as_slice() {
+        [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(lit)]
+            if key.to_string() == "code" && eq.as_char() == '=' =>
+        {
+            let code = lit.to_string().replace('_', "");
+            let valid = match code.strip_prefix("0x") {
+                Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+                None => !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()),
+            };
+            match valid {
+                true => Ok(Some((code, lit.span()))),
+                false => Err((format!("wire code must be an unsuffixed integer, got {}", lit), lit.span())),
+            }
+        },
+        _ => Err(usage()),
+    }
+}
+
+// [serde(rename = "<code>")]
+fn rename(code: &str, span: Span) -> Group {
+    let args: TokenStream = [
+        TokenTree::Ident(Ident::new("rename", span)),
+        TokenTree::Punct(Punct::new('=', Spacing::Alone)),
+        TokenTree::Literal(Literal::string(code)),
+    ]
+    .into_iter()
+    .collect();
+    let attr: TokenStream = [
+        TokenTree::Ident(Ident::new("serde", span)),
+        TokenTree::Group(Group::new(Delimiter::Parenthesis, args)),
+    ]
+    .into_iter()
+    .collect();
+    Group::new(Delimiter::Bracket, attr)
+}
+
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    let mut args = Group::new(Delimiter::Parenthesis, TokenTree::Literal(message).into());
+    args.set_span(span);
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+        TokenTree::Group(args),
+        TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+    ]
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,9 @@
+// Decoding garbage as a BMP message, header included. Must not panic.
+#![no_main]
+
+use bgp4_serde::BmpMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = BmpMessage::from_bytes(data);
+});
@@ -0,0 +1,38 @@
+// Decoding garbage. The first octet picks the message type and the options
+// (4-octet AS numbers, ADD-PATH for IPv4 and IPv6 unicast, skipping unknown
+// attributes and capabilities), the rest is the body, behind a header made
+// to match so the fuzzer doesn't have to find the marker. Decoding must not panic, and a message that decodes must encode
+// and then decode to itself again under the same options.
+#![no_main]
+
+use bgp4_serde::{
+    to_message_bytes_with, Afi, BgpMessage, DeserializerOptions, OnUnknown, Safi, SerializerOptions, MARKER,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, body)) = data.split_first() else { return };
+    let Ok(len) = u16::try_from(19 + body.len()) else { return };
+    let mut message = MARKER.to_vec();
+    message.extend_from_slice(&len.to_be_bytes());
+    message.push(1 + flags % 6);
+    message.extend_from_slice(body);
+
+    let mut options = DeserializerOptions { four_octet_asn: flags & 0x08 != 0, ..Default::default() };
+    if flags & 0x10 != 0 {
+        options.add_path.insert(Afi::Ipv4, Safi::Unicast);
+        options.add_path.insert(Afi::Ipv6, Safi::Unicast);
+    }
+    if flags & 0x20 != 0 {
+        options.on_unknown = OnUnknown::Skip;
+    }
+    let Ok(decoded) = BgpMessage::from_bytes_with(&message, options) else { return };
+
+    let ser_options = SerializerOptions {
+        four_octet_asn: options.four_octet_asn,
+        add_path: options.add_path,
+        ..Default::default()
+    };
+    let wire = to_message_bytes_with(decoded.message_type(), &decoded, ser_options).expect("decoded message encodes");
+    assert_eq!(BgpMessage::from_bytes_with(&wire, options).expect("encoded message decodes"), decoded);
+});
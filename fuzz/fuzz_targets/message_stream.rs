@@ -0,0 +1,35 @@
+// A byte stream fed to MessageStream in pieces, the first octet giving their
+// size. Must not panic, and must yield the same messages (or the same first
+// error) as when the whole stream is fed at once.
+#![no_main]
+
+use bgp4_serde::MessageStream;
+use libfuzzer_sys::fuzz_target;
+
+fn messages<'a>(pieces: impl Iterator<Item = &'a [u8]>) -> Vec<Result<String, String>> {
+    let mut stream = MessageStream::new();
+    let mut out = Vec::new();
+    for piece in pieces {
+        let mut next = stream.feed(piece);
+        loop {
+            match next {
+                Ok(Some(message)) => out.push(Ok(format!("{:?}", message))),
+                Ok(None) => break,
+                // The stream stays failed, the rest can't be compared.
+                Err(e) => {
+                    out.push(Err(e.to_string()));
+                    return out;
+                },
+            }
+            next = stream.next_message();
+        }
+    }
+    out
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&size, stream)) = data.split_first() else { return };
+    let whole = messages(std::iter::once(stream));
+    let pieces = messages(stream.chunks(size as usize + 1));
+    assert_eq!(whole, pieces);
+});
@@ -0,0 +1,16 @@
+// Reading garbage as an MRT file. Must not panic, or allocate for lengths
+// the input doesn't back up.
+#![no_main]
+
+use bgp4_serde::mrt::MrtReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for record in MrtReader::new(data) {
+        // A record that fails to decode doesn't stop the reader, one cut
+        // short does.
+        if let Err(bgp4_serde::SerializerError::Io(_)) = record {
+            break;
+        }
+    }
+});
@@ -0,0 +1,32 @@
+// Reading garbage as a capture. The first octet picks a pcap (raw IPv4) or
+// pcapng file header to put in front, so the fuzzer starts past the magic
+// numbers. Must not panic, or allocate for lengths the input doesn't back up.
+#![no_main]
+
+use bgp4_serde::pcap::PcapReader;
+use libfuzzer_sys::fuzz_target;
+
+const PCAP_HEADER: [u8; 24] = [
+    0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 228, 0, 0, 0,
+];
+const PCAPNG_SECTION_HEADER: [u8; 28] = [
+    0x0a, 0x0d, 0x0d, 0x0a, 28, 0, 0, 0, 0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 28, 0, 0, 0,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&format, rest)) = data.split_first() else { return };
+    let header: &[u8] = match format % 3 {
+        0 => &PCAP_HEADER,
+        1 => &PCAPNG_SECTION_HEADER,
+        _ => &[],
+    };
+    let input = [header, rest].concat();
+    // A decode error on one direction is reported once, so every packet
+    // gets looked at; an I/O error ends the capture.
+    for message in PcapReader::new(input.as_slice()) {
+        if let Err(bgp4_serde::SerializerError::Io(_)) = message {
+            break;
+        }
+    }
+});
@@ -0,0 +1,263 @@
+// Address Family and Subsequent Address Family Identifiers.
+//
+// Values from the IANA "Address Family Numbers" and "SAFI Values" registries.
+// Anything not modeled here is carried in the Unknown variant so it still
+// round-trips.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::types::TextOrNumber;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Afi {
+    Ipv4,
+    Ipv6,
+    L2vpn,
+    BgpLs,
+    Unknown(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Safi {
+    Unicast,
+    Multicast,
+    // Labeled unicast (RFC 8277)
+    Mpls,
+    McastVpn,
+    Vpls,
+    Evpn,
+    BgpLs,
+    BgpLsVpn,
+    MplsVpn,
+    MplsVpnMulticast,
+    RouteTarget,
+    FlowSpec,
+    FlowSpecVpn,
+    Unknown(u8),
+}
+
+impl From<u16> for Afi {
+    fn from(v: u16) -> Self {
+        match v {
+            1 => Afi::Ipv4,
+            2 => Afi::Ipv6,
+            25 => Afi::L2vpn,
+            16388 => Afi::BgpLs,
+            other => Afi::Unknown(other),
+        }
+    }
+}
+
+impl From<Afi> for u16 {
+    fn from(afi: Afi) -> Self {
+        match afi {
+            Afi::Ipv4 => 1,
+            Afi::Ipv6 => 2,
+            Afi::L2vpn => 25,
+            Afi::BgpLs => 16388,
+            Afi::Unknown(other) => other,
+        }
+    }
+}
+
+impl From<u8> for Safi {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => Safi::Unicast,
+            2 => Safi::Multicast,
+            4 => Safi::Mpls,
+            5 => Safi::McastVpn,
+            65 => Safi::Vpls,
+            70 => Safi::Evpn,
+            71 => Safi::BgpLs,
+            72 => Safi::BgpLsVpn,
+            128 => Safi::MplsVpn,
+            129 => Safi::MplsVpnMulticast,
+            132 => Safi::RouteTarget,
+            133 => Safi::FlowSpec,
+            134 => Safi::FlowSpecVpn,
+            other => Safi::Unknown(other),
+        }
+    }
+}
+
+impl From<Safi> for u8 {
+    fn from(safi: Safi) -> Self {
+        match safi {
+            Safi::Unicast => 1,
+            Safi::Multicast => 2,
+            Safi::Mpls => 4,
+            Safi::McastVpn => 5,
+            Safi::Vpls => 65,
+            Safi::Evpn => 70,
+            Safi::BgpLs => 71,
+            Safi::BgpLsVpn => 72,
+            Safi::MplsVpn => 128,
+            Safi::MplsVpnMulticast => 129,
+            Safi::RouteTarget => 132,
+            Safi::FlowSpec => 133,
+            Safi::FlowSpecVpn => 134,
+            Safi::Unknown(other) => other,
+        }
+    }
+}
+
+// The families ADD-PATH (RFC 7911) was negotiated for, in the direction
+// being encoded or decoded. NLRI of these families has a 4 octet path
+// identifier in front of every prefix. Only families whose NLRI the crate
+// decodes can be listed: anywhere else the path identifiers stay part of the
+// raw NLRI octets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AddPathFamilies(u8);
+
+impl AddPathFamilies {
+    pub fn new() -> Self {
+        AddPathFamilies(0)
+    }
+
+    fn bit(afi: Afi, safi: Safi) -> Option<u8> {
+        match (afi, safi) {
+            (Afi::Ipv4, Safi::Unicast) => Some(0x01),
+            (Afi::Ipv4, Safi::Multicast) => Some(0x02),
+            (Afi::Ipv6, Safi::Unicast) => Some(0x04),
+            (Afi::Ipv6, Safi::Multicast) => Some(0x08),
+            _ => None,
+        }
+    }
+
+    // False if the family's NLRI isn't decoded by the crate.
+    pub fn insert(&mut self, afi: Afi, safi: Safi) -> bool {
+        match Self::bit(afi, safi) {
+            Some(bit) => {
+                self.0 |= bit;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn with(mut self, afi: Afi, safi: Safi) -> Self {
+        self.insert(afi, safi);
+        self
+    }
+
+    pub fn contains(&self, afi: Afi, safi: Safi) -> bool {
+        Self::bit(afi, safi).is_some_and(|bit| self.0 & bit != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+// -- Text form, "ipv6" and "unicast", the number for unknown values --
+
+const AFI_NAMES: [(Afi, &str); 4] =
+    [(Afi::Ipv4, "ipv4"), (Afi::Ipv6, "ipv6"), (Afi::L2vpn, "l2vpn"), (Afi::BgpLs, "bgp_ls")];
+
+const SAFI_NAMES: [(Safi, &str); 13] = [
+    (Safi::Unicast, "unicast"),
+    (Safi::Multicast, "multicast"),
+    (Safi::Mpls, "mpls"),
+    (Safi::McastVpn, "mcast_vpn"),
+    (Safi::Vpls, "vpls"),
+    (Safi::Evpn, "evpn"),
+    (Safi::BgpLs, "bgp_ls"),
+    (Safi::BgpLsVpn, "bgp_ls_vpn"),
+    (Safi::MplsVpn, "mpls_vpn"),
+    (Safi::MplsVpnMulticast, "mpls_vpn_multicast"),
+    (Safi::RouteTarget, "route_target"),
+    (Safi::FlowSpec, "flowspec"),
+    (Safi::FlowSpecVpn, "flowspec_vpn"),
+];
+
+fn name_of<T: PartialEq>(names: &[(T, &'static str)], value: &T) -> Option<&'static str> {
+    names.iter().find(|(v, _)| v == value).map(|&(_, name)| name)
+}
+
+fn from_name<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names.iter().find(|&&(_, n)| n == name).map(|&(v, _)| v)
+}
+
+// -- Wire form --
+
+// On the wire an AFI is always 2 octets and a SAFI 1 octet.
+impl Serialize for Afi {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match name_of(&AFI_NAMES, self) {
+            Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => serializer.serialize_u16(u16::from(*self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Afi {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return TextOrNumber::deserialize(deserializer)?.decode(
+                "AFI",
+                |s| from_name(&AFI_NAMES, s),
+                |v| u16::try_from(v).ok().map(Afi::from),
+            );
+        }
+        u16::deserialize(deserializer).map(Afi::from)
+    }
+}
+
+impl Serialize for Safi {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match name_of(&SAFI_NAMES, self) {
+            Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => serializer.serialize_u8(u8::from(*self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Safi {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return TextOrNumber::deserialize(deserializer)?.decode(
+                "SAFI",
+                |s| from_name(&SAFI_NAMES, s),
+                |v| u8::try_from(v).ok().map(Safi::from),
+            );
+        }
+        u8::deserialize(deserializer).map(Safi::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_bytes, SerializerError};
+    use serde::de::IntoDeserializer;
+
+    #[test]
+    fn test_afi_safi_ser() {
+        assert_eq!(to_bytes((Afi::Ipv6, Safi::Unicast)).unwrap().as_ref(), &[0, 2, 1]);
+        assert_eq!(to_bytes((Afi::BgpLs, Safi::FlowSpec)).unwrap().as_ref(), &[0x40, 0x04, 133]);
+        assert_eq!(to_bytes((Afi::Unknown(99), Safi::Unknown(250))).unwrap().as_ref(), &[0, 99, 250]);
+    }
+
+    #[test]
+    fn test_afi_safi_de() {
+        let afi = Afi::deserialize(IntoDeserializer::<SerializerError>::into_deserializer(25u16)).unwrap();
+        let safi = Safi::deserialize(IntoDeserializer::<SerializerError>::into_deserializer(70u8)).unwrap();
+        assert_eq!((afi, safi), (Afi::L2vpn, Safi::Evpn));
+
+        let unknown = Safi::deserialize(IntoDeserializer::<SerializerError>::into_deserializer(3u8)).unwrap();
+        assert_eq!(unknown, Safi::Unknown(3));
+    }
+
+    #[test]
+    fn test_afi_safi_conversions() {
+        for v in 0..=u8::MAX {
+            assert_eq!(u8::from(Safi::from(v)), v);
+        }
+        for v in [0u16, 1, 2, 25, 16388, 65535] {
+            assert_eq!(u16::from(Afi::from(v)), v);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// Accumulated IGP Metric attribute (RFC 7311).
+//
+// The value is a list of TLVs with a 1 octet type and a 2 octet length that,
+// unlike most BGP TLVs, counts the 3 octet header too. The only one defined
+// is the AIGP TLV, 11 octets carrying a u64 metric.
+//
+// A speaker passing a route on adds its own IGP distance to the metric.
+// increment_aigp and increment_aigp_in_update do that on the encoded octets,
+// for forwarding an UPDATE without decoding and re-encoding all of it.
+use std::fmt;
+
+use bytes::Bytes;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::attributes::PathAttributeRef;
+use crate::de::{from_bytes, from_message_bytes};
+use crate::error::DeserializeError;
+use crate::messages::UpdateMessageRef;
+use crate::registry::path_attr;
+
+pub const AIGP_TLV: u8 = 1;
+pub const AIGP_TLV_LEN: usize = 11;
+// Type and length
+const TLV_HEADER_LEN: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AigpTlv {
+    Metric(u64),
+    Unknown {
+        tlv_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+impl AigpTlv {
+    pub fn tlv_type(&self) -> u8 {
+        match self {
+            AigpTlv::Metric(_) => AIGP_TLV,
+            AigpTlv::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+// Adds `increment` to the metric in an encoded AIGP attribute value (as in
+// PathAttributeRef::data), saturating at u64::MAX. Returns the new metric,
+// or None if there is no AIGP TLV.
+pub fn increment_aigp(value: &mut [u8], increment: u64) -> std::result::Result<Option<u64>, DeserializeError> {
+    let mut pos = 0;
+    while pos < value.len() {
+        let header = value.get(pos..pos + TLV_HEADER_LEN).ok_or(DeserializeError::UnexpectedEof {
+            needed: TLV_HEADER_LEN,
+            available: value.len() - pos,
+            offset: pos,
+        })?;
+        let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+        if len < TLV_HEADER_LEN || pos + len > value.len() {
+            return Err(DeserializeError::LengthMismatch { declared: len, actual: value.len() - pos, offset: pos + 1 });
+        }
+        if header[0] == AIGP_TLV {
+            if len != AIGP_TLV_LEN {
+                return Err(de::Error::custom(format!("Invalid AIGP TLV length {}", len)));
+            }
+            let octets = &mut value[pos + TLV_HEADER_LEN..pos + len];
+            let metric = u64::from_be_bytes(<[u8; 8]>::try_from(&*octets).unwrap()).saturating_add(increment);
+            octets.copy_from_slice(&metric.to_be_bytes());
+            return Ok(Some(metric));
+        }
+        pos += len;
+    }
+    Ok(None)
+}
+
+// increment_aigp on the AIGP attribute of an encoded UPDATE, header
+// included. None if the UPDATE has no AIGP attribute or it has no AIGP TLV.
+pub fn increment_aigp_in_update(
+    message: &mut [u8],
+    increment: u64,
+) -> std::result::Result<Option<u64>, DeserializeError> {
+    let span = {
+        let update: UpdateMessageRef = from_message_bytes(message)?;
+        let attributes: Vec<PathAttributeRef> = from_bytes(update.path_attributes.value)?;
+        let start = message.as_ptr() as usize;
+        attributes
+            .iter()
+            .find(|attr| attr.code == path_attr::AIGP)
+            .map(|attr| (attr.data.as_ptr() as usize - start, attr.data.len()))
+    };
+    match span {
+        Some((offset, len)) => increment_aigp(&mut message[offset..offset + len], increment),
+        None => Ok(None),
+    }
+}
+
+// In text formats a TLV is {"metric": 1000}.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "AigpTlv", rename_all = "snake_case")]
+enum AigpTlvText {
+    Metric(u64),
+    Unknown { tlv_type: u8, data: Bytes },
+}
+
+// -- Wire form --
+
+impl Serialize for AigpTlv {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return AigpTlvText::serialize(self, serializer);
+        }
+        match self {
+            AigpTlv::Metric(metric) => (AIGP_TLV, AIGP_TLV_LEN as u16, metric).serialize(serializer),
+            AigpTlv::Unknown { tlv_type, data } => {
+                let len = u16::try_from(TLV_HEADER_LEN + data.len())
+                    .map_err(|_| ser::Error::custom(format!("AIGP TLV of {} octets is too long", data.len())))?;
+                let mut tup = serializer.serialize_tuple(3)?;
+                tup.serialize_element(tlv_type)?;
+                tup.serialize_element(&len)?;
+                tup.serialize_element(&RawOctets(data))?;
+                tup.end()
+            },
+        }
+    }
+}
+
+struct RawOctets<'a>(&'a [u8]);
+
+impl Serialize for RawOctets<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for AigpTlv {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return AigpTlvText::deserialize(deserializer);
+        }
+        struct TlvVisitor;
+
+        impl<'de> Visitor<'de> for TlvVisitor {
+            type Value = AigpTlv;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an AIGP TLV")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let tlv_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let len: u16 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let len = len as usize;
+                if tlv_type == AIGP_TLV {
+                    if len != AIGP_TLV_LEN {
+                        return Err(de::Error::custom(format!("Invalid AIGP TLV length {}", len)));
+                    }
+                    let metric = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                    return Ok(AigpTlv::Metric(metric));
+                }
+                // The length counts the header
+                let n = len
+                    .checked_sub(TLV_HEADER_LEN)
+                    .ok_or_else(|| de::Error::custom(format!("Invalid AIGP TLV length {}", len)))?;
+                let mut data = Vec::with_capacity(n);
+                for i in 0..n {
+                    data.push(seq.next_element::<u8>()?.ok_or_else(|| de::Error::invalid_length(2 + i, &self))?);
+                }
+                Ok(AigpTlv::Unknown { tlv_type, data: Bytes::from(data) })
+            }
+        }
+
+        deserializer.deserialize_tuple(TLV_HEADER_LEN + u16::MAX as usize, TlvVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_message_bytes, to_bytes, to_message_bytes, MessageType, PathAttribute, UpdateMessage};
+
+    #[test]
+    fn test_aigp_wire() {
+        let attr = PathAttribute::Aigp(vec![
+            AigpTlv::Unknown { tlv_type: 9, data: Bytes::from_static(&[1, 2]) },
+            AigpTlv::Metric(1000),
+        ]);
+        let wire: &[u8] = &[0x80, 26, 16, 9, 0, 5, 1, 2, 1, 0, 11, 0, 0, 0, 0, 0, 0, 0x03, 0xe8];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<PathAttribute>(wire).unwrap(), attr);
+
+        match from_bytes::<AigpTlv>(&[1, 0, 10, 0, 0, 0, 0, 0, 0, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid AIGP TLV length 10"),
+        }
+    }
+
+    #[test]
+    fn test_increment_aigp() {
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![
+            PathAttribute::MultiExitDisc(5),
+            PathAttribute::Aigp(vec![AigpTlv::Unknown { tlv_type: 9, data: Bytes::new() }, AigpTlv::Metric(1000)]),
+        ];
+        let mut wire = to_message_bytes(MessageType::Update, &update).unwrap().to_vec();
+
+        assert_eq!(increment_aigp_in_update(&mut wire, 24).unwrap(), Some(1024));
+        assert_eq!(increment_aigp_in_update(&mut wire, u64::MAX).unwrap(), Some(u64::MAX));
+        let patched: UpdateMessage = from_message_bytes(&wire).unwrap();
+        assert_eq!(patched.path_attributes.value[1], PathAttribute::Aigp(vec![
+            AigpTlv::Unknown { tlv_type: 9, data: Bytes::new() },
+            AigpTlv::Metric(u64::MAX),
+        ]));
+
+        let mut wire = to_message_bytes(MessageType::Update, UpdateMessage::default()).unwrap().to_vec();
+        assert_eq!(increment_aigp_in_update(&mut wire, 1).unwrap(), None);
+
+        // TLV length running past the value
+        let mut value = [1, 0, 12, 0, 0, 0, 0, 0, 0, 0, 1];
+        match increment_aigp(&mut value, 1) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::LengthMismatch { declared: 12, actual: 11, offset: 1 }),
+        }
+    }
+}
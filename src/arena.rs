@@ -0,0 +1,129 @@
+// Decoding into a bump arena (feature = "bumpalo").
+//
+// Going through millions of MRT records, much of the time goes into
+// allocating and freeing the Vecs of each decoded message. The lists here
+// are kept in the caller's bumpalo::Bump instead, which is reset once a
+// batch is done with, and attribute values stay borrowed from the input, so
+// an UPDATE decodes without going near the global allocator.
+use std::fmt;
+use std::marker::PhantomData;
+
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::attributes::PathAttributeRef;
+use crate::de::{message_header, Deserializer, DeserializerOptions};
+use crate::error::DeserializeError;
+use crate::messages::{MessageType, HEADER_LEN};
+use crate::prefix::Ipv4Prefix;
+
+type Result<T> = std::result::Result<T, DeserializeError>;
+
+// Decodes values of T to the end of the current length scope, into the
+// arena. The building block for arena-backed models of other messages.
+pub fn seq_in<'de, 'bump, T>(deserializer: &mut Deserializer<'de>, bump: &'bump Bump) -> Result<BumpVec<'bump, T>>
+where
+    T: Deserialize<'de> + 'bump,
+{
+    SeqIn(bump, PhantomData).deserialize(deserializer)
+}
+
+struct SeqIn<'bump, T>(&'bump Bump, PhantomData<T>);
+
+impl<'de, 'bump, T: Deserialize<'de> + 'bump> DeserializeSeed<'de> for SeqIn<'bump, T> {
+    type Value = BumpVec<'bump, T>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'bump, T: Deserialize<'de> + 'bump> Visitor<'de> for SeqIn<'bump, T> {
+    type Value = BumpVec<'bump, T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut out = BumpVec::new_in(self.0);
+        while let Some(value) = seq.next_element()? {
+            out.push(value);
+        }
+        Ok(out)
+    }
+}
+
+// An UPDATE with its lists in an arena and its attributes left encoded, to
+// be decoded one at a time through PathAttributeRef as needed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UpdateMessageIn<'a, 'bump> {
+    pub withdrawn_routes: BumpVec<'bump, Ipv4Prefix>,
+    pub path_attributes: BumpVec<'bump, PathAttributeRef<'a>>,
+    pub nlri: BumpVec<'bump, Ipv4Prefix>,
+}
+
+impl<'a, 'bump> UpdateMessageIn<'a, 'bump> {
+    // The message body, after the header, as from_bytes_with takes it.
+    pub fn from_bytes_in(input: &'a [u8], options: DeserializerOptions, bump: &'bump Bump) -> Result<Self> {
+        let mut de = Deserializer::with_options(input, options);
+        let len = u16::deserialize(&mut de)? as usize;
+        let withdrawn_routes = de.with_limit(len, |de| seq_in(de, bump))?;
+        let len = u16::deserialize(&mut de)? as usize;
+        let path_attributes = de.with_limit(len, |de| seq_in(de, bump))?;
+        let nlri = seq_in(&mut de, bump)?;
+        de.end()?;
+        Ok(UpdateMessageIn { withdrawn_routes, path_attributes, nlri })
+    }
+
+    // A whole message, header included, as from_message_bytes_with takes it.
+    pub fn from_message_bytes_in(input: &'a [u8], options: DeserializerOptions, bump: &'bump Bump) -> Result<Self> {
+        let msg_type = message_header(input)?;
+        if msg_type != MessageType::Update {
+            return Err(DeserializeError::Custom(format!("Expected Update message, header says {:?}", msg_type)));
+        }
+        Self::from_bytes_in(&input[HEADER_LEN..], options, bump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_update_in_arena() {
+        let mut update = UpdateMessage::default();
+        update.withdrawn_routes.value = vec!["198.51.100.0/24".parse().unwrap()];
+        update.path_attributes.value = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001])]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+        ];
+        update.nlri = vec!["203.0.113.0/24".parse().unwrap(), "192.0.2.0/25".parse().unwrap()];
+        let wire = to_message_bytes(MessageType::Update, &update).unwrap();
+
+        let mut bump = Bump::new();
+        for _ in 0..2 {
+            let decoded = UpdateMessageIn::from_message_bytes_in(&wire, DeserializerOptions::default(), &bump).unwrap();
+            assert_eq!(decoded.withdrawn_routes.as_slice(), update.withdrawn_routes.value.as_slice());
+            assert_eq!(decoded.nlri.as_slice(), update.nlri.as_slice());
+            let attrs: Vec<_> = decoded.path_attributes.iter().map(|a| a.decode().unwrap()).collect();
+            assert_eq!(attrs, update.path_attributes.value);
+            drop(decoded);
+            bump.reset();
+        }
+
+        let truncated = &wire[HEADER_LEN..wire.len() - 1];
+        match UpdateMessageIn::from_bytes_in(truncated, DeserializerOptions::default(), &bump) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e, DeserializeError::At { .. } | DeserializeError::UnexpectedEof { .. })),
+        }
+        let keepalive = to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap();
+        assert!(UpdateMessageIn::from_message_bytes_in(&keepalive, DeserializerOptions::default(), &bump).is_err());
+    }
+}
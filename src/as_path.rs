@@ -0,0 +1,464 @@
+// AS_PATH attribute handling.
+//
+// AsPathSegment is the owned model of one segment (segment type, ASN count,
+// ASNs). AsPathIter walks the raw attribute value without allocating, for hot
+// paths such as loop detection on every UPDATE. The ASN width is not on the
+// wire, it comes from whether 4-octet AS numbers were negotiated with the
+// peer; AsPathSegment leaves that to the Serializer/Deserializer setting.
+use std::fmt;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::DeserializeError;
+use crate::types::{ASN, FOUR_OCTET_ASN, TWO_OCTET_SESSION};
+
+pub const AS_SET: u8 = 1;
+pub const AS_SEQUENCE: u8 = 2;
+pub const AS_CONFED_SEQUENCE: u8 = 3;
+pub const AS_CONFED_SET: u8 = 4;
+
+// RFC 6793: stands in for AS numbers over 65535 on 2-octet sessions.
+pub const AS_TRANS: u32 = 23456;
+
+// An AS number at the session's ASN width.
+pub(crate) struct Asn(pub u32);
+
+impl Serialize for Asn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(ASN, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Asn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct AsnVisitor;
+
+        impl<'de> Visitor<'de> for AsnVisitor {
+            type Value = Asn;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an AS number")
+            }
+
+            fn visit_u32<E: de::Error>(self, v: u32) -> std::result::Result<Asn, E> {
+                Ok(Asn(v))
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Asn, D::Error> {
+                u32::deserialize(d).map(Asn)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(ASN, AsnVisitor)
+    }
+}
+
+// Always 4-octet AS numbers inside, for AS4_PATH and AS4_AGGREGATOR.
+pub(crate) struct FourOctet<T>(pub T);
+
+impl<T: Serialize> Serialize for FourOctet<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(FOUR_OCTET_ASN, &self.0)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for FourOctet<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct FourOctetVisitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for FourOctetVisitor<T> {
+            type Value = FourOctet<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a value with 4-octet AS numbers")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                T::deserialize(d).map(FourOctet)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(FOUR_OCTET_ASN, FourOctetVisitor(std::marker::PhantomData))
+    }
+}
+
+// Written only on 2-octet sessions; serialize only.
+pub(crate) struct TwoOctetSession<T>(pub T);
+
+impl<T: Serialize> Serialize for TwoOctetSession<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(TWO_OCTET_SESSION, &self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AsPathSegment {
+    AsSet(Vec<u32>),
+    AsSequence(Vec<u32>),
+    AsConfedSequence(Vec<u32>),
+    AsConfedSet(Vec<u32>),
+}
+
+impl AsPathSegment {
+    pub fn segment_type(&self) -> u8 {
+        match self {
+            AsPathSegment::AsSet(_) => AS_SET,
+            AsPathSegment::AsSequence(_) => AS_SEQUENCE,
+            AsPathSegment::AsConfedSequence(_) => AS_CONFED_SEQUENCE,
+            AsPathSegment::AsConfedSet(_) => AS_CONFED_SET,
+        }
+    }
+
+    pub fn asns(&self) -> &[u32] {
+        match self {
+            AsPathSegment::AsSet(a)
+            | AsPathSegment::AsSequence(a)
+            | AsPathSegment::AsConfedSequence(a)
+            | AsPathSegment::AsConfedSet(a) => a,
+        }
+    }
+}
+
+// -- Path operations --
+//
+// What a speaker does to a decoded AS_PATH before passing it on. The path
+// is the Vec<AsPathSegment> of PathAttribute::AsPath.
+
+// Adds `asn` to the front `count` times, filling the leading AS_SEQUENCE
+// and starting new ones when it would go over 255 ASNs.
+pub fn prepend_asn(path: &mut Vec<AsPathSegment>, asn: u32, count: usize) {
+    for _ in 0..count {
+        match path.first_mut() {
+            Some(AsPathSegment::AsSequence(asns)) if asns.len() < u8::MAX as usize => asns.insert(0, asn),
+            _ => path.insert(0, AsPathSegment::AsSequence(vec![asn])),
+        }
+    }
+}
+
+// The length used in best path selection (RFC 4271 9.1.2.2): an AS_SET
+// counts as 1 however many ASNs it holds, confederation segments count 0
+// (RFC 5065 5.3).
+pub fn as_path_len(path: &[AsPathSegment]) -> usize {
+    path.iter()
+        .map(|segment| match segment {
+            AsPathSegment::AsSequence(asns) => asns.len(),
+            AsPathSegment::AsSet(_) => 1,
+            AsPathSegment::AsConfedSequence(_) | AsPathSegment::AsConfedSet(_) => 0,
+        })
+        .sum()
+}
+
+// Loop detection: whether `asn` appears in any segment. AsPathIter::contains
+// does the same on undecoded bytes.
+pub fn as_path_contains(path: &[AsPathSegment], asn: u32) -> bool {
+    path.iter().any(|segment| segment.asns().contains(&asn))
+}
+
+// Drops the confederation segments, as a confederation member does before
+// advertising outside it (RFC 5065 5.1).
+pub fn strip_confederations(path: &mut Vec<AsPathSegment>) {
+    path.retain(|segment| !matches!(segment, AsPathSegment::AsConfedSequence(_) | AsPathSegment::AsConfedSet(_)));
+}
+
+// The AS the route was learned from, for MED comparison: the first ASN of
+// the path once confederation segments are skipped. None for an empty path
+// or one that starts with an AS_SET.
+pub fn neighbor_as(path: &[AsPathSegment]) -> Option<u32> {
+    match path
+        .iter()
+        .find(|segment| !matches!(segment, AsPathSegment::AsConfedSequence(_) | AsPathSegment::AsConfedSet(_)))?
+    {
+        AsPathSegment::AsSequence(asns) => asns.first().copied(),
+        _ => None,
+    }
+}
+
+// In text formats a segment is {"as_sequence": [65001, 65002]}.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "AsPathSegment", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum AsPathSegmentText {
+    AsSet(Vec<u32>),
+    AsSequence(Vec<u32>),
+    AsConfedSequence(Vec<u32>),
+    AsConfedSet(Vec<u32>),
+}
+
+impl Serialize for AsPathSegment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return AsPathSegmentText::serialize(self, serializer);
+        }
+        let asns = self.asns();
+        let count = u8::try_from(asns.len()).map_err(|_| {
+            serde::ser::Error::custom(format!("AS_PATH segment holds at most 255 ASNs, got {}", asns.len()))
+        })?;
+        let mut tup = serializer.serialize_tuple(2 + asns.len())?;
+        tup.serialize_element(&self.segment_type())?;
+        tup.serialize_element(&count)?;
+        for &asn in asns {
+            tup.serialize_element(&Asn(asn))?;
+        }
+        tup.end()
+    }
+}
+
+struct SegmentVisitor;
+
+impl<'de> Visitor<'de> for SegmentVisitor {
+    type Value = AsPathSegment;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an AS_PATH segment")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<AsPathSegment, A::Error> {
+        let segment_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let count: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let mut asns = Vec::with_capacity(count as usize);
+        for i in 0..count as usize {
+            // Running out of input part way (the count disagreeing with the
+            // attribute length) is the Deserializer's error, kept as it is.
+            let asn: Asn = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2 + i, &self))?;
+            asns.push(asn.0);
+        }
+        match segment_type {
+            AS_SET => Ok(AsPathSegment::AsSet(asns)),
+            AS_SEQUENCE => Ok(AsPathSegment::AsSequence(asns)),
+            AS_CONFED_SEQUENCE => Ok(AsPathSegment::AsConfedSequence(asns)),
+            AS_CONFED_SET => Ok(AsPathSegment::AsConfedSet(asns)),
+            other => Err(de::Error::custom(format!("Unknown AS_PATH segment type {}", other))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AsPathSegment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return AsPathSegmentText::deserialize(deserializer);
+        }
+        // Only as many elements as the count calls for are read.
+        deserializer.deserialize_tuple(2 + u8::MAX as usize, SegmentVisitor)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AsPathIter<'a> {
+    rest: &'a [u8],
+    asn_len: usize,
+}
+
+// One segment borrowed from the attribute bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsPathSegmentRef<'a> {
+    pub segment_type: u8,
+    asns: &'a [u8],
+    asn_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsnIter<'a> {
+    rest: &'a [u8],
+    asn_len: usize,
+}
+
+impl<'a> AsPathIter<'a> {
+    pub fn new(bytes: &'a [u8], four_octet_asn: bool) -> Self {
+        AsPathIter {
+            rest: bytes,
+            asn_len: if four_octet_asn { 4 } else { 2 },
+        }
+    }
+
+    // True if the ASN shows up anywhere in the path. Stops at the first
+    // match, or the first malformed segment.
+    pub fn contains(self, asn: u32) -> std::result::Result<bool, DeserializeError> {
+        for segment in self {
+            if segment?.asns().any(|a| a == asn) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<'a> Iterator for AsPathIter<'a> {
+    type Item = std::result::Result<AsPathSegmentRef<'a>, DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        if self.rest.len() < 2 {
+            let len = self.rest.len();
+            self.rest = &[];
+            return Some(Err(DeserializeError::Custom(format!(
+                "AS_PATH segment header truncated, {} octet(s) left", len
+            ))));
+        }
+        let segment_type = self.rest[0];
+        let needed = self.rest[1] as usize * self.asn_len;
+        let body = &self.rest[2..];
+        if body.len() < needed {
+            self.rest = &[];
+            return Some(Err(DeserializeError::Custom(format!(
+                "AS_PATH segment needs {} octets, {} available", needed, body.len()
+            ))));
+        }
+        self.rest = &body[needed..];
+        Some(Ok(AsPathSegmentRef {
+            segment_type,
+            asns: &body[..needed],
+            asn_len: self.asn_len,
+        }))
+    }
+}
+
+impl<'a> AsPathSegmentRef<'a> {
+    // Number of ASNs in the segment.
+    pub fn len(&self) -> usize {
+        self.asns.len() / self.asn_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.asns.is_empty()
+    }
+
+    pub fn asns(&self) -> AsnIter<'a> {
+        AsnIter { rest: self.asns, asn_len: self.asn_len }
+    }
+}
+
+impl Iterator for AsnIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.rest.len() < self.asn_len {
+            return None;
+        }
+        let (asn, rest) = self.rest.split_at(self.asn_len);
+        self.rest = rest;
+        Some(asn.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.rest.len() / self.asn_len;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for AsnIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_bytes, from_bytes_with, to_bytes, to_bytes_with, DeserializeError, DeserializerOptions, SerializerOptions,
+    };
+
+    #[test]
+    fn test_as_path_iter_two_octet() {
+        // AS_SEQUENCE 65001 65002, AS_SET 100
+        let raw = [2, 2, 0xfd, 0xe9, 0xfd, 0xea, 1, 1, 0, 100];
+        let segments: Vec<_> = AsPathIter::new(&raw, false).map(|s| s.unwrap()).collect();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].segment_type, AS_SEQUENCE);
+        assert_eq!(segments[0].asns().collect::<Vec<_>>(), vec![65001, 65002]);
+        assert_eq!(segments[1].segment_type, AS_SET);
+        assert_eq!(segments[1].len(), 1);
+        assert_eq!(segments[1].asns().collect::<Vec<_>>(), vec![100]);
+    }
+
+    #[test]
+    fn test_as_path_iter_four_octet() {
+        let raw = [2, 2, 0, 1, 0, 0, 0, 0, 0xfd, 0xe9];
+        assert!(AsPathIter::new(&raw, true).contains(65536).unwrap());
+        assert!(AsPathIter::new(&raw, true).contains(65001).unwrap());
+        assert!(!AsPathIter::new(&raw, true).contains(1).unwrap());
+    }
+
+    #[test]
+    fn test_as_path_iter_truncated() {
+        // Count says 3 ASNs but only 2 are present.
+        let raw = [2, 3, 0, 1, 0, 2];
+        let mut iter = AsPathIter::new(&raw, false);
+        match iter.next() {
+            Some(Err(e)) => assert_eq!(e.to_string(), "AS_PATH segment needs 6 octets, 4 available"),
+            other => panic!("Expected Err, got {:?}", other),
+        }
+        assert!(iter.next().is_none());
+
+        assert!(AsPathIter::new(&[2], false).contains(1).is_err());
+    }
+
+    #[test]
+    fn test_segment_wire() {
+        let path = vec![AsPathSegment::AsSequence(vec![65001, 65002]), AsPathSegment::AsSet(vec![100])];
+        let four = [2, 2, 0, 0, 0xfd, 0xe9, 0, 0, 0xfd, 0xea, 1, 1, 0, 0, 0, 100];
+        assert_eq!(to_bytes(&path).unwrap().as_ref(), &four);
+        assert_eq!(from_bytes::<Vec<AsPathSegment>>(&four).unwrap(), path);
+
+        let two = [2, 2, 0xfd, 0xe9, 0xfd, 0xea, 1, 1, 0, 100];
+        let ser_opts = SerializerOptions { four_octet_asn: false, ..Default::default() };
+        assert_eq!(to_bytes_with(&path, ser_opts).unwrap().as_ref(), &two);
+        let de_opts = DeserializerOptions { four_octet_asn: false, ..Default::default() };
+        assert_eq!(from_bytes_with::<Vec<AsPathSegment>>(&two, de_opts).unwrap(), path);
+    }
+
+    #[test]
+    fn test_segment_as_trans() {
+        let opts = SerializerOptions { four_octet_asn: false, ..Default::default() };
+        let bytes = to_bytes_with(AsPathSegment::AsSequence(vec![4200000000, 65001]), opts).unwrap();
+        assert_eq!(bytes.as_ref(), &[2, 2, 0x5b, 0xa0, 0xfd, 0xe9]);
+    }
+
+    #[test]
+    fn test_path_operations() {
+        let mut path = vec![
+            AsPathSegment::AsConfedSequence(vec![64512, 64513]),
+            AsPathSegment::AsSequence(vec![65001, 65002]),
+            AsPathSegment::AsSet(vec![100, 200, 300]),
+        ];
+        assert_eq!(as_path_len(&path), 3);
+        assert_eq!(neighbor_as(&path), Some(65001));
+        assert!(as_path_contains(&path, 200));
+        assert!(as_path_contains(&path, 64513));
+        assert!(!as_path_contains(&path, 65003));
+
+        strip_confederations(&mut path);
+        assert_eq!(path[0], AsPathSegment::AsSequence(vec![65001, 65002]));
+        prepend_asn(&mut path, 65000, 2);
+        assert_eq!(path[0], AsPathSegment::AsSequence(vec![65000, 65000, 65001, 65002]));
+        assert_eq!(as_path_len(&path), 5);
+
+        // A full leading AS_SEQUENCE, or one that isn't a sequence, gets a
+        // new segment in front.
+        let mut path = vec![AsPathSegment::AsSequence(vec![1; 255])];
+        prepend_asn(&mut path, 2, 1);
+        assert_eq!(path, [AsPathSegment::AsSequence(vec![2]), AsPathSegment::AsSequence(vec![1; 255])]);
+        let mut path = vec![AsPathSegment::AsSet(vec![1, 2])];
+        assert_eq!(neighbor_as(&path), None);
+        prepend_asn(&mut path, 3, 1);
+        assert_eq!(neighbor_as(&path), Some(3));
+        assert_eq!(neighbor_as(&[]), None);
+    }
+
+    #[test]
+    fn test_segment_err() {
+        match from_bytes::<AsPathSegment>(&[2, 3, 0, 0, 0, 1]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                let eof = DeserializeError::UnexpectedEof { needed: 4, available: 0, offset: 6 };
+                assert_eq!(e.inner(), &eof);
+            },
+        }
+        match from_bytes::<AsPathSegment>(&[7, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Unknown AS_PATH segment type 7"),
+        }
+        assert!(to_bytes(AsPathSegment::AsSet(vec![1; 256])).is_err());
+    }
+}
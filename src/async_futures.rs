@@ -0,0 +1,84 @@
+// Message I/O on futures-io streams (feature = "futures-io").
+//
+// The same as async_tokio, for runtimes built on the futures::io traits
+// (async-std, smol) without pulling in tokio. recv_message is cancellation
+// safe the same way: received octets live in the caller's MessageStream.
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::messages::{BgpMessage, Message};
+use crate::ser::{to_message_bytes_with, SerializerOptions};
+use crate::stream::MessageStream;
+
+// The next message, or None if the peer closed the connection between
+// messages. Closing mid-message is an UnexpectedEof error.
+pub async fn recv_message<R>(reader: &mut R, stream: &mut MessageStream) -> Result<Option<BgpMessage>>
+where
+    R: AsyncRead + Unpin,
+{
+    if let Some(message) = stream.next_message()? {
+        return Ok(Some(message));
+    }
+    let mut chunk = vec![0u8; stream.options().max_message_len];
+    loop {
+        match poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut chunk)).await? {
+            0 if stream.buffered() == 0 => return Ok(None),
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            n => {
+                if let Some(message) = stream.feed(&chunk[..n])? {
+                    return Ok(Some(message));
+                }
+            },
+        }
+    }
+}
+
+// Not cancellation safe, see async_tokio::send_message.
+pub async fn send_message<W, T>(writer: &mut W, message: &T, options: SerializerOptions) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Message + Serialize,
+{
+    let bytes = to_message_bytes_with(T::MESSAGE_TYPE, message, options)?;
+    let mut written = 0;
+    while written < bytes.len() {
+        match poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, &bytes[written..])).await? {
+            0 => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+            n => written += n,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{KeepaliveMessage, UpdateMessage};
+
+    // futures-io implements the traits for Vec<u8> and &[u8]; tokio's
+    // runtime only drives the futures.
+    #[tokio::test]
+    async fn test_futures_send_recv() {
+        let update = UpdateMessage { nlri: vec!["192.0.2.0/24".parse().unwrap()], ..Default::default() };
+        let mut out = Vec::new();
+        send_message(&mut out, &update, SerializerOptions::default()).await.unwrap();
+        send_message(&mut out, &KeepaliveMessage, SerializerOptions::default()).await.unwrap();
+        out.extend_from_slice(&[0xff; 10]);
+
+        let mut reader = out.as_slice();
+        let mut stream = MessageStream::new();
+        let update = BgpMessage::Update(update);
+        assert_eq!(recv_message(&mut reader, &mut stream).await.unwrap(), Some(update));
+        let keepalive = BgpMessage::Keepalive(KeepaliveMessage);
+        assert_eq!(recv_message(&mut reader, &mut stream).await.unwrap(), Some(keepalive));
+        assert!(recv_message(&mut reader, &mut stream).await.is_err());
+
+        assert_eq!(recv_message(&mut [].as_slice(), &mut MessageStream::new()).await.unwrap(), None);
+    }
+}
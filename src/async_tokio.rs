@@ -0,0 +1,87 @@
+// Message I/O on tokio streams (feature = "tokio").
+//
+// The async counterparts of send_message/recv_message, for speakers that
+// don't want Framed<TcpStream, BgpCodec>. Received octets are kept in a
+// MessageStream owned by the caller, not in the future, so a recv_message
+// dropped by select! or a timeout loses nothing: the next call picks up
+// where it left off.
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::messages::{BgpMessage, Message};
+use crate::ser::{to_message_bytes_with, SerializerOptions};
+use crate::stream::MessageStream;
+
+// The next message, or None if the peer closed the connection between
+// messages. Closing mid-message is an UnexpectedEof error.
+pub async fn recv_message<R>(reader: &mut R, stream: &mut MessageStream) -> Result<Option<BgpMessage>>
+where
+    R: AsyncRead + Unpin,
+{
+    if let Some(message) = stream.next_message()? {
+        return Ok(Some(message));
+    }
+    let mut chunk = vec![0u8; stream.options().max_message_len];
+    loop {
+        // A cancelled read has read nothing, and nothing is awaited between
+        // a completed read and feeding what it got.
+        match reader.read(&mut chunk).await? {
+            0 if stream.buffered() == 0 => return Ok(None),
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            n => {
+                if let Some(message) = stream.feed(&chunk[..n])? {
+                    return Ok(Some(message));
+                }
+            },
+        }
+    }
+}
+
+// Not cancellation safe: a write dropped partway leaves part of a message
+// on the wire, and the session has to be torn down.
+pub async fn send_message<W, T>(writer: &mut W, message: &T, options: SerializerOptions) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Message + Serialize,
+{
+    writer.write_all(&to_message_bytes_with(T::MESSAGE_TYPE, message, options)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{KeepaliveMessage, UpdateMessage};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn test_tokio_send_recv() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        let update = UpdateMessage { nlri: vec!["192.0.2.0/24".parse().unwrap()], ..Default::default() };
+        send_message(&mut client, &update, SerializerOptions::default()).await.unwrap();
+        send_message(&mut client, &KeepaliveMessage, SerializerOptions::default()).await.unwrap();
+        // Half a KEEPALIVE, then the connection closes
+        client.write_all(&[0xff; 10]).await.unwrap();
+        drop(client);
+
+        let mut stream = MessageStream::new();
+        let update = BgpMessage::Update(update);
+        assert_eq!(recv_message(&mut server, &mut stream).await.unwrap(), Some(update));
+        let keepalive = BgpMessage::Keepalive(KeepaliveMessage);
+        assert_eq!(recv_message(&mut server, &mut stream).await.unwrap(), Some(keepalive));
+        assert!(recv_message(&mut server, &mut stream).await.is_err());
+
+        // Closed between messages
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        drop(client);
+        assert_eq!(recv_message(&mut server, &mut MessageStream::new()).await.unwrap(), None);
+    }
+}
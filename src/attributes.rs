@@ -0,0 +1,840 @@
+// UPDATE path attributes (RFC 4271 4.3).
+//
+// Each attribute is a flags octet, a type code, a 1 or 2 octet length (2 when
+// the Extended Length flag is set) and the value. PathAttribute only models
+// the value; flags come from the attribute type and the Serializer picks the
+// length width from the encoded value, so neither can be got wrong by hand.
+// Attributes the crate doesn't know are kept as Unknown with their flags and
+// raw value so they can be passed on unchanged (or skipped, or rejected, see
+// OnUnknown). Known attributes received with flags their type doesn't set
+// (Partial from a speaker that passed them on, reserved bits under
+// ReservedFlags::Preserve) are wrapped in WithFlags, which keeps the flags
+// as received.
+//
+// AS numbers in AS_PATH and AGGREGATOR take the session's width
+// (SerializerOptions::four_octet_asn). On 2-octet sessions an AS_PATH or
+// AGGREGATOR holding AS numbers over 65535 is followed by the AS4_PATH or
+// AS4_AGGREGATOR carrying the real ones (RFC 6793 4.2.2).
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::Ipv4Addr;
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::aigp::AigpTlv;
+use crate::as_path::{AsPathSegment, Asn, FourOctet, TwoOctetSession};
+use crate::bgp_ls::LinkStateAttribute;
+use crate::bgpsec::BgpsecPath;
+use crate::communities::{Community, ExtendedCommunity, LargeCommunity};
+use crate::de::{from_bytes_seed, DeserializerOptions};
+use crate::error::DeserializeError;
+use crate::multiprotocol::{MpReachNlri, MpUnreachNlri};
+use crate::prefix_sid::PrefixSidTlv;
+use crate::registry::path_attr;
+use crate::types::{
+    BgpIdentifier, LengthPrefixed, LengthPrefixedSeed, Skippable, SkippingList, TextOrNumber, UnknownValueSeed, ATTRIBUTE_FLAGS,
+    ATTRIBUTE_LIST, PATH_ATTRIBUTE,
+};
+
+// Attribute flags octet
+pub const ATTR_OPTIONAL: u8 = 0x80;
+pub const ATTR_TRANSITIVE: u8 = 0x40;
+pub const ATTR_PARTIAL: u8 = 0x20;
+pub const ATTR_EXTENDED_LENGTH: u8 = 0x10;
+// Unused, must be zero (RFC 4271 4.3)
+pub const ATTR_RESERVED: u8 = 0x0f;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Origin {
+    Igp,
+    Egp,
+    Incomplete,
+    Unknown(u8),
+}
+
+impl From<u8> for Origin {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Origin::Igp,
+            1 => Origin::Egp,
+            2 => Origin::Incomplete,
+            other => Origin::Unknown(other),
+        }
+    }
+}
+
+impl From<Origin> for u8 {
+    fn from(o: Origin) -> Self {
+        match o {
+            Origin::Igp => 0,
+            Origin::Egp => 1,
+            Origin::Incomplete => 2,
+            Origin::Unknown(other) => other,
+        }
+    }
+}
+
+impl Origin {
+    // Name in text formats
+    fn name(&self) -> Option<&'static str> {
+        match self {
+            Origin::Igp => Some("igp"),
+            Origin::Egp => Some("egp"),
+            Origin::Incomplete => Some("incomplete"),
+            Origin::Unknown(_) => None,
+        }
+    }
+}
+
+impl Serialize for Origin {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.name() {
+            Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => serializer.serialize_u8(u8::from(*self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Origin {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return TextOrNumber::deserialize(deserializer)?.decode(
+                "ORIGIN",
+                |s| [Origin::Igp, Origin::Egp, Origin::Incomplete].into_iter().find(|o| o.name() == Some(s)),
+                |v| u8::try_from(v).ok().map(Origin::from),
+            );
+        }
+        u8::deserialize(deserializer).map(Origin::from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PathAttribute {
+    Origin(Origin),
+    AsPath(Vec<AsPathSegment>),
+    NextHop(Ipv4Addr),
+    MultiExitDisc(u32),
+    LocalPref(u32),
+    AtomicAggregate,
+    Aggregator { asn: u32, address: Ipv4Addr },
+    As4Path(Vec<AsPathSegment>),
+    Communities(Vec<Community>),
+    // RFC 4456
+    OriginatorId(BgpIdentifier),
+    ClusterList(Vec<Ipv4Addr>),
+    As4Aggregator { asn: u32, address: Ipv4Addr },
+    MpReachNlri(MpReachNlri),
+    MpUnreachNlri(MpUnreachNlri),
+    ExtendedCommunities(Vec<ExtendedCommunity>),
+    LargeCommunities(Vec<LargeCommunity>),
+    // RFC 7311
+    Aigp(Vec<AigpTlv>),
+    // RFC 6790 ELC, deprecated by RFC 7447 but still found in old MRT dumps.
+    // Always empty; a value received with any octets in it is kept as
+    // Unknown instead.
+    EntropyLabelCapability,
+    // RFC 7752
+    BgpLs(Vec<LinkStateAttribute>),
+    // RFC 8205
+    BgpsecPath(BgpsecPath),
+    // RFC 8669
+    PrefixSid(Vec<PrefixSidTlv>),
+    // Flags are kept as received; the Extended Length flag is still set on
+    // the way out if the value needs it.
+    Unknown {
+        code: u8,
+        flags: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+    // An attribute received with flags its type doesn't set, which go out
+    // again in place of its own, Extended Length included.
+    WithFlags { flags: u8, attribute: Box<PathAttribute> },
+}
+
+impl PathAttribute {
+    pub fn type_code(&self) -> u8 {
+        match self {
+            PathAttribute::Origin(_) => path_attr::ORIGIN,
+            PathAttribute::AsPath(_) => path_attr::AS_PATH,
+            PathAttribute::NextHop(_) => path_attr::NEXT_HOP,
+            PathAttribute::MultiExitDisc(_) => path_attr::MULTI_EXIT_DISC,
+            PathAttribute::LocalPref(_) => path_attr::LOCAL_PREF,
+            PathAttribute::AtomicAggregate => path_attr::ATOMIC_AGGREGATE,
+            PathAttribute::Aggregator { .. } => path_attr::AGGREGATOR,
+            PathAttribute::Communities(_) => path_attr::COMMUNITIES,
+            PathAttribute::OriginatorId(_) => path_attr::ORIGINATOR_ID,
+            PathAttribute::ClusterList(_) => path_attr::CLUSTER_LIST,
+            PathAttribute::As4Path(_) => path_attr::AS4_PATH,
+            PathAttribute::As4Aggregator { .. } => path_attr::AS4_AGGREGATOR,
+            PathAttribute::MpReachNlri(_) => path_attr::MP_REACH_NLRI,
+            PathAttribute::MpUnreachNlri(_) => path_attr::MP_UNREACH_NLRI,
+            PathAttribute::ExtendedCommunities(_) => path_attr::EXTENDED_COMMUNITIES,
+            PathAttribute::LargeCommunities(_) => path_attr::LARGE_COMMUNITIES,
+            PathAttribute::Aigp(_) => path_attr::AIGP,
+            PathAttribute::EntropyLabelCapability => path_attr::ENTROPY_LABEL_CAPABILITY,
+            PathAttribute::BgpLs(_) => path_attr::BGP_LS,
+            PathAttribute::BgpsecPath(_) => path_attr::BGPSEC_PATH,
+            PathAttribute::PrefixSid(_) => path_attr::PREFIX_SID,
+            PathAttribute::Unknown { code, .. } => *code,
+            PathAttribute::WithFlags { attribute, .. } => attribute.type_code(),
+        }
+    }
+
+    // The attribute itself, looking through WithFlags.
+    pub fn attribute(&self) -> &PathAttribute {
+        match self {
+            PathAttribute::WithFlags { attribute, .. } => attribute.attribute(),
+            attribute => attribute,
+        }
+    }
+
+    pub fn attribute_mut(&mut self) -> &mut PathAttribute {
+        match self {
+            PathAttribute::WithFlags { attribute, .. } => attribute.attribute_mut(),
+            attribute => attribute,
+        }
+    }
+
+    // Keeps `flags`, as decoded, if they have bits the attribute wouldn't be
+    // written with. Extended Length doesn't count, the Serializer sets it
+    // when the value needs it; nor do bits missing, they go out set.
+    fn with_received_flags(self, flags: u8) -> PathAttribute {
+        match flags & !self.flags() & !ATTR_EXTENDED_LENGTH {
+            0 => self,
+            _ => PathAttribute::WithFlags { flags, attribute: Box::new(self) },
+        }
+    }
+
+    // Flags as written, before the Extended Length flag is worked out.
+    pub fn flags(&self) -> u8 {
+        match self {
+            PathAttribute::Origin(_)
+            | PathAttribute::AsPath(_)
+            | PathAttribute::NextHop(_)
+            | PathAttribute::LocalPref(_)
+            | PathAttribute::AtomicAggregate => ATTR_TRANSITIVE,
+            PathAttribute::MultiExitDisc(_)
+            | PathAttribute::OriginatorId(_)
+            | PathAttribute::ClusterList(_)
+            | PathAttribute::MpReachNlri(_)
+            | PathAttribute::MpUnreachNlri(_)
+            | PathAttribute::Aigp(_)
+            | PathAttribute::BgpLs(_)
+            | PathAttribute::BgpsecPath(_) => ATTR_OPTIONAL,
+            PathAttribute::Aggregator { .. }
+            | PathAttribute::Communities(_)
+            | PathAttribute::As4Path(_)
+            | PathAttribute::As4Aggregator { .. }
+            | PathAttribute::ExtendedCommunities(_)
+            | PathAttribute::LargeCommunities(_)
+            | PathAttribute::PrefixSid(_) => ATTR_OPTIONAL | ATTR_TRANSITIVE,
+            // RFC 7447: speakers pass it on as an unrecognized attribute, so
+            // it goes out as one would, with Partial set
+            PathAttribute::EntropyLabelCapability => ATTR_OPTIONAL | ATTR_TRANSITIVE | ATTR_PARTIAL,
+            PathAttribute::Unknown { flags, .. } | PathAttribute::WithFlags { flags, .. } => *flags,
+        }
+    }
+}
+
+// -- Route reflection (RFC 4456) --
+
+// A reflected route that has come back: it was originated by this router,
+// or has already been through this cluster.
+pub fn reflection_loop(attributes: &[PathAttribute], router_id: BgpIdentifier, cluster_id: Ipv4Addr) -> bool {
+    attributes.iter().any(|a| match a.attribute() {
+        PathAttribute::OriginatorId(id) => *id == router_id,
+        PathAttribute::ClusterList(ids) => ids.contains(&cluster_id),
+        _ => false,
+    })
+}
+
+// What a route reflector adds before reflecting a route: ORIGINATOR_ID (the
+// router ID of the peer the route came from) unless one is already there,
+// and its cluster ID in front of CLUSTER_LIST. New attributes go in type
+// code order.
+pub fn reflect(attributes: &mut Vec<PathAttribute>, originator_id: BgpIdentifier, cluster_id: Ipv4Addr) {
+    if !attributes.iter().any(|a| matches!(a.attribute(), PathAttribute::OriginatorId(_))) {
+        insert_in_order(attributes, PathAttribute::OriginatorId(originator_id));
+    }
+    match attributes.iter_mut().find_map(|a| match a.attribute_mut() {
+        PathAttribute::ClusterList(ids) => Some(ids),
+        _ => None,
+    }) {
+        Some(ids) => ids.insert(0, cluster_id),
+        None => insert_in_order(attributes, PathAttribute::ClusterList(vec![cluster_id])),
+    }
+}
+
+// Removes the ELC, whether it decoded as EntropyLabelCapability or, with a
+// value in it, as Unknown. RFC 6790 5.2 requires it gone once the next hop
+// is changed by a speaker that doesn't handle entropy labels, which with
+// RFC 7447 is every speaker. Returns whether there was one.
+pub fn strip_elc(attributes: &mut Vec<PathAttribute>) -> bool {
+    let before = attributes.len();
+    attributes.retain(|a| a.type_code() != path_attr::ENTROPY_LABEL_CAPABILITY);
+    attributes.len() != before
+}
+
+fn insert_in_order(attributes: &mut Vec<PathAttribute>, attr: PathAttribute) {
+    let at = attributes.iter().position(|a| a.type_code() > attr.type_code()).unwrap_or(attributes.len());
+    attributes.insert(at, attr);
+}
+
+// The value part of an attribute, without flags, type or length.
+struct AttributeValue<'a>(&'a PathAttribute);
+
+impl Serialize for AttributeValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            PathAttribute::Origin(origin) => origin.serialize(serializer),
+            PathAttribute::AsPath(segments) => segments.serialize(serializer),
+            PathAttribute::NextHop(addr) => addr.serialize(serializer),
+            PathAttribute::MultiExitDisc(v) | PathAttribute::LocalPref(v) => serializer.serialize_u32(*v),
+            PathAttribute::AtomicAggregate | PathAttribute::EntropyLabelCapability => serializer.serialize_unit(),
+            PathAttribute::Aggregator { asn, address } => (Asn(*asn), address).serialize(serializer),
+            PathAttribute::Communities(communities) => communities.serialize(serializer),
+            PathAttribute::OriginatorId(id) => id.serialize(serializer),
+            PathAttribute::ClusterList(ids) => ids.serialize(serializer),
+            PathAttribute::As4Path(segments) => FourOctet(segments).serialize(serializer),
+            PathAttribute::As4Aggregator { asn, address } => (asn, address).serialize(serializer),
+            PathAttribute::MpReachNlri(reach) => reach.serialize(serializer),
+            PathAttribute::MpUnreachNlri(unreach) => unreach.serialize(serializer),
+            PathAttribute::ExtendedCommunities(communities) => communities.serialize(serializer),
+            PathAttribute::LargeCommunities(communities) => communities.serialize(serializer),
+            PathAttribute::Aigp(tlvs) => tlvs.serialize(serializer),
+            PathAttribute::BgpLs(tlvs) => tlvs.serialize(serializer),
+            PathAttribute::BgpsecPath(path) => path.serialize(serializer),
+            PathAttribute::PrefixSid(tlvs) => tlvs.serialize(serializer),
+            PathAttribute::Unknown { data, .. } => serializer.serialize_bytes(data),
+            PathAttribute::WithFlags { attribute, .. } => AttributeValue(attribute).serialize(serializer),
+        }
+    }
+}
+
+// Flags, type code and length around a value.
+pub(crate) struct Encoded<V> {
+    pub flags: u8,
+    pub type_code: u8,
+    pub value: V,
+}
+
+impl<V: Serialize> Serialize for Encoded<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(&self.value);
+        serializer.serialize_newtype_struct(PATH_ATTRIBUTE, &(self.flags, self.type_code, value))
+    }
+}
+
+impl Serialize for PathAttribute {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return PathAttributeText::serialize(self, serializer);
+        }
+        let encoded = Encoded { flags: self.flags(), type_code: self.type_code(), value: AttributeValue(self) };
+        let as4_flags = ATTR_OPTIONAL | ATTR_TRANSITIVE;
+        match self.attribute() {
+            PathAttribute::AsPath(segments) if segments.iter().flat_map(|s| s.asns()).any(|&a| a > 0xffff) => {
+                let as4 = Encoded { flags: as4_flags, type_code: path_attr::AS4_PATH, value: FourOctet(segments) };
+                (encoded, TwoOctetSession(as4)).serialize(serializer)
+            },
+            PathAttribute::Aggregator { asn, address } if *asn > 0xffff => {
+                let as4 = Encoded { flags: as4_flags, type_code: path_attr::AS4_AGGREGATOR, value: (asn, address) };
+                (encoded, TwoOctetSession(as4)).serialize(serializer)
+            },
+            _ => encoded.serialize(serializer),
+        }
+    }
+}
+
+// The flags octet in front of an attribute, checked for reserved bits.
+struct AttributeFlags(u8);
+
+impl<'de> Deserialize<'de> for AttributeFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct FlagsVisitor;
+
+        impl<'de> Visitor<'de> for FlagsVisitor {
+            type Value = AttributeFlags;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("attribute flags")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                u8::deserialize(d).map(AttributeFlags)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(ATTRIBUTE_FLAGS, FlagsVisitor)
+    }
+}
+
+// Decodes the value once flags and type code are known; the length prefix
+// around it has already scoped the input to exactly the value. An attribute
+// received with flags its type doesn't set (the Partial bit, or reserved
+// bits that got past the Deserializer) keeps them in WithFlags, so they
+// aren't lost and it goes out again unchanged.
+struct ValueSeed {
+    flags: u8,
+    type_code: u8,
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = Option<PathAttribute>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Option<PathAttribute>, D::Error> {
+        let attribute = match self.type_code {
+            path_attr::ORIGIN => PathAttribute::Origin(Origin::deserialize(d)?),
+            path_attr::AS_PATH => PathAttribute::AsPath(Vec::deserialize(d)?),
+            path_attr::NEXT_HOP => PathAttribute::NextHop(Ipv4Addr::deserialize(d)?),
+            path_attr::MULTI_EXIT_DISC => PathAttribute::MultiExitDisc(u32::deserialize(d)?),
+            path_attr::LOCAL_PREF => PathAttribute::LocalPref(u32::deserialize(d)?),
+            path_attr::ATOMIC_AGGREGATE => {
+                <()>::deserialize(d)?;
+                PathAttribute::AtomicAggregate
+            },
+            path_attr::AGGREGATOR => {
+                let (Asn(asn), address) = Deserialize::deserialize(d)?;
+                PathAttribute::Aggregator { asn, address }
+            },
+            path_attr::COMMUNITIES => PathAttribute::Communities(Vec::deserialize(d)?),
+            path_attr::ORIGINATOR_ID => PathAttribute::OriginatorId(BgpIdentifier::deserialize(d)?),
+            path_attr::CLUSTER_LIST => PathAttribute::ClusterList(Vec::deserialize(d)?),
+            path_attr::AS4_PATH => PathAttribute::As4Path(FourOctet::deserialize(d)?.0),
+            path_attr::AS4_AGGREGATOR => {
+                let (asn, address) = Deserialize::deserialize(d)?;
+                PathAttribute::As4Aggregator { asn, address }
+            },
+            path_attr::MP_REACH_NLRI => PathAttribute::MpReachNlri(MpReachNlri::deserialize(d)?),
+            path_attr::MP_UNREACH_NLRI => PathAttribute::MpUnreachNlri(MpUnreachNlri::deserialize(d)?),
+            path_attr::EXTENDED_COMMUNITIES => PathAttribute::ExtendedCommunities(Vec::deserialize(d)?),
+            path_attr::LARGE_COMMUNITIES => PathAttribute::LargeCommunities(Vec::deserialize(d)?),
+            path_attr::AIGP => PathAttribute::Aigp(Vec::deserialize(d)?),
+            path_attr::ENTROPY_LABEL_CAPABILITY => match Bytes::deserialize(d)? {
+                data if data.is_empty() => PathAttribute::EntropyLabelCapability,
+                data => PathAttribute::Unknown { code: self.type_code, flags: self.flags, data },
+            },
+            path_attr::BGP_LS => PathAttribute::BgpLs(Vec::deserialize(d)?),
+            path_attr::BGPSEC_PATH => PathAttribute::BgpsecPath(BgpsecPath::deserialize(d)?),
+            path_attr::PREFIX_SID => PathAttribute::PrefixSid(Vec::deserialize(d)?),
+            code => {
+                let seed = UnknownValueSeed { what: "path attribute type", code };
+                return Ok(seed.deserialize(d)?.map(|data| PathAttribute::Unknown { code, flags: self.flags, data }));
+            },
+        };
+        Ok(Some(attribute.with_received_flags(self.flags)))
+    }
+}
+
+struct PathAttributeVisitor;
+
+impl<'de> Visitor<'de> for PathAttributeVisitor {
+    type Value = Option<PathAttribute>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a path attribute")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let AttributeFlags(flags) = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let type_code: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let width = if flags & ATTR_EXTENDED_LENGTH != 0 { 2 } else { 1 };
+        seq.next_element_seed(LengthPrefixedSeed { width, seed: ValueSeed { flags, type_code } })?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Skippable<PathAttribute> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return PathAttributeText::deserialize(deserializer).map(|attr| Skippable(Some(attr)));
+        }
+        let fields = &["flags", "type_code", "value"];
+        deserializer.deserialize_struct("PathAttribute", fields, PathAttributeVisitor).map(Skippable)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathAttribute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Skippable::deserialize(deserializer)?
+            .0
+            .ok_or_else(|| de::Error::custom("Unrecognized path attribute skipped outside an attribute list"))
+    }
+}
+
+// -- Text form --
+//
+// Text formats get each attribute as {"local_pref": 100}, its value in the
+// usual notation (AS numbers as numbers, addresses, prefixes and communities
+// as strings). Flags and type codes are implied by the variant, as on the
+// wire, except for Unknown. BGP-LS attributes have no text form of their
+// own: they are written in their wire model and can't be read back.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PathAttribute", rename_all = "snake_case")]
+enum PathAttributeText {
+    Origin(Origin),
+    AsPath(Vec<AsPathSegment>),
+    NextHop(Ipv4Addr),
+    MultiExitDisc(u32),
+    LocalPref(u32),
+    AtomicAggregate,
+    Aggregator { asn: u32, address: Ipv4Addr },
+    As4Path(Vec<AsPathSegment>),
+    Communities(Vec<Community>),
+    OriginatorId(BgpIdentifier),
+    ClusterList(Vec<Ipv4Addr>),
+    As4Aggregator { asn: u32, address: Ipv4Addr },
+    MpReachNlri(MpReachNlri),
+    MpUnreachNlri(MpUnreachNlri),
+    ExtendedCommunities(Vec<ExtendedCommunity>),
+    LargeCommunities(Vec<LargeCommunity>),
+    Aigp(Vec<AigpTlv>),
+    EntropyLabelCapability,
+    #[serde(skip_deserializing)]
+    BgpLs(Vec<LinkStateAttribute>),
+    BgpsecPath(BgpsecPath),
+    PrefixSid(Vec<PrefixSidTlv>),
+    Unknown { code: u8, flags: u8, data: Bytes },
+    WithFlags { flags: u8, attribute: Box<PathAttribute> },
+}
+
+// One attribute with its value borrowed from the input, for walking the
+// attributes of an UpdateMessageRef without decoding (or copying) the ones
+// that aren't needed. Flags are kept as received, Extended Length included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathAttributeRef<'a> {
+    pub flags: u8,
+    pub code: u8,
+    pub data: &'a [u8],
+}
+
+impl PathAttributeRef<'_> {
+    // Decodes the value into a PathAttribute. Not every value is independent
+    // of the session, use decode_with for 2-octet AS sessions.
+    pub fn decode(&self) -> std::result::Result<PathAttribute, DeserializeError> {
+        self.decode_with(DeserializerOptions::default())
+    }
+
+    pub fn decode_with(&self, options: DeserializerOptions) -> std::result::Result<PathAttribute, DeserializeError> {
+        let seed = ValueSeed { flags: self.flags, type_code: self.code };
+        from_bytes_seed(self.data, options, seed)?
+            .ok_or_else(|| de::Error::custom("Unrecognized path attribute skipped outside an attribute list"))
+    }
+}
+
+impl Serialize for PathAttributeRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Encoded { flags: self.flags, type_code: self.code, value: self.data }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathAttributeRef<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RefVisitor;
+
+        impl<'de> Visitor<'de> for RefVisitor {
+            type Value = PathAttributeRef<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a path attribute")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let flags: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let code: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let width = if flags & ATTR_EXTENDED_LENGTH != 0 { 2 } else { 1 };
+                let data = seq.next_element_seed(LengthPrefixedSeed { width, seed: PhantomData::<&'de [u8]> })?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                Ok(PathAttributeRef { flags, code, data })
+            }
+        }
+
+        deserializer.deserialize_tuple(3, RefVisitor)
+    }
+}
+
+// For the UPDATE's attribute list, which drops skipped attributes.
+pub(crate) fn deserialize_attribute_list<'de, D>(
+    deserializer: D,
+) -> std::result::Result<LengthPrefixed<u16, Vec<PathAttribute>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ListVisitor;
+
+    impl<'de> Visitor<'de> for ListVisitor {
+        type Value = LengthPrefixed<u16, Vec<PathAttribute>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a path attribute list")
+        }
+
+        fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+            let list = LengthPrefixed::<u16, SkippingList<PathAttribute>>::deserialize(d)?;
+            Ok(list.into_inner().0.into())
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(ATTRIBUTE_LIST, ListVisitor)
+}
+
+// The attribute headers of an UPDATE's attribute list, Total Path Attribute
+// Length first, add up to exactly that length and it fits in `input`
+// (RFC 4271 6.3). What is left after it is the NLRI.
+pub(crate) fn attribute_list_fits(input: &[u8]) -> bool {
+    let total = match input.get(..2) {
+        Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+        None => return false,
+    };
+    let attrs = match input.get(2..2 + total) {
+        Some(attrs) => attrs,
+        None => return false,
+    };
+    let mut pos = 0;
+    while pos < attrs.len() {
+        let header = if attrs[pos] & ATTR_EXTENDED_LENGTH != 0 { 4 } else { 3 };
+        let len = match attrs.get(pos + 2..pos + header) {
+            Some(len) => len.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize),
+            None => return false,
+        };
+        pos += header + len;
+    }
+    pos == attrs.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        describe_layout, from_bytes, from_bytes_with, to_bytes, to_bytes_with, DeserializerOptions, LayoutKind,
+        OnUnknown, SerializerOptions, UpdateMessage,
+    };
+
+    #[test]
+    fn test_route_reflection() {
+        let router_id = BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        let peer_id = BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 2)).unwrap();
+        let cluster_id = Ipv4Addr::new(10, 0, 0, 1);
+        let mut attributes = vec![PathAttribute::Origin(Origin::Igp), PathAttribute::LargeCommunities(vec![])];
+        assert!(!reflection_loop(&attributes, router_id, cluster_id));
+
+        reflect(&mut attributes, peer_id, cluster_id);
+        assert_eq!(attributes[1], PathAttribute::OriginatorId(peer_id));
+        assert_eq!(attributes[2], PathAttribute::ClusterList(vec![cluster_id]));
+        assert!(reflection_loop(&attributes, router_id, cluster_id));
+        assert!(reflection_loop(&attributes, peer_id, Ipv4Addr::new(10, 0, 0, 2)));
+
+        // A second reflector keeps the ORIGINATOR_ID and prepends its cluster
+        reflect(&mut attributes, router_id, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(attributes[1], PathAttribute::OriginatorId(peer_id));
+        assert_eq!(attributes[2], PathAttribute::ClusterList(vec![Ipv4Addr::new(10, 0, 0, 2), cluster_id]));
+
+        let wire = [0x80, 9, 4, 192, 0, 2, 2, 0x80, 10, 8, 10, 0, 0, 2, 10, 0, 0, 1];
+        assert_eq!(to_bytes(&attributes[1..3]).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Vec<PathAttribute>>(&wire).unwrap(), &attributes[1..3]);
+    }
+
+    #[test]
+    fn test_attribute_wire() {
+        let cases: [(PathAttribute, &[u8]); 7] = [
+            (PathAttribute::Origin(Origin::Igp), &[0x40, 1, 1, 0]),
+            (
+                PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001])]),
+                &[0x40, 2, 6, 2, 1, 0, 0, 0xfd, 0xe9],
+            ),
+            (PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)), &[0x40, 3, 4, 192, 0, 2, 1]),
+            (PathAttribute::MultiExitDisc(100), &[0x80, 4, 4, 0, 0, 0, 100]),
+            (PathAttribute::LocalPref(200), &[0x40, 5, 4, 0, 0, 0, 200]),
+            (PathAttribute::AtomicAggregate, &[0x40, 6, 0]),
+            (
+                PathAttribute::Aggregator { asn: 65001, address: Ipv4Addr::new(192, 0, 2, 1) },
+                &[0xc0, 7, 8, 0, 0, 0xfd, 0xe9, 192, 0, 2, 1],
+            ),
+        ];
+        for (attr, wire) in cases {
+            assert_eq!(to_bytes(&attr).unwrap().as_ref(), wire, "{:?}", attr);
+            assert_eq!(from_bytes::<PathAttribute>(wire).unwrap(), attr);
+        }
+    }
+
+    #[test]
+    fn test_entropy_label_capability() {
+        let wire = [0xe0, 28, 0];
+        assert_eq!(to_bytes(&PathAttribute::EntropyLabelCapability).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), PathAttribute::EntropyLabelCapability);
+        // As first sent, without Partial, and still written back with it
+        assert_eq!(from_bytes::<PathAttribute>(&[0xc0, 28, 0]).unwrap(), PathAttribute::EntropyLabelCapability);
+        // Not empty as it should be, but not an error either
+        let attr = from_bytes::<PathAttribute>(&[0xc0, 28, 1, 0xaa]).unwrap();
+        assert_eq!(attr, PathAttribute::Unknown { code: 28, flags: 0xc0, data: Bytes::from_static(&[0xaa]) });
+
+        let mut attrs = vec![PathAttribute::Origin(Origin::Igp), attr, PathAttribute::EntropyLabelCapability];
+        assert!(strip_elc(&mut attrs));
+        assert_eq!(attrs, [PathAttribute::Origin(Origin::Igp)]);
+        assert!(!strip_elc(&mut attrs));
+    }
+
+    #[test]
+    fn test_attribute_two_octet_session() {
+        let ser_opts = SerializerOptions { four_octet_asn: false, ..Default::default() };
+        let de_opts = DeserializerOptions { four_octet_asn: false, ..Default::default() };
+        let address = Ipv4Addr::new(192, 0, 2, 1);
+
+        // Everything fits, no AS4_* attributes.
+        let attrs = vec![
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001])]),
+            PathAttribute::Aggregator { asn: 65001, address },
+        ];
+        let bytes = to_bytes_with(&attrs, ser_opts).unwrap();
+        assert_eq!(bytes.as_ref(), &[
+            0x40, 2, 4, 2, 1, 0xfd, 0xe9,
+            0xc0, 7, 6, 0xfd, 0xe9, 192, 0, 2, 1,
+        ]);
+        assert_eq!(from_bytes_with::<Vec<PathAttribute>>(&bytes, de_opts).unwrap(), attrs);
+
+        // AS4_PATH and AS4_AGGREGATOR carry what AS_TRANS replaced.
+        let attrs = vec![
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001, 4200000000])]),
+            PathAttribute::Aggregator { asn: 4200000000, address },
+        ];
+        let bytes = to_bytes_with(&attrs, ser_opts).unwrap();
+        assert_eq!(bytes.as_ref(), &[
+            0x40, 2, 6, 2, 2, 0xfd, 0xe9, 0x5b, 0xa0,
+            0xc0, 17, 10, 2, 2, 0, 0, 0xfd, 0xe9, 0xfa, 0x56, 0xea, 0,
+            0xc0, 7, 6, 0x5b, 0xa0, 192, 0, 2, 1,
+            0xc0, 18, 8, 0xfa, 0x56, 0xea, 0, 192, 0, 2, 1,
+        ]);
+        assert_eq!(from_bytes_with::<Vec<PathAttribute>>(&bytes, de_opts).unwrap(), vec![
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001, 23456])]),
+            PathAttribute::As4Path(vec![AsPathSegment::AsSequence(vec![65001, 4200000000])]),
+            PathAttribute::Aggregator { asn: 23456, address },
+            PathAttribute::As4Aggregator { asn: 4200000000, address },
+        ]);
+
+        // 4-octet sessions never get them.
+        let bytes = to_bytes(&attrs).unwrap();
+        assert_eq!(from_bytes::<Vec<PathAttribute>>(&bytes).unwrap(), attrs);
+    }
+
+    #[test]
+    fn test_attribute_extended_length() {
+        // Over 255 octets gets the flag and a 2 octet length.
+        let attr = PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001; 100])]);
+        let bytes = to_bytes(&attr).unwrap();
+        assert_eq!(&bytes[..4], &[0x50, 2, 0x01, 0x92]);
+        assert_eq!(bytes.len(), 406);
+        assert_eq!(from_bytes::<PathAttribute>(&bytes).unwrap(), attr);
+
+        // Short values received with the flag set keep it.
+        let wire = [0xd0, 99, 0, 2, 0xab, 0xcd];
+        let attr: PathAttribute = from_bytes(&wire).unwrap();
+        assert_eq!(attr, PathAttribute::Unknown { code: 99, flags: 0xd0, data: Bytes::from_static(&[0xab, 0xcd]) });
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+    }
+
+    #[test]
+    fn test_attribute_layout() {
+        let layout = describe_layout(&vec![PathAttribute::LocalPref(1), PathAttribute::AtomicAggregate]).unwrap();
+        let lengths: Vec<_> = layout.iter()
+            .filter(|e| e.kind == LayoutKind::Length)
+            .map(|e| (e.offset, e.width))
+            .collect();
+        assert_eq!(lengths, vec![(2, 1), (9, 1)]);
+        assert_eq!(layout[0].width, 10);
+    }
+
+    #[test]
+    fn test_attribute_on_unknown() {
+        // UPDATE body: no withdrawn routes, ORIGIN, unknown type 99, no NLRI
+        let wire = [0, 0, 0, 9, 0x40, 1, 1, 0, 0xc0, 99, 2, 0xab, 0xcd];
+        let decode = |on_unknown| {
+            let opts = DeserializerOptions { on_unknown, ..Default::default() };
+            from_bytes_with::<UpdateMessage>(&wire, opts).map(|u| u.path_attributes.into_inner())
+        };
+
+        let preserved = decode(OnUnknown::Preserve).unwrap();
+        assert_eq!(preserved[1], PathAttribute::Unknown { code: 99, flags: 0xc0, data: Bytes::from_static(&[0xab, 0xcd]) });
+        assert_eq!(decode(OnUnknown::Skip).unwrap(), vec![PathAttribute::Origin(Origin::Igp)]);
+        match decode(OnUnknown::Error) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Unrecognized path attribute type 99 (at offset 10 while decoding UpdateMessage.path_attributes[1].value)"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_attribute_list_malformed() {
+        use crate::ValidationError;
+
+        // ORIGIN says 2 octets, running into the NLRI
+        let wire = [0, 0, 0, 4, 0x40, 1, 2, 0, 24, 192, 0, 2];
+        let err = from_bytes::<UpdateMessage>(&wire).unwrap_err();
+        assert_eq!(err.inner(), &DeserializeError::Invalid(ValidationError::MalformedAttributeList));
+        assert_eq!(err.path(), Some("UpdateMessage.path_attributes"));
+        // Total Path Attribute Length past the end of the message
+        let wire = [0, 0, 0, 9, 0x40, 1, 1, 0];
+        assert!(matches!(from_bytes::<UpdateMessage>(&wire).unwrap_err().inner(), DeserializeError::Invalid(_)));
+        // The attributes stop short of it
+        let wire = [0, 0, 0, 5, 0x40, 1, 1, 0, 0];
+        assert!(matches!(from_bytes::<UpdateMessage>(&wire).unwrap_err().inner(), DeserializeError::Invalid(_)));
+
+        let wire = [0, 0, 0, 4, 0x40, 1, 1, 0, 24, 192, 0, 2];
+        assert_eq!(from_bytes::<UpdateMessage>(&wire).unwrap().nlri[0].to_string(), "192.0.2.0/24");
+    }
+
+    #[test]
+    fn test_attribute_reserved_flags() {
+        use crate::{ReservedFlags, ValidationError};
+
+        // LOCAL_PREF with flag bit 0x01 set
+        let wire = [0x41, 5, 4, 0, 0, 0, 100];
+        match from_bytes::<PathAttribute>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.inner(), &DeserializeError::Invalid(ValidationError::AttributeFlags(0x41)));
+                assert_eq!(e.path(), Some("PathAttribute.flags"));
+            },
+        }
+
+        let opts = DeserializerOptions { reserved_flags: ReservedFlags::Preserve, ..Default::default() };
+        let attr: PathAttribute = from_bytes_with(&wire, opts).unwrap();
+        let local_pref = PathAttribute::LocalPref(100);
+        assert_eq!(attr, PathAttribute::WithFlags { flags: 0x41, attribute: Box::new(local_pref.clone()) });
+        assert_eq!(attr.attribute(), &local_pref);
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+    }
+
+    #[test]
+    fn test_attribute_partial_flag() {
+        // COMMUNITIES passed on by a speaker that didn't recognize it
+        let wire = [0xe0, 8, 4, 0xff, 0xff, 0xff, 0x01];
+        let attr: PathAttribute = from_bytes(&wire).unwrap();
+        assert_eq!(attr.flags(), ATTR_OPTIONAL | ATTR_TRANSITIVE | ATTR_PARTIAL);
+        assert_eq!(attr.attribute(), &PathAttribute::Communities(vec![Community(0xffff_ff01)]));
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+
+        // Extended Length alone is worked out again
+        let attr: PathAttribute = from_bytes(&[0xd0, 8, 0, 4, 0xff, 0xff, 0xff, 0x01]).unwrap();
+        assert_eq!(attr, PathAttribute::Communities(vec![Community(0xffff_ff01)]));
+    }
+
+    #[test]
+    fn test_attribute_err() {
+        // LOCAL_PREF is 4 octets, the length says 5
+        match from_bytes::<PathAttribute>(&[0x40, 5, 5, 0, 0, 0, 1, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e.inner(), DeserializeError::LengthMismatch { .. }), "{}", e),
+        }
+        // Length runs past the input
+        assert!(matches!(from_bytes::<PathAttribute>(&[0x40, 1, 1]).unwrap_err().inner(), DeserializeError::LengthMismatch { .. }));
+        assert!(matches!(from_bytes::<PathAttribute>(&[0x40]).unwrap_err().inner(), DeserializeError::UnexpectedEof { .. }));
+    }
+}
@@ -0,0 +1,899 @@
+// BGP Link-State NLRI and the BGP-LS attribute (RFC 7752).
+//
+// Past the NLRI type and length everything is TLVs with a 2 octet type and a
+// 2 octet length, nested: an NLRI is a protocol ID and identifier followed by
+// node descriptor TLVs that are themselves lists of sub-TLVs, then the link
+// or prefix descriptor TLVs. The attribute (path attribute 29) is a flat list
+// of node, link and prefix TLVs, of which SR Capabilities (RFC 9085) again
+// nests a SID/Label sub-TLV in each range. TLVs the crate doesn't model are
+// kept as Unknown with their raw value so they still round-trip.
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::Afi;
+use crate::label::{prefix_octets, read_prefix, MAX_LABEL};
+use crate::prefix::IpPrefix;
+use crate::registry::bgp_ls::*;
+use crate::types::{LengthPrefixed, LengthPrefixedSeed, U24};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum LinkStateNlri {
+    Node {
+        protocol: u8,
+        identifier: u64,
+        local: Vec<NodeDescriptor>,
+    },
+    Link {
+        protocol: u8,
+        identifier: u64,
+        local: Vec<NodeDescriptor>,
+        remote: Vec<NodeDescriptor>,
+        link: Vec<LinkDescriptor>,
+    },
+    // The IP Reachability descriptor has to be of the variant's family.
+    Ipv4Prefix {
+        protocol: u8,
+        identifier: u64,
+        local: Vec<NodeDescriptor>,
+        prefix: Vec<PrefixDescriptor>,
+    },
+    Ipv6Prefix {
+        protocol: u8,
+        identifier: u64,
+        local: Vec<NodeDescriptor>,
+        prefix: Vec<PrefixDescriptor>,
+    },
+    Unknown {
+        nlri_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// Sub-TLVs of the Local and Remote Node Descriptors
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum NodeDescriptor {
+    AutonomousSystem(u32),
+    BgpLsIdentifier(u32),
+    OspfAreaId(u32),
+    // 4 to 8 octets depending on the protocol
+    IgpRouterId(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))] Bytes),
+    Unknown {
+        tlv_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum LinkDescriptor {
+    LinkIdentifiers { local: u32, remote: u32 },
+    Ipv4Interface(Ipv4Addr),
+    Ipv4Neighbor(Ipv4Addr),
+    Ipv6Interface(Ipv6Addr),
+    Ipv6Neighbor(Ipv6Addr),
+    MultiTopology(Vec<u16>),
+    Unknown {
+        tlv_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PrefixDescriptor {
+    MultiTopology(Vec<u16>),
+    OspfRouteType(u8),
+    IpReachability(IpPrefix),
+    Unknown {
+        tlv_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// TLVs of the BGP-LS attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum LinkStateAttribute {
+    // Node
+    NodeFlags(u8),
+    NodeName(String),
+    IsisAreaId(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))] Bytes),
+    Ipv4RouterId(Ipv4Addr),
+    Ipv6RouterId(Ipv6Addr),
+    SrCapabilities { flags: u8, ranges: Vec<SrgbRange> },
+    // Link
+    AdminGroup(u32),
+    MaxLinkBandwidth(Bandwidth),
+    MaxReservableBandwidth(Bandwidth),
+    // One per priority, 0 to 7
+    UnreservedBandwidth([Bandwidth; 8]),
+    TeDefaultMetric(u32),
+    // The metric in `len` octets, 1 to 3 depending on the IGP.
+    IgpMetric { metric: u32, len: u8 },
+    Srlg(Vec<u32>),
+    LinkName(String),
+    // RFC 7308
+    ExtendedAdminGroup(Vec<u32>),
+    // Prefix
+    IgpFlags(u8),
+    PrefixMetric(u32),
+    OspfForwardingAddress(IpAddr),
+    Unknown {
+        tlv_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// Bandwidth in bytes per second as the IEEE 754 single precision value it
+// is sent as. Kept as the bits so attributes stay Eq.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Bandwidth(pub u32);
+
+// A range of the SR Global Block: `size` labels or indexes from `first`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SrgbRange {
+    pub size: U24,
+    pub first: SidLabel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SidLabel {
+    // 20 bit MPLS label, sent in 3 octets
+    Label(u32),
+    Index(u32),
+}
+
+impl LinkStateNlri {
+    pub fn nlri_type(&self) -> u16 {
+        match self {
+            LinkStateNlri::Node { .. } => NLRI_NODE,
+            LinkStateNlri::Link { .. } => NLRI_LINK,
+            LinkStateNlri::Ipv4Prefix { .. } => NLRI_IPV4_PREFIX,
+            LinkStateNlri::Ipv6Prefix { .. } => NLRI_IPV6_PREFIX,
+            LinkStateNlri::Unknown { nlri_type, .. } => *nlri_type,
+        }
+    }
+}
+
+impl NodeDescriptor {
+    pub fn tlv_type(&self) -> u16 {
+        match self {
+            NodeDescriptor::AutonomousSystem(_) => AUTONOMOUS_SYSTEM,
+            NodeDescriptor::BgpLsIdentifier(_) => BGP_LS_IDENTIFIER,
+            NodeDescriptor::OspfAreaId(_) => OSPF_AREA_ID,
+            NodeDescriptor::IgpRouterId(_) => IGP_ROUTER_ID,
+            NodeDescriptor::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+impl LinkDescriptor {
+    pub fn tlv_type(&self) -> u16 {
+        match self {
+            LinkDescriptor::LinkIdentifiers { .. } => LINK_IDENTIFIERS,
+            LinkDescriptor::Ipv4Interface(_) => IPV4_INTERFACE_ADDRESS,
+            LinkDescriptor::Ipv4Neighbor(_) => IPV4_NEIGHBOR_ADDRESS,
+            LinkDescriptor::Ipv6Interface(_) => IPV6_INTERFACE_ADDRESS,
+            LinkDescriptor::Ipv6Neighbor(_) => IPV6_NEIGHBOR_ADDRESS,
+            LinkDescriptor::MultiTopology(_) => MULTI_TOPOLOGY_ID,
+            LinkDescriptor::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+impl PrefixDescriptor {
+    pub fn tlv_type(&self) -> u16 {
+        match self {
+            PrefixDescriptor::MultiTopology(_) => MULTI_TOPOLOGY_ID,
+            PrefixDescriptor::OspfRouteType(_) => OSPF_ROUTE_TYPE,
+            PrefixDescriptor::IpReachability(_) => IP_REACHABILITY,
+            PrefixDescriptor::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+impl LinkStateAttribute {
+    pub fn tlv_type(&self) -> u16 {
+        match self {
+            LinkStateAttribute::NodeFlags(_) => NODE_FLAGS,
+            LinkStateAttribute::NodeName(_) => NODE_NAME,
+            LinkStateAttribute::IsisAreaId(_) => ISIS_AREA_ID,
+            LinkStateAttribute::Ipv4RouterId(_) => IPV4_ROUTER_ID_LOCAL,
+            LinkStateAttribute::Ipv6RouterId(_) => IPV6_ROUTER_ID_LOCAL,
+            LinkStateAttribute::SrCapabilities { .. } => SR_CAPABILITIES,
+            LinkStateAttribute::AdminGroup(_) => ADMIN_GROUP,
+            LinkStateAttribute::MaxLinkBandwidth(_) => MAX_LINK_BANDWIDTH,
+            LinkStateAttribute::MaxReservableBandwidth(_) => MAX_RESERVABLE_BANDWIDTH,
+            LinkStateAttribute::UnreservedBandwidth(_) => UNRESERVED_BANDWIDTH,
+            LinkStateAttribute::TeDefaultMetric(_) => TE_DEFAULT_METRIC,
+            LinkStateAttribute::IgpMetric { .. } => IGP_METRIC,
+            LinkStateAttribute::Srlg(_) => SRLG,
+            LinkStateAttribute::LinkName(_) => LINK_NAME,
+            LinkStateAttribute::ExtendedAdminGroup(_) => EXTENDED_ADMIN_GROUP,
+            LinkStateAttribute::IgpFlags(_) => IGP_FLAGS,
+            LinkStateAttribute::PrefixMetric(_) => PREFIX_METRIC,
+            LinkStateAttribute::OspfForwardingAddress(_) => OSPF_FORWARDING_ADDRESS,
+            LinkStateAttribute::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+impl Bandwidth {
+    pub fn new(bytes_per_sec: f32) -> Self {
+        Bandwidth(bytes_per_sec.to_bits())
+    }
+
+    pub fn bytes_per_sec(&self) -> f32 {
+        f32::from_bits(self.0)
+    }
+}
+
+// -- Type, length, value --
+
+// 2 octet type followed by the value with a 2 octet length in front.
+struct Tlv<V> {
+    tlv_type: u16,
+    value: V,
+}
+
+impl<V: Serialize> Serialize for Tlv<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(&self.value);
+        (self.tlv_type, value).serialize(serializer)
+    }
+}
+
+// Reads the type and hands it to `seed_for` to pick how the value, scoped to
+// its length, is decoded.
+struct TlvVisitor<F> {
+    expecting: &'static str,
+    seed_for: F,
+}
+
+impl<'de, F, S> Visitor<'de> for TlvVisitor<F>
+where
+    F: Fn(u16) -> S,
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<S::Value, A::Error> {
+        let tlv_type: u16 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let seed = LengthPrefixedSeed { width: 2, seed: (self.seed_for)(tlv_type) };
+        seq.next_element_seed(seed)?.ok_or_else(|| de::Error::invalid_length(1, &self))
+    }
+}
+
+// A TLV that has to be of type `expected`, such as the Local Node
+// Descriptors, holding a list of sub-TLVs.
+struct ExpectedTlv<T> {
+    expected: u16,
+    list: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for ExpectedTlv<T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<T, D::Error> {
+        let expected = self.expected;
+        let seed_for = move |tlv_type| ExpectedValue { expected, tlv_type, value: self.list };
+        d.deserialize_tuple(2, TlvVisitor { expecting: "a BGP-LS TLV", seed_for })
+    }
+}
+
+struct ExpectedValue<T> {
+    expected: u16,
+    tlv_type: u16,
+    value: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for ExpectedValue<T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<T, D::Error> {
+        if self.tlv_type != self.expected {
+            return Err(de::Error::custom(format!("Expected BGP-LS TLV {}, found {}", self.expected, self.tlv_type)));
+        }
+        T::deserialize(d)
+    }
+}
+
+fn expected<T>(tlv_type: u16) -> ExpectedTlv<T> {
+    ExpectedTlv { expected: tlv_type, list: PhantomData }
+}
+
+fn utf8<E: de::Error>(data: Bytes) -> std::result::Result<String, E> {
+    String::from_utf8(data.to_vec()).map_err(|_| E::custom("BGP-LS name is not UTF-8"))
+}
+
+// -- NLRI --
+
+struct NlriValue<'a>(&'a LinkStateNlri);
+
+impl Serialize for NlriValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let local_tlv = |local| Tlv { tlv_type: LOCAL_NODE_DESCRIPTORS, value: local };
+        match self.0 {
+            LinkStateNlri::Node { protocol, identifier, local } => {
+                (protocol, identifier, local_tlv(local)).serialize(serializer)
+            },
+            LinkStateNlri::Link { protocol, identifier, local, remote, link } => {
+                let remote = Tlv { tlv_type: REMOTE_NODE_DESCRIPTORS, value: remote };
+                (protocol, identifier, local_tlv(local), remote, link).serialize(serializer)
+            },
+            LinkStateNlri::Ipv4Prefix { protocol, identifier, local, prefix }
+            | LinkStateNlri::Ipv6Prefix { protocol, identifier, local, prefix } => {
+                let ipv6 = matches!(self.0, LinkStateNlri::Ipv6Prefix { .. });
+                let mismatch = prefix.iter().any(|d| match d {
+                    PrefixDescriptor::IpReachability(p) => p.addr().is_ipv6() != ipv6,
+                    _ => false,
+                });
+                if mismatch {
+                    return Err(serde::ser::Error::custom("BGP-LS prefix NLRI and IP reachability families differ"));
+                }
+                (protocol, identifier, local_tlv(local), prefix).serialize(serializer)
+            },
+            LinkStateNlri::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for LinkStateNlri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Tlv { tlv_type: self.nlri_type(), value: NlriValue(self) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LinkStateNlri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let visitor = TlvVisitor { expecting: "a BGP-LS NLRI", seed_for: NlriSeed };
+        deserializer.deserialize_tuple(2, visitor)
+    }
+}
+
+struct NlriSeed(u16);
+
+impl<'de> DeserializeSeed<'de> for NlriSeed {
+    type Value = LinkStateNlri;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<LinkStateNlri, D::Error> {
+        match self.0 {
+            NLRI_NODE | NLRI_LINK | NLRI_IPV4_PREFIX | NLRI_IPV6_PREFIX => d.deserialize_tuple(5, self),
+            nlri_type => Ok(LinkStateNlri::Unknown { nlri_type, data: Bytes::deserialize(d)? }),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for NlriSeed {
+    type Value = LinkStateNlri;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BGP-LS NLRI type {}", self.0)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let protocol: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let identifier: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let local = seq
+            .next_element_seed(expected(LOCAL_NODE_DESCRIPTORS))?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        Ok(match self.0 {
+            NLRI_NODE => LinkStateNlri::Node { protocol, identifier, local },
+            NLRI_LINK => {
+                let remote = seq
+                    .next_element_seed(expected(REMOTE_NODE_DESCRIPTORS))?
+                    .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                let link = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?;
+                LinkStateNlri::Link { protocol, identifier, local, remote, link }
+            },
+            nlri_type => {
+                let afi = if nlri_type == NLRI_IPV4_PREFIX { Afi::Ipv4 } else { Afi::Ipv6 };
+                let prefix = seq
+                    .next_element_seed(PrefixDescriptorsSeed { afi })?
+                    .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                match afi {
+                    Afi::Ipv4 => LinkStateNlri::Ipv4Prefix { protocol, identifier, local, prefix },
+                    _ => LinkStateNlri::Ipv6Prefix { protocol, identifier, local, prefix },
+                }
+            },
+        })
+    }
+}
+
+// -- Descriptors --
+
+impl Serialize for NodeDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            NodeDescriptor::AutonomousSystem(v) | NodeDescriptor::BgpLsIdentifier(v) | NodeDescriptor::OspfAreaId(v) => {
+                Tlv { tlv_type: self.tlv_type(), value: v }.serialize(serializer)
+            },
+            NodeDescriptor::IgpRouterId(data) | NodeDescriptor::Unknown { data, .. } => {
+                Tlv { tlv_type: self.tlv_type(), value: data }.serialize(serializer)
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let visitor = TlvVisitor { expecting: "a node descriptor", seed_for: NodeDescriptorSeed };
+        deserializer.deserialize_tuple(2, visitor)
+    }
+}
+
+struct NodeDescriptorSeed(u16);
+
+impl<'de> DeserializeSeed<'de> for NodeDescriptorSeed {
+    type Value = NodeDescriptor;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<NodeDescriptor, D::Error> {
+        Ok(match self.0 {
+            AUTONOMOUS_SYSTEM => NodeDescriptor::AutonomousSystem(u32::deserialize(d)?),
+            BGP_LS_IDENTIFIER => NodeDescriptor::BgpLsIdentifier(u32::deserialize(d)?),
+            OSPF_AREA_ID => NodeDescriptor::OspfAreaId(u32::deserialize(d)?),
+            IGP_ROUTER_ID => NodeDescriptor::IgpRouterId(Bytes::deserialize(d)?),
+            tlv_type => NodeDescriptor::Unknown { tlv_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+struct LinkDescriptorValue<'a>(&'a LinkDescriptor);
+
+impl Serialize for LinkDescriptorValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            LinkDescriptor::LinkIdentifiers { local, remote } => (local, remote).serialize(serializer),
+            LinkDescriptor::Ipv4Interface(a) | LinkDescriptor::Ipv4Neighbor(a) => a.serialize(serializer),
+            LinkDescriptor::Ipv6Interface(a) | LinkDescriptor::Ipv6Neighbor(a) => a.serialize(serializer),
+            LinkDescriptor::MultiTopology(ids) => ids.serialize(serializer),
+            LinkDescriptor::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for LinkDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Tlv { tlv_type: self.tlv_type(), value: LinkDescriptorValue(self) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LinkDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let visitor = TlvVisitor { expecting: "a link descriptor", seed_for: LinkDescriptorSeed };
+        deserializer.deserialize_tuple(2, visitor)
+    }
+}
+
+struct LinkDescriptorSeed(u16);
+
+impl<'de> DeserializeSeed<'de> for LinkDescriptorSeed {
+    type Value = LinkDescriptor;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<LinkDescriptor, D::Error> {
+        Ok(match self.0 {
+            LINK_IDENTIFIERS => {
+                let (local, remote) = Deserialize::deserialize(d)?;
+                LinkDescriptor::LinkIdentifiers { local, remote }
+            },
+            IPV4_INTERFACE_ADDRESS => LinkDescriptor::Ipv4Interface(Ipv4Addr::deserialize(d)?),
+            IPV4_NEIGHBOR_ADDRESS => LinkDescriptor::Ipv4Neighbor(Ipv4Addr::deserialize(d)?),
+            IPV6_INTERFACE_ADDRESS => LinkDescriptor::Ipv6Interface(Ipv6Addr::deserialize(d)?),
+            IPV6_NEIGHBOR_ADDRESS => LinkDescriptor::Ipv6Neighbor(Ipv6Addr::deserialize(d)?),
+            MULTI_TOPOLOGY_ID => LinkDescriptor::MultiTopology(Vec::deserialize(d)?),
+            tlv_type => LinkDescriptor::Unknown { tlv_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+// Prefix length, then its significant octets.
+struct Reachability<'a>(&'a IpPrefix);
+
+impl Serialize for Reachability<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let len = self.0.prefix_len();
+        let n = (len as usize).div_ceil(8);
+        let mut tup = serializer.serialize_tuple(1 + n)?;
+        tup.serialize_element(&len)?;
+        for b in &prefix_octets(self.0)[..n] {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+struct PrefixDescriptorValue<'a>(&'a PrefixDescriptor);
+
+impl Serialize for PrefixDescriptorValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            PrefixDescriptor::MultiTopology(ids) => ids.serialize(serializer),
+            PrefixDescriptor::OspfRouteType(v) => serializer.serialize_u8(*v),
+            PrefixDescriptor::IpReachability(prefix) => Reachability(prefix).serialize(serializer),
+            PrefixDescriptor::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for PrefixDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Tlv { tlv_type: self.tlv_type(), value: PrefixDescriptorValue(self) }.serialize(serializer)
+    }
+}
+
+// Prefix descriptors up to the end of the NLRI. The IP Reachability prefix
+// doesn't say its family, the NLRI type does.
+struct PrefixDescriptorsSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefixDescriptorsSeed {
+    type Value = Vec<PrefixDescriptor>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PrefixDescriptorsSeed {
+    type Value = Vec<PrefixDescriptor>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("prefix descriptors")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut descriptors = Vec::new();
+        while let Some(descriptor) = seq.next_element_seed(PrefixDescriptorSeed { afi: self.afi })? {
+            descriptors.push(descriptor);
+        }
+        Ok(descriptors)
+    }
+}
+
+struct PrefixDescriptorSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefixDescriptorSeed {
+    type Value = PrefixDescriptor;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<PrefixDescriptor, D::Error> {
+        let afi = self.afi;
+        let seed_for = move |tlv_type| PrefixValueSeed { afi, tlv_type };
+        d.deserialize_tuple(2, TlvVisitor { expecting: "a prefix descriptor", seed_for })
+    }
+}
+
+struct PrefixValueSeed {
+    afi: Afi,
+    tlv_type: u16,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefixValueSeed {
+    type Value = PrefixDescriptor;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<PrefixDescriptor, D::Error> {
+        Ok(match self.tlv_type {
+            MULTI_TOPOLOGY_ID => PrefixDescriptor::MultiTopology(Vec::deserialize(d)?),
+            OSPF_ROUTE_TYPE => PrefixDescriptor::OspfRouteType(u8::deserialize(d)?),
+            IP_REACHABILITY => d.deserialize_tuple(1 + 16, self)?,
+            tlv_type => PrefixDescriptor::Unknown { tlv_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for PrefixValueSeed {
+    type Value = PrefixDescriptor;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("IP reachability information")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let len: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        read_prefix(&mut seq, self.afi, len as usize, 1, &self).map(PrefixDescriptor::IpReachability)
+    }
+}
+
+// -- Attribute --
+
+struct AttributeValue<'a>(&'a LinkStateAttribute);
+
+impl Serialize for AttributeValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            LinkStateAttribute::NodeFlags(v) | LinkStateAttribute::IgpFlags(v) => serializer.serialize_u8(*v),
+            LinkStateAttribute::NodeName(name) | LinkStateAttribute::LinkName(name) => {
+                serializer.serialize_bytes(name.as_bytes())
+            },
+            LinkStateAttribute::IsisAreaId(data) | LinkStateAttribute::Unknown { data, .. } => {
+                serializer.serialize_bytes(data)
+            },
+            LinkStateAttribute::Ipv4RouterId(a) => a.serialize(serializer),
+            LinkStateAttribute::Ipv6RouterId(a) => a.serialize(serializer),
+            LinkStateAttribute::SrCapabilities { flags, ranges } => (flags, 0u8, ranges).serialize(serializer),
+            LinkStateAttribute::AdminGroup(v)
+            | LinkStateAttribute::TeDefaultMetric(v)
+            | LinkStateAttribute::PrefixMetric(v) => serializer.serialize_u32(*v),
+            LinkStateAttribute::MaxLinkBandwidth(bw) | LinkStateAttribute::MaxReservableBandwidth(bw) => {
+                bw.serialize(serializer)
+            },
+            LinkStateAttribute::UnreservedBandwidth(bws) => bws.serialize(serializer),
+            LinkStateAttribute::IgpMetric { metric, len } => {
+                let fits = (1..=3).contains(len) && *metric >> (8 * len) == 0;
+                if !fits {
+                    let msg = format!("IGP metric {} does not fit in {} octets", metric, len);
+                    return Err(serde::ser::Error::custom(msg));
+                }
+                serializer.serialize_bytes(&metric.to_be_bytes()[4 - *len as usize..])
+            },
+            LinkStateAttribute::Srlg(v) | LinkStateAttribute::ExtendedAdminGroup(v) => v.serialize(serializer),
+            LinkStateAttribute::OspfForwardingAddress(IpAddr::V4(a)) => a.serialize(serializer),
+            LinkStateAttribute::OspfForwardingAddress(IpAddr::V6(a)) => a.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for LinkStateAttribute {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Tlv { tlv_type: self.tlv_type(), value: AttributeValue(self) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LinkStateAttribute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let visitor = TlvVisitor { expecting: "a BGP-LS attribute TLV", seed_for: AttributeSeed };
+        deserializer.deserialize_tuple(2, visitor)
+    }
+}
+
+struct AttributeSeed(u16);
+
+impl<'de> DeserializeSeed<'de> for AttributeSeed {
+    type Value = LinkStateAttribute;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<LinkStateAttribute, D::Error> {
+        Ok(match self.0 {
+            NODE_FLAGS => LinkStateAttribute::NodeFlags(u8::deserialize(d)?),
+            NODE_NAME => LinkStateAttribute::NodeName(utf8(Bytes::deserialize(d)?)?),
+            ISIS_AREA_ID => LinkStateAttribute::IsisAreaId(Bytes::deserialize(d)?),
+            IPV4_ROUTER_ID_LOCAL => LinkStateAttribute::Ipv4RouterId(Ipv4Addr::deserialize(d)?),
+            IPV6_ROUTER_ID_LOCAL => LinkStateAttribute::Ipv6RouterId(Ipv6Addr::deserialize(d)?),
+            SR_CAPABILITIES => {
+                let (flags, _reserved, ranges): (u8, u8, _) = Deserialize::deserialize(d)?;
+                LinkStateAttribute::SrCapabilities { flags, ranges }
+            },
+            ADMIN_GROUP => LinkStateAttribute::AdminGroup(u32::deserialize(d)?),
+            MAX_LINK_BANDWIDTH => LinkStateAttribute::MaxLinkBandwidth(Bandwidth::deserialize(d)?),
+            MAX_RESERVABLE_BANDWIDTH => LinkStateAttribute::MaxReservableBandwidth(Bandwidth::deserialize(d)?),
+            UNRESERVED_BANDWIDTH => LinkStateAttribute::UnreservedBandwidth(Deserialize::deserialize(d)?),
+            TE_DEFAULT_METRIC => LinkStateAttribute::TeDefaultMetric(u32::deserialize(d)?),
+            IGP_METRIC => {
+                let data = Bytes::deserialize(d)?;
+                if !(1..=3).contains(&data.len()) {
+                    return Err(de::Error::custom(format!("Invalid IGP metric length {}", data.len())));
+                }
+                let metric = data.iter().fold(0u32, |acc, &b| acc << 8 | b as u32);
+                LinkStateAttribute::IgpMetric { metric, len: data.len() as u8 }
+            },
+            SRLG => LinkStateAttribute::Srlg(Vec::deserialize(d)?),
+            LINK_NAME => LinkStateAttribute::LinkName(utf8(Bytes::deserialize(d)?)?),
+            EXTENDED_ADMIN_GROUP => LinkStateAttribute::ExtendedAdminGroup(Vec::deserialize(d)?),
+            IGP_FLAGS => LinkStateAttribute::IgpFlags(u8::deserialize(d)?),
+            PREFIX_METRIC => LinkStateAttribute::PrefixMetric(u32::deserialize(d)?),
+            OSPF_FORWARDING_ADDRESS => {
+                let data = Bytes::deserialize(d)?;
+                let address = match data.len() {
+                    4 => IpAddr::from(<[u8; 4]>::try_from(&data[..]).unwrap()),
+                    16 => IpAddr::from(<[u8; 16]>::try_from(&data[..]).unwrap()),
+                    other => return Err(de::Error::custom(format!("Invalid OSPF forwarding address length {}", other))),
+                };
+                LinkStateAttribute::OspfForwardingAddress(address)
+            },
+            tlv_type => LinkStateAttribute::Unknown { tlv_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+impl Serialize for SrgbRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (self.size, self.first).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SrgbRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (size, first) = Deserialize::deserialize(deserializer)?;
+        Ok(SrgbRange { size, first })
+    }
+}
+
+// The SID/Label sub-TLV; the length tells a label (3) from an index (4).
+impl Serialize for SidLabel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            SidLabel::Label(label) => {
+                let label = U24::new(*label).filter(|l| l.get() <= MAX_LABEL).ok_or_else(|| {
+                    serde::ser::Error::custom(format!("MPLS label {} does not fit in 20 bits", label))
+                })?;
+                Tlv { tlv_type: SID_LABEL, value: label }.serialize(serializer)
+            },
+            SidLabel::Index(index) => Tlv { tlv_type: SID_LABEL, value: index }.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SidLabel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let data: Bytes = expected(SID_LABEL).deserialize(deserializer)?;
+        match data.len() {
+            3 => Ok(SidLabel::Label(u32::from_be_bytes([0, data[0], data[1], data[2]]) & MAX_LABEL)),
+            4 => Ok(SidLabel::Index(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))),
+            other => Err(de::Error::custom(format!("Invalid SID/Label length {}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, MpReachNlri, NextHop, Nlri, PathAttribute, Safi};
+
+    fn local() -> Vec<NodeDescriptor> {
+        vec![
+            NodeDescriptor::AutonomousSystem(65001),
+            NodeDescriptor::IgpRouterId(Bytes::from_static(&[1, 2, 3, 4, 5, 6])),
+        ]
+    }
+
+    #[test]
+    fn test_bgp_ls_node_wire() {
+        let node = LinkStateNlri::Node { protocol: PROTOCOL_ISIS_L2, identifier: 0, local: local() };
+        #[rustfmt::skip]
+        let wire: &[u8] = &[
+            0, 1, 0, 31,
+            2, 0, 0, 0, 0, 0, 0, 0, 0,
+            1, 0, 0, 18,
+            2, 0, 0, 4, 0, 0, 0xfd, 0xe9,
+            2, 3, 0, 6, 1, 2, 3, 4, 5, 6,
+        ];
+        assert_eq!(to_bytes(&node).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<LinkStateNlri>(wire).unwrap(), node);
+
+        let unknown = LinkStateNlri::Unknown { nlri_type: 6, data: Bytes::from_static(&[1, 2]) };
+        assert_eq!(to_bytes(&unknown).unwrap().as_ref(), &[0, 6, 0, 2, 1, 2]);
+        assert_eq!(from_bytes::<LinkStateNlri>(&[0, 6, 0, 2, 1, 2]).unwrap(), unknown);
+    }
+
+    #[test]
+    fn test_bgp_ls_mp_reach() {
+        let remote = vec![NodeDescriptor::AutonomousSystem(65002), NodeDescriptor::OspfAreaId(0)];
+        let nlri = vec![
+            LinkStateNlri::Link {
+                protocol: PROTOCOL_OSPFV2,
+                identifier: 7,
+                local: local(),
+                remote,
+                link: vec![
+                    LinkDescriptor::LinkIdentifiers { local: 1, remote: 2 },
+                    LinkDescriptor::Ipv4Interface(Ipv4Addr::new(10, 0, 0, 1)),
+                    LinkDescriptor::Ipv4Neighbor(Ipv4Addr::new(10, 0, 0, 2)),
+                    LinkDescriptor::MultiTopology(vec![0, 2]),
+                ],
+            },
+            LinkStateNlri::Ipv6Prefix {
+                protocol: PROTOCOL_OSPFV3,
+                identifier: 0,
+                local: local(),
+                prefix: vec![
+                    PrefixDescriptor::OspfRouteType(1),
+                    PrefixDescriptor::IpReachability("2001:db8:1::/48".parse().unwrap()),
+                ],
+            },
+            LinkStateNlri::Ipv4Prefix {
+                protocol: PROTOCOL_DIRECT,
+                identifier: 0,
+                local: local(),
+                prefix: vec![PrefixDescriptor::IpReachability("10.1.0.0/16".parse().unwrap())],
+            },
+        ];
+        let reach = MpReachNlri {
+            afi: Afi::BgpLs,
+            safi: Safi::BgpLs,
+            next_hop: NextHop::Ipv4(Ipv4Addr::new(192, 0, 2, 1)),
+            nlri: Nlri::LinkState(nlri),
+        };
+        let wire = to_bytes(&reach).unwrap();
+        assert_eq!(&wire[..4], &[0x40, 0x04, 71, 4]);
+        assert_eq!(from_bytes::<MpReachNlri>(&wire).unwrap(), reach);
+        // IP Reachability of the IPv4 prefix, at the very end
+        assert_eq!(&wire[wire.len() - 7..], &[1, 9, 0, 3, 16, 10, 1]);
+    }
+
+    #[test]
+    fn test_bgp_ls_attribute_wire() {
+        let srgb = SrgbRange { size: U24::new(8000).unwrap(), first: SidLabel::Label(16000) };
+        let tlvs = vec![
+            LinkStateAttribute::NodeName("core-1".into()),
+            LinkStateAttribute::SrCapabilities {
+                flags: 0x80,
+                ranges: vec![srgb, SrgbRange { size: U24::new(100).unwrap(), first: SidLabel::Index(1) }],
+            },
+            LinkStateAttribute::AdminGroup(0x11),
+            LinkStateAttribute::ExtendedAdminGroup(vec![0x11, 0]),
+            LinkStateAttribute::MaxLinkBandwidth(Bandwidth::new(1.25e9)),
+            LinkStateAttribute::UnreservedBandwidth([Bandwidth::new(1.25e9); 8]),
+            LinkStateAttribute::IgpMetric { metric: 10, len: 3 },
+            LinkStateAttribute::Srlg(vec![1, 2]),
+            LinkStateAttribute::OspfForwardingAddress("2001:db8::1".parse().unwrap()),
+            LinkStateAttribute::Unknown { tlv_type: 1100, data: Bytes::from_static(&[7]) },
+        ];
+        let attr = PathAttribute::BgpLs(tlvs);
+        let wire = to_bytes(&attr).unwrap();
+        assert_eq!(&wire[..3], &[0x80, 29, 145]);
+        assert_eq!(&wire[3..13], b"\x04\x02\x00\x06core-1");
+        #[rustfmt::skip]
+        assert_eq!(&wire[13..40], &[
+            0x04, 0x0a, 0, 23, 0x80, 0,
+            0, 0x1f, 0x40, 0x04, 0x89, 0, 3, 0, 0x3e, 0x80,
+            0, 0, 100, 0x04, 0x89, 0, 4, 0, 0, 0, 1,
+        ]);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+
+        match from_bytes::<PathAttribute>(&wire).unwrap() {
+            PathAttribute::BgpLs(tlvs) => match tlvs[4] {
+                LinkStateAttribute::MaxLinkBandwidth(bw) => assert_eq!(bw.bytes_per_sec(), 1.25e9),
+                ref other => panic!("Unexpected TLV {:?}", other),
+            },
+            other => panic!("Unexpected attribute {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bgp_ls_err() {
+        // Remote node descriptors where the local ones should be
+        let wire = [0, 1, 0, 13, 2, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0];
+        match from_bytes::<LinkStateNlri>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Expected BGP-LS TLV 256, found 257"),
+        }
+        match from_bytes::<LinkStateAttribute>(&[0x04, 0x47, 0, 4, 0, 0, 0, 1]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid IGP metric length 4"),
+        }
+        assert!(to_bytes(LinkStateAttribute::IgpMetric { metric: 256, len: 1 }).is_err());
+
+        let mismatch = LinkStateNlri::Ipv4Prefix {
+            protocol: PROTOCOL_STATIC,
+            identifier: 0,
+            local: local(),
+            prefix: vec![PrefixDescriptor::IpReachability("2001:db8::/32".parse().unwrap())],
+        };
+        match to_bytes(&mismatch) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "BGP-LS prefix NLRI and IP reachability families differ"),
+        }
+    }
+}
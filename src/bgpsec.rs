@@ -0,0 +1,328 @@
+// BGPsec_Path attribute (RFC 8205 3).
+//
+// The Secure_Path, then one Signature_Block per algorithm suite in use (two
+// during a suite rollover). Both start with a 2 octet length that, unlike
+// a LengthPrefixed one, counts the length field itself. Secure_Path segments
+// are 6 octets: pCount, flags and the AS number. A Signature_Block is the
+// suite identifier followed by one signature segment per AS, most recent
+// first: the 20 octet Subject Key Identifier of the router's key and the
+// signature with a 2 octet length.
+//
+// Only the wire model is here; signing and verifying are left to the user.
+use std::fmt;
+
+use bytes::Bytes;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::types::LengthPrefixed;
+
+// Secure_Path segment flags
+pub const BGPSEC_CONFED_SEGMENT: u8 = 0x80;
+
+// Secure_Path length
+const LENGTH_LEN: usize = 2;
+const SEGMENT_LEN: usize = 6;
+// SKI and signature length
+const SIGNATURE_SEGMENT_HEADER_LEN: usize = 22;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BgpsecPath {
+    pub secure_path: Vec<SecurePathSegment>,
+    pub signature_blocks: Vec<SignatureBlock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SecurePathSegment {
+    // Times the AS appears in the path, for prepending
+    pub pcount: u8,
+    pub flags: u8,
+    pub asn: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SignatureBlock {
+    pub suite: AlgorithmSuite,
+    pub segments: Vec<SignatureSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SignatureSegment {
+    pub ski: [u8; 20],
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+    pub signature: Bytes,
+}
+
+// BGPsec Algorithm Suite Registry (RFC 8208)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AlgorithmSuite {
+    // SHA-256 and ECDSA with curve P-256
+    EcdsaP256Sha256,
+    Unknown(u8),
+}
+
+impl BgpsecPath {
+    pub fn signature_block(&self, suite: AlgorithmSuite) -> Option<&SignatureBlock> {
+        self.signature_blocks.iter().find(|block| block.suite == suite)
+    }
+}
+
+impl SecurePathSegment {
+    pub fn new(asn: u32) -> Self {
+        SecurePathSegment { pcount: 1, flags: 0, asn }
+    }
+}
+
+impl From<u8> for AlgorithmSuite {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => AlgorithmSuite::EcdsaP256Sha256,
+            other => AlgorithmSuite::Unknown(other),
+        }
+    }
+}
+
+impl From<AlgorithmSuite> for u8 {
+    fn from(suite: AlgorithmSuite) -> Self {
+        match suite {
+            AlgorithmSuite::EcdsaP256Sha256 => 1,
+            AlgorithmSuite::Unknown(other) => other,
+        }
+    }
+}
+
+// -- Text form --
+//
+// Text formats drop the lengths and get plain structs, with the suite as
+// its number.
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "BgpsecPath")]
+struct BgpsecPathText {
+    secure_path: Vec<SecurePathSegment>,
+    signature_blocks: Vec<SignatureBlock>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SignatureBlock")]
+struct SignatureBlockText {
+    suite: AlgorithmSuite,
+    segments: Vec<SignatureSegment>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SignatureSegment")]
+struct SignatureSegmentText {
+    ski: [u8; 20],
+    signature: Bytes,
+}
+
+// -- Wire form --
+
+impl Serialize for AlgorithmSuite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for AlgorithmSuite {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(AlgorithmSuite::from)
+    }
+}
+
+// A length counting itself, for what follows being `len` octets.
+fn inclusive_length<E: ser::Error>(what: &str, len: usize) -> std::result::Result<u16, E> {
+    u16::try_from(LENGTH_LEN + len).map_err(|_| E::custom(format!("{} of {} octets is too long", what, len)))
+}
+
+struct SecurePath<'a>(&'a [SecurePathSegment]);
+
+impl Serialize for SecurePath<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let len: u16 = inclusive_length("Secure_Path", self.0.len() * SEGMENT_LEN)?;
+        (len, self.0).serialize(serializer)
+    }
+}
+
+impl Serialize for BgpsecPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return BgpsecPathText::serialize(self, serializer);
+        }
+        (SecurePath(&self.secure_path), &self.signature_blocks).serialize(serializer)
+    }
+}
+
+impl Serialize for SignatureSegment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return SignatureSegmentText::serialize(self, serializer);
+        }
+        let signature: LengthPrefixed<u16, _> = LengthPrefixed::new(&self.signature);
+        (self.ski, signature).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SignatureSegment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return SignatureSegmentText::deserialize(deserializer);
+        }
+        let (ski, signature): (_, LengthPrefixed<u16, Bytes>) = Deserialize::deserialize(deserializer)?;
+        Ok(SignatureSegment { ski, signature: signature.value })
+    }
+}
+
+impl Serialize for SignatureBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return SignatureBlockText::serialize(self, serializer);
+        }
+        let segments_len: usize =
+            self.segments.iter().map(|s| SIGNATURE_SEGMENT_HEADER_LEN + s.signature.len()).sum();
+        let len: u16 = inclusive_length("Signature_Block", 1 + segments_len)?;
+        let mut tup = serializer.serialize_tuple(3)?;
+        tup.serialize_element(&len)?;
+        tup.serialize_element(&self.suite)?;
+        tup.serialize_element(&self.segments)?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BgpsecPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return BgpsecPathText::deserialize(deserializer);
+        }
+        struct PathVisitor;
+
+        impl<'de> Visitor<'de> for PathVisitor {
+            type Value = BgpsecPath;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a BGPsec_Path")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let len: u16 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let segments_len = (len as usize).checked_sub(LENGTH_LEN).filter(|n| n % SEGMENT_LEN == 0);
+                let count = segments_len
+                    .ok_or_else(|| de::Error::custom(format!("Invalid Secure_Path length {}", len)))?
+                    / SEGMENT_LEN;
+                let mut secure_path = Vec::with_capacity(count);
+                for i in 0..count {
+                    secure_path.push(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1 + i, &self))?);
+                }
+                let signature_blocks =
+                    seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1 + count, &self))?;
+                Ok(BgpsecPath { secure_path, signature_blocks })
+            }
+        }
+
+        // The length, as many segments as it allows and the blocks
+        deserializer.deserialize_tuple(2 + u16::MAX as usize / SEGMENT_LEN, PathVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for SignatureBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return SignatureBlockText::deserialize(deserializer);
+        }
+        struct BlockVisitor;
+
+        impl<'de> Visitor<'de> for BlockVisitor {
+            type Value = SignatureBlock;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a Signature_Block")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let len: u16 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let suite = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let invalid = || de::Error::custom(format!("Invalid Signature_Block length {}", len));
+                let mut left = (len as usize).checked_sub(LENGTH_LEN + 1).ok_or_else(invalid)?;
+                let mut segments = Vec::new();
+                while left > 0 {
+                    let segment: SignatureSegment =
+                        seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2 + segments.len(), &self))?;
+                    left = left
+                        .checked_sub(SIGNATURE_SEGMENT_HEADER_LEN + segment.signature.len())
+                        .ok_or_else(invalid)?;
+                    segments.push(segment);
+                }
+                Ok(SignatureBlock { suite, segments })
+            }
+        }
+
+        // Length, suite and as many segments as the length allows
+        let max = 2 + u16::MAX as usize / SIGNATURE_SEGMENT_HEADER_LEN;
+        deserializer.deserialize_tuple(max, BlockVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, PathAttribute};
+
+    fn path() -> BgpsecPath {
+        BgpsecPath {
+            secure_path: vec![
+                SecurePathSegment::new(65002),
+                SecurePathSegment { pcount: 0, flags: BGPSEC_CONFED_SEGMENT, asn: 65001 },
+            ],
+            signature_blocks: vec![SignatureBlock {
+                suite: AlgorithmSuite::EcdsaP256Sha256,
+                segments: vec![
+                    SignatureSegment { ski: [0xaa; 20], signature: Bytes::from_static(&[1, 2, 3]) },
+                    SignatureSegment { ski: [0xbb; 20], signature: Bytes::from_static(&[4]) },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_bgpsec_path_wire() {
+        let attr = PathAttribute::BgpsecPath(path());
+        let wire = to_bytes(&attr).unwrap();
+        assert_eq!(&wire[..3], &[0x80, 33, 14 + 51]);
+        assert_eq!(&wire[3..17], &[0, 14, 1, 0, 0, 0, 0xfd, 0xea, 0, 0x80, 0, 0, 0xfd, 0xe9]);
+        // Block length, suite, then the first SKI
+        assert_eq!(&wire[17..21], &[0, 51, 1, 0xaa]);
+        assert_eq!(&wire[39..44], &[0xaa, 0, 3, 1, 2]);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+
+        // Two blocks while rolling over to a new suite
+        let mut rollover = path();
+        rollover.signature_blocks.push(SignatureBlock { suite: AlgorithmSuite::Unknown(2), segments: vec![] });
+        let decoded: BgpsecPath = from_bytes(&to_bytes(&rollover).unwrap()).unwrap();
+        assert_eq!(decoded, rollover);
+        assert_eq!(decoded.signature_block(AlgorithmSuite::Unknown(2)).unwrap().segments.len(), 0);
+    }
+
+    #[test]
+    fn test_bgpsec_path_err() {
+        match from_bytes::<BgpsecPath>(&[0, 7, 1, 0, 0, 0, 0xfd, 0xe9, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid Secure_Path length 7"),
+        }
+        // Block length ending inside the signature
+        let mut wire = vec![0, 2, 0, 26, 1];
+        wire.extend_from_slice(&[0xaa; 20]);
+        wire.extend_from_slice(&[0, 2, 1, 2]);
+        match from_bytes::<BgpsecPath>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid Signature_Block length 26"),
+        }
+    }
+}
@@ -0,0 +1,751 @@
+// BGP Monitoring Protocol messages (RFC 7854).
+//
+// A BMP message is a 6 octet common header (version 3, a 4 octet length
+// counting the header, the type) and a body. All but Initiation and
+// Termination start the body with the 42 octet per-peer header naming the
+// monitored session. The BGP PDUs inside (the UPDATE of a Route Monitoring
+// message, the OPENs of a Peer Up, the NOTIFICATION of a Peer Down) are
+// complete BGP messages, decoded as such with 4 octet AS numbers unless the
+// peer header's legacy AS_PATH flag is set. Route Mirroring and unknown types
+// keep their raw body.
+//
+// Version 4 (draft-ietf-grow-bmp-tlv) keeps the per-peer header but follows
+// it with TLVs, the BGP PDU among them, each with an index that Group TLVs
+// use to tie TLVs to NLRI. Those messages decode as V4 with their TLVs;
+// Initiation and Termination are the same in both versions and go back out
+// as version 3.
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::{Bytes, BytesMut};
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::de::{from_bytes, from_message_bytes_with, DeserializerOptions};
+use crate::error::{DeserializeError, Result, SerializerError};
+use crate::messages::{Message, NotificationMessage, OpenMessage, UpdateMessage, HEADER_LEN};
+use crate::registry::bmp;
+use crate::ser::{to_bytes_in, to_message_bytes_with, SerializerOptions};
+use crate::types::LengthPrefixed;
+use crate::vpn::Rd;
+
+pub const BMP_VERSION: u8 = 3;
+pub const BMP_VERSION_4: u8 = 4;
+// Type, length and index
+const V4_TLV_HEADER_LEN: usize = 6;
+pub const BMP_HEADER_LEN: usize = 6;
+const PEER_HEADER_LEN: usize = 42;
+
+// Per-peer header flags. The V (IPv6) flag isn't among them, it follows
+// from the peer address.
+const FLAG_IPV6: u8 = 0x80;
+pub const BMP_FLAG_POST_POLICY: u8 = 0x40;
+pub const BMP_FLAG_LEGACY_AS_PATH: u8 = 0x20;
+// RFC 8671
+pub const BMP_FLAG_ADJ_RIB_OUT: u8 = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BmpMessage {
+    RouteMonitoring { peer: BmpPeerHeader, update: UpdateMessage },
+    StatisticsReport { peer: BmpPeerHeader, stats: Vec<BmpStat> },
+    PeerDown { peer: BmpPeerHeader, reason: PeerDownReason },
+    PeerUp(Box<BmpPeerUp>),
+    Initiation(Vec<BmpInformation>),
+    Termination(Vec<BmpInformation>),
+    // A version 4 message with a per-peer header
+    V4 { message_type: u8, peer: BmpPeerHeader, tlvs: Vec<BmpTlv> },
+    Unknown {
+        message_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BmpPeerHeader {
+    pub peer_type: u8,
+    pub flags: u8,
+    pub distinguisher: Rd,
+    pub address: IpAddr,
+    pub asn: u32,
+    pub bgp_id: Ipv4Addr,
+    pub seconds: u32,
+    pub microseconds: u32,
+}
+
+// The local end of the session comes in the same family as the peer's
+// address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BmpPeerUp {
+    pub peer: BmpPeerHeader,
+    pub local_address: IpAddr,
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub sent_open: OpenMessage,
+    pub received_open: OpenMessage,
+    pub information: Vec<BmpInformation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PeerDownReason {
+    LocalNotification(NotificationMessage),
+    // The FSM event that brought the session down
+    LocalNoNotification(u16),
+    RemoteNotification(NotificationMessage),
+    RemoteNoData,
+    PeerDeconfigured,
+    Unknown {
+        reason: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// A Statistics Report counter. The type doesn't say how wide the value is,
+// so it's read by length: 4 octet counters, 8 octet gauges and the 11 octet
+// per AFI/SAFI gauges. Anything else keeps its raw value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BmpStat {
+    Counter { stat_type: u16, value: u32 },
+    Gauge { stat_type: u16, value: u64 },
+    FamilyGauge { stat_type: u16, afi: Afi, safi: Safi, value: u64 },
+    Unknown {
+        stat_type: u16,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// A BMPv4 message TLV. The length on the wire covers only the value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BmpTlv {
+    pub tlv_type: u16,
+    pub index: u16,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+    pub value: Bytes,
+}
+
+// An information TLV of an Initiation, Peer Up or Termination message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BmpInformation {
+    pub info_type: u16,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+    pub value: Bytes,
+}
+
+impl BmpMessage {
+    pub fn message_type(&self) -> u8 {
+        match self {
+            BmpMessage::RouteMonitoring { .. } => bmp::ROUTE_MONITORING,
+            BmpMessage::StatisticsReport { .. } => bmp::STATISTICS_REPORT,
+            BmpMessage::PeerDown { .. } => bmp::PEER_DOWN_NOTIFICATION,
+            BmpMessage::PeerUp(_) => bmp::PEER_UP_NOTIFICATION,
+            BmpMessage::Initiation(_) => bmp::INITIATION,
+            BmpMessage::Termination(_) => bmp::TERMINATION,
+            BmpMessage::V4 { message_type, .. } | BmpMessage::Unknown { message_type, .. } => *message_type,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        match self {
+            BmpMessage::V4 { .. } => BMP_VERSION_4,
+            _ => BMP_VERSION,
+        }
+    }
+
+    // Decodes one complete message, common header included.
+    pub fn from_bytes(input: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let header = input.get(..BMP_HEADER_LEN).ok_or(DeserializeError::UnexpectedEof {
+            needed: BMP_HEADER_LEN,
+            available: input.len(),
+            offset: 0,
+        })?;
+        let (version, len, message_type): (u8, u32, u8) = from_bytes(header)?;
+        if version != BMP_VERSION && version != BMP_VERSION_4 {
+            return Err(DeserializeError::Custom(format!("Unsupported BMP version {}", version)));
+        }
+        if len as usize != input.len() {
+            return Err(DeserializeError::LengthMismatch { declared: len as usize, actual: input.len(), offset: 1 });
+        }
+        let body = &input[BMP_HEADER_LEN..];
+        let per_peer = matches!(
+            message_type,
+            bmp::ROUTE_MONITORING | bmp::STATISTICS_REPORT | bmp::PEER_DOWN_NOTIFICATION | bmp::PEER_UP_NOTIFICATION
+        );
+        if version == BMP_VERSION_4 && per_peer {
+            let (peer, rest) = split_peer_header(body)?;
+            let tlvs = BmpTlv::read_all(rest, BMP_HEADER_LEN + PEER_HEADER_LEN)?;
+            return Ok(BmpMessage::V4 { message_type, peer, tlvs });
+        }
+        Ok(match message_type {
+            bmp::ROUTE_MONITORING => {
+                let (peer, rest) = split_peer_header(body)?;
+                let update = from_message_bytes_with(rest, peer.deserializer_options())?;
+                BmpMessage::RouteMonitoring { peer, update }
+            },
+            bmp::STATISTICS_REPORT => {
+                let (peer, rest) = split_peer_header(body)?;
+                let (count, stats): (u32, Vec<BmpStat>) = from_bytes(rest)?;
+                if count as usize != stats.len() {
+                    return Err(DeserializeError::Custom(format!(
+                        "Statistics Report says {} stats, found {}", count, stats.len()
+                    )));
+                }
+                BmpMessage::StatisticsReport { peer, stats }
+            },
+            bmp::PEER_DOWN_NOTIFICATION => {
+                let (peer, rest) = split_peer_header(body)?;
+                let reason = PeerDownReason::from_bytes(rest, peer.deserializer_options())?;
+                BmpMessage::PeerDown { peer, reason }
+            },
+            bmp::PEER_UP_NOTIFICATION => BmpMessage::PeerUp(Box::new(BmpPeerUp::from_bytes(body)?)),
+            bmp::INITIATION => BmpMessage::Initiation(from_bytes(body)?),
+            bmp::TERMINATION => BmpMessage::Termination(from_bytes(body)?),
+            _ => BmpMessage::Unknown { message_type, data: Bytes::copy_from_slice(body) },
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<BytesMut> {
+        let mut out = BytesMut::new();
+        to_bytes_in((self.version(), 0u32, self.message_type()), &mut out)?;
+        match self {
+            BmpMessage::RouteMonitoring { peer, update } => {
+                to_bytes_in(peer, &mut out)?;
+                let pdu = to_message_bytes_with(UpdateMessage::MESSAGE_TYPE, update, peer.serializer_options())?;
+                out.extend_from_slice(&pdu);
+            },
+            BmpMessage::StatisticsReport { peer, stats } => {
+                let count = u32::try_from(stats.len()).map_err(|_| {
+                    SerializerError::LengthOverflow(Some(format!("{} stats in a Statistics Report", stats.len())))
+                })?;
+                to_bytes_in((peer, count, stats), &mut out)?;
+            },
+            BmpMessage::PeerDown { peer, reason } => {
+                to_bytes_in(peer, &mut out)?;
+                reason.write(&mut out, peer.serializer_options())?;
+            },
+            BmpMessage::PeerUp(up) => up.write(&mut out)?,
+            BmpMessage::Initiation(information) | BmpMessage::Termination(information) => {
+                to_bytes_in(information, &mut out)?
+            },
+            BmpMessage::V4 { peer, tlvs, .. } => {
+                to_bytes_in(peer, &mut out)?;
+                tlvs.iter().try_for_each(|tlv| tlv.write(&mut out))?;
+            },
+            BmpMessage::Unknown { data, .. } => out.extend_from_slice(data),
+        }
+        let len = u32::try_from(out.len())
+            .map_err(|_| SerializerError::LengthOverflow(Some(format!("BMP message is {} octets", out.len()))))?;
+        out[1..5].copy_from_slice(&len.to_be_bytes());
+        Ok(out)
+    }
+}
+
+impl BmpPeerHeader {
+    pub fn new(address: IpAddr, asn: u32, bgp_id: Ipv4Addr) -> Self {
+        BmpPeerHeader {
+            peer_type: bmp::PEER_GLOBAL,
+            flags: 0,
+            distinguisher: Rd::default(),
+            address,
+            asn,
+            bgp_id,
+            seconds: 0,
+            microseconds: 0,
+        }
+    }
+
+    // Whether AS_PATHs in the PDUs have 4 octet AS numbers.
+    pub fn four_octet_asn(&self) -> bool {
+        self.flags & BMP_FLAG_LEGACY_AS_PATH == 0
+    }
+
+    fn deserializer_options(&self) -> DeserializerOptions {
+        DeserializerOptions { four_octet_asn: self.four_octet_asn(), ..Default::default() }
+    }
+
+    fn serializer_options(&self) -> SerializerOptions {
+        SerializerOptions { four_octet_asn: self.four_octet_asn(), ..Default::default() }
+    }
+}
+
+impl BmpPeerUp {
+    fn from_bytes(body: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let (peer, rest) = split_peer_header(body)?;
+        let ports_end = 16 + 2 + 2;
+        let local = rest.get(..ports_end).ok_or(DeserializeError::UnexpectedEof {
+            needed: ports_end,
+            available: rest.len(),
+            offset: BMP_HEADER_LEN + PEER_HEADER_LEN,
+        })?;
+        let (local_address, local_port, remote_port): ([u8; 16], u16, u16) = from_bytes(local)?;
+        let local_address = address_from(local_address, peer.address.is_ipv6());
+        let options = peer.deserializer_options();
+        let (sent, rest) = split_pdu(&rest[ports_end..])?;
+        let (received, rest) = split_pdu(rest)?;
+        Ok(BmpPeerUp {
+            peer,
+            local_address,
+            local_port,
+            remote_port,
+            sent_open: from_message_bytes_with(sent, options)?,
+            received_open: from_message_bytes_with(received, options)?,
+            information: from_bytes(rest)?,
+        })
+    }
+
+    fn write(&self, out: &mut BytesMut) -> Result<()> {
+        if self.local_address.is_ipv6() != self.peer.address.is_ipv6() {
+            return Err(SerializerError::CustomMsg(String::from(
+                "Peer Up local and peer addresses must be the same family",
+            )));
+        }
+        let local_address = address_octets(&self.local_address);
+        to_bytes_in((&self.peer, local_address, self.local_port, self.remote_port), out)?;
+        let options = self.peer.serializer_options();
+        out.extend_from_slice(&to_message_bytes_with(OpenMessage::MESSAGE_TYPE, &self.sent_open, options)?);
+        out.extend_from_slice(&to_message_bytes_with(OpenMessage::MESSAGE_TYPE, &self.received_open, options)?);
+        to_bytes_in(&self.information, out)
+    }
+}
+
+impl PeerDownReason {
+    pub fn reason(&self) -> u8 {
+        match self {
+            PeerDownReason::LocalNotification(_) => bmp::LOCAL_NOTIFICATION,
+            PeerDownReason::LocalNoNotification(_) => bmp::LOCAL_NO_NOTIFICATION,
+            PeerDownReason::RemoteNotification(_) => bmp::REMOTE_NOTIFICATION,
+            PeerDownReason::RemoteNoData => bmp::REMOTE_NO_DATA,
+            PeerDownReason::PeerDeconfigured => bmp::PEER_DECONFIGURED,
+            PeerDownReason::Unknown { reason, .. } => *reason,
+        }
+    }
+
+    fn from_bytes(body: &[u8], options: DeserializerOptions) -> std::result::Result<Self, DeserializeError> {
+        let (&reason, data) = body.split_first().ok_or(DeserializeError::UnexpectedEof {
+            needed: 1,
+            available: 0,
+            offset: BMP_HEADER_LEN + PEER_HEADER_LEN,
+        })?;
+        Ok(match reason {
+            bmp::LOCAL_NOTIFICATION => PeerDownReason::LocalNotification(from_message_bytes_with(data, options)?),
+            bmp::LOCAL_NO_NOTIFICATION => PeerDownReason::LocalNoNotification(from_bytes(data)?),
+            bmp::REMOTE_NOTIFICATION => PeerDownReason::RemoteNotification(from_message_bytes_with(data, options)?),
+            bmp::REMOTE_NO_DATA | bmp::PEER_DECONFIGURED if !data.is_empty() => {
+                return Err(DeserializeError::TrailingBytes { unused: data.len(), len: body.len() })
+            },
+            bmp::REMOTE_NO_DATA => PeerDownReason::RemoteNoData,
+            bmp::PEER_DECONFIGURED => PeerDownReason::PeerDeconfigured,
+            _ => PeerDownReason::Unknown { reason, data: Bytes::copy_from_slice(data) },
+        })
+    }
+
+    fn write(&self, out: &mut BytesMut, options: SerializerOptions) -> Result<()> {
+        to_bytes_in(self.reason(), out)?;
+        match self {
+            PeerDownReason::LocalNotification(notification) | PeerDownReason::RemoteNotification(notification) => {
+                let pdu = to_message_bytes_with(NotificationMessage::MESSAGE_TYPE, notification, options)?;
+                out.extend_from_slice(&pdu);
+            },
+            PeerDownReason::LocalNoNotification(event) => to_bytes_in(event, out)?,
+            PeerDownReason::RemoteNoData | PeerDownReason::PeerDeconfigured => {},
+            PeerDownReason::Unknown { data, .. } => out.extend_from_slice(data),
+        }
+        Ok(())
+    }
+}
+
+impl BmpStat {
+    pub fn stat_type(&self) -> u16 {
+        match self {
+            BmpStat::Counter { stat_type, .. }
+            | BmpStat::Gauge { stat_type, .. }
+            | BmpStat::FamilyGauge { stat_type, .. }
+            | BmpStat::Unknown { stat_type, .. } => *stat_type,
+        }
+    }
+}
+
+impl BmpInformation {
+    pub fn string(info_type: u16, text: &str) -> Self {
+        BmpInformation { info_type, value: Bytes::copy_from_slice(text.as_bytes()) }
+    }
+
+    // The value of the UTF-8 string TLVs (everything but the Termination
+    // reason).
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.value).ok()
+    }
+}
+
+fn split_peer_header(body: &[u8]) -> std::result::Result<(BmpPeerHeader, &[u8]), DeserializeError> {
+    let header = body.get(..PEER_HEADER_LEN).ok_or(DeserializeError::UnexpectedEof {
+        needed: PEER_HEADER_LEN,
+        available: body.len(),
+        offset: BMP_HEADER_LEN,
+    })?;
+    Ok((from_bytes(header)?, &body[PEER_HEADER_LEN..]))
+}
+
+impl BmpTlv {
+    // A BGP PDU TLV holding `update`, sent with the peer's AS number width.
+    pub fn bgp_pdu(index: u16, update: &UpdateMessage, peer: &BmpPeerHeader) -> Result<Self> {
+        let pdu = to_message_bytes_with(UpdateMessage::MESSAGE_TYPE, update, peer.serializer_options())?;
+        Ok(BmpTlv { tlv_type: bmp::TLV_BGP_PDU, index, value: pdu.freeze() })
+    }
+
+    // The UPDATE in a BGP PDU TLV, None for other types.
+    pub fn update(&self, peer: &BmpPeerHeader) -> Option<std::result::Result<UpdateMessage, DeserializeError>> {
+        (self.tlv_type == bmp::TLV_BGP_PDU).then(|| from_message_bytes_with(&self.value, peer.deserializer_options()))
+    }
+
+    // The indexes a Group TLV gathers, None for other types or an odd
+    // length.
+    pub fn group_indexes(&self) -> Option<Vec<u16>> {
+        if self.tlv_type != bmp::TLV_GROUP || !self.value.len().is_multiple_of(2) {
+            return None;
+        }
+        Some(self.value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect())
+    }
+
+    // `offset` is where `input` starts in the message, for errors.
+    fn read_all(mut input: &[u8], mut offset: usize) -> std::result::Result<Vec<Self>, DeserializeError> {
+        let mut tlvs = Vec::new();
+        while !input.is_empty() {
+            if input.len() < V4_TLV_HEADER_LEN {
+                return Err(DeserializeError::UnexpectedEof { needed: V4_TLV_HEADER_LEN, available: input.len(), offset });
+            }
+            let u16_at = |i: usize| u16::from_be_bytes([input[i], input[i + 1]]);
+            let (tlv_type, len, index) = (u16_at(0), u16_at(2) as usize, u16_at(4));
+            let value = input.get(V4_TLV_HEADER_LEN..V4_TLV_HEADER_LEN + len).ok_or(DeserializeError::UnexpectedEof {
+                needed: len,
+                available: input.len() - V4_TLV_HEADER_LEN,
+                offset: offset + V4_TLV_HEADER_LEN,
+            })?;
+            tlvs.push(BmpTlv { tlv_type, index, value: Bytes::copy_from_slice(value) });
+            input = &input[V4_TLV_HEADER_LEN + len..];
+            offset += V4_TLV_HEADER_LEN + len;
+        }
+        Ok(tlvs)
+    }
+
+    fn write(&self, out: &mut BytesMut) -> Result<()> {
+        let len = u16::try_from(self.value.len())
+            .map_err(|_| SerializerError::LengthOverflow(Some(format!("BMP TLV value is {} octets", self.value.len()))))?;
+        to_bytes_in((self.tlv_type, len, self.index), out)?;
+        out.extend_from_slice(&self.value);
+        Ok(())
+    }
+}
+
+// The BGP message at the start of `input`, by its header length, and what
+// follows it.
+fn split_pdu(input: &[u8]) -> std::result::Result<(&[u8], &[u8]), DeserializeError> {
+    if input.len() < HEADER_LEN {
+        return Err(DeserializeError::UnexpectedEof { needed: HEADER_LEN, available: input.len(), offset: 0 });
+    }
+    let len = u16::from_be_bytes([input[16], input[17]]) as usize;
+    if len < HEADER_LEN || len > input.len() {
+        return Err(DeserializeError::LengthMismatch { declared: len, actual: input.len(), offset: 16 });
+    }
+    Ok(input.split_at(len))
+}
+
+// IPv4 addresses go in the last 4 of the 16 octets.
+fn address_octets(address: &IpAddr) -> [u8; 16] {
+    match address {
+        IpAddr::V4(addr) => {
+            let mut octets = [0u8; 16];
+            octets[12..].copy_from_slice(&addr.octets());
+            octets
+        },
+        IpAddr::V6(addr) => addr.octets(),
+    }
+}
+
+fn address_from(octets: [u8; 16], ipv6: bool) -> IpAddr {
+    match ipv6 {
+        true => IpAddr::V6(Ipv6Addr::from(octets)),
+        false => IpAddr::V4(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15])),
+    }
+}
+
+// -- Wire form --
+
+impl Serialize for BmpPeerHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let flags = match self.address {
+            IpAddr::V4(_) => self.flags & !FLAG_IPV6,
+            IpAddr::V6(_) => self.flags | FLAG_IPV6,
+        };
+        let mut tup = serializer.serialize_tuple(8)?;
+        tup.serialize_element(&self.peer_type)?;
+        tup.serialize_element(&flags)?;
+        tup.serialize_element(&self.distinguisher)?;
+        tup.serialize_element(&address_octets(&self.address))?;
+        tup.serialize_element(&self.asn)?;
+        tup.serialize_element(&self.bgp_id)?;
+        tup.serialize_element(&self.seconds)?;
+        tup.serialize_element(&self.microseconds)?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BmpPeerHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct HeaderVisitor;
+
+        impl<'de> Visitor<'de> for HeaderVisitor {
+            type Value = BmpPeerHeader;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a BMP per-peer header")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let peer_type = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let flags: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let distinguisher = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                let address = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                Ok(BmpPeerHeader {
+                    peer_type,
+                    flags: flags & !FLAG_IPV6,
+                    distinguisher,
+                    address: address_from(address, flags & FLAG_IPV6 != 0),
+                    asn: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?,
+                    bgp_id: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(5, &self))?,
+                    seconds: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(6, &self))?,
+                    microseconds: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(7, &self))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_tuple(8, HeaderVisitor)
+    }
+}
+
+struct StatValue<'a>(&'a BmpStat);
+
+impl Serialize for StatValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            BmpStat::Counter { value, .. } => value.serialize(serializer),
+            BmpStat::Gauge { value, .. } => value.serialize(serializer),
+            BmpStat::FamilyGauge { afi, safi, value, .. } => {
+                (u16::from(*afi), u8::from(*safi), value).serialize(serializer)
+            },
+            BmpStat::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for BmpStat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(StatValue(self));
+        (self.stat_type(), value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BmpStat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (stat_type, data): (u16, LengthPrefixed<u16, Bytes>) = Deserialize::deserialize(deserializer)?;
+        let data = data.value;
+        Ok(match data.len() {
+            4 => BmpStat::Counter { stat_type, value: u32::from_be_bytes(data[..].try_into().unwrap()) },
+            8 => BmpStat::Gauge { stat_type, value: u64::from_be_bytes(data[..].try_into().unwrap()) },
+            11 => BmpStat::FamilyGauge {
+                stat_type,
+                afi: Afi::from(u16::from_be_bytes([data[0], data[1]])),
+                safi: Safi::from(data[2]),
+                value: u64::from_be_bytes(data[3..].try_into().unwrap()),
+            },
+            _ => BmpStat::Unknown { stat_type, data },
+        })
+    }
+}
+
+impl Serialize for BmpInformation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(&self.value);
+        (self.info_type, value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BmpInformation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (info_type, value): (u16, LengthPrefixed<u16, Bytes>) = Deserialize::deserialize(deserializer)?;
+        Ok(BmpInformation { info_type, value: value.value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsPathSegment, BgpIdentifier, CeaseSubcode, NotificationCode, Origin, PathAttribute};
+
+    fn peer() -> BmpPeerHeader {
+        let mut peer = BmpPeerHeader::new(
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            64500,
+            Ipv4Addr::new(192, 0, 2, 1),
+        );
+        peer.flags = BMP_FLAG_POST_POLICY;
+        peer.seconds = 1_700_000_000;
+        peer
+    }
+
+    #[test]
+    fn test_bmp_route_monitoring() {
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![64500, 64496])]),
+        ];
+        update.nlri = vec!["198.51.100.0/24".parse().unwrap()];
+        let mut msg = BmpMessage::RouteMonitoring { peer: peer(), update };
+        let wire = msg.to_bytes().unwrap();
+        assert_eq!(&wire[..6], &[3, 0, 0, 0, wire.len() as u8, 0]);
+        #[rustfmt::skip]
+        assert_eq!(&wire[6..48], &[
+            0, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 192, 0, 2, 1,
+            0, 0, 0xfb, 0xf4, 192, 0, 2, 1, 0x65, 0x53, 0xf1, 0x00, 0, 0, 0, 0,
+        ]);
+        assert_eq!(&wire[48..64], &[0xff; 16]);
+        assert_eq!(BmpMessage::from_bytes(&wire).unwrap(), msg);
+
+        // Legacy AS_PATH: 2 octet AS numbers in the UPDATE, IPv6 peer sets V
+        if let BmpMessage::RouteMonitoring { peer, .. } = &mut msg {
+            peer.flags |= BMP_FLAG_LEGACY_AS_PATH;
+            peer.address = "2001:db8::1".parse().unwrap();
+        }
+        let wire = msg.to_bytes().unwrap();
+        assert_eq!(wire[7], 0xe0);
+        assert_eq!(&wire[wire.len() - 13..wire.len() - 4], &[0x40, 2, 6, 2, 2, 0xfb, 0xf4, 0xfb, 0xf0]);
+        assert_eq!(BmpMessage::from_bytes(&wire).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_bmp_session_messages() {
+        let open = OpenMessage::new(64500, 90, BgpIdentifier::new(0xc000_0201).unwrap());
+        let messages = [
+            BmpMessage::Initiation(vec![
+                BmpInformation::string(bmp::INFO_SYS_NAME, "router"),
+                BmpInformation::string(bmp::INFO_SYS_DESCR, "test"),
+            ]),
+            BmpMessage::PeerUp(Box::new(BmpPeerUp {
+                peer: peer(),
+                local_address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+                local_port: 179,
+                remote_port: 40000,
+                sent_open: open.clone(),
+                received_open: open,
+                information: vec![BmpInformation::string(bmp::INFO_STRING, "up")],
+            })),
+            BmpMessage::StatisticsReport {
+                peer: peer(),
+                stats: vec![
+                    BmpStat::Counter { stat_type: 0, value: 3 },
+                    BmpStat::Gauge { stat_type: 7, value: 100_000 },
+                    BmpStat::FamilyGauge { stat_type: 9, afi: Afi::Ipv6, safi: Safi::Unicast, value: 42 },
+                ],
+            },
+            BmpMessage::PeerDown {
+                peer: peer(),
+                reason: PeerDownReason::RemoteNotification(NotificationMessage::new(
+                    NotificationCode::Cease(CeaseSubcode::AdministrativeShutdown),
+                    Vec::new(),
+                )),
+            },
+            BmpMessage::PeerDown { peer: peer(), reason: PeerDownReason::LocalNoNotification(2) },
+            BmpMessage::Termination(vec![BmpInformation {
+                info_type: bmp::TERM_REASON,
+                value: Bytes::from_static(&[0, 1]),
+            }]),
+            BmpMessage::Unknown { message_type: bmp::ROUTE_MIRRORING, data: Bytes::from_static(&[1, 2, 3]) },
+        ];
+        for msg in messages {
+            let wire = msg.to_bytes().unwrap();
+            assert_eq!(BmpMessage::from_bytes(&wire).unwrap(), msg, "{:?}", msg.message_type());
+        }
+
+        let wire = BmpMessage::Initiation(vec![BmpInformation::string(bmp::INFO_SYS_NAME, "r1")]).to_bytes().unwrap();
+        assert_eq!(wire.as_ref(), &[3, 0, 0, 0, 12, 4, 0, 2, 0, 2, b'r', b'1']);
+    }
+
+    #[test]
+    fn test_bmp_v4() {
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![PathAttribute::Origin(Origin::Igp)];
+        update.nlri = vec!["198.51.100.0/24".parse().unwrap(), "203.0.113.0/24".parse().unwrap()];
+        let msg = BmpMessage::V4 {
+            message_type: bmp::ROUTE_MONITORING,
+            peer: peer(),
+            tlvs: vec![
+                BmpTlv::bgp_pdu(0, &update, &peer()).unwrap(),
+                BmpTlv { tlv_type: bmp::TLV_GROUP, index: 1, value: Bytes::from_static(&[0, 1, 0, 2]) },
+            ],
+        };
+        let wire = msg.to_bytes().unwrap();
+        assert_eq!(wire[0], BMP_VERSION_4);
+        assert_eq!(&wire[48..54], &[0, 1, 0, 35, 0, 0]);
+        let decoded = BmpMessage::from_bytes(&wire).unwrap();
+        assert_eq!(decoded, msg);
+        match decoded {
+            BmpMessage::V4 { peer, tlvs, .. } => {
+                assert_eq!(tlvs[0].update(&peer).unwrap().unwrap(), update);
+                assert!(tlvs[1].update(&peer).is_none());
+                assert_eq!(tlvs[1].group_indexes(), Some(vec![1, 2]));
+            },
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        // Messages without a per-peer header read as in version 3
+        let mut wire = BmpMessage::Initiation(vec![BmpInformation::string(bmp::INFO_SYS_NAME, "r1")]).to_bytes().unwrap();
+        wire[0] = BMP_VERSION_4;
+        assert!(matches!(BmpMessage::from_bytes(&wire).unwrap(), BmpMessage::Initiation(_)));
+
+        // A TLV running past the end
+        let mut wire = BmpMessage::V4 { message_type: bmp::ROUTE_MONITORING, peer: peer(), tlvs: vec![] }
+            .to_bytes()
+            .unwrap();
+        wire.extend_from_slice(&[0, 1, 0, 4, 0, 0, 1]);
+        wire[4] += 7;
+        match BmpMessage::from_bytes(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::UnexpectedEof { needed: 4, available: 1, offset: 54 }),
+        }
+    }
+
+    #[test]
+    fn test_bmp_err() {
+        match BmpMessage::from_bytes(&[2, 0, 0, 0, 6, 4]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Unsupported BMP version 2"),
+        }
+        match BmpMessage::from_bytes(&[3, 0, 0, 0, 7, 4]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::LengthMismatch { declared: 7, actual: 6, offset: 1 }),
+        }
+        // Per-peer header cut short
+        match BmpMessage::from_bytes(&[3, 0, 0, 0, 10, 0, 0, 0, 0, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::UnexpectedEof { needed: 42, available: 4, offset: 6 }),
+        }
+        // Remote close without data, with data
+        let mut wire = BmpMessage::PeerDown { peer: peer(), reason: PeerDownReason::RemoteNoData }.to_bytes().unwrap();
+        wire.extend_from_slice(&[0]);
+        wire[4] += 1;
+        assert!(BmpMessage::from_bytes(&wire).is_err());
+    }
+}
@@ -0,0 +1,851 @@
+// OPEN optional parameters and the capabilities carried in them (RFC 5492).
+//
+// Both are type/length/value with a 1 octet type and a 1 octet length. An
+// optional parameter of type Capabilities holds one or more capabilities
+// back to back. Codes the crate doesn't model decode as Unknown with the raw
+// value so the OPEN still round-trips; unknown capabilities can also be
+// skipped or rejected (OnUnknown).
+//
+// Dynamic Capability (draft-ietf-idr-dynamic-cap) lets a speaker set or
+// remove capabilities after the OPEN with CAPABILITY messages. Each change
+// there is an action octet and the capability with a 2 octet length.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::registry::{capability, optional_param};
+use crate::types::{
+    Counted, CountedRef, LengthPrefixed, LengthPrefixedSeed, Skippable, SkippingList, TextOrNumber, UnknownValueSeed,
+    U24,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OptionalParameter {
+    Capabilities(Vec<Capability>),
+    Unknown { param_type: u8, value: Vec<u8> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Capability {
+    // RFC 4760
+    Multiprotocol { afi: Afi, safi: Safi },
+    // RFC 2918
+    RouteRefresh,
+    // RFC 5291
+    Orf(Vec<OrfFamily>),
+    // RFC 6793
+    FourOctetAs(u32),
+    // RFC 4724
+    GracefulRestart(GracefulRestart),
+    // RFC 7911
+    AddPath(Vec<AddPathFamily>),
+    // RFC 8950
+    ExtendedNextHop(Vec<ExtendedNextHopFamily>),
+    // RFC 8654
+    ExtendedMessage,
+    // RFC 9494
+    LongLivedGracefulRestart(Vec<LlgrFamily>),
+    // draft-ietf-idr-dynamic-cap: the capability codes that can be changed
+    // with CAPABILITY messages
+    Dynamic(Vec<u8>),
+    Unknown {
+        code: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// Restart flags are the top 4 bits of the first 2 octets, the restart time
+// (seconds) the low 12.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct GracefulRestart {
+    pub restart_flags: u8,
+    pub restart_time: u16,
+    pub families: Vec<GracefulRestartFamily>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct GracefulRestartFamily {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub flags: u8,
+}
+
+// GracefulRestart::restart_flags
+pub const GR_RESTART_STATE: u8 = 0x8;
+// RFC 8538
+pub const GR_NOTIFICATION: u8 = 0x4;
+
+// GracefulRestartFamily::flags and LlgrFamily::flags
+pub const GR_FORWARDING_STATE: u8 = 0x80;
+
+// Same layout as a Graceful Restart family, with the stale time (seconds)
+// in 3 octets after the flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LlgrFamily {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub flags: u8,
+    pub stale_time: U24,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AddPathDirection {
+    Receive,
+    Send,
+    Both,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AddPathFamily {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub direction: AddPathDirection,
+}
+
+// The ORF types (registry::orf, the codes Orf::orf_type gives) a speaker
+// handles for one AFI/SAFI. On the wire the list is preceded by a 1 octet
+// count rather than a length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OrfFamily {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub orf_types: Vec<OrfSupport>,
+}
+
+// The Send/Receive field takes the same values as ADD-PATH's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OrfSupport {
+    pub orf_type: u8,
+    pub direction: AddPathDirection,
+}
+
+// One entry of a CAPABILITY message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CapabilityChange {
+    pub action: CapabilityAction,
+    pub capability: Capability,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum CapabilityAction {
+    Set,
+    Remove,
+    Unknown(u8),
+}
+
+// The NLRI SAFI is 2 octets in this capability, unlike everywhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ExtendedNextHopFamily {
+    pub nlri_afi: Afi,
+    pub nlri_safi: Safi,
+    pub next_hop_afi: Afi,
+}
+
+impl OptionalParameter {
+    pub fn param_type(&self) -> u8 {
+        match self {
+            OptionalParameter::Capabilities(_) => optional_param::CAPABILITIES,
+            OptionalParameter::Unknown { param_type, .. } => *param_type,
+        }
+    }
+}
+
+impl Capability {
+    pub fn code(&self) -> u8 {
+        match self {
+            Capability::Multiprotocol { .. } => capability::MULTIPROTOCOL,
+            Capability::RouteRefresh => capability::ROUTE_REFRESH,
+            Capability::Orf(_) => capability::OUTBOUND_ROUTE_FILTERING,
+            Capability::FourOctetAs(_) => capability::FOUR_OCTET_AS,
+            Capability::GracefulRestart(_) => capability::GRACEFUL_RESTART,
+            Capability::AddPath(_) => capability::ADD_PATH,
+            Capability::ExtendedNextHop(_) => capability::EXTENDED_NEXT_HOP,
+            Capability::ExtendedMessage => capability::EXTENDED_MESSAGE,
+            Capability::LongLivedGracefulRestart(_) => capability::LONG_LIVED_GRACEFUL_RESTART,
+            Capability::Dynamic(_) => capability::DYNAMIC,
+            Capability::Unknown { code, .. } => *code,
+        }
+    }
+}
+
+impl From<u8> for CapabilityAction {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => CapabilityAction::Set,
+            1 => CapabilityAction::Remove,
+            other => CapabilityAction::Unknown(other),
+        }
+    }
+}
+
+impl From<CapabilityAction> for u8 {
+    fn from(a: CapabilityAction) -> Self {
+        match a {
+            CapabilityAction::Set => 0,
+            CapabilityAction::Remove => 1,
+            CapabilityAction::Unknown(other) => other,
+        }
+    }
+}
+
+impl Serialize for CapabilityAction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for CapabilityAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(CapabilityAction::from)
+    }
+}
+
+impl From<u8> for AddPathDirection {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => AddPathDirection::Receive,
+            2 => AddPathDirection::Send,
+            3 => AddPathDirection::Both,
+            other => AddPathDirection::Unknown(other),
+        }
+    }
+}
+
+impl From<AddPathDirection> for u8 {
+    fn from(d: AddPathDirection) -> Self {
+        match d {
+            AddPathDirection::Receive => 1,
+            AddPathDirection::Send => 2,
+            AddPathDirection::Both => 3,
+            AddPathDirection::Unknown(other) => other,
+        }
+    }
+}
+
+impl AddPathDirection {
+    // Name in text formats
+    fn name(&self) -> Option<&'static str> {
+        match self {
+            AddPathDirection::Receive => Some("receive"),
+            AddPathDirection::Send => Some("send"),
+            AddPathDirection::Both => Some("both"),
+            AddPathDirection::Unknown(_) => None,
+        }
+    }
+}
+
+impl Serialize for AddPathDirection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.name() {
+            Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => serializer.serialize_u8(u8::from(*self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AddPathDirection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let directions = [AddPathDirection::Receive, AddPathDirection::Send, AddPathDirection::Both];
+            return TextOrNumber::deserialize(deserializer)?.decode(
+                "ADD-PATH direction",
+                |s| directions.into_iter().find(|d| d.name() == Some(s)),
+                |v| u8::try_from(v).ok().map(AddPathDirection::from),
+            );
+        }
+        u8::deserialize(deserializer).map(AddPathDirection::from)
+    }
+}
+
+impl GracefulRestart {
+    // The speaker has restarted (R bit).
+    pub fn is_restarting(&self) -> bool {
+        self.restart_flags & GR_RESTART_STATE != 0
+    }
+
+    // Graceful restart also applies to NOTIFICATIONs (N bit).
+    pub fn supports_notification(&self) -> bool {
+        self.restart_flags & GR_NOTIFICATION != 0
+    }
+
+    pub fn family(&self, afi: Afi, safi: Safi) -> Option<&GracefulRestartFamily> {
+        self.families.iter().find(|f| f.afi == afi && f.safi == safi)
+    }
+
+    // Whether the peer kept forwarding state for `afi`/`safi` across the
+    // restart. Families it didn't list weren't preserved.
+    pub fn forwarding_preserved(&self, afi: Afi, safi: Safi) -> bool {
+        self.family(afi, safi).is_some_and(GracefulRestartFamily::forwarding_preserved)
+    }
+
+    // Every listed family and its F bit. A family listed twice keeps the
+    // last entry.
+    pub fn forwarding_state(&self) -> BTreeMap<(Afi, Safi), bool> {
+        self.families.iter().map(|f| ((f.afi, f.safi), f.forwarding_preserved())).collect()
+    }
+}
+
+impl GracefulRestartFamily {
+    // Forwarding state was kept across the restart (F bit).
+    pub fn forwarding_preserved(&self) -> bool {
+        self.flags & GR_FORWARDING_STATE != 0
+    }
+}
+
+impl LlgrFamily {
+    pub fn forwarding_preserved(&self) -> bool {
+        self.flags & GR_FORWARDING_STATE != 0
+    }
+}
+
+impl OrfFamily {
+    // How the speaker handles `orf_type` for this family, if at all.
+    pub fn direction(&self, orf_type: u8) -> Option<AddPathDirection> {
+        self.orf_types.iter().find(|o| o.orf_type == orf_type).map(|o| o.direction)
+    }
+}
+
+// -- Text form --
+//
+// Text formats get parameters and capabilities as enums,
+// {"four_octet_as": 4200000000}, and their values as plain structs.
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OptionalParameter", rename_all = "snake_case")]
+enum OptionalParameterText {
+    Capabilities(Vec<Capability>),
+    Unknown { param_type: u8, value: Vec<u8> },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Capability", rename_all = "snake_case")]
+enum CapabilityText {
+    Multiprotocol { afi: Afi, safi: Safi },
+    RouteRefresh,
+    Orf(Vec<OrfFamily>),
+    FourOctetAs(u32),
+    GracefulRestart(GracefulRestart),
+    AddPath(Vec<AddPathFamily>),
+    ExtendedNextHop(Vec<ExtendedNextHopFamily>),
+    ExtendedMessage,
+    LongLivedGracefulRestart(Vec<LlgrFamily>),
+    Dynamic(Vec<u8>),
+    Unknown { code: u8, data: Bytes },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CapabilityChange")]
+struct CapabilityChangeText {
+    action: CapabilityAction,
+    capability: Capability,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GracefulRestart")]
+struct GracefulRestartText {
+    restart_flags: u8,
+    restart_time: u16,
+    families: Vec<GracefulRestartFamily>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrfFamily")]
+struct OrfFamilyText {
+    afi: Afi,
+    safi: Safi,
+    orf_types: Vec<OrfSupport>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ExtendedNextHopFamily")]
+struct ExtendedNextHopFamilyText {
+    nlri_afi: Afi,
+    nlri_safi: Safi,
+    next_hop_afi: Afi,
+}
+
+// -- Wire form --
+
+impl Serialize for GracefulRestart {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return GracefulRestartText::serialize(self, serializer);
+        }
+        if self.restart_flags > 0x0f {
+            return Err(ser::Error::custom(format!("Restart flags {:#x} do not fit in 4 bits", self.restart_flags)));
+        }
+        if self.restart_time > 0x0fff {
+            return Err(ser::Error::custom(format!("Restart time {} does not fit in 12 bits", self.restart_time)));
+        }
+        let head = ((self.restart_flags as u16) << 12) | self.restart_time;
+        (head, &self.families).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GracefulRestart {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return GracefulRestartText::deserialize(deserializer);
+        }
+        let (head, families): (u16, _) = Deserialize::deserialize(deserializer)?;
+        Ok(GracefulRestart {
+            restart_flags: (head >> 12) as u8,
+            restart_time: head & 0x0fff,
+            families,
+        })
+    }
+}
+
+impl Serialize for OrfFamily {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return OrfFamilyText::serialize(self, serializer);
+        }
+        let orf_types = CountedRef::<u8, _>(&self.orf_types, PhantomData);
+        (self.afi, 0u8, self.safi, orf_types).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for OrfFamily {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return OrfFamilyText::deserialize(deserializer);
+        }
+
+        let (afi, _reserved, safi, orf_types): (Afi, u8, Safi, Counted<u8, _>) =
+            Deserialize::deserialize(deserializer)?;
+        Ok(OrfFamily { afi, safi, orf_types: orf_types.into_inner() })
+    }
+}
+
+impl Serialize for ExtendedNextHopFamily {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return ExtendedNextHopFamilyText::serialize(self, serializer);
+        }
+        (self.nlri_afi, u8::from(self.nlri_safi) as u16, self.next_hop_afi).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExtendedNextHopFamily {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return ExtendedNextHopFamilyText::deserialize(deserializer);
+        }
+        let (nlri_afi, nlri_safi, next_hop_afi): (Afi, u16, Afi) = Deserialize::deserialize(deserializer)?;
+        let nlri_safi = u8::try_from(nlri_safi)
+            .map_err(|_| de::Error::custom(format!("Invalid SAFI {} in Extended Next Hop capability", nlri_safi)))?;
+        Ok(ExtendedNextHopFamily { nlri_afi, nlri_safi: Safi::from(nlri_safi), next_hop_afi })
+    }
+}
+
+// -- Type, length, value --
+
+// Type octet followed by the value with a 1 octet length in front.
+struct Tlv<V> {
+    code: u8,
+    value: V,
+}
+
+impl<V: Serialize> Serialize for Tlv<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u8, _> = LengthPrefixed::new(&self.value);
+        (self.code, value).serialize(serializer)
+    }
+}
+
+// Reads the type octet and hands it to `seed_for` to pick how the value,
+// scoped to its length, is decoded.
+struct TlvVisitor<F> {
+    expecting: &'static str,
+    seed_for: F,
+}
+
+impl<'de, F, S> Visitor<'de> for TlvVisitor<F>
+where
+    F: Fn(u8) -> S,
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<S::Value, A::Error> {
+        let code: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let seed = LengthPrefixedSeed { width: 1, seed: (self.seed_for)(code) };
+        match seq.next_element_seed(seed)? {
+            Some(value) => Ok(value),
+            None => Err(de::Error::invalid_length(1, &self)),
+        }
+    }
+}
+
+struct CapabilityValue<'a>(&'a Capability);
+
+impl Serialize for CapabilityValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            Capability::Multiprotocol { afi, safi } => (afi, 0u8, safi).serialize(serializer),
+            Capability::RouteRefresh => serializer.serialize_unit(),
+            Capability::Orf(families) => families.serialize(serializer),
+            Capability::FourOctetAs(asn) => serializer.serialize_u32(*asn),
+            Capability::GracefulRestart(gr) => gr.serialize(serializer),
+            Capability::AddPath(families) => families.serialize(serializer),
+            Capability::ExtendedNextHop(families) => families.serialize(serializer),
+            Capability::ExtendedMessage => serializer.serialize_unit(),
+            Capability::LongLivedGracefulRestart(families) => families.serialize(serializer),
+            Capability::Dynamic(codes) => codes.serialize(serializer),
+            Capability::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for Capability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return CapabilityText::serialize(self, serializer);
+        }
+        Tlv { code: self.code(), value: CapabilityValue(self) }.serialize(serializer)
+    }
+}
+
+struct CapabilitySeed(u8);
+
+impl<'de> DeserializeSeed<'de> for CapabilitySeed {
+    type Value = Option<Capability>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Option<Capability>, D::Error> {
+        Ok(Some(match self.0 {
+            capability::MULTIPROTOCOL => {
+                let (afi, _reserved, safi): (Afi, u8, Safi) = Deserialize::deserialize(d)?;
+                Capability::Multiprotocol { afi, safi }
+            },
+            capability::ROUTE_REFRESH => {
+                <()>::deserialize(d)?;
+                Capability::RouteRefresh
+            },
+            capability::OUTBOUND_ROUTE_FILTERING => Capability::Orf(Vec::deserialize(d)?),
+            capability::FOUR_OCTET_AS => Capability::FourOctetAs(u32::deserialize(d)?),
+            capability::GRACEFUL_RESTART => Capability::GracefulRestart(GracefulRestart::deserialize(d)?),
+            capability::ADD_PATH => Capability::AddPath(Vec::deserialize(d)?),
+            capability::EXTENDED_NEXT_HOP => Capability::ExtendedNextHop(Vec::deserialize(d)?),
+            capability::EXTENDED_MESSAGE => {
+                <()>::deserialize(d)?;
+                Capability::ExtendedMessage
+            },
+            capability::LONG_LIVED_GRACEFUL_RESTART => Capability::LongLivedGracefulRestart(Vec::deserialize(d)?),
+            capability::DYNAMIC => Capability::Dynamic(Vec::deserialize(d)?),
+            code => {
+                let seed = UnknownValueSeed { what: "capability code", code };
+                return Ok(seed.deserialize(d)?.map(|data| Capability::Unknown { code, data }));
+            },
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for Skippable<Capability> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return CapabilityText::deserialize(deserializer).map(|cap| Skippable(Some(cap)));
+        }
+        let visitor = TlvVisitor { expecting: "a capability", seed_for: CapabilitySeed };
+        deserializer.deserialize_tuple(2, visitor).map(Skippable)
+    }
+}
+
+impl<'de> Deserialize<'de> for Capability {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Skippable::deserialize(deserializer)?
+            .0
+            .ok_or_else(|| de::Error::custom("Unrecognized capability skipped outside a capability list"))
+    }
+}
+
+impl Serialize for CapabilityChange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return CapabilityChangeText::serialize(self, serializer);
+        }
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(CapabilityValue(&self.capability));
+        (self.action, self.capability.code(), value).serialize(serializer)
+    }
+}
+
+// Unknown capabilities are skipped or rejected as in an OPEN, taking the
+// action with them.
+impl<'de> Deserialize<'de> for Skippable<CapabilityChange> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return CapabilityChangeText::deserialize(deserializer).map(|change| Skippable(Some(change)));
+        }
+
+        struct ChangeVisitor;
+
+        impl<'de> Visitor<'de> for ChangeVisitor {
+            type Value = Skippable<CapabilityChange>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a capability change")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let action = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let code: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let seed = LengthPrefixedSeed { width: 2, seed: CapabilitySeed(code) };
+                let capability = seq.next_element_seed(seed)?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                Ok(Skippable(capability.map(|capability| CapabilityChange { action, capability })))
+            }
+        }
+
+        deserializer.deserialize_tuple(3, ChangeVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for CapabilityChange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Skippable::deserialize(deserializer)?
+            .0
+            .ok_or_else(|| de::Error::custom("Unrecognized capability skipped outside a capability list"))
+    }
+}
+
+// A CAPABILITY message has at least one change on the wire, so an empty
+// list means OnUnknown::Skip dropped all of them. That can't be encoded
+// again, and fails like a lone skipped capability.
+pub(crate) fn deserialize_changes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<CapabilityChange>, D::Error> {
+    let human_readable = deserializer.is_human_readable();
+    let changes = SkippingList::deserialize(deserializer)?.0;
+    if changes.is_empty() && !human_readable {
+        return Err(de::Error::custom("Every capability change was skipped"));
+    }
+    Ok(changes)
+}
+
+impl Serialize for OptionalParameter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return OptionalParameterText::serialize(self, serializer);
+        }
+        match self {
+            OptionalParameter::Capabilities(caps) => Tlv { code: self.param_type(), value: caps }.serialize(serializer),
+            OptionalParameter::Unknown { param_type, value } => {
+                Tlv { code: *param_type, value: RawBytes(value) }.serialize(serializer)
+            },
+        }
+    }
+}
+
+// Raw octets, written as is.
+struct RawBytes<'a>(&'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct ParameterSeed(u8);
+
+impl<'de> DeserializeSeed<'de> for ParameterSeed {
+    type Value = OptionalParameter;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<OptionalParameter, D::Error> {
+        Ok(match self.0 {
+            optional_param::CAPABILITIES => OptionalParameter::Capabilities(SkippingList::deserialize(d)?.0),
+            param_type => OptionalParameter::Unknown { param_type, value: Vec::deserialize(d)? },
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for OptionalParameter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return OptionalParameterText::deserialize(deserializer);
+        }
+        deserializer.deserialize_tuple(2, TlvVisitor { expecting: "an optional parameter", seed_for: ParameterSeed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, from_bytes_with, to_bytes, DeserializeError, DeserializerOptions, OnUnknown};
+
+    #[test]
+    fn test_capability_wire() {
+        let cases: [(Capability, &[u8]); 9] = [
+            (Capability::Multiprotocol { afi: Afi::Ipv6, safi: Safi::Unicast }, &[1, 4, 0, 2, 0, 1]),
+            (Capability::RouteRefresh, &[2, 0]),
+            (Capability::FourOctetAs(4200000000), &[65, 4, 0xfa, 0x56, 0xea, 0]),
+            (
+                Capability::GracefulRestart(GracefulRestart {
+                    restart_flags: 0x8,
+                    restart_time: 120,
+                    families: vec![GracefulRestartFamily { afi: Afi::Ipv4, safi: Safi::Unicast, flags: 0x80 }],
+                }),
+                &[64, 6, 0x80, 120, 0, 1, 1, 0x80],
+            ),
+            (
+                Capability::AddPath(vec![AddPathFamily {
+                    afi: Afi::Ipv4,
+                    safi: Safi::Unicast,
+                    direction: AddPathDirection::Both,
+                }]),
+                &[69, 4, 0, 1, 1, 3],
+            ),
+            (
+                Capability::ExtendedNextHop(vec![ExtendedNextHopFamily {
+                    nlri_afi: Afi::Ipv4,
+                    nlri_safi: Safi::Unicast,
+                    next_hop_afi: Afi::Ipv6,
+                }]),
+                &[5, 6, 0, 1, 0, 1, 0, 2],
+            ),
+            (Capability::ExtendedMessage, &[6, 0]),
+            (Capability::Dynamic(vec![2, 64]), &[67, 2, 2, 64]),
+            (Capability::Unknown { code: 73, data: Bytes::from_static(&[1, 2]) }, &[73, 2, 1, 2]),
+        ];
+        for (cap, wire) in cases {
+            assert_eq!(to_bytes(&cap).unwrap().as_ref(), wire, "{:?}", cap);
+            assert_eq!(from_bytes::<Capability>(wire).unwrap(), cap);
+        }
+    }
+
+    #[test]
+    fn test_graceful_restart() {
+        let day = U24::new(86400).unwrap();
+        let llgr = Capability::LongLivedGracefulRestart(vec![
+            LlgrFamily { afi: Afi::Ipv4, safi: Safi::Unicast, flags: GR_FORWARDING_STATE, stale_time: day },
+            LlgrFamily { afi: Afi::Ipv6, safi: Safi::Unicast, flags: 0, stale_time: U24::MAX },
+        ]);
+        let wire = [71, 14, 0, 1, 1, 0x80, 0x01, 0x51, 0x80, 0, 2, 1, 0, 0xff, 0xff, 0xff];
+        assert_eq!(to_bytes(&llgr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Capability>(&wire).unwrap(), llgr);
+
+        let gr: Capability = from_bytes(&[64, 6, 0xc0, 120, 0, 1, 1, 0x80]).unwrap();
+        match gr {
+            Capability::GracefulRestart(gr) => {
+                assert!(gr.is_restarting());
+                assert!(gr.supports_notification());
+                assert_eq!(gr.restart_time, 120);
+                assert!(gr.families[0].forwarding_preserved());
+                assert!(gr.forwarding_preserved(Afi::Ipv4, Safi::Unicast));
+                assert!(!gr.forwarding_preserved(Afi::Ipv6, Safi::Unicast));
+            },
+            other => panic!("Unexpected capability {:?}", other),
+        }
+
+        let gr: Capability = from_bytes(&[64, 10, 0x00, 90, 0, 1, 1, 0x80, 0, 2, 1, 0]).unwrap();
+        match gr {
+            Capability::GracefulRestart(gr) => {
+                assert!(!gr.is_restarting());
+                assert_eq!(gr.family(Afi::Ipv6, Safi::Unicast).map(|f| f.flags), Some(0));
+                let state = gr.forwarding_state();
+                assert_eq!(state.len(), 2);
+                assert!(state[&(Afi::Ipv4, Safi::Unicast)]);
+                assert!(!state[&(Afi::Ipv6, Safi::Unicast)]);
+            },
+            other => panic!("Unexpected capability {:?}", other),
+        }
+
+        let gr = GracefulRestart { restart_flags: 0, restart_time: 4096, families: vec![] };
+        assert!(to_bytes(Capability::GracefulRestart(gr)).is_err());
+        let gr = GracefulRestart { restart_flags: 0x10, restart_time: 120, families: vec![] };
+        assert!(to_bytes(Capability::GracefulRestart(gr)).is_err());
+    }
+
+    #[test]
+    fn test_orf_capability() {
+        use crate::registry::orf;
+
+        let cap = Capability::Orf(vec![
+            OrfFamily {
+                afi: Afi::Ipv4,
+                safi: Safi::Unicast,
+                orf_types: vec![OrfSupport { orf_type: orf::ADDRESS_PREFIX, direction: AddPathDirection::Both }],
+            },
+            OrfFamily { afi: Afi::Ipv6, safi: Safi::Unicast, orf_types: vec![] },
+        ]);
+        let wire = [3, 12, 0, 1, 0, 1, 1, 64, 3, 0, 2, 0, 1, 0];
+        assert_eq!(to_bytes(&cap).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Capability>(&wire).unwrap(), cap);
+        match cap {
+            Capability::Orf(families) => {
+                assert_eq!(families[0].direction(orf::ADDRESS_PREFIX), Some(AddPathDirection::Both));
+                assert_eq!(families[1].direction(orf::ADDRESS_PREFIX), None);
+            },
+            other => panic!("Unexpected capability {:?}", other),
+        }
+
+        // The count says two ORF types, only one follows
+        assert!(from_bytes::<Capability>(&[3, 7, 0, 1, 0, 1, 2, 64, 1]).is_err());
+    }
+
+    #[test]
+    fn test_optional_parameters() {
+        let params = vec![
+            OptionalParameter::Capabilities(vec![
+                Capability::Multiprotocol { afi: Afi::Ipv4, safi: Safi::Unicast },
+                Capability::RouteRefresh,
+            ]),
+            OptionalParameter::Unknown { param_type: 1, value: vec![0xaa] },
+        ];
+        let wire = [2, 8, 1, 4, 0, 1, 0, 1, 2, 0, 1, 1, 0xaa];
+        assert_eq!(to_bytes(&params).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Vec<OptionalParameter>>(&wire).unwrap(), params);
+    }
+
+    #[test]
+    fn test_capability_skip_unknown() {
+        let wire = [2, 6, 73, 2, 1, 2, 2, 0];
+        let opts = DeserializerOptions { on_unknown: OnUnknown::Skip, ..Default::default() };
+        let param: OptionalParameter = from_bytes_with(&wire, opts).unwrap();
+        assert_eq!(param, OptionalParameter::Capabilities(vec![Capability::RouteRefresh]));
+
+        let opts = DeserializerOptions { on_unknown: OnUnknown::Error, ..Default::default() };
+        match from_bytes_with::<OptionalParameter>(&wire, opts) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Unrecognized capability code 73"),
+        }
+    }
+
+    #[test]
+    fn test_capability_err() {
+        // 4-octet AS capability with only 2 octets of value
+        assert!(matches!(
+            from_bytes::<Capability>(&[65, 2, 0xfd, 0xe9]).unwrap_err().inner(),
+            DeserializeError::UnexpectedEof { .. }
+        ));
+        // Route refresh with a value it shouldn't have
+        assert!(matches!(from_bytes::<Capability>(&[2, 1, 0]).unwrap_err().inner(), DeserializeError::LengthMismatch { .. }));
+        match from_bytes::<Capability>(&[5, 6, 0, 1, 1, 0, 0, 2]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid SAFI 256 in Extended Next Hop capability"),
+        }
+    }
+}
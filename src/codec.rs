@@ -0,0 +1,145 @@
+// tokio_util codec (feature = "tokio").
+//
+// Frames on the header length field, so it can be dropped straight into
+// Framed<TcpStream, BgpCodec>. Decoding and encoding go through the same
+// engines as from_message_bytes/to_message_bytes.
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::de::DeserializerOptions;
+use crate::error::{SerializerError, Result};
+use crate::messages::BgpMessage;
+use crate::ser::{to_message_bytes_with, SerializerOptions};
+use crate::stream::{decode_frame, DecodeStats, Quarantine};
+
+#[derive(Debug, Clone, Default)]
+pub struct BgpCodec {
+    ser_options: SerializerOptions,
+    de_options: DeserializerOptions,
+    stats: DecodeStats,
+    quarantine: Option<Quarantine>,
+}
+
+impl BgpCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(ser_options: SerializerOptions, de_options: DeserializerOptions) -> Self {
+        BgpCodec { ser_options, de_options, stats: DecodeStats::default(), quarantine: None }
+    }
+
+    // For switching over once capabilities are negotiated, e.g. through
+    // Framed::codec_mut.
+    pub fn set_options(&mut self, ser_options: SerializerOptions, de_options: DeserializerOptions) {
+        self.ser_options = ser_options;
+        self.de_options = de_options;
+    }
+
+    // Both directions at once, once Extended Messages are negotiated.
+    pub fn set_max_message_len(&mut self, max_message_len: usize) {
+        self.ser_options.max_message_len = max_message_len;
+        self.de_options.max_message_len = max_message_len;
+    }
+
+    // Decoding so far, e.g. through Framed::codec.
+    pub fn stats(&self) -> &DecodeStats {
+        &self.stats
+    }
+
+    // As MessageStream::set_quarantine.
+    pub fn set_quarantine(&mut self, quarantine: Option<Quarantine>) {
+        self.quarantine = quarantine;
+    }
+
+    pub fn quarantine(&self) -> Option<&Quarantine> {
+        self.quarantine.as_ref()
+    }
+
+    pub fn quarantine_mut(&mut self) -> Option<&mut Quarantine> {
+        self.quarantine.as_mut()
+    }
+}
+
+impl Decoder for BgpCodec {
+    type Item = BgpMessage;
+    type Error = SerializerError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BgpMessage>> {
+        let options = self.de_options;
+        let decode = |frame: Bytes| BgpMessage::from_bytes_with(&frame, options);
+        Ok(decode_frame(src, options.max_message_len, &mut self.stats, self.quarantine.as_mut(), decode)?)
+    }
+}
+
+impl Encoder<BgpMessage> for BgpCodec {
+    type Error = SerializerError;
+
+    fn encode(&mut self, item: BgpMessage, dst: &mut BytesMut) -> Result<()> {
+        self.encode(&item, dst)
+    }
+}
+
+impl Encoder<&BgpMessage> for BgpCodec {
+    type Error = SerializerError;
+
+    fn encode(&mut self, item: &BgpMessage, dst: &mut BytesMut) -> Result<()> {
+        dst.extend_from_slice(&to_message_bytes_with(item.message_type(), item, self.ser_options)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{KeepaliveMessage, NotificationMessage};
+    use crate::notification::{NotificationCode, UpdateMessageError};
+
+    #[test]
+    fn test_codec_round_trip() {
+        let mut codec = BgpCodec::new();
+        let mut buf = BytesMut::new();
+        let notification = BgpMessage::Notification(NotificationMessage {
+            error_code: 6,
+            error_subcode: 4,
+            data: vec![],
+        });
+        codec.encode(BgpMessage::Keepalive(KeepaliveMessage), &mut buf).unwrap();
+        codec.encode(&notification, &mut buf).unwrap();
+        assert_eq!(buf.len(), 19 + 21);
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(BgpMessage::Keepalive(KeepaliveMessage)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(notification));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.stats().total_messages(), 2);
+        assert_eq!(codec.stats().octets, 19 + 21);
+    }
+
+    #[test]
+    fn test_codec_partial_frame() {
+        let mut codec = BgpCodec::new();
+        let mut full = BytesMut::new();
+        codec.encode(BgpMessage::Keepalive(KeepaliveMessage), &mut full).unwrap();
+
+        let mut buf = BytesMut::from(&full[..10]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&full[10..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(BgpMessage::Keepalive(KeepaliveMessage)));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_codec_quarantine() {
+        let mut codec = BgpCodec::new();
+        codec.set_quarantine(Some(Quarantine::new(4096)));
+        // An UPDATE whose withdrawn routes run past the end of the message
+        let mut buf = BytesMut::from(&crate::messages::MARKER[..]);
+        buf.extend_from_slice(&[0, 23, 2, 0, 9, 0, 0]);
+        let frame = buf.to_vec();
+        assert!(codec.decode(&mut buf).is_err());
+        let quarantined = codec.quarantine().unwrap().iter().next().unwrap();
+        assert_eq!(quarantined.frame, frame);
+        let code = NotificationCode::UpdateMessage(UpdateMessageError::MalformedAttributeList);
+        assert_eq!((codec.stats().message_errors, codec.stats().errors(code)), (1, 1));
+    }
+}
@@ -0,0 +1,563 @@
+// COMMUNITIES (RFC 1997), EXTENDED_COMMUNITIES (RFC 4360, RFC 5668) and
+// LARGE_COMMUNITIES (RFC 8092) values.
+//
+// All three are flat lists of fixed size entries (4, 8 and 12 octets) with
+// no count, so the attribute length alone says how many there are.
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::attributes::PathAttribute;
+use crate::error::SerializerError;
+
+// Upper 16 bits an AS number, lower 16 bits a value of its choosing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Community(pub u32);
+
+impl Community {
+    // RFC 9494
+    pub const LLGR_STALE: Community = Community(0xffff_0006);
+    pub const NO_LLGR: Community = Community(0xffff_0007);
+
+    pub fn new(asn: u16, value: u16) -> Self {
+        Community(((asn as u32) << 16) | value as u32)
+    }
+
+    pub fn asn(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    pub fn value(&self) -> u16 {
+        self.0 as u16
+    }
+
+    // Well-known communities
+    pub fn graceful_shutdown() -> Self {
+        Community(0xffff_0000)
+    }
+
+    pub fn accept_own() -> Self {
+        Community(0xffff_0001)
+    }
+
+    pub fn llgr_stale() -> Self {
+        Community::LLGR_STALE
+    }
+
+    pub fn no_llgr() -> Self {
+        Community::NO_LLGR
+    }
+
+    pub fn blackhole() -> Self {
+        Community(0xffff_029a)
+    }
+
+    pub fn no_export() -> Self {
+        Community(0xffff_ff01)
+    }
+
+    pub fn no_advertise() -> Self {
+        Community(0xffff_ff02)
+    }
+
+    pub fn no_export_subconfed() -> Self {
+        Community(0xffff_ff03)
+    }
+
+    pub fn no_peer() -> Self {
+        Community(0xffff_ff04)
+    }
+}
+
+// Whether a route's COMMUNITIES mark it as retained under long-lived
+// graceful restart.
+pub fn is_llgr_stale(attributes: &[PathAttribute]) -> bool {
+    attributes.iter().any(|a| matches!(a.attribute(), PathAttribute::Communities(c) if c.contains(&Community::LLGR_STALE)))
+}
+
+// Takes LLGR_STALE and NO_LLGR out of COMMUNITIES, for when the route is
+// refreshed or leaves the LLGR domain. An attribute left with no
+// communities is removed.
+pub fn strip_llgr(attributes: &mut Vec<PathAttribute>) {
+    attributes.retain_mut(|a| match a.attribute_mut() {
+        PathAttribute::Communities(c) => {
+            c.retain(|c| *c != Community::LLGR_STALE && *c != Community::NO_LLGR);
+            !c.is_empty()
+        },
+        _ => true,
+    });
+}
+
+impl Display for Community {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.asn(), self.value())
+    }
+}
+
+impl FromStr for Community {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once(':')
+            .and_then(|(asn, value)| Some(Community::new(asn.parse().ok()?, value.parse().ok()?)))
+            .ok_or_else(|| invalid("community", s))
+    }
+}
+
+fn invalid(what: &str, s: &str) -> SerializerError {
+    SerializerError::CustomMsg(format!("Invalid {} \"{}\"", what, s))
+}
+
+// Text formats get all three kinds as their Display strings.
+fn deserialize_text<'de, D: Deserializer<'de>, T: FromStr<Err = SerializerError>>(
+    deserializer: D,
+) -> std::result::Result<T, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+}
+
+impl Serialize for Community {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(self);
+        }
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Community {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return deserialize_text(deserializer);
+        }
+        u32::deserialize(deserializer).map(Community)
+    }
+}
+
+// Global administrator and local value of the AS and address specific
+// extended community types. Which one it is is in the type octet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ExtendedAdmin {
+    // Type 0x00
+    TwoOctetAs { asn: u16, local: u32 },
+    // Type 0x01
+    Ipv4 { address: Ipv4Addr, local: u16 },
+    // Type 0x02
+    FourOctetAs { asn: u32, local: u16 },
+}
+
+// Compared and hashed by wire octets, so a LinkBandwidth holding NaN still
+// equals itself.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ExtendedCommunity {
+    // Subtype 0x02
+    RouteTarget(ExtendedAdmin),
+    // Subtype 0x03
+    RouteOrigin(ExtendedAdmin),
+    // Type 0x40 subtype 0x04 (draft-ietf-idr-link-bandwidth): non-transitive,
+    // the bandwidth in bytes per second as an IEEE float.
+    LinkBandwidth { asn: u16, bandwidth: f32 },
+    // Everything else, type and subtype octets included, kept as received
+    // so forwarding it changes nothing: the non-transitive forms of the
+    // route target and origin (type 0x40-0x42) land here as well.
+    Unknown([u8; 8]),
+}
+
+const ROUTE_TARGET: u8 = 0x02;
+const ROUTE_ORIGIN: u8 = 0x03;
+const LINK_BANDWIDTH: [u8; 2] = [0x40, 0x04];
+
+impl PartialEq for ExtendedCommunity {
+    fn eq(&self, other: &Self) -> bool {
+        self.octets() == other.octets()
+    }
+}
+
+impl Eq for ExtendedCommunity {}
+
+impl Hash for ExtendedCommunity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.octets().hash(state)
+    }
+}
+
+impl ExtendedAdmin {
+    fn for_asn(asn: u32, local: u16) -> Self {
+        match u16::try_from(asn) {
+            Ok(asn) => ExtendedAdmin::TwoOctetAs { asn, local: local as u32 },
+            Err(_) => ExtendedAdmin::FourOctetAs { asn, local },
+        }
+    }
+
+    fn type_octet(&self) -> u8 {
+        match self {
+            ExtendedAdmin::TwoOctetAs { .. } => 0x00,
+            ExtendedAdmin::Ipv4 { .. } => 0x01,
+            ExtendedAdmin::FourOctetAs { .. } => 0x02,
+        }
+    }
+
+    fn value_octets(&self) -> [u8; 6] {
+        let mut out = [0u8; 6];
+        match self {
+            ExtendedAdmin::TwoOctetAs { asn, local } => {
+                out[..2].copy_from_slice(&asn.to_be_bytes());
+                out[2..].copy_from_slice(&local.to_be_bytes());
+            },
+            ExtendedAdmin::Ipv4 { address, local } => {
+                out[..4].copy_from_slice(&address.octets());
+                out[4..].copy_from_slice(&local.to_be_bytes());
+            },
+            ExtendedAdmin::FourOctetAs { asn, local } => {
+                out[..4].copy_from_slice(&asn.to_be_bytes());
+                out[4..].copy_from_slice(&local.to_be_bytes());
+            },
+        }
+        out
+    }
+
+    fn from_octets(type_octet: u8, v: &[u8; 6]) -> Option<Self> {
+        let u16_at = |i: usize| u16::from_be_bytes([v[i], v[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([v[i], v[i + 1], v[i + 2], v[i + 3]]);
+        match type_octet {
+            0x00 => Some(ExtendedAdmin::TwoOctetAs { asn: u16_at(0), local: u32_at(2) }),
+            0x01 => Some(ExtendedAdmin::Ipv4 { address: Ipv4Addr::from(u32_at(0)), local: u16_at(4) }),
+            0x02 => Some(ExtendedAdmin::FourOctetAs { asn: u32_at(0), local: u16_at(4) }),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ExtendedAdmin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtendedAdmin::TwoOctetAs { asn, local } => write!(f, "{}:{}", asn, local),
+            ExtendedAdmin::Ipv4 { address, local } => write!(f, "{}:{}", address, local),
+            // "L" marks a 4-octet AS number that would also fit in 2 octets.
+            ExtendedAdmin::FourOctetAs { asn, local } if *asn <= 0xffff => write!(f, "{}L:{}", asn, local),
+            ExtendedAdmin::FourOctetAs { asn, local } => write!(f, "{}:{}", asn, local),
+        }
+    }
+}
+
+impl FromStr for ExtendedAdmin {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let (global, local) = s.rsplit_once(':')?;
+            if let Some(asn) = global.strip_suffix('L') {
+                return Some(ExtendedAdmin::FourOctetAs { asn: asn.parse().ok()?, local: local.parse().ok()? });
+            }
+            if let Ok(address) = global.parse() {
+                return Some(ExtendedAdmin::Ipv4 { address, local: local.parse().ok()? });
+            }
+            match global.parse::<u32>().ok()? {
+                asn @ 0..=0xffff => Some(ExtendedAdmin::TwoOctetAs { asn: asn as u16, local: local.parse().ok()? }),
+                asn => Some(ExtendedAdmin::FourOctetAs { asn, local: local.parse().ok()? }),
+            }
+        };
+        parse().ok_or_else(|| invalid("extended community administrator", s))
+    }
+}
+
+impl ExtendedCommunity {
+    // Route target for an AS number, in the 2-octet AS form when it fits.
+    pub fn route_target(asn: u32, local: u16) -> Self {
+        ExtendedCommunity::RouteTarget(ExtendedAdmin::for_asn(asn, local))
+    }
+
+    pub fn route_origin(asn: u32, local: u16) -> Self {
+        ExtendedCommunity::RouteOrigin(ExtendedAdmin::for_asn(asn, local))
+    }
+
+    // Transitive across ASes (the 0x40 bit of the type octet is clear).
+    pub fn is_transitive(&self) -> bool {
+        self.octets()[0] & 0x40 == 0
+    }
+
+    // The type octet, IANA authority (0x80) and transitive (0x40) bits
+    // included.
+    pub fn type_octet(&self) -> u8 {
+        self.octets()[0]
+    }
+
+    // Only meaningful for the types that have one; for the rest it is the
+    // first octet of the value.
+    pub fn subtype(&self) -> u8 {
+        self.octets()[1]
+    }
+
+    pub fn octets(&self) -> [u8; 8] {
+        let (admin, subtype) = match self {
+            ExtendedCommunity::RouteTarget(admin) => (admin, ROUTE_TARGET),
+            ExtendedCommunity::RouteOrigin(admin) => (admin, ROUTE_ORIGIN),
+            ExtendedCommunity::LinkBandwidth { asn, bandwidth } => {
+                let mut out = [0u8; 8];
+                out[..2].copy_from_slice(&LINK_BANDWIDTH);
+                out[2..4].copy_from_slice(&asn.to_be_bytes());
+                out[4..].copy_from_slice(&bandwidth.to_bits().to_be_bytes());
+                return out;
+            },
+            ExtendedCommunity::Unknown(octets) => return *octets,
+        };
+        let mut out = [0u8; 8];
+        out[0] = admin.type_octet();
+        out[1] = subtype;
+        out[2..].copy_from_slice(&admin.value_octets());
+        out
+    }
+
+    pub fn from_octets(octets: [u8; 8]) -> Self {
+        if octets[..2] == LINK_BANDWIDTH {
+            let asn = u16::from_be_bytes([octets[2], octets[3]]);
+            let bandwidth = f32::from_bits(u32::from_be_bytes([octets[4], octets[5], octets[6], octets[7]]));
+            return ExtendedCommunity::LinkBandwidth { asn, bandwidth };
+        }
+        let value: &[u8; 6] = octets[2..].try_into().unwrap();
+        match (ExtendedAdmin::from_octets(octets[0], value), octets[1]) {
+            (Some(admin), ROUTE_TARGET) => ExtendedCommunity::RouteTarget(admin),
+            (Some(admin), ROUTE_ORIGIN) => ExtendedCommunity::RouteOrigin(admin),
+            _ => ExtendedCommunity::Unknown(octets),
+        }
+    }
+}
+
+impl Display for ExtendedCommunity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtendedCommunity::RouteTarget(admin) => write!(f, "target:{}", admin),
+            ExtendedCommunity::RouteOrigin(admin) => write!(f, "origin:{}", admin),
+            ExtendedCommunity::LinkBandwidth { asn, bandwidth } => write!(f, "bandwidth:{}:{}", asn, bandwidth),
+            ExtendedCommunity::Unknown(octets) => {
+                f.write_str("0x")?;
+                octets.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            },
+        }
+    }
+}
+
+impl FromStr for ExtendedCommunity {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(admin) = s.strip_prefix("target:") {
+            return admin.parse().map(ExtendedCommunity::RouteTarget);
+        }
+        if let Some(admin) = s.strip_prefix("origin:") {
+            return admin.parse().map(ExtendedCommunity::RouteOrigin);
+        }
+        if let Some(value) = s.strip_prefix("bandwidth:") {
+            return value
+                .split_once(':')
+                .and_then(|(asn, bandwidth)| {
+                    Some(ExtendedCommunity::LinkBandwidth { asn: asn.parse().ok()?, bandwidth: bandwidth.parse().ok()? })
+                })
+                .ok_or_else(|| invalid("link bandwidth", s));
+        }
+        s.strip_prefix("0x")
+            .filter(|hex| hex.len() == 16 && hex.is_ascii())
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .map(|v| ExtendedCommunity::from_octets(v.to_be_bytes()))
+            .ok_or_else(|| invalid("extended community", s))
+    }
+}
+
+impl Serialize for ExtendedCommunity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(self);
+        }
+        self.octets().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExtendedCommunity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return deserialize_text(deserializer);
+        }
+        <[u8; 8]>::deserialize(deserializer).map(ExtendedCommunity::from_octets)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LargeCommunity {
+    pub global_admin: u32,
+    pub local_data_1: u32,
+    pub local_data_2: u32,
+}
+
+impl LargeCommunity {
+    pub fn new(global_admin: u32, local_data_1: u32, local_data_2: u32) -> Self {
+        LargeCommunity { global_admin, local_data_1, local_data_2 }
+    }
+}
+
+impl Display for LargeCommunity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.global_admin, self.local_data_1, self.local_data_2)
+    }
+}
+
+impl FromStr for LargeCommunity {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':').map(|part| part.parse().ok());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(a)), Some(Some(b)), Some(Some(c)), None) => Ok(LargeCommunity::new(a, b, c)),
+            _ => Err(invalid("large community", s)),
+        }
+    }
+}
+
+impl Serialize for LargeCommunity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(self);
+        }
+        (self.global_admin, self.local_data_1, self.local_data_2).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LargeCommunity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return deserialize_text(deserializer);
+        }
+        let (a, b, c) = Deserialize::deserialize(deserializer)?;
+        Ok(LargeCommunity::new(a, b, c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, Origin, PathAttribute};
+
+    #[test]
+    fn test_communities_wire() {
+        let attr = PathAttribute::Communities(vec![Community::new(65001, 100), Community::no_export()]);
+        let wire = [0xc0, 8, 8, 0xfd, 0xe9, 0, 100, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+        assert_eq!(Community::new(65001, 100).to_string(), "65001:100");
+        assert_eq!(Community::no_export().asn(), 0xffff);
+    }
+
+    #[test]
+    fn test_extended_communities_wire() {
+        let communities = vec![
+            ExtendedCommunity::route_target(65001, 100),
+            ExtendedCommunity::route_origin(4200000000, 7),
+            ExtendedCommunity::RouteTarget(ExtendedAdmin::Ipv4 { address: Ipv4Addr::new(192, 0, 2, 1), local: 5 }),
+            ExtendedCommunity::Unknown([0x43, 0x0c, 0, 0, 0, 0, 0, 8]),
+        ];
+        let attr = PathAttribute::ExtendedCommunities(communities.clone());
+        let bytes = to_bytes(&attr).unwrap();
+        assert_eq!(&bytes[..3], &[0xc0, 16, 32]);
+        assert_eq!(&bytes[3..], &[
+            0x00, 0x02, 0xfd, 0xe9, 0, 0, 0, 100,
+            0x02, 0x03, 0xfa, 0x56, 0xea, 0, 0, 7,
+            0x01, 0x02, 192, 0, 2, 1, 0, 5,
+            0x43, 0x0c, 0, 0, 0, 0, 0, 8,
+        ]);
+        assert_eq!(from_bytes::<PathAttribute>(&bytes).unwrap(), attr);
+
+        let text: Vec<_> = communities.iter().map(|c| c.to_string()).collect();
+        assert_eq!(text, ["target:65001:100", "origin:4200000000:7", "target:192.0.2.1:5", "0x430c000000000008"]);
+        let parsed: Vec<ExtendedCommunity> = text.iter().map(|t| t.parse().unwrap()).collect();
+        assert_eq!(parsed, communities);
+        // A 4-octet AS number that would fit in 2 octets keeps its type
+        let small = ExtendedCommunity::RouteTarget(ExtendedAdmin::FourOctetAs { asn: 65001, local: 1 });
+        assert_eq!(small.to_string(), "target:65001L:1");
+        assert_eq!("target:65001L:1".parse::<ExtendedCommunity>().unwrap(), small);
+        assert!(communities[0].is_transitive());
+        assert!(!communities[3].is_transitive());
+    }
+
+    #[test]
+    fn test_extended_communities_unknown_passthrough() {
+        let wire = [
+            // Non-transitive 2-octet AS route target
+            [0x40, 0x02, 0xfd, 0xe9, 0, 0, 0, 100],
+            // Transitive link bandwidth, which some implementations send
+            [0x00, 0x04, 0xfd, 0xe9, 0x4e, 0x95, 0x02, 0xf9],
+            // Experimental use, IANA authority bit set
+            [0x80, 0x06, 1, 2, 3, 4, 5, 6],
+            [0xc3, 0xff, 0xff, 0, 0xff, 0, 0xff, 0],
+        ];
+        for octets in wire {
+            let community = ExtendedCommunity::from_octets(octets);
+            assert!(matches!(community, ExtendedCommunity::Unknown(o) if o == octets));
+            assert_eq!(community.is_transitive(), octets[0] & 0x40 == 0);
+            assert_eq!((community.type_octet(), community.subtype()), (octets[0], octets[1]));
+            assert_eq!(community.to_string().parse::<ExtendedCommunity>().unwrap().octets(), octets);
+        }
+
+        let value: Vec<u8> = wire.concat();
+        let mut attr = vec![0xc0, 16, 32];
+        attr.extend_from_slice(&value);
+        let decoded = from_bytes::<PathAttribute>(&attr).unwrap();
+        assert_eq!(to_bytes(&decoded).unwrap().as_ref(), attr.as_slice());
+    }
+
+    #[test]
+    fn test_link_bandwidth() {
+        // 10 Gbit/s
+        let bw = ExtendedCommunity::LinkBandwidth { asn: 65001, bandwidth: 1.25e9 };
+        let wire = [0x40, 0x04, 0xfd, 0xe9, 0x4e, 0x95, 0x02, 0xf9];
+        assert_eq!(bw.octets(), wire);
+        assert_eq!(ExtendedCommunity::from_octets(wire), bw);
+        assert!(!bw.is_transitive());
+        let attr = PathAttribute::ExtendedCommunities(vec![bw]);
+        assert_eq!(from_bytes::<PathAttribute>(&to_bytes(&attr).unwrap()).unwrap(), attr);
+        assert_eq!(bw.to_string(), "bandwidth:65001:1250000000");
+        assert_eq!("bandwidth:65001:1250000000".parse::<ExtendedCommunity>().unwrap(), bw);
+
+        let nan = ExtendedCommunity::LinkBandwidth { asn: 1, bandwidth: f32::NAN };
+        assert_eq!(ExtendedCommunity::from_octets(nan.octets()), nan);
+    }
+
+    #[test]
+    fn test_llgr_communities() {
+        let mut attributes = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::Communities(vec![Community::LLGR_STALE, Community::new(65001, 1), Community::NO_LLGR]),
+        ];
+        assert!(is_llgr_stale(&attributes));
+        strip_llgr(&mut attributes);
+        assert!(!is_llgr_stale(&attributes));
+        assert_eq!(attributes[1], PathAttribute::Communities(vec![Community::new(65001, 1)]));
+
+        let mut attributes = vec![PathAttribute::Communities(vec![Community::llgr_stale()])];
+        assert_eq!(to_bytes(&attributes[0]).unwrap().as_ref(), &[0xc0, 8, 4, 0xff, 0xff, 0, 6]);
+        strip_llgr(&mut attributes);
+        assert!(attributes.is_empty());
+    }
+
+    #[test]
+    fn test_large_communities_wire() {
+        let attr = PathAttribute::LargeCommunities(vec![LargeCommunity::new(4200000000, 1, 2)]);
+        let wire = [0xc0, 32, 12, 0xfa, 0x56, 0xea, 0, 0, 0, 0, 1, 0, 0, 0, 2];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+        assert_eq!(LargeCommunity::new(4200000000, 1, 2).to_string(), "4200000000:1:2");
+    }
+
+    #[test]
+    fn test_communities_err() {
+        // 6 octets is not a whole number of communities
+        match from_bytes::<PathAttribute>(&[0xc0, 8, 6, 0, 0, 0, 1, 0, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().starts_with("Unexpected end of input"), "{}", e),
+        }
+    }
+}
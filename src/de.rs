@@ -1,3 +1,1142 @@
 // Definition of the custom Deserializer
+use std::marker::PhantomData;
 
-pub struct Deserializer {}
\ No newline at end of file
+use serde::de::{self, DeserializeSeed, IntoDeserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::afi::AddPathFamilies;
+use crate::attributes::{attribute_list_fits, ATTR_RESERVED};
+use crate::error::DeserializeError;
+use crate::messages::{Message, MessageType, HEADER_LEN, MARKER, MAX_MESSAGE_LEN};
+use crate::types::{
+    length_prefix_width, max_prefix_len, path_id_family, tag_width, variant_code, ASN, ATTRIBUTE_FLAGS, ATTRIBUTE_LIST, FOUR_OCTET_ASN,
+    MULTICAST_NLRI, PADDED, TAKE_REST, UNKNOWN_VALUE,
+};
+use crate::validate::{check_type_length, ValidationError};
+
+type Result<T> = std::result::Result<T, DeserializeError>;
+
+// The format is not self-describing, so everything is driven by the type
+// being deserialized: integers are read in network byte order at their
+// natural width, structs and tuples are their fields back to back, and
+// sequences/Options run until the current length scope is used up.
+// The limits stack holds the start and end offsets of the nested length
+// scopes (LengthPrefixed values), innermost last; without any scope the end
+// of the input is the limit.
+// The path stack names the element being decoded (root type, then fields and
+// indices) so errors can say where they happened.
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+    pos: usize,
+    limits: Vec<(usize, usize)>,
+    options: DeserializerOptions,
+    // Inside MULTICAST_NLRI
+    multicast_nlri: bool,
+    // Type code width for the enum right inside a TaggedEnum
+    tag_width: Option<usize>,
+    path: Vec<Segment>,
+}
+
+// A read position saved by Deserializer::checkpoint (the Serializer has its
+// own Checkpoint, for rolling output back).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadCheckpoint {
+    pos: usize,
+    // Depth of the limits and path stacks
+    limits: usize,
+    path: usize,
+    // Start and end of the innermost length scope, so a sibling scope at
+    // the same depth isn't taken for it.
+    scope: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Segment {
+    Type(&'static str),
+    Field(&'static str),
+    // Element of a sequence
+    Index(usize),
+    // Element of a tuple
+    Position(usize),
+}
+
+// Counterpart to SerializerOptions; has to match what the peer negotiated,
+// the wire doesn't say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeserializerOptions {
+    pub four_octet_asn: bool,
+    pub on_unknown: OnUnknown,
+    // RFC 7911. Families whose prefixes come with a path identifier.
+    pub add_path: AddPathFamilies,
+    pub reserved_flags: ReservedFlags,
+    pub unknown_messages: UnknownMessages,
+    // Largest message MessageStream and BgpCodec accept, header included.
+    // MAX_MESSAGE_LEN unless Extended Messages (RFC 8654) were negotiated,
+    // see negotiate_max_message_len.
+    pub max_message_len: usize,
+}
+
+impl Default for DeserializerOptions {
+    fn default() -> Self {
+        DeserializerOptions {
+            four_octet_asn: true,
+            on_unknown: OnUnknown::Preserve,
+            add_path: AddPathFamilies::new(),
+            reserved_flags: ReservedFlags::Reject,
+            unknown_messages: UnknownMessages::Reject,
+            max_message_len: MAX_MESSAGE_LEN,
+        }
+    }
+}
+
+// What to do with a path attribute whose reserved low four flag bits are
+// set. Some older implementations set them; a speaker should refuse the
+// UPDATE, a collector usually wants to keep going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReservedFlags {
+    // Fail the decode with an Attribute Flags Error.
+    #[default]
+    Reject,
+    // Decode the attribute as usual, in PathAttribute::WithFlags to keep
+    // the flags as received.
+    Preserve,
+}
+
+// What BgpMessage::from_bytes does with a header type it doesn't know. A
+// speaker has to answer one with a Bad Message Type NOTIFICATION; a
+// collector would rather keep going past proprietary or newer messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownMessages {
+    // Fail the decode.
+    #[default]
+    Reject,
+    // Decode it as BgpMessage::Unknown, body as received.
+    Preserve,
+}
+
+// What to do with path attributes and capabilities whose code isn't
+// recognized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnUnknown {
+    // Keep the raw value in the Unknown variant.
+    #[default]
+    Preserve,
+    // Drop it from the attribute/capability list. A lone attribute or
+    // capability can't be dropped and fails to decode instead, as does a
+    // CAPABILITY message whose every change would be.
+    Skip,
+    // Fail the decode.
+    Error,
+}
+
+// Counterpart to to_bytes. The whole input must be consumed.
+pub fn from_bytes<'a, T: Deserialize<'a>>(input: &'a [u8]) -> Result<T> {
+    from_bytes_with(input, DeserializerOptions::default())
+}
+
+pub fn from_bytes_with<'a, T: Deserialize<'a>>(input: &'a [u8], options: DeserializerOptions) -> Result<T> {
+    from_bytes_seed(input, options, PhantomData::<T>)
+}
+
+// from_bytes_with for values whose type is picked at runtime.
+pub(crate) fn from_bytes_seed<'a, S>(input: &'a [u8], options: DeserializerOptions, seed: S) -> Result<S::Value>
+where
+    S: DeserializeSeed<'a>,
+{
+    let mut deserializer = Deserializer::with_options(input, options);
+    let value = seed.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+// Counterpart to to_message_bytes. Checks the marker, that the header length
+// matches the input and that the type is the one T expects, then decodes the
+// body.
+pub fn from_message_bytes<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Message + Deserialize<'a>,
+{
+    from_message_bytes_with(input, DeserializerOptions::default())
+}
+
+pub fn from_message_bytes_with<'a, T>(input: &'a [u8], options: DeserializerOptions) -> Result<T>
+where
+    T: Message + Deserialize<'a>,
+{
+    let msg_type = message_header(input)?;
+    if msg_type != T::MESSAGE_TYPE {
+        return Err(DeserializeError::Custom(format!(
+            "Expected {:?} message, header says {:?}", T::MESSAGE_TYPE, msg_type
+        )));
+    }
+    from_bytes_with(&input[HEADER_LEN..], options)
+}
+
+// Checks the marker, that the header length matches the input and suits the
+// message type, returns the type octet.
+pub(crate) fn message_header(input: &[u8]) -> Result<MessageType> {
+    if input.len() < HEADER_LEN {
+        return Err(DeserializeError::UnexpectedEof { needed: HEADER_LEN, available: input.len(), offset: 0 });
+    }
+    if input[..16] != MARKER {
+        return Err(DeserializeError::Custom(String::from("Message header marker is not all ones")));
+    }
+    let len = u16::from_be_bytes([input[16], input[17]]) as usize;
+    if len != input.len() {
+        return Err(DeserializeError::LengthMismatch { declared: len, actual: input.len(), offset: 0 });
+    }
+    let msg_type = MessageType::from(input[18]);
+    check_type_length(msg_type, len).map_err(DeserializeError::Invalid)?;
+    Ok(msg_type)
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn from_bytes(input: &'de [u8]) -> Self {
+        Self::with_options(input, DeserializerOptions::default())
+    }
+
+    pub fn with_options(input: &'de [u8], options: DeserializerOptions) -> Self {
+        Deserializer {
+            input,
+            pos: 0,
+            limits: Vec::new(),
+            options,
+            multicast_nlri: false,
+            tag_width: None,
+            path: Vec::new(),
+        }
+    }
+
+    pub fn options(&self) -> DeserializerOptions {
+        self.options
+    }
+
+    // Points the Deserializer at the next input, keeping its options and the
+    // allocations behind its scope and path stacks, so decoding message after
+    // message out of one buffer doesn't set up a new one each time. Whatever
+    // was left of the previous input is dropped.
+    pub fn reset(&mut self, input: &'de [u8]) {
+        self.input = input;
+        self.pos = 0;
+        self.limits.clear();
+        self.path.clear();
+        self.multicast_nlri = false;
+        self.tag_width = None;
+    }
+
+    // reset for an input that doesn't live as long as the previous one,
+    // e.g. each read into a buffer that is then reused.
+    pub fn reuse<'a>(self, input: &'a [u8]) -> Deserializer<'a> {
+        let Deserializer { pos, limits, options, multicast_nlri, tag_width, path, .. } = self;
+        let mut deserializer = Deserializer { input, pos, limits, options, multicast_nlri, tag_width, path };
+        deserializer.reset(input);
+        deserializer
+    }
+
+    // Whether the whole input was used, as from_bytes requires.
+    pub fn end(&self) -> Result<()> {
+        let len = self.input.len();
+        match self.pos == len {
+            true => Ok(()),
+            false => Err(DeserializeError::TrailingBytes { unused: len - self.pos, len }),
+        }
+    }
+
+    // Formats the path stack, like "UpdateMessage.path_attributes[2].len".
+    fn current_path(&self) -> String {
+        let mut out = String::new();
+        for segment in &self.path {
+            match segment {
+                Segment::Index(i) => out.push_str(&format!("[{}]", i)),
+                Segment::Type(name) | Segment::Field(name) => {
+                    if !out.is_empty() {
+                        out.push('.');
+                    }
+                    out.push_str(name);
+                },
+                Segment::Position(i) => {
+                    if !out.is_empty() {
+                        out.push('.');
+                    }
+                    out.push_str(&i.to_string());
+                },
+            }
+        }
+        out
+    }
+
+    // Decodes under the given path segment. The first error out of it gets
+    // the segment's offset and the full path attached; errors that already
+    // have them are passed on as they are.
+    fn nested<T, F>(&mut self, segment: Segment, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let start = self.pos;
+        self.path.push(segment);
+        let value = f(self).map_err(|e| match e {
+            e @ DeserializeError::At { .. } => e,
+            e => DeserializeError::At { offset: start, path: self.current_path(), error: Box::new(e) },
+        });
+        self.path.pop();
+        value
+    }
+
+    // The root type names the path; nested types are named by their field.
+    fn enter_type(&mut self, name: &'static str) {
+        if self.path.is_empty() {
+            self.path.push(Segment::Type(name));
+        }
+    }
+
+    // Saves the read position, so a decode that turns out to be the wrong
+    // reading of the input (legacy vs current encodings, say) can be undone
+    // with restore and another one tried.
+    pub fn checkpoint(&self) -> ReadCheckpoint {
+        ReadCheckpoint {
+            pos: self.pos,
+            limits: self.limits.len(),
+            path: self.path.len(),
+            scope: self.limits.last().copied(),
+        }
+    }
+
+    // Goes back to `checkpoint`. It has to have been taken in the same
+    // length scope: one taken inside a with_limit that has since ended, or
+    // outside the one being decoded, can't be restored.
+    pub fn restore(&mut self, checkpoint: ReadCheckpoint) -> Result<()> {
+        let same_scope = checkpoint.limits == self.limits.len() && checkpoint.scope == self.limits.last().copied();
+        if !same_scope || checkpoint.pos > self.limit() {
+            return Err(DeserializeError::Custom(String::from("Checkpoint restored outside the scope it was taken in")));
+        }
+        self.pos = checkpoint.pos;
+        self.path.truncate(checkpoint.path);
+        Ok(())
+    }
+
+    // End of the current length scope.
+    fn limit(&self) -> usize {
+        self.limits.last().map_or(self.input.len(), |&(_, end)| end)
+    }
+
+    // Octets left before the end of the current limit.
+    pub fn remaining(&self) -> usize {
+        self.limit() - self.pos
+    }
+
+    // Octets consumed so far, from the start of the input rather than of the
+    // current scope; the offset errors report.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'de [u8]> {
+        if self.remaining() < n {
+            return Err(DeserializeError::UnexpectedEof { needed: n, available: self.remaining(), offset: self.pos });
+        }
+        let bytes = &self.input[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    // The next octet(s), for dispatching on a type code before picking what
+    // to decode. Nothing is consumed.
+    pub fn peek_u8(&self) -> Result<u8> {
+        self.peek_array().map(u8::from_be_bytes)
+    }
+
+    pub fn peek_u16(&self) -> Result<u16> {
+        self.peek_array().map(u16::from_be_bytes)
+    }
+
+    fn peek_array<const N: usize>(&self) -> Result<[u8; N]> {
+        let bytes = self.input[self.pos..self.limit()].get(..N).ok_or(DeserializeError::UnexpectedEof {
+            needed: N,
+            available: self.remaining(),
+            offset: self.pos,
+        })?;
+        let mut out = [0u8; N];
+        out.copy_from_slice(bytes);
+        Ok(out)
+    }
+
+    // Passes over `n` octets without decoding them.
+    pub fn skip(&mut self, n: usize) -> Result<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    // Runs `f` with the input limited to the next `len` octets, for decoding
+    // by hand when the length that bounds a section isn't right in front of
+    // it (LengthPrefixed covers the case where it is). Sequences inside stop
+    // at the limit rather than the end of the input, and `f` must use up all
+    // `len` octets.
+    pub fn with_limit<T, F>(&mut self, len: usize, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if len > self.remaining() {
+            return Err(DeserializeError::LengthMismatch { declared: len, actual: self.remaining(), offset: self.pos });
+        }
+        let start = self.pos;
+        let end = start + len;
+        self.limits.push((start, end));
+        let value = f(self);
+        self.limits.pop();
+        let value = value?;
+        if self.pos != end {
+            return Err(DeserializeError::LengthMismatch { declared: len, actual: self.pos - start, offset: start });
+        }
+        Ok(value)
+    }
+
+    // Decodes the single field of a Padded within the next `width` octets,
+    // then checks the rest of them are zero and skips them.
+    fn deserialize_padded<V>(&mut self, width: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if width > self.remaining() {
+            return Err(DeserializeError::UnexpectedEof { needed: width, available: self.remaining(), offset: self.pos });
+        }
+        let end = self.pos + width;
+        self.limits.push((self.pos, end));
+        let value = visitor.visit_seq(Elements { de: &mut *self, left: Some(1), fields: None, next: 0 });
+        self.limits.pop();
+        let value = value?;
+        if let Some(i) = self.input[self.pos..end].iter().position(|&b| b != 0) {
+            return Err(DeserializeError::NonZeroPadding { offset: self.pos + i });
+        }
+        self.pos = end;
+        Ok(value)
+    }
+
+    // Reads a big-endian length of the given width, then decodes the value
+    // restricted to exactly that many octets.
+    fn deserialize_length_prefixed<V>(&mut self, width: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.nested(Segment::Field("len"), |de| de.take(width))?;
+        let len = len.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        self.with_limit(len, |de| visitor.visit_newtype_struct(de))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = DeserializeError;
+
+    // The wire format carries no type information.
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("self-describing input")))
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.take(1)?[0] {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            other => Err(DeserializeError::InvalidEnumDiscriminant {
+                type_name: "bool",
+                value: other as u64,
+                offset: self.pos - 1,
+            }),
+        }
+    }
+
+    // BGP4 doesn't support signed integers
+    fn deserialize_i8<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("signed ints")))
+    }
+
+    fn deserialize_i16<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("signed ints")))
+    }
+
+    fn deserialize_i32<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("signed ints")))
+    }
+
+    fn deserialize_i64<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("signed ints")))
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.take(1)?[0])
+    }
+
+    // Multi-byte values are in network byte order.
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(u16::from_be_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(u32::from_be_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(u64::from_be_bytes(self.take_array()?))
+    }
+
+    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("floats")))
+    }
+
+    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("floats")))
+    }
+
+    fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("text types")))
+    }
+
+    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("text types")))
+    }
+
+    fn deserialize_string<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("text types")))
+    }
+
+    // Byte strings carry no length of their own, they take the rest of
+    // the current scope. Borrowed for &[u8], copied out for Vec<u8> and
+    // Bytes.
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let n = self.remaining();
+        visitor.visit_borrowed_bytes(self.take(n)?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let n = self.remaining();
+        visitor.visit_byte_buf(self.take(n)?.to_vec())
+    }
+
+    // None serializes to nothing, so an exhausted scope means None.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.remaining() == 0 {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(width) = length_prefix_width(name) {
+            return self.deserialize_length_prefixed(width, visitor);
+        }
+        if name == ASN {
+            return match self.options.four_octet_asn {
+                true => visitor.visit_u32(u32::from_be_bytes(self.take_array()?)),
+                false => visitor.visit_u32(u16::from_be_bytes(self.take_array()?) as u32),
+            };
+        }
+        if name == UNKNOWN_VALUE {
+            return match self.options.on_unknown {
+                OnUnknown::Preserve => visitor.visit_newtype_struct(self),
+                OnUnknown::Skip => {
+                    self.pos = self.limit();
+                    visitor.visit_none()
+                },
+                OnUnknown::Error => Err(DeserializeError::Custom(format!(
+                    "Unrecognized {}", &visitor as &dyn de::Expected
+                ))),
+            };
+        }
+        if let Some((afi, safi)) = path_id_family(name, self.multicast_nlri) {
+            return match self.options.add_path.contains(afi, safi) {
+                true => visitor.visit_newtype_struct(self),
+                false => visitor.visit_none(),
+            };
+        }
+        if let Some(width) = tag_width(name) {
+            let saved = self.tag_width;
+            self.tag_width = Some(width);
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.tag_width = saved;
+            return value;
+        }
+        if name == MULTICAST_NLRI {
+            let saved = self.multicast_nlri;
+            self.multicast_nlri = true;
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.multicast_nlri = saved;
+            return value;
+        }
+        if let Some(max) = max_prefix_len(name) {
+            if let Some(&len) = self.input[..self.limit()].get(self.pos) {
+                if len > max || (len as usize).div_ceil(8) >= self.remaining() {
+                    return Err(DeserializeError::Invalid(ValidationError::InvalidNetworkField(len)));
+                }
+            }
+            return visitor.visit_newtype_struct(self);
+        }
+        if name == ATTRIBUTE_FLAGS {
+            let flags = self.input.get(self.pos).copied().unwrap_or(0);
+            if flags & ATTR_RESERVED != 0 && self.options.reserved_flags == ReservedFlags::Reject {
+                return Err(DeserializeError::Invalid(ValidationError::AttributeFlags(flags)));
+            }
+            return visitor.visit_newtype_struct(self);
+        }
+        if name == ATTRIBUTE_LIST {
+            if !attribute_list_fits(&self.input[self.pos..self.limit()]) {
+                return Err(DeserializeError::Invalid(ValidationError::MalformedAttributeList));
+            }
+            return visitor.visit_newtype_struct(self);
+        }
+        if name == FOUR_OCTET_ASN {
+            let saved = self.options.four_octet_asn;
+            self.options.four_octet_asn = true;
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.options.four_octet_asn = saved;
+            return value;
+        }
+        if name == TAKE_REST {
+            let len = self.remaining();
+            return self.with_limit(len, |de| visitor.visit_newtype_struct(de));
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Sequences have no count on the wire, they run to the end of the scope.
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Elements { de: self, left: None, fields: None, next: 0 })
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Elements { de: self, left: Some(len), fields: None, next: 0 })
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == PADDED {
+            return self.deserialize_padded(len, visitor);
+        }
+        self.enter_type(name);
+        visitor.visit_seq(Elements { de: self, left: Some(len), fields: None, next: 0 })
+    }
+
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("maps")))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.enter_type(name);
+        visitor.visit_seq(Elements { de: self, left: Some(fields.len()), fields: Some(fields), next: 0 })
+    }
+
+    // Only inside a TaggedEnum, where a type code picks the variant. Anywhere
+    // else serde's variants aren't on the wire, so there is nothing to go by.
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // Taken so an enum inside the variant doesn't use it too.
+        let width = match self.tag_width.take() {
+            Some(width) => width,
+            None => return Err(DeserializeError::Unsupported(format!("enum \"{}\"", name))),
+        };
+        let offset = self.pos;
+        let tag = self.take(width)?.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        let index = (0..variants.len() as u32)
+            .find(|&i| variant_code(variants[i as usize], i) == tag)
+            .ok_or(DeserializeError::InvalidEnumDiscriminant { type_name: name, value: tag, offset })?;
+        visitor.visit_enum(Variant { de: self, index, name: variants[index as usize] })
+    }
+
+    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("self-describing input")))
+    }
+
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(DeserializeError::Unsupported(String::from("self-describing input")))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Gives serde the elements of a sequence, tuple or struct. `left` is the
+// fixed element count, or None for a sequence that ends with its scope.
+// `fields` names the elements of a struct, `next` counts them for the path.
+// The count handed out as a size hint is capped by what is left of the
+// input, so a tuple length picked to cover the largest value a Deserialize
+// impl could meet doesn't make serde preallocate for all of it.
+struct Elements<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    left: Option<usize>,
+    fields: Option<&'static [&'static str]>,
+    next: usize,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.left {
+            Some(0) => return Ok(None),
+            Some(ref mut n) => *n -= 1,
+            None if self.de.remaining() == 0 => return Ok(None),
+            None => {},
+        }
+        let segment = match (self.fields, self.left) {
+            (Some(fields), _) => fields.get(self.next).map_or(Segment::Position(self.next), |f| Segment::Field(f)),
+            (None, Some(_)) => Segment::Position(self.next),
+            (None, None) => Segment::Index(self.next),
+        };
+        self.next += 1;
+        let start = self.de.pos;
+        let value = self.de.nested(segment, |de| seed.deserialize(de))?;
+        // One that took nothing would be decoded again, forever.
+        if self.left.is_none() && self.de.pos == start {
+            return Err(DeserializeError::Unsupported(String::from("sequences of zero-width elements")));
+        }
+        Ok(Some(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.left.map(|n| n.min(self.de.remaining()))
+    }
+}
+
+// The variant picked by deserialize_enum. serde is handed its index.
+struct Variant<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    index: u32,
+    name: &'static str,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant<'_, 'de> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(self.index.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.de.nested(Segment::Field(self.name), |de| seed.deserialize(de))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.nested(Segment::Field(self.name), |de| {
+            visitor.visit_seq(Elements { de, left: Some(len), fields: None, next: 0 })
+        })
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.nested(Segment::Field(self.name), |de| {
+            visitor.visit_seq(Elements { de, left: Some(fields.len()), fields: Some(fields), next: 0 })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_bytes, SerializerError};
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Header {
+        marker: [u8; 16],
+        length: u16,
+        msg_type: u8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Open {
+        version: u8,
+        my_as: u16,
+        hold_time: u16,
+        bgp_id: u32,
+        opt_params: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wide(u64, bool, Option<u32>);
+
+    #[test]
+    fn test_round_trip_header() {
+        let header = Header { marker: [0xff; 16], length: 19, msg_type: 4 };
+        let bytes = to_bytes(&header).unwrap();
+        assert_eq!(from_bytes::<Header>(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn test_round_trip_trailing_seq() {
+        let open = Open {
+            version: 4,
+            my_as: 65001,
+            hold_time: 90,
+            bgp_id: 0xc0000201,
+            opt_params: vec![2, 6, 1, 4, 0, 1, 0, 1],
+        };
+        let bytes = to_bytes(&open).unwrap();
+        assert_eq!(&bytes[..10], &[4, 0xfd, 0xe9, 0, 90, 192, 0, 2, 1, 2]);
+        assert_eq!(from_bytes::<Open>(&bytes).unwrap(), open);
+    }
+
+    #[test]
+    fn test_round_trip_option() {
+        let some = Wide(u64::MAX - 1, true, Some(7));
+        let none = Wide(1, false, None);
+        assert_eq!(from_bytes::<Wide>(&to_bytes(&some).unwrap()).unwrap(), some);
+        assert_eq!(from_bytes::<Wide>(&to_bytes(&none).unwrap()).unwrap(), none);
+    }
+
+    #[test]
+    fn test_borrowed_bytes() {
+        #[derive(Deserialize)]
+        struct Raw<'a> {
+            code: u8,
+            data: &'a [u8],
+        }
+        let input = [9u8, 1, 2, 3];
+        let raw: Raw = from_bytes(&input).unwrap();
+        assert_eq!(raw.code, 9);
+        assert_eq!(raw.data, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_owned_bytes() {
+        // Header decoded, rest kept opaque.
+        #[derive(Deserialize)]
+        struct Passthrough {
+            code: u8,
+            rest: bytes::Bytes,
+        }
+        let input = [9u8, 1, 2, 3];
+        let msg: Passthrough = from_bytes(&input).unwrap();
+        assert_eq!(msg.code, 9);
+        assert_eq!(msg.rest.as_ref(), &[1, 2, 3]);
+        assert!(from_bytes::<Passthrough>(&input[..1]).unwrap().rest.is_empty());
+    }
+
+    #[test]
+    fn test_with_limit() {
+        // Section length up front, then two sections it doesn't cover.
+        let input = [0, 3, 1, 2, 3, 0xaa, 0xbb];
+        let mut de = Deserializer::from_bytes(&input);
+        let len = u16::deserialize(&mut de).unwrap() as usize;
+        let section: Vec<u8> = de.with_limit(len, |de| Vec::deserialize(de)).unwrap();
+        assert_eq!(section, vec![1, 2, 3]);
+        assert_eq!(de.remaining(), 2);
+        assert_eq!(u16::deserialize(&mut de).unwrap(), 0xaabb);
+
+        let mut de = Deserializer::from_bytes(&input);
+        match de.with_limit(3, |de| u8::deserialize(de)) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e, DeserializeError::LengthMismatch { declared: 3, actual: 1, offset: 0 })),
+        }
+        assert!(matches!(de.with_limit(8, |de| u8::deserialize(de)), Err(DeserializeError::LengthMismatch { .. })));
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        // Either a 4 octet AS number, or a 2 octet one followed by a count
+        let input = [0xfd, 0xe9, 2, 0xaa, 0xbb];
+        let mut de = Deserializer::from_bytes(&input);
+        let cp = de.checkpoint();
+        let wide = <(u32, Vec<u8>)>::deserialize(&mut de).unwrap();
+        assert_eq!(wide, (0xfde902aa, vec![0xbb]));
+        de.restore(cp).unwrap();
+        let (asn, count) = <(u16, u8)>::deserialize(&mut de).unwrap();
+        assert_eq!((asn, count), (65001, 2));
+        assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), vec![0xaa, 0xbb]);
+
+        // After a failed attempt too
+        let mut de = Deserializer::from_bytes(&input[..3]);
+        let cp = de.checkpoint();
+        assert!(u32::deserialize(&mut de).is_err());
+        de.restore(cp).unwrap();
+        assert_eq!(<(u16, u8)>::deserialize(&mut de).unwrap(), (65001, 2));
+
+        // Not from inside a scope that has ended
+        let mut de = Deserializer::from_bytes(&input);
+        let inner = de.with_limit(2, |de| Ok((de.checkpoint(), u16::deserialize(de)?))).unwrap().0;
+        assert!(de.restore(inner).is_err());
+        let outer = de.checkpoint();
+        assert!(de.with_limit(1, |de| de.restore(outer)).is_err());
+
+        // Nor from a sibling scope at the same depth
+        let mut de = Deserializer::from_bytes(&input);
+        let first = de.with_limit(2, |de| Ok((de.checkpoint(), u16::deserialize(de)?))).unwrap().0;
+        let restored = de.with_limit(3, |de| {
+            let restored = de.restore(first);
+            <(u8, u16)>::deserialize(&mut *de)?;
+            Ok(restored)
+        });
+        assert!(restored.unwrap().is_err());
+        assert_eq!(de.position(), 5);
+    }
+
+    #[test]
+    fn test_position() {
+        // Withdrawn length, withdrawn routes, then NLRI to the end
+        let input = [0, 2, 8, 10, 16, 172, 16];
+        let mut de = Deserializer::from_bytes(&input);
+        let len = u16::deserialize(&mut de).unwrap() as usize;
+        assert_eq!((de.position(), de.remaining()), (2, 5));
+        de.with_limit(len, |de| {
+            assert_eq!((de.position(), de.remaining()), (2, 2));
+            de.skip(2)
+        })
+        .unwrap();
+        assert_eq!((de.position(), de.remaining()), (4, 3));
+        let start = de.position();
+        let nlri = Vec::<u8>::deserialize(&mut de).unwrap();
+        assert_eq!(de.position() - start, nlri.len());
+        assert_eq!(de.remaining(), 0);
+    }
+
+    #[test]
+    fn test_reset() {
+        // Three records out of one buffer, the middle one truncated
+        let input = [0, 2, 0xaa, 0xbb, 0, 3, 0xcc, 0, 1, 0xdd];
+        let mut de = Deserializer::from_bytes(&input[..4]);
+        let record = |de: &mut Deserializer| {
+            crate::types::LengthPrefixed::<u16, Vec<u8>>::deserialize(de).map(|r| r.value)
+        };
+        assert_eq!(record(&mut de).unwrap(), vec![0xaa, 0xbb]);
+        de.end().unwrap();
+        de.reset(&input[4..7]);
+        match record(&mut de) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e, DeserializeError::LengthMismatch { declared: 3, actual: 1, .. }), "{}", e),
+        }
+        de.reset(&input[7..]);
+        assert_eq!((de.position(), de.remaining()), (0, 3));
+        assert_eq!(record(&mut de).unwrap(), vec![0xdd]);
+
+        // Into a shorter-lived input, keeping the options
+        let options = DeserializerOptions { four_octet_asn: false, ..Default::default() };
+        let de = Deserializer::with_options(&input, options);
+        let buf = vec![0, 0, 1];
+        let mut de = de.reuse(&buf);
+        assert!(!de.options().four_octet_asn);
+        assert_eq!(record(&mut de).unwrap(), Vec::<u8>::new());
+        assert!(matches!(de.end(), Err(DeserializeError::TrailingBytes { unused: 1, len: 3 })));
+    }
+
+    #[test]
+    fn test_peek_skip() {
+        // Type code, then a value whose shape depends on it
+        let input = [0, 2, 0xaa, 1, 0xfd, 0xe9];
+        let mut de = Deserializer::from_bytes(&input);
+        assert_eq!(de.peek_u16().unwrap(), 2);
+        assert_eq!(de.peek_u8().unwrap(), 0);
+        assert_eq!(de.remaining(), 6);
+        de.skip(3).unwrap();
+        assert_eq!(de.peek_u8().unwrap(), 1);
+        assert_eq!(<(u8, u16)>::deserialize(&mut de).unwrap(), (1, 65001));
+
+        // Peeking stops at the scope, like reading does
+        let mut de = Deserializer::from_bytes(&input);
+        let peeked = de.with_limit(1, |de| {
+            let peeked = de.peek_u16();
+            de.skip(1)?;
+            Ok(peeked)
+        });
+        assert_eq!(
+            peeked.unwrap(),
+            Err(DeserializeError::UnexpectedEof { needed: 2, available: 1, offset: 0 })
+        );
+        assert!(matches!(de.skip(6), Err(DeserializeError::UnexpectedEof { needed: 6, available: 5, offset: 1 })));
+    }
+
+    #[test]
+    fn test_err_eof() {
+        match from_bytes::<Header>(&[0xff; 17]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(
+                    e.to_string(),
+                    "Unexpected end of input. Error info - needed 2 octets at offset 16, 1 available. \
+                     (at offset 16 while decoding Header.length)"
+                );
+                assert_eq!(e.path(), Some("Header.length"))
+            },
+        }
+    }
+
+    #[test]
+    fn test_err_trailing() {
+        match from_bytes::<u16>(&[0, 1, 2]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.to_string(), "Trailing bytes after deserialization. Error info - 1 of 3 octets unused.");
+                // Same message through the old error type
+                assert_eq!(SerializerError::from(e.clone()).to_string(), e.to_string());
+            },
+        }
+    }
+
+    #[test]
+    fn test_hostile_input() {
+        // Elements that take no octets would never reach the end of the scope.
+        match from_bytes::<Vec<()>>(&[1]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e.inner(), DeserializeError::Unsupported(_)), "{}", e),
+        }
+
+        struct SizeHint;
+        impl<'de> Visitor<'de> for SizeHint {
+            type Value = Option<usize>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("anything")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> std::result::Result<Self::Value, A::Error> {
+                Ok(seq.size_hint())
+            }
+        }
+        let mut de = Deserializer::from_bytes(&[1, 2, 3]);
+        assert_eq!(de::Deserializer::deserialize_tuple(&mut de, usize::MAX, SizeHint).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_err_unsupported() {
+        assert!(matches!(from_bytes::<i32>(&[0; 4]), Err(DeserializeError::Unsupported(_))));
+        assert!(matches!(from_bytes::<String>(&[0; 4]), Err(DeserializeError::Unsupported(_))));
+        assert_eq!(
+            from_bytes::<bool>(&[2]),
+            Err(DeserializeError::InvalidEnumDiscriminant { type_name: "bool", value: 2, offset: 0 })
+        );
+    }
+}
@@ -1,10 +1,13 @@
-// Defines the errors used by both Serializer and Deserializer
+// Defines the errors used by the Serializer and Deserializer
 
-use std;
 use std::fmt::{self, Display};
 
 use serde::{de, ser};
 
+use crate::messages::{MessageType, NotificationMessage, MAX_MESSAGE_LEN};
+use crate::notification::{MessageHeaderError, NotificationCode, OpenMessageError, UpdateMessageError};
+use crate::validate::{validate_header_with, ValidationError};
+
 pub type Result<T> = std::result::Result<T, SerializerError>;
 
 #[derive(Debug)]
@@ -16,7 +19,17 @@ pub enum SerializerError {
     UnsupportedSignedInt(Option<String>),
     UnsupportedFloat(Option<String>),
     UnsupportedMap(Option<String>),
-    UnsupportedText(Option<String>)
+    UnsupportedText(Option<String>),
+    UnsupportedEnum(Option<String>),
+    UnsupportedAny(Option<String>),
+    UnexpectedEof(Option<String>),
+    TrailingBytes(Option<String>),
+    LengthOverflow(Option<String>),
+    LengthMismatch(Option<String>),
+    // The message came out longer than the session allows (4096 octets, or
+    // 65535 once Extended Messages are negotiated).
+    MessageTooLarge { limit: usize, attempted: usize },
+    Io(std::io::Error)
 }
 
 impl std::error::Error for SerializerError {}
@@ -49,10 +62,263 @@ impl Display for SerializerError {
             SerializerError::UnsupportedText(None) => {
                 f.write_str("Serialization of text types unsupported.")
             },
-            _ => f.write_str("Undefined metadata.")
+            SerializerError::UnsupportedEnum(Some(msg)) => {
+                f.write_str(&format!("Deserialization of enums unsupported. Error info - {}.", msg))
+            },
+            SerializerError::UnsupportedEnum(None) => {
+                f.write_str("Deserialization of enums unsupported.")
+            },
+            SerializerError::UnsupportedAny(Some(msg)) => {
+                f.write_str(&format!("Format is not self-describing, type must be known. Error info - {}.", msg))
+            },
+            SerializerError::UnsupportedAny(None) => {
+                f.write_str("Format is not self-describing, type must be known.")
+            },
+            SerializerError::UnexpectedEof(Some(msg)) => {
+                f.write_str(&format!("Unexpected end of input. Error info - {}.", msg))
+            },
+            SerializerError::UnexpectedEof(None) => {
+                f.write_str("Unexpected end of input.")
+            },
+            SerializerError::TrailingBytes(Some(msg)) => {
+                f.write_str(&format!("Trailing bytes after deserialization. Error info - {}.", msg))
+            },
+            SerializerError::TrailingBytes(None) => {
+                f.write_str("Trailing bytes after deserialization.")
+            },
+            SerializerError::LengthOverflow(Some(msg)) => {
+                f.write_str(&format!("Value too long for its length field. Error info - {}.", msg))
+            },
+            SerializerError::LengthOverflow(None) => {
+                f.write_str("Value too long for its length field.")
+            },
+            SerializerError::LengthMismatch(Some(msg)) => {
+                f.write_str(&format!("Length field does not match the value. Error info - {}.", msg))
+            },
+            SerializerError::LengthMismatch(None) => {
+                f.write_str("Length field does not match the value.")
+            },
+            SerializerError::MessageTooLarge { limit, attempted } => {
+                f.write_str(&format!("Message is {} octets, the limit is {}.", attempted, limit))
+            },
+            SerializerError::Io(e) => {
+                f.write_str(&format!("I/O error - {}.", e))
+            },
+        }
+    }
+}
+
+impl SerializerError {
+    // The NOTIFICATION to answer a message that failed to decode with, given
+    // the whole message as received. See DeserializeError::to_notification.
+    pub fn to_notification(&self, message: &[u8]) -> Option<NotificationMessage> {
+        self.to_notification_with(message, MAX_MESSAGE_LEN)
+    }
+
+    // to_notification for a session that negotiated Extended Messages.
+    pub fn to_notification_with(&self, message: &[u8], max_message_len: usize) -> Option<NotificationMessage> {
+        let length_error = matches!(
+            self,
+            SerializerError::UnexpectedEof(_) | SerializerError::TrailingBytes(_) | SerializerError::LengthMismatch(_)
+        );
+        decode_notification(message, max_message_len, length_error, None)
+    }
+}
+
+// Errors from the Deserializer. Offsets count from the start of the input
+// handed to it, so for the body of a message they are 19 short of the
+// offset in the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeError {
+    // From de::Error::custom, mostly Deserialize impls rejecting a value.
+    Custom(String),
+    UnexpectedEof { needed: usize, available: usize, offset: usize },
+    // Input left over once the value was decoded.
+    TrailingBytes { unused: usize, len: usize },
+    // The length field for the value at `offset` says `declared` octets, but
+    // `actual` were there (or, for a value that didn't use them all, used).
+    LengthMismatch { declared: usize, actual: usize, offset: usize },
+    InvalidEnumDiscriminant { type_name: &'static str, value: u64, offset: usize },
+    // Padding after a Padded value with a non-zero octet at `offset`.
+    NonZeroPadding { offset: usize },
+    // A serde data model type the wire format has no encoding for.
+    Unsupported(String),
+    // Octets that decode but break a rule RFC 4271 6 has a NOTIFICATION
+    // for, like a KEEPALIVE that isn't exactly 19 octets.
+    Invalid(ValidationError),
+    // Where `error` happened: the offset the innermost element being decoded
+    // started at, and its path from the root type.
+    At { offset: usize, path: String, error: Box<DeserializeError> },
+}
+
+impl std::error::Error for DeserializeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeserializeError::At { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserializeError::Custom(msg) => f.write_str(msg),
+            DeserializeError::UnexpectedEof { needed, available, offset } => write!(
+                f,
+                "Unexpected end of input. Error info - needed {} octets at offset {}, {} available.",
+                needed, offset, available
+            ),
+            DeserializeError::TrailingBytes { unused, len } => write!(
+                f,
+                "Trailing bytes after deserialization. Error info - {} of {} octets unused.",
+                unused, len
+            ),
+            DeserializeError::LengthMismatch { declared, actual, offset } => write!(
+                f,
+                "Length field does not match the value. Error info - length says {} octets for the value at offset {}, found {}.",
+                declared, offset, actual
+            ),
+            DeserializeError::InvalidEnumDiscriminant { type_name, value, offset } => {
+                write!(f, "Invalid {} value {} at offset {}.", type_name, value, offset)
+            },
+            DeserializeError::NonZeroPadding { offset } => write!(f, "Non-zero padding octet at offset {}.", offset),
+            DeserializeError::Unsupported(what) => write!(f, "Deserialization of {} unsupported.", what),
+            DeserializeError::Invalid(e) => write!(f, "{}.", e),
+            DeserializeError::At { offset, path, error } => {
+                write!(f, "{} (at offset {} while decoding {})", error, offset, path)
+            },
         }
     }
 }
+
+impl DeserializeError {
+    // The error itself, without the location.
+    pub fn inner(&self) -> &DeserializeError {
+        match self {
+            DeserializeError::At { error, .. } => error.inner(),
+            e => e,
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            DeserializeError::At { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    // The NOTIFICATION to answer a message that failed to decode with, given
+    // the whole message as received. Header problems get their own subcode
+    // and data from validate_header, Invalid errors the ones they map to;
+    // otherwise the error only says which message was bad, so the subcode is
+    // the message's generic one. None for a bad NOTIFICATION, which is never
+    // answered (RFC 4271 6.4).
+    pub fn to_notification(&self, message: &[u8]) -> Option<NotificationMessage> {
+        self.to_notification_with(message, MAX_MESSAGE_LEN)
+    }
+
+    // to_notification for a session that negotiated Extended Messages, with
+    // its DeserializerOptions::max_message_len. Otherwise a message over
+    // 4096 octets is answered with Bad Message Length whatever its error.
+    pub fn to_notification_with(&self, message: &[u8], max_message_len: usize) -> Option<NotificationMessage> {
+        let length_error = matches!(
+            self.inner(),
+            DeserializeError::UnexpectedEof { .. }
+                | DeserializeError::TrailingBytes { .. }
+                | DeserializeError::LengthMismatch { .. }
+        );
+        let invalid = match self.inner() {
+            DeserializeError::Invalid(e) => Some(e),
+            _ => None,
+        };
+        decode_notification(message, max_message_len, length_error, invalid)
+    }
+}
+
+fn decode_notification(
+    message: &[u8],
+    max_message_len: usize,
+    length_error: bool,
+    invalid: Option<&ValidationError>,
+) -> Option<NotificationMessage> {
+    if let Err(e) = validate_header_with(message, max_message_len) {
+        return Some(e.to_notification());
+    }
+    let msg_type = MessageType::from(message[18]);
+    match invalid {
+        Some(e) if msg_type != MessageType::Notification => return Some(e.to_notification()),
+        _ => {},
+    }
+    let code = match msg_type {
+        MessageType::Open => NotificationCode::OpenMessage(OpenMessageError::Unspecific),
+        // The withdrawn routes or attribute lengths don't add up
+        MessageType::Update if length_error => {
+            NotificationCode::UpdateMessage(UpdateMessageError::MalformedAttributeList)
+        },
+        MessageType::Update => NotificationCode::UpdateMessage(UpdateMessageError::Unspecific),
+        MessageType::Notification => return None,
+        // ROUTE-REFRESH Message Error / Invalid Message Length (RFC 7313 5),
+        // with the whole message as data.
+        MessageType::RouteRefresh => {
+            let code = NotificationCode::Unknown { code: 7, subcode: 1 };
+            return Some(NotificationMessage::new(code, message.to_vec()));
+        },
+        // The draft's Capability Message Error code was never assigned
+        MessageType::Capability => NotificationCode::MessageHeader(MessageHeaderError::Unspecific),
+        // Only when the message is cut short of its header length
+        MessageType::Keepalive | MessageType::Unknown(_) => {
+            NotificationCode::MessageHeader(MessageHeaderError::Unspecific)
+        },
+    };
+    Some(NotificationMessage::new(code, Vec::new()))
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        DeserializeError::Custom(msg.to_string())
+    }
+}
+
+// For callers still on the single error type.
+impl From<DeserializeError> for SerializerError {
+    fn from(e: DeserializeError) -> Self {
+        match e {
+            DeserializeError::Custom(msg) => SerializerError::CustomMsg(msg),
+            DeserializeError::UnexpectedEof { needed, available, offset } => SerializerError::UnexpectedEof(Some(
+                format!("needed {} octets at offset {}, {} available", needed, offset, available),
+            )),
+            DeserializeError::TrailingBytes { unused, len } => {
+                SerializerError::TrailingBytes(Some(format!("{} of {} octets unused", unused, len)))
+            },
+            DeserializeError::LengthMismatch { declared, actual, offset } => SerializerError::LengthMismatch(Some(
+                format!("length says {} octets for the value at offset {}, found {}", declared, offset, actual),
+            )),
+            e @ DeserializeError::InvalidEnumDiscriminant { .. } => SerializerError::CustomMsg(e.to_string()),
+            e @ DeserializeError::NonZeroPadding { .. } => SerializerError::CustomMsg(e.to_string()),
+            e @ DeserializeError::Unsupported(_) => SerializerError::CustomMsg(e.to_string()),
+            e @ DeserializeError::Invalid(_) => SerializerError::CustomMsg(e.to_string()),
+            DeserializeError::At { offset, path, error } => {
+                let at = format!("at offset {} while decoding {}", offset, path);
+                let info = |info: Option<String>| Some(info.map_or(at.clone(), |i| format!("{}, {}", i, at)));
+                match SerializerError::from(*error) {
+                    SerializerError::CustomMsg(msg) => SerializerError::CustomMsg(format!("{} ({})", msg, at)),
+                    SerializerError::UnexpectedEof(i) => SerializerError::UnexpectedEof(info(i)),
+                    SerializerError::TrailingBytes(i) => SerializerError::TrailingBytes(info(i)),
+                    SerializerError::LengthMismatch(i) => SerializerError::LengthMismatch(info(i)),
+                    other => other,
+                }
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for SerializerError {
+    fn from(e: std::io::Error) -> Self {
+        SerializerError::Io(e)
+    }
+}
+
 impl ser::Error for SerializerError {
     fn custom<T: Display>(msg: T) -> Self {
         SerializerError::CustomMsg(msg.to_string())
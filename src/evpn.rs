@@ -0,0 +1,406 @@
+// EVPN NLRI (RFC 7432 7, IP Prefix routes from RFC 9136).
+//
+// Each route is a 1 octet route type, a 1 octet length and the route. The
+// fixed parts are the RD, a 10 octet Ethernet Segment Identifier and a 4
+// octet Ethernet Tag; IP addresses come with their length in bits (0, 32 or
+// 128) and MPLS label fields are 3 octets (for VXLAN they hold the VNI).
+// IP Prefix routes carry no address length, the route length tells IPv4 (34)
+// from IPv6 (58). Route types the crate doesn't model keep their raw value.
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+use crate::types::{LengthPrefixed, LengthPrefixedSeed, U24};
+use crate::vpn::Rd;
+
+// Route types
+pub const EVPN_ETHERNET_AUTO_DISCOVERY: u8 = 1;
+pub const EVPN_MAC_IP_ADVERTISEMENT: u8 = 2;
+pub const EVPN_INCLUSIVE_MULTICAST: u8 = 3;
+pub const EVPN_ETHERNET_SEGMENT: u8 = 4;
+pub const EVPN_IP_PREFIX: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum EvpnRoute {
+    EthernetAutoDiscovery { rd: Rd, esi: Esi, ethernet_tag: u32, label: U24 },
+    MacIpAdvertisement {
+        rd: Rd,
+        esi: Esi,
+        ethernet_tag: u32,
+        mac: MacAddress,
+        ip: Option<IpAddr>,
+        label: U24,
+        // Second label, e.g. the L3 VNI of symmetric IRB
+        label2: Option<U24>,
+    },
+    InclusiveMulticast { rd: Rd, ethernet_tag: u32, originator: IpAddr },
+    EthernetSegment { rd: Rd, esi: Esi, originator: IpAddr },
+    // The gateway has the prefix's family; unspecified when there is none.
+    IpPrefix { rd: Rd, esi: Esi, ethernet_tag: u32, prefix: IpPrefix, gateway: IpAddr, label: U24 },
+    Unknown {
+        route_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// Ethernet Segment Identifier; the first octet is the ESI type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Esi(pub [u8; 10]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MacAddress(pub [u8; 6]);
+
+impl EvpnRoute {
+    pub fn route_type(&self) -> u8 {
+        match self {
+            EvpnRoute::EthernetAutoDiscovery { .. } => EVPN_ETHERNET_AUTO_DISCOVERY,
+            EvpnRoute::MacIpAdvertisement { .. } => EVPN_MAC_IP_ADVERTISEMENT,
+            EvpnRoute::InclusiveMulticast { .. } => EVPN_INCLUSIVE_MULTICAST,
+            EvpnRoute::EthernetSegment { .. } => EVPN_ETHERNET_SEGMENT,
+            EvpnRoute::IpPrefix { .. } => EVPN_IP_PREFIX,
+            EvpnRoute::Unknown { route_type, .. } => *route_type,
+        }
+    }
+
+    pub fn rd(&self) -> Option<Rd> {
+        match self {
+            EvpnRoute::EthernetAutoDiscovery { rd, .. }
+            | EvpnRoute::MacIpAdvertisement { rd, .. }
+            | EvpnRoute::InclusiveMulticast { rd, .. }
+            | EvpnRoute::EthernetSegment { rd, .. }
+            | EvpnRoute::IpPrefix { rd, .. } => Some(*rd),
+            EvpnRoute::Unknown { .. } => None,
+        }
+    }
+}
+
+impl Esi {
+    // Single-homed sites
+    pub const ZERO: Esi = Esi([0; 10]);
+
+    pub fn esi_type(&self) -> u8 {
+        self.0[0]
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+// -- Wire form --
+
+fn ip_octets(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    }
+}
+
+fn ip_from_octets(octets: &[u8]) -> Option<IpAddr> {
+    match octets.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?))),
+        _ => None,
+    }
+}
+
+// An address preceded by its length in bits; None is length 0.
+struct LengthIp(Option<IpAddr>);
+
+impl Serialize for LengthIp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let octets = self.0.as_ref().map(ip_octets).unwrap_or_default();
+        let mut tup = serializer.serialize_tuple(1 + octets.len())?;
+        tup.serialize_element(&(octets.len() as u8 * 8))?;
+        for b in &octets {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for LengthIp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct LengthIpVisitor;
+
+        impl<'de> Visitor<'de> for LengthIpVisitor {
+            type Value = LengthIp;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an IP address length and address")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let bits: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let n = match bits {
+                    0 => return Ok(LengthIp(None)),
+                    32 => 4,
+                    128 => 16,
+                    other => return Err(de::Error::custom(format!("Invalid EVPN IP address length {}", other))),
+                };
+                let mut octets = Vec::with_capacity(n);
+                for i in 0..n {
+                    octets.push(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i + 1, &self))?);
+                }
+                Ok(LengthIp(ip_from_octets(&octets)))
+            }
+        }
+
+        deserializer.deserialize_tuple(1 + 16, LengthIpVisitor)
+    }
+}
+
+// A fixed-size address, written without a length.
+struct BareIp<'a>(&'a IpAddr);
+
+impl Serialize for BareIp<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            IpAddr::V4(a) => a.serialize(serializer),
+            IpAddr::V6(a) => a.serialize(serializer),
+        }
+    }
+}
+
+struct RouteValue<'a>(&'a EvpnRoute);
+
+impl Serialize for RouteValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            EvpnRoute::EthernetAutoDiscovery { rd, esi, ethernet_tag, label } => {
+                (rd, esi, ethernet_tag, label).serialize(serializer)
+            },
+            EvpnRoute::MacIpAdvertisement { rd, esi, ethernet_tag, mac, ip, label, label2 } => {
+                (rd, esi, ethernet_tag, 48u8, mac, LengthIp(*ip), label, label2).serialize(serializer)
+            },
+            EvpnRoute::InclusiveMulticast { rd, ethernet_tag, originator } => {
+                (rd, ethernet_tag, LengthIp(Some(*originator))).serialize(serializer)
+            },
+            EvpnRoute::EthernetSegment { rd, esi, originator } => {
+                (rd, esi, LengthIp(Some(*originator))).serialize(serializer)
+            },
+            EvpnRoute::IpPrefix { rd, esi, ethernet_tag, prefix, gateway, label } => {
+                let same_family = matches!(
+                    (prefix, gateway),
+                    (IpPrefix::V4(_), IpAddr::V4(_)) | (IpPrefix::V6(_), IpAddr::V6(_))
+                );
+                if !same_family {
+                    return Err(serde::ser::Error::custom("EVPN IP Prefix route gateway and prefix families differ"));
+                }
+                let prefix_addr = BareIp(&prefix.addr());
+                let head = (rd, esi, ethernet_tag, prefix.prefix_len());
+                (head, prefix_addr, BareIp(gateway), label).serialize(serializer)
+            },
+            EvpnRoute::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for EvpnRoute {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let value: LengthPrefixed<u8, _> = LengthPrefixed::new(RouteValue(self));
+        (self.route_type(), value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EvpnRoute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RouteVisitor;
+
+        impl<'de> Visitor<'de> for RouteVisitor {
+            type Value = EvpnRoute;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an EVPN route")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let route_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let seed = LengthPrefixedSeed { width: 1, seed: RouteSeed(route_type) };
+                seq.next_element_seed(seed)?.ok_or_else(|| de::Error::invalid_length(1, &self))
+            }
+        }
+
+        deserializer.deserialize_tuple(2, RouteVisitor)
+    }
+}
+
+struct RouteSeed(u8);
+
+impl<'de> DeserializeSeed<'de> for RouteSeed {
+    type Value = EvpnRoute;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<EvpnRoute, D::Error> {
+        Ok(match self.0 {
+            EVPN_ETHERNET_AUTO_DISCOVERY => {
+                let (rd, esi, ethernet_tag, label) = Deserialize::deserialize(d)?;
+                EvpnRoute::EthernetAutoDiscovery { rd, esi, ethernet_tag, label }
+            },
+            EVPN_MAC_IP_ADVERTISEMENT => {
+                let (rd, esi, ethernet_tag, mac_len, mac, LengthIp(ip), label, label2): (_, _, _, u8, _, _, _, _) =
+                    Deserialize::deserialize(d)?;
+                if mac_len != 48 {
+                    return Err(de::Error::custom(format!("Invalid EVPN MAC address length {}", mac_len)));
+                }
+                EvpnRoute::MacIpAdvertisement { rd, esi, ethernet_tag, mac, ip, label, label2 }
+            },
+            EVPN_INCLUSIVE_MULTICAST => {
+                let (rd, ethernet_tag, LengthIp(originator)) = Deserialize::deserialize(d)?;
+                let originator = originator.ok_or_else(|| de::Error::custom("EVPN route without originator"))?;
+                EvpnRoute::InclusiveMulticast { rd, ethernet_tag, originator }
+            },
+            EVPN_ETHERNET_SEGMENT => {
+                let (rd, esi, LengthIp(originator)) = Deserialize::deserialize(d)?;
+                let originator = originator.ok_or_else(|| de::Error::custom("EVPN route without originator"))?;
+                EvpnRoute::EthernetSegment { rd, esi, originator }
+            },
+            EVPN_IP_PREFIX => {
+                let (rd, esi, ethernet_tag, len, rest): (_, _, _, u8, Bytes) = Deserialize::deserialize(d)?;
+                // Prefix and gateway, then the label
+                let n = match rest.len() {
+                    11 => 4,
+                    35 => 16,
+                    other => {
+                        return Err(de::Error::custom(format!("Invalid EVPN IP Prefix route length {}", other + 23)))
+                    },
+                };
+                let invalid = || de::Error::custom(format!("Invalid EVPN IP prefix length {}", len));
+                let prefix = match ip_from_octets(&rest[..n]) {
+                    Some(IpAddr::V4(a)) => Ipv4Prefix::new(a, len).map(IpPrefix::V4).ok_or_else(invalid)?,
+                    Some(IpAddr::V6(a)) => Ipv6Prefix::new(a, len).map(IpPrefix::V6).ok_or_else(invalid)?,
+                    None => return Err(invalid()),
+                };
+                let gateway = ip_from_octets(&rest[n..2 * n]).ok_or_else(invalid)?;
+                let label = U24::new(u32::from_be_bytes([0, rest[2 * n], rest[2 * n + 1], rest[2 * n + 2]]))
+                    .ok_or_else(invalid)?;
+                EvpnRoute::IpPrefix { rd, esi, ethernet_tag, prefix, gateway, label }
+            },
+            route_type => EvpnRoute::Unknown { route_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, Afi, MpReachNlri, NextHop, Nlri, Safi};
+
+    fn rd() -> Rd {
+        Rd::from_octets([0, 1, 0, 0, 0xfd, 0xe9, 0, 100])
+    }
+
+    fn esi() -> Esi {
+        Esi([0, 1, 2, 3, 4, 5, 6, 7, 8, 9])
+    }
+
+    #[test]
+    fn test_evpn_mac_ip_wire() {
+        let route = EvpnRoute::MacIpAdvertisement {
+            rd: rd(),
+            esi: Esi::ZERO,
+            ethernet_tag: 0,
+            mac: MacAddress([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            ip: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+            label: U24::new(10100).unwrap(),
+            label2: Some(U24::new(50000).unwrap()),
+        };
+        #[rustfmt::skip]
+        let wire: &[u8] = &[
+            2, 40,
+            0, 1, 0, 0, 0xfd, 0xe9, 0, 100,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0,
+            48, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55,
+            32, 10, 0, 0, 1,
+            0, 0x27, 0x74,
+            0, 0xc3, 0x50,
+        ];
+        assert_eq!(to_bytes(&route).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<EvpnRoute>(wire).unwrap(), route);
+        assert_eq!(MacAddress([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]).to_string(), "00:11:22:33:44:55");
+    }
+
+    #[test]
+    fn test_evpn_route_types() {
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let routes = vec![
+            EvpnRoute::EthernetAutoDiscovery { rd: rd(), esi: esi(), ethernet_tag: u32::MAX, label: U24::new(1).unwrap() },
+            EvpnRoute::MacIpAdvertisement {
+                rd: rd(),
+                esi: esi(),
+                ethernet_tag: 7,
+                mac: MacAddress([2, 0, 0, 0, 0, 1]),
+                ip: None,
+                label: U24::new(100).unwrap(),
+                label2: None,
+            },
+            EvpnRoute::InclusiveMulticast { rd: rd(), ethernet_tag: 0, originator: v6 },
+            EvpnRoute::EthernetSegment { rd: rd(), esi: esi(), originator: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)) },
+            EvpnRoute::IpPrefix {
+                rd: rd(),
+                esi: Esi::ZERO,
+                ethernet_tag: 0,
+                prefix: "192.0.2.0/24".parse().unwrap(),
+                gateway: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                label: U24::new(5000).unwrap(),
+            },
+            EvpnRoute::IpPrefix {
+                rd: rd(),
+                esi: Esi::ZERO,
+                ethernet_tag: 0,
+                prefix: "2001:db8:1::/48".parse().unwrap(),
+                gateway: v6,
+                label: U24::new(5000).unwrap(),
+            },
+            EvpnRoute::Unknown { route_type: 11, data: Bytes::from_static(&[1, 2, 3]) },
+        ];
+        let lengths = [25, 33, 29, 23, 34, 58, 3];
+        for (route, len) in routes.iter().zip(lengths) {
+            assert_eq!(to_bytes(route).unwrap()[1], len);
+        }
+
+        let reach = MpReachNlri {
+            afi: Afi::L2vpn,
+            safi: Safi::Evpn,
+            next_hop: NextHop::Ipv4(Ipv4Addr::new(192, 0, 2, 1)),
+            nlri: Nlri::Evpn(routes),
+        };
+        let wire = to_bytes(&reach).unwrap();
+        assert_eq!(from_bytes::<MpReachNlri>(&wire).unwrap(), reach);
+    }
+
+    #[test]
+    fn test_evpn_err() {
+        let mut wire = to_bytes(EvpnRoute::EthernetSegment { rd: rd(), esi: esi(), originator: "10.0.0.1".parse().unwrap() })
+            .unwrap()
+            .to_vec();
+        wire[20] = 24;
+        match from_bytes::<EvpnRoute>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid EVPN IP address length 24"),
+        }
+
+        let mismatched = EvpnRoute::IpPrefix {
+            rd: rd(),
+            esi: Esi::ZERO,
+            ethernet_tag: 0,
+            prefix: "192.0.2.0/24".parse().unwrap(),
+            gateway: "2001:db8::1".parse().unwrap(),
+            label: U24::default(),
+        };
+        assert!(to_bytes(mismatched).is_err());
+    }
+}
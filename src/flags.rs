@@ -0,0 +1,118 @@
+// bitflags integration (feature = "bitflags").
+//
+// Flag octets (GR flags, EVPN flags, BMP peer flags, ...) go on the wire as
+// their underlying integer. bitflags' own serde support writes text for
+// human-readable formats, so these wrappers always go through the raw bits
+// instead. StrictFlags refuses bits the type doesn't define, LenientFlags
+// keeps them so they survive a decode/encode round trip.
+use std::fmt::Debug;
+
+use bitflags::Flags;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictFlags<F>(pub F);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LenientFlags<F>(pub F);
+
+impl<F> LenientFlags<F>
+where
+    F: Flags,
+{
+    // The bits that were set but are not defined by the flags type.
+    pub fn unknown_bits(&self) -> F::Bits {
+        F::from_bits_retain(self.0.bits()).difference(F::all()).bits()
+    }
+}
+
+impl<F> Serialize for StrictFlags<F>
+where
+    F: Flags,
+    F::Bits: Serialize + Debug,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let bits = self.0.bits();
+        match F::from_bits(bits) {
+            Some(_) => bits.serialize(serializer),
+            None => Err(ser::Error::custom(format!("Undefined flag bits set: {:?}", bits))),
+        }
+    }
+}
+
+impl<'de, F> Deserialize<'de> for StrictFlags<F>
+where
+    F: Flags,
+    F::Bits: Deserialize<'de> + Debug,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let bits = F::Bits::deserialize(deserializer)?;
+        F::from_bits(bits)
+            .map(StrictFlags)
+            .ok_or_else(|| de::Error::custom(format!("Undefined flag bits set: {:?}", bits)))
+    }
+}
+
+impl<F> Serialize for LenientFlags<F>
+where
+    F: Flags,
+    F::Bits: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.bits().serialize(serializer)
+    }
+}
+
+impl<'de, F> Deserialize<'de> for LenientFlags<F>
+where
+    F: Flags,
+    F::Bits: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        F::Bits::deserialize(deserializer).map(|bits| LenientFlags(F::from_bits_retain(bits)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_bytes, SerializerError};
+    use serde::de::IntoDeserializer;
+
+    bitflags::bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct GrFlags: u8 {
+            const RESTART = 0x80;
+            const NOTIFICATION = 0x40;
+        }
+    }
+
+    fn from_bits<'de, T: Deserialize<'de>>(bits: u8) -> std::result::Result<T, SerializerError> {
+        T::deserialize(IntoDeserializer::<SerializerError>::into_deserializer(bits))
+    }
+
+    #[test]
+    fn test_flags_ser() {
+        let flags = GrFlags::RESTART | GrFlags::NOTIFICATION;
+        assert_eq!(to_bytes(StrictFlags(flags)).unwrap().as_ref(), &[0xc0]);
+        assert_eq!(to_bytes(LenientFlags(flags)).unwrap().as_ref(), &[0xc0]);
+
+        let unknown = GrFlags::from_bits_retain(0x81);
+        assert!(to_bytes(StrictFlags(unknown)).is_err());
+        assert_eq!(to_bytes(LenientFlags(unknown)).unwrap().as_ref(), &[0x81]);
+    }
+
+    #[test]
+    fn test_flags_de() {
+        let strict: StrictFlags<GrFlags> = from_bits(0x80).unwrap();
+        assert_eq!(strict.0, GrFlags::RESTART);
+        match from_bits::<StrictFlags<GrFlags>>(0x81) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Undefined flag bits set: 129"),
+        }
+
+        let lenient: LenientFlags<GrFlags> = from_bits(0x81).unwrap();
+        assert_eq!(lenient.0.bits(), 0x81);
+        assert_eq!(lenient.unknown_bits(), 0x01);
+    }
+}
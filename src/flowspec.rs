@@ -0,0 +1,554 @@
+// Flow Specification NLRI (RFC 8955, IPv6 components from RFC 8956).
+//
+// Each rule is a length followed by its components in increasing type order.
+// The length is one octet below 240; from 240 on it is two octets with the
+// top nibble of the first set (0xf000 | len), so at most 4095. Prefix
+// components are a length and the significant octets (IPv6 adds a bit
+// offset before the octets). The others are a list of operator/value pairs:
+// the operator octet carries the end-of-list bit, the AND bit, the value
+// length (1, 2, 4 or 8 octets) and the comparison bits. Values are written in
+// the fewest octets that hold them, so a rule decoded from a peer that used
+// wider values serializes shorter than it came in.
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Serialize, Serializer};
+
+use crate::afi::Afi;
+use crate::prefix::{Ipv4Prefix, Ipv6Prefix};
+use crate::registry::flowspec as component;
+use crate::size::serialized_size;
+
+// Longest rule the length field can describe.
+pub const FLOWSPEC_MAX_LEN: usize = 0x0fff;
+
+// Operator bits shared by numeric and bitmask operators.
+const OP_END_OF_LIST: u8 = 0x80;
+const OP_AND: u8 = 0x40;
+const OP_LEN: u8 = 0x30;
+// Numeric
+const OP_LT: u8 = 0x04;
+const OP_GT: u8 = 0x02;
+const OP_EQ: u8 = 0x01;
+// Bitmask
+const OP_NOT: u8 = 0x02;
+const OP_MATCH: u8 = 0x01;
+
+// Fragment component bits
+pub const FRAGMENT_DONT: u8 = 0x01;
+pub const FRAGMENT_IS: u8 = 0x02;
+pub const FRAGMENT_FIRST: u8 = 0x04;
+pub const FRAGMENT_LAST: u8 = 0x08;
+
+// One rule: a packet matches if it matches every component.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FlowSpecRule {
+    pub components: Vec<FlowSpecComponent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FlowSpecComponent {
+    DestinationPrefix(FlowSpecPrefix),
+    SourcePrefix(FlowSpecPrefix),
+    // IPv4 protocol, IPv6 next header
+    IpProtocol(Vec<NumericMatch>),
+    // Source or destination
+    Port(Vec<NumericMatch>),
+    DestinationPort(Vec<NumericMatch>),
+    SourcePort(Vec<NumericMatch>),
+    IcmpType(Vec<NumericMatch>),
+    IcmpCode(Vec<NumericMatch>),
+    TcpFlags(Vec<BitmaskMatch>),
+    PacketLength(Vec<NumericMatch>),
+    Dscp(Vec<NumericMatch>),
+    Fragment(Vec<BitmaskMatch>),
+    FlowLabel(Vec<NumericMatch>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum FlowSpecPrefix {
+    Ipv4(Ipv4Prefix),
+    // Only the bits from `offset` up to the prefix length are compared.
+    Ipv6 { prefix: Ipv6Prefix, offset: u8 },
+}
+
+// `value` compared with lt/gt/eq; all three clear never matches, all three
+// set always does. `and` binds it to the previous operator instead of
+// starting a new alternative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct NumericMatch {
+    pub and: bool,
+    pub lt: bool,
+    pub gt: bool,
+    pub eq: bool,
+    pub value: u64,
+}
+
+// Matches if all bits of `value` are set (`exact`), or any of them are
+// otherwise; `not` inverts the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BitmaskMatch {
+    pub and: bool,
+    pub not: bool,
+    pub exact: bool,
+    pub value: u64,
+}
+
+impl FlowSpecRule {
+    // Puts the components in the order the wire requires.
+    pub fn new(mut components: Vec<FlowSpecComponent>) -> Self {
+        components.sort_by_key(|c| c.component_type());
+        FlowSpecRule { components }
+    }
+}
+
+impl FlowSpecComponent {
+    pub fn component_type(&self) -> u8 {
+        match self {
+            FlowSpecComponent::DestinationPrefix(_) => component::DESTINATION_PREFIX,
+            FlowSpecComponent::SourcePrefix(_) => component::SOURCE_PREFIX,
+            FlowSpecComponent::IpProtocol(_) => component::IP_PROTOCOL,
+            FlowSpecComponent::Port(_) => component::PORT,
+            FlowSpecComponent::DestinationPort(_) => component::DESTINATION_PORT,
+            FlowSpecComponent::SourcePort(_) => component::SOURCE_PORT,
+            FlowSpecComponent::IcmpType(_) => component::ICMP_TYPE,
+            FlowSpecComponent::IcmpCode(_) => component::ICMP_CODE,
+            FlowSpecComponent::TcpFlags(_) => component::TCP_FLAGS,
+            FlowSpecComponent::PacketLength(_) => component::PACKET_LENGTH,
+            FlowSpecComponent::Dscp(_) => component::DSCP,
+            FlowSpecComponent::Fragment(_) => component::FRAGMENT,
+            FlowSpecComponent::FlowLabel(_) => component::FLOW_LABEL,
+        }
+    }
+}
+
+impl NumericMatch {
+    pub fn eq(value: u64) -> Self {
+        NumericMatch { eq: true, value, ..Self::default() }
+    }
+
+    pub fn lt(value: u64) -> Self {
+        NumericMatch { lt: true, value, ..Self::default() }
+    }
+
+    pub fn gt(value: u64) -> Self {
+        NumericMatch { gt: true, value, ..Self::default() }
+    }
+
+    pub fn and(self) -> Self {
+        NumericMatch { and: true, ..self }
+    }
+}
+
+impl BitmaskMatch {
+    // Any of the bits set
+    pub fn any(value: u64) -> Self {
+        BitmaskMatch { value, ..Self::default() }
+    }
+
+    // All of the bits set
+    pub fn all(value: u64) -> Self {
+        BitmaskMatch { exact: true, value, ..Self::default() }
+    }
+
+    pub fn negate(self) -> Self {
+        BitmaskMatch { not: true, ..self }
+    }
+
+    pub fn and(self) -> Self {
+        BitmaskMatch { and: true, ..self }
+    }
+}
+
+// -- Wire form --
+
+// Value length field for the fewest octets holding `value`.
+fn value_len(value: u64) -> (u8, usize) {
+    match value {
+        0..=0xff => (0x00, 1),
+        0x100..=0xffff => (0x10, 2),
+        0x1_0000..=0xffff_ffff => (0x20, 4),
+        _ => (0x30, 8),
+    }
+}
+
+// An operator list, the last operator carrying the end-of-list bit.
+struct Operators<'a, T>(&'a [T]);
+
+trait Operator {
+    // Operator octet without the end-of-list and length bits
+    fn bits(&self) -> u8;
+    fn value(&self) -> u64;
+}
+
+impl Operator for NumericMatch {
+    fn bits(&self) -> u8 {
+        let flag = |set: bool, bit: u8| if set { bit } else { 0 };
+        flag(self.and, OP_AND) | flag(self.lt, OP_LT) | flag(self.gt, OP_GT) | flag(self.eq, OP_EQ)
+    }
+
+    fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+impl Operator for BitmaskMatch {
+    fn bits(&self) -> u8 {
+        let flag = |set: bool, bit: u8| if set { bit } else { 0 };
+        flag(self.and, OP_AND) | flag(self.not, OP_NOT) | flag(self.exact, OP_MATCH)
+    }
+
+    fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+impl<T: Operator> Serialize for Operators<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.0.is_empty() {
+            return Err(ser::Error::custom("FlowSpec component without operators"));
+        }
+        let mut tup = serializer.serialize_tuple(self.0.len() * 9)?;
+        for (i, op) in self.0.iter().enumerate() {
+            let (len_bits, len) = value_len(op.value());
+            let end = if i + 1 == self.0.len() { OP_END_OF_LIST } else { 0 };
+            tup.serialize_element(&(end | len_bits | op.bits()))?;
+            for b in &op.value().to_be_bytes()[8 - len..] {
+                tup.serialize_element(b)?;
+            }
+        }
+        tup.end()
+    }
+}
+
+impl Serialize for FlowSpecPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let (len, offset, octets) = match self {
+            FlowSpecPrefix::Ipv4(p) => (p.prefix_len(), None, p.addr().octets().to_vec()),
+            FlowSpecPrefix::Ipv6 { prefix, offset } => (prefix.prefix_len(), Some(*offset), prefix.addr().octets().to_vec()),
+        };
+        // IPv6 sends only the bits past the offset, starting at the octet
+        // the offset falls in.
+        let first = offset.map_or(0, |o| o as usize / 8);
+        let n = (len as usize).div_ceil(8);
+        let mut tup = serializer.serialize_tuple(2 + n)?;
+        tup.serialize_element(&len)?;
+        if let Some(offset) = offset {
+            tup.serialize_element(&offset)?;
+        }
+        for b in &octets[first.min(n)..n] {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+impl Serialize for FlowSpecComponent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(2)?;
+        tup.serialize_element(&self.component_type())?;
+        match self {
+            FlowSpecComponent::DestinationPrefix(p) | FlowSpecComponent::SourcePrefix(p) => tup.serialize_element(p)?,
+            FlowSpecComponent::TcpFlags(ops) | FlowSpecComponent::Fragment(ops) => {
+                tup.serialize_element(&Operators(ops))?
+            },
+            FlowSpecComponent::IpProtocol(ops)
+            | FlowSpecComponent::Port(ops)
+            | FlowSpecComponent::DestinationPort(ops)
+            | FlowSpecComponent::SourcePort(ops)
+            | FlowSpecComponent::IcmpType(ops)
+            | FlowSpecComponent::IcmpCode(ops)
+            | FlowSpecComponent::PacketLength(ops)
+            | FlowSpecComponent::Dscp(ops)
+            | FlowSpecComponent::FlowLabel(ops) => tup.serialize_element(&Operators(ops))?,
+        }
+        tup.end()
+    }
+}
+
+impl Serialize for FlowSpecRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let len = serialized_size(&self.components).map_err(ser::Error::custom)?;
+        if len > FLOWSPEC_MAX_LEN {
+            return Err(ser::Error::custom(format!(
+                "FlowSpec rule is {} octets, at most {} fit the length field", len, FLOWSPEC_MAX_LEN
+            )));
+        }
+        let mut tup = serializer.serialize_tuple(2)?;
+        match len {
+            0..=0xef => tup.serialize_element(&(len as u8))?,
+            _ => tup.serialize_element(&(0xf000 | len as u16))?,
+        }
+        tup.serialize_element(&self.components)?;
+        tup.end()
+    }
+}
+
+// Rules up to the end of the NLRI, with prefix components of `afi`.
+pub(crate) struct FlowSpecSeed {
+    pub afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for FlowSpecSeed {
+    type Value = Vec<FlowSpecRule>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FlowSpecSeed {
+    type Value = Vec<FlowSpecRule>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FlowSpec NLRI")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut rules = Vec::new();
+        while let Some(rule) = seq.next_element_seed(RuleSeed { afi: self.afi })? {
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+}
+
+struct RuleSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for RuleSeed {
+    type Value = FlowSpecRule;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        // Two length octets and the rule, read an octet at a time
+        d.deserialize_tuple(2 + FLOWSPEC_MAX_LEN, self)
+    }
+}
+
+impl<'de> Visitor<'de> for RuleSeed {
+    type Value = FlowSpecRule;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a FlowSpec rule")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let first: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let len = match first {
+            0xf0.. => {
+                let second: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                ((first as usize & 0x0f) << 8) | second as usize
+            },
+            _ => first as usize,
+        };
+
+        let mut octets = Octets { seq, left: len };
+        let mut components: Vec<FlowSpecComponent> = Vec::new();
+        while octets.left > 0 {
+            let component = octets.component(self.afi)?;
+            if let Some(last) = components.last() {
+                if component.component_type() <= last.component_type() {
+                    return Err(de::Error::custom(format!(
+                        "FlowSpec component {} follows component {}",
+                        component.component_type(),
+                        last.component_type()
+                    )));
+                }
+            }
+            components.push(component);
+        }
+        Ok(FlowSpecRule { components })
+    }
+}
+
+// The octets of one rule, limited to its length.
+struct Octets<A> {
+    seq: A,
+    left: usize,
+}
+
+impl<'de, A: SeqAccess<'de>> Octets<A> {
+    fn u8(&mut self) -> std::result::Result<u8, A::Error> {
+        if self.left == 0 {
+            return Err(de::Error::custom("FlowSpec component runs past the end of the rule"));
+        }
+        self.left -= 1;
+        self.seq.next_element()?.ok_or_else(|| de::Error::custom("FlowSpec rule cut short"))
+    }
+
+    fn component(&mut self, afi: Afi) -> std::result::Result<FlowSpecComponent, A::Error> {
+        let component_type = self.u8()?;
+        Ok(match component_type {
+            component::DESTINATION_PREFIX => FlowSpecComponent::DestinationPrefix(self.prefix(afi)?),
+            component::SOURCE_PREFIX => FlowSpecComponent::SourcePrefix(self.prefix(afi)?),
+            component::IP_PROTOCOL => FlowSpecComponent::IpProtocol(self.numeric()?),
+            component::PORT => FlowSpecComponent::Port(self.numeric()?),
+            component::DESTINATION_PORT => FlowSpecComponent::DestinationPort(self.numeric()?),
+            component::SOURCE_PORT => FlowSpecComponent::SourcePort(self.numeric()?),
+            component::ICMP_TYPE => FlowSpecComponent::IcmpType(self.numeric()?),
+            component::ICMP_CODE => FlowSpecComponent::IcmpCode(self.numeric()?),
+            component::TCP_FLAGS => FlowSpecComponent::TcpFlags(self.bitmask()?),
+            component::PACKET_LENGTH => FlowSpecComponent::PacketLength(self.numeric()?),
+            component::DSCP => FlowSpecComponent::Dscp(self.numeric()?),
+            component::FRAGMENT => FlowSpecComponent::Fragment(self.bitmask()?),
+            component::FLOW_LABEL if afi == Afi::Ipv6 => FlowSpecComponent::FlowLabel(self.numeric()?),
+            // The rest of the rule can't be parsed past a type we don't know
+            other => return Err(de::Error::custom(format!("Unknown FlowSpec component type {}", other))),
+        })
+    }
+
+    fn prefix(&mut self, afi: Afi) -> std::result::Result<FlowSpecPrefix, A::Error> {
+        let len = self.u8()?;
+        match afi {
+            Afi::Ipv4 => {
+                if len > 32 {
+                    return Err(de::Error::custom(format!("Invalid prefix length {}, at most 32 allowed", len)));
+                }
+                let mut octets = [0u8; 4];
+                for b in octets.iter_mut().take((len as usize).div_ceil(8)) {
+                    *b = self.u8()?;
+                }
+                let prefix = Ipv4Prefix::new(Ipv4Addr::from(octets), len);
+                Ok(FlowSpecPrefix::Ipv4(prefix.ok_or_else(|| de::Error::custom("Invalid FlowSpec prefix"))?))
+            },
+            _ => {
+                let offset = self.u8()?;
+                if len > 128 || offset > len {
+                    return Err(de::Error::custom(format!("Invalid prefix length {} with offset {}", len, offset)));
+                }
+                let mut octets = [0u8; 16];
+                let n = (len as usize).div_ceil(8);
+                for b in octets.iter_mut().take(n).skip(offset as usize / 8) {
+                    *b = self.u8()?;
+                }
+                let prefix = Ipv6Prefix::new(Ipv6Addr::from(octets), len);
+                let prefix = prefix.ok_or_else(|| de::Error::custom("Invalid FlowSpec prefix"))?;
+                Ok(FlowSpecPrefix::Ipv6 { prefix, offset })
+            },
+        }
+    }
+
+    // Operator octets and values up to the end-of-list bit.
+    fn operators(&mut self) -> std::result::Result<Vec<(u8, u64)>, A::Error> {
+        let mut ops = Vec::new();
+        loop {
+            let op = self.u8()?;
+            let mut value = 0u64;
+            for _ in 0..1 << ((op & OP_LEN) >> 4) {
+                value = (value << 8) | self.u8()? as u64;
+            }
+            ops.push((op, value));
+            if op & OP_END_OF_LIST != 0 {
+                return Ok(ops);
+            }
+        }
+    }
+
+    fn numeric(&mut self) -> std::result::Result<Vec<NumericMatch>, A::Error> {
+        Ok(self
+            .operators()?
+            .into_iter()
+            .map(|(op, value)| NumericMatch {
+                and: op & OP_AND != 0,
+                lt: op & OP_LT != 0,
+                gt: op & OP_GT != 0,
+                eq: op & OP_EQ != 0,
+                value,
+            })
+            .collect())
+    }
+
+    fn bitmask(&mut self) -> std::result::Result<Vec<BitmaskMatch>, A::Error> {
+        Ok(self
+            .operators()?
+            .into_iter()
+            .map(|(op, value)| BitmaskMatch {
+                and: op & OP_AND != 0,
+                not: op & OP_NOT != 0,
+                exact: op & OP_MATCH != 0,
+                value,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, MpReachNlri, NextHop, Nlri, Safi};
+    use bytes::Bytes;
+
+    fn reach(afi: Afi, rules: Vec<FlowSpecRule>) -> MpReachNlri {
+        MpReachNlri { afi, safi: Safi::FlowSpec, next_hop: NextHop::Other(Bytes::new()), nlri: Nlri::FlowSpec(rules) }
+    }
+
+    #[test]
+    fn test_flowspec_wire() {
+        // RFC 8955 example: packets to 192.0.2.0/24 from 203.0.113.0/24, TCP
+        // port 80 or 8080.
+        let rule = FlowSpecRule::new(vec![
+            FlowSpecComponent::DestinationPort(vec![NumericMatch::eq(80), NumericMatch::eq(8080)]),
+            FlowSpecComponent::DestinationPrefix(FlowSpecPrefix::Ipv4("192.0.2.0/24".parse().unwrap())),
+            FlowSpecComponent::SourcePrefix(FlowSpecPrefix::Ipv4("203.0.113.0/24".parse().unwrap())),
+            FlowSpecComponent::IpProtocol(vec![NumericMatch::eq(6)]),
+            FlowSpecComponent::Fragment(vec![BitmaskMatch::all(FRAGMENT_IS as u64).negate()]),
+        ]);
+        let reach = reach(Afi::Ipv4, vec![rule]);
+        #[rustfmt::skip]
+        let wire: &[u8] = &[
+            0, 1, 133, 0, 0,
+            22,
+            1, 24, 192, 0, 2,
+            2, 24, 203, 0, 113,
+            3, 0x81, 6,
+            5, 0x01, 80, 0x91, 0x1f, 0x90,
+            12, 0x83, 2,
+        ];
+        assert_eq!(to_bytes(&reach).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<MpReachNlri>(wire).unwrap(), reach);
+    }
+
+    #[test]
+    fn test_flowspec_ipv6_and_long_rules() {
+        // Bits before the offset aren't sent and come back as zero.
+        let prefix = FlowSpecPrefix::Ipv6 { prefix: "0:db8::/32".parse().unwrap(), offset: 16 };
+        let short = FlowSpecRule::new(vec![
+            FlowSpecComponent::DestinationPrefix(prefix),
+            FlowSpecComponent::FlowLabel(vec![NumericMatch::gt(0x1_0000), NumericMatch::lt(0x2_0000).and()]),
+        ]);
+        // 122 port ranges need the 2 octet rule length
+        let ports = (0..122u64).map(|p| NumericMatch::eq(1000 + p)).collect();
+        let long = FlowSpecRule::new(vec![FlowSpecComponent::Port(ports)]);
+        let reach = reach(Afi::Ipv6, vec![short, long]);
+
+        let wire = to_bytes(&reach).unwrap();
+        // Prefix sends the octets past the offset only: 0x0d, 0xb8
+        assert_eq!(&wire[5..12], &[16, 1, 32, 16, 0x0d, 0xb8, 13]);
+        assert_eq!(&wire[22..24], &[0xf1, 0x6f]);
+        assert_eq!(from_bytes::<MpReachNlri>(&wire).unwrap(), reach);
+    }
+
+    #[test]
+    fn test_flowspec_err() {
+        // Components out of order
+        let wire = [0, 1, 133, 0, 0, 6, 3, 0x81, 6, 1, 8, 10];
+        match from_bytes::<MpReachNlri>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "FlowSpec component 1 follows component 3"),
+        }
+        // Operator list runs past the rule length
+        let wire = [0, 1, 133, 0, 0, 3, 3, 0x01, 6, 0x81, 7];
+        assert!(from_bytes::<MpReachNlri>(&wire).is_err());
+
+        let empty = FlowSpecRule::new(vec![FlowSpecComponent::Dscp(vec![])]);
+        assert!(to_bytes(reach(Afi::Ipv4, vec![empty])).is_err());
+    }
+}
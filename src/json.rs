@@ -0,0 +1,727 @@
+// JSON text form of decoded messages (feature = "json"), for logging and
+// debugging pipelines.
+//
+// Every message and attribute type also serializes for human-readable serde
+// formats: addresses and prefixes as strings, communities as "65000:100",
+// AS paths as lists of segments, AFI/SAFI and other code points by name
+// (the number when there is none). The crate's own Serializer isn't
+// human-readable, so the wire encoding doesn't change. Lengths, flags and
+// type codes the variants already imply are left out.
+//
+// A few payloads have no text form of their own and are written in their
+// wire model, which can't be read back: BGP-LS attributes, the NLRI of the
+// FlowSpec, EVPN, BGP-LS, labeled and VPN families, and ROUTE-REFRESH
+// messages (for their ORFs).
+//
+// to_exabgp and from_exabgp convert to and from ExaBGP's JSON message schema
+// instead, for pipelines built around its output.
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use serde_json::{json, Map, Value};
+
+use crate::afi::{Afi, Safi};
+use crate::as_path::AsPathSegment;
+use crate::attributes::{Origin, PathAttribute};
+use crate::communities::{Community, ExtendedCommunity, LargeCommunity};
+use crate::messages::{BgpMessage, KeepaliveMessage, NotificationMessage, UpdateMessage};
+use crate::multiprotocol::{MpReachNlri, MpUnreachNlri, NextHop, Nlri};
+use crate::prefix::{Ipv4Prefix, Ipv6Prefix};
+use crate::types::BgpIdentifier;
+
+pub fn to_json(message: &BgpMessage) -> serde_json::Result<String> {
+    serde_json::to_string(message)
+}
+
+pub fn from_json(input: &str) -> serde_json::Result<BgpMessage> {
+    serde_json::from_str(input)
+}
+
+// -- ExaBGP --
+//
+// The schema ExaBGP 4 writes for received and sent messages: KEEPALIVE,
+// NOTIFICATION, and UPDATEs for IPv4 and IPv6 unicast with the attributes it
+// has names for. Anything else (other messages, families or attributes) is
+// an error rather than being left out. Routes are grouped by family and next
+// hop under "announce", so an IPv6 link-local next hop doesn't make it
+// across. AS_SET segments are nested lists in "as-path", and confederation
+// segments go in "confederation-path" the same way.
+
+const EXABGP_VERSION: &str = "4.0.1";
+
+// The session a message belongs to, which ExaBGP writes alongside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExaBgpNeighbor {
+    pub local_address: IpAddr,
+    pub peer_address: IpAddr,
+    pub local_asn: u32,
+    pub peer_asn: u32,
+    // "receive", or "send" for messages to the peer.
+    pub received: bool,
+}
+
+pub fn to_exabgp(message: &BgpMessage, neighbor: &ExaBgpNeighbor) -> serde_json::Result<Value> {
+    let mut body = json!({
+        "address": { "local": neighbor.local_address.to_string(), "peer": neighbor.peer_address.to_string() },
+        "asn": { "local": neighbor.local_asn, "peer": neighbor.peer_asn },
+        "direction": if neighbor.received { "receive" } else { "send" },
+    });
+    let message_type = match message {
+        BgpMessage::Keepalive(_) => "keepalive",
+        BgpMessage::Notification(notification) => {
+            let data: String = notification.data.iter().map(|b| format!("{:02x}", b)).collect();
+            body["notification"] = json!({
+                "code": notification.error_code,
+                "subcode": notification.error_subcode,
+                "data": data,
+            });
+            "notification"
+        },
+        BgpMessage::Update(update) => {
+            body["message"] = json!({ "update": exabgp_update(update)? });
+            "update"
+        },
+        _ => return Err(exabgp_error(format_args!("has no {:?} message", message.message_type()))),
+    };
+    Ok(json!({ "exabgp": EXABGP_VERSION, "type": message_type, "neighbor": body }))
+}
+
+pub fn from_exabgp(value: &Value) -> serde_json::Result<(ExaBgpNeighbor, BgpMessage)> {
+    let body = &value["neighbor"];
+    let neighbor = ExaBgpNeighbor {
+        local_address: parse_str(&body["address"], "local")?,
+        peer_address: parse_str(&body["address"], "peer")?,
+        local_asn: get_u32(&body["asn"], "local")?,
+        peer_asn: get_u32(&body["asn"], "peer")?,
+        received: match get_str(body, "direction")? {
+            "receive" => true,
+            "send" => false,
+            other => return Err(exabgp_error(format_args!("direction \"{}\" unknown", other))),
+        },
+    };
+    let message = match get_str(value, "type")? {
+        "keepalive" => BgpMessage::Keepalive(KeepaliveMessage),
+        "notification" => {
+            let notification = &body["notification"];
+            let hex = get_str(notification, "data")?;
+            let data = (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| exabgp_error(format_args!("notification data \"{}\" isn't hex", hex)))?;
+            let get_u8 = |name| {
+                u8::try_from(get_u32(notification, name)?)
+                    .map_err(|_| exabgp_error(format_args!("notification {} out of range", name)))
+            };
+            BgpMessage::Notification(NotificationMessage {
+                error_code: get_u8("code")?,
+                error_subcode: get_u8("subcode")?,
+                data,
+            })
+        },
+        "update" => BgpMessage::Update(update_from_exabgp(&body["message"]["update"])?),
+        other => return Err(exabgp_error(format_args!("message type \"{}\" unsupported", other))),
+    };
+    Ok((neighbor, message))
+}
+
+fn exabgp_update(update: &UpdateMessage) -> serde_json::Result<Value> {
+    let mut attribute = Map::new();
+    let mut announce = Map::new();
+    let mut withdraw = Map::new();
+    let mut next_hop = None;
+    for attr in &update.path_attributes.value {
+        let (name, value) = match attr.attribute() {
+            PathAttribute::Origin(Origin::Igp) => ("origin", json!("igp")),
+            PathAttribute::Origin(Origin::Egp) => ("origin", json!("egp")),
+            PathAttribute::Origin(Origin::Incomplete) => ("origin", json!("incomplete")),
+            PathAttribute::AsPath(segments) => {
+                let confederation = exabgp_path(segments, true);
+                if !confederation.is_empty() {
+                    attribute.insert(String::from("confederation-path"), Value::Array(confederation));
+                }
+                ("as-path", Value::Array(exabgp_path(segments, false)))
+            },
+            PathAttribute::NextHop(addr) => {
+                next_hop = Some(addr);
+                continue;
+            },
+            PathAttribute::MultiExitDisc(med) => ("med", json!(med)),
+            PathAttribute::LocalPref(pref) => ("local-preference", json!(pref)),
+            PathAttribute::AtomicAggregate => ("atomic-aggregate", json!(true)),
+            PathAttribute::Aggregator { asn, address } => ("aggregator", json!(format!("{}:{}", asn, address))),
+            PathAttribute::Communities(communities) => {
+                ("community", communities.iter().map(|c| json!([c.asn(), c.value()])).collect())
+            },
+            PathAttribute::OriginatorId(id) => ("originator-id", json!(id.to_string())),
+            PathAttribute::ClusterList(ids) => ("cluster-list", ids.iter().map(|id| json!(id.to_string())).collect()),
+            PathAttribute::ExtendedCommunities(communities) => {
+                let communities = communities
+                    .iter()
+                    .map(|c| json!({ "value": u64::from_be_bytes(c.octets()), "string": c.to_string() }))
+                    .collect();
+                ("extended-community", communities)
+            },
+            PathAttribute::LargeCommunities(communities) => {
+                let communities = communities
+                    .iter()
+                    .map(|c| json!([c.global_admin, c.local_data_1, c.local_data_2]))
+                    .collect();
+                ("large-community", communities)
+            },
+            PathAttribute::MpReachNlri(MpReachNlri { afi: Afi::Ipv6, safi: Safi::Unicast, next_hop, nlri }) => {
+                let (NextHop::Ipv6(global) | NextHop::Ipv6LinkLocal { global, .. }) = next_hop else {
+                    return Err(exabgp_error(format_args!("next hop {:?} unsupported", next_hop)));
+                };
+                let Nlri::Ipv6(nlri) = nlri else {
+                    return Err(exabgp_error("IPv6 unicast routes must be Nlri::Ipv6"));
+                };
+                let routes = exabgp_routes(nlri.iter().map(|p| (p.to_string(), p.path_id())));
+                announce.insert(String::from("ipv6 unicast"), json!({ global.to_string(): routes }));
+                continue;
+            },
+            PathAttribute::MpUnreachNlri(MpUnreachNlri { afi: Afi::Ipv6, safi: Safi::Unicast, withdrawn }) => {
+                let Nlri::Ipv6(withdrawn) = withdrawn else {
+                    return Err(exabgp_error("IPv6 unicast routes must be Nlri::Ipv6"));
+                };
+                let routes = exabgp_routes(withdrawn.iter().map(|p| (p.to_string(), p.path_id())));
+                withdraw.insert(String::from("ipv6 unicast"), routes);
+                continue;
+            },
+            _ => return Err(exabgp_error(format_args!("has no attribute for {:?}", attr))),
+        };
+        attribute.insert(String::from(name), value);
+    }
+
+    let ipv4 = |routes: &[Ipv4Prefix]| exabgp_routes(routes.iter().map(|p| (p.to_string(), p.path_id())));
+    match next_hop {
+        Some(next_hop) => {
+            announce.insert(String::from("ipv4 unicast"), json!({ next_hop.to_string(): ipv4(&update.nlri) }));
+        },
+        None if !update.nlri.is_empty() => return Err(exabgp_error("needs a NEXT_HOP for IPv4 routes")),
+        None => {},
+    }
+    if !update.withdrawn_routes.value.is_empty() {
+        withdraw.insert(String::from("ipv4 unicast"), ipv4(&update.withdrawn_routes.value));
+    }
+
+    let mut out = Map::new();
+    for (name, section) in [("attribute", attribute), ("announce", announce), ("withdraw", withdraw)] {
+        if !section.is_empty() {
+            out.insert(String::from(name), Value::Object(section));
+        }
+    }
+    Ok(Value::Object(out))
+}
+
+// The segments of one kind, sequences flattened and sets as nested lists.
+fn exabgp_path(segments: &[AsPathSegment], confederation: bool) -> Vec<Value> {
+    let mut path = Vec::new();
+    for segment in segments {
+        match (segment, confederation) {
+            (AsPathSegment::AsSequence(asns), false) | (AsPathSegment::AsConfedSequence(asns), true) => {
+                path.extend(asns.iter().map(|asn| json!(asn)));
+            },
+            (AsPathSegment::AsSet(asns), false) | (AsPathSegment::AsConfedSet(asns), true) => path.push(json!(asns)),
+            _ => {},
+        }
+    }
+    path
+}
+
+fn exabgp_routes(routes: impl Iterator<Item = (String, Option<u32>)>) -> Value {
+    routes
+        .map(|(nlri, path_id)| match path_id {
+            Some(id) => json!({ "nlri": nlri, "path-information": Ipv4Addr::from(id).to_string() }),
+            None => json!({ "nlri": nlri }),
+        })
+        .collect()
+}
+
+fn update_from_exabgp(value: &Value) -> serde_json::Result<UpdateMessage> {
+    let attribute = &value["attribute"];
+    let mut attrs = Vec::new();
+    let mut update = UpdateMessage::default();
+
+    if !attribute.is_null() && !attribute.is_object() {
+        return Err(exabgp_error("\"attribute\" isn't an object"));
+    }
+    if attribute.get("origin").is_some() {
+        attrs.push(PathAttribute::Origin(match get_str(attribute, "origin")? {
+            "igp" => Origin::Igp,
+            "egp" => Origin::Egp,
+            "incomplete" => Origin::Incomplete,
+            other => return Err(exabgp_error(format_args!("origin \"{}\" unknown", other))),
+        }));
+    }
+    if attribute.get("as-path").is_some() || attribute.get("confederation-path").is_some() {
+        let mut segments = path_from_exabgp(&attribute["confederation-path"], true)?;
+        segments.extend(path_from_exabgp(&attribute["as-path"], false)?);
+        attrs.push(PathAttribute::AsPath(segments));
+    }
+    let ipv4_announce = match &value["announce"]["ipv4 unicast"] {
+        Value::Null => None,
+        Value::Object(by_next_hop) if by_next_hop.len() == 1 => by_next_hop.iter().next(),
+        _ => return Err(exabgp_error("IPv4 routes must share one next hop to fit in an UPDATE")),
+    };
+    if let Some((next_hop, routes)) = ipv4_announce {
+        attrs.push(PathAttribute::NextHop(parse(next_hop)?));
+        update.nlri = routes_from_exabgp(routes, Ipv4Prefix::with_path_id)?;
+    }
+    if attribute.get("med").is_some() {
+        attrs.push(PathAttribute::MultiExitDisc(get_u32(attribute, "med")?));
+    }
+    if attribute.get("local-preference").is_some() {
+        attrs.push(PathAttribute::LocalPref(get_u32(attribute, "local-preference")?));
+    }
+    if attribute.get("atomic-aggregate") == Some(&Value::Bool(true)) {
+        attrs.push(PathAttribute::AtomicAggregate);
+    }
+    if attribute.get("aggregator").is_some() {
+        let aggregator = get_str(attribute, "aggregator")?;
+        let (asn, address) = aggregator
+            .split_once(':')
+            .ok_or_else(|| exabgp_error(format_args!("aggregator \"{}\" isn't <asn>:<address>", aggregator)))?;
+        attrs.push(PathAttribute::Aggregator { asn: parse(asn)?, address: parse(address)? });
+    }
+    if let Some(communities) = attribute.get("community") {
+        let communities = elements(communities, "community")?
+            .iter()
+            .map(|c| match (c[0].as_u64(), c[1].as_u64()) {
+                (Some(asn), Some(value)) if asn <= 0xffff && value <= 0xffff => {
+                    Ok(Community::new(asn as u16, value as u16))
+                },
+                _ => Err(exabgp_error(format_args!("community {} isn't [<asn>, <value>]", c))),
+            })
+            .collect::<serde_json::Result<_>>()?;
+        attrs.push(PathAttribute::Communities(communities));
+    }
+    if attribute.get("originator-id").is_some() {
+        let id = get_str(attribute, "originator-id")?;
+        let id = BgpIdentifier::from_ipv4(parse(id)?);
+        attrs.push(PathAttribute::OriginatorId(id.ok_or_else(|| exabgp_error("originator-id invalid"))?));
+    }
+    if let Some(ids) = attribute.get("cluster-list") {
+        let ids = elements(ids, "cluster-list")?.iter().map(parse_value).collect::<serde_json::Result<_>>()?;
+        attrs.push(PathAttribute::ClusterList(ids));
+    }
+    let ipv6_announce = match &value["announce"]["ipv6 unicast"] {
+        Value::Null => None,
+        Value::Object(by_next_hop) if by_next_hop.len() == 1 => by_next_hop.iter().next(),
+        _ => return Err(exabgp_error("IPv6 routes must share one next hop to fit in an UPDATE")),
+    };
+    if let Some((next_hop, routes)) = ipv6_announce {
+        let nlri = routes_from_exabgp(routes, Ipv6Prefix::with_path_id)?;
+        attrs.push(PathAttribute::MpReachNlri(MpReachNlri::ipv6_unicast(parse(next_hop)?, None, nlri)));
+    }
+    if let Some(routes) = value["withdraw"].get("ipv6 unicast") {
+        attrs.push(PathAttribute::MpUnreachNlri(MpUnreachNlri {
+            afi: Afi::Ipv6,
+            safi: Safi::Unicast,
+            withdrawn: Nlri::Ipv6(routes_from_exabgp(routes, Ipv6Prefix::with_path_id)?),
+        }));
+    }
+    if let Some(communities) = attribute.get("extended-community") {
+        let communities = elements(communities, "extended-community")?
+            .iter()
+            .map(|c| match c["value"].as_u64() {
+                Some(value) => Ok(ExtendedCommunity::from_octets(value.to_be_bytes())),
+                None => Err(exabgp_error(format_args!("extended community {} has no value", c))),
+            })
+            .collect::<serde_json::Result<_>>()?;
+        attrs.push(PathAttribute::ExtendedCommunities(communities));
+    }
+    if let Some(communities) = attribute.get("large-community") {
+        let communities = elements(communities, "large-community")?
+            .iter()
+            .map(|c| {
+                let part = |i: usize| c[i].as_u64().and_then(|v| u32::try_from(v).ok());
+                match (part(0), part(1), part(2)) {
+                    (Some(global), Some(local_1), Some(local_2)) => Ok(LargeCommunity::new(global, local_1, local_2)),
+                    _ => Err(exabgp_error(format_args!("large community {} isn't three u32s", c))),
+                }
+            })
+            .collect::<serde_json::Result<_>>()?;
+        attrs.push(PathAttribute::LargeCommunities(communities));
+    }
+    if let Some(routes) = value["withdraw"].get("ipv4 unicast") {
+        update.withdrawn_routes.value = routes_from_exabgp(routes, Ipv4Prefix::with_path_id)?;
+    }
+    update.path_attributes.value = attrs;
+    Ok(update)
+}
+
+fn path_from_exabgp(path: &Value, confederation: bool) -> serde_json::Result<Vec<AsPathSegment>> {
+    let segment = |asns, set| match (confederation, set) {
+        (false, false) => AsPathSegment::AsSequence(asns),
+        (false, true) => AsPathSegment::AsSet(asns),
+        (true, false) => AsPathSegment::AsConfedSequence(asns),
+        (true, true) => AsPathSegment::AsConfedSet(asns),
+    };
+    let asn = |v: &Value| {
+        let asn = v.as_u64().and_then(|asn| u32::try_from(asn).ok());
+        asn.ok_or_else(|| exabgp_error(format_args!("ASN {} invalid", v)))
+    };
+    let mut segments = Vec::new();
+    let mut run = Vec::new();
+    for element in path.as_array().map_or(&[][..], |path| path.as_slice()) {
+        match element {
+            Value::Array(asns) => {
+                if !run.is_empty() {
+                    segments.push(segment(std::mem::take(&mut run), false));
+                }
+                segments.push(segment(asns.iter().map(asn).collect::<serde_json::Result<_>>()?, true));
+            },
+            element => run.push(asn(element)?),
+        }
+    }
+    if !run.is_empty() {
+        segments.push(segment(run, false));
+    }
+    Ok(segments)
+}
+
+fn routes_from_exabgp<P>(routes: &Value, with_path_id: fn(P, u32) -> P) -> serde_json::Result<Vec<P>>
+where
+    P: FromStr,
+    P::Err: Display,
+{
+    elements(routes, "routes")?
+        .iter()
+        .map(|route| {
+            let prefix = parse(get_str(route, "nlri")?)?;
+            match route.get("path-information") {
+                Some(_) => Ok(with_path_id(prefix, parse::<Ipv4Addr>(get_str(route, "path-information")?)?.into())),
+                None => Ok(prefix),
+            }
+        })
+        .collect()
+}
+
+fn exabgp_error(msg: impl Display) -> serde_json::Error {
+    serde::de::Error::custom(format!("ExaBGP {}", msg))
+}
+
+fn elements<'a>(value: &'a Value, name: &str) -> serde_json::Result<&'a Vec<Value>> {
+    value.as_array().ok_or_else(|| exabgp_error(format_args!("\"{}\" isn't a list", name)))
+}
+
+fn get_str<'a>(value: &'a Value, name: &str) -> serde_json::Result<&'a str> {
+    value[name].as_str().ok_or_else(|| exabgp_error(format_args!("\"{}\" missing or not a string", name)))
+}
+
+fn get_u32(value: &Value, name: &str) -> serde_json::Result<u32> {
+    value[name]
+        .as_u64()
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| exabgp_error(format_args!("\"{}\" missing or not a u32", name)))
+}
+
+fn parse<T>(s: &str) -> serde_json::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    s.parse().map_err(|e| exabgp_error(format_args!("\"{}\": {}", s, e)))
+}
+
+fn parse_str<T>(value: &Value, name: &str) -> serde_json::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse(get_str(value, name)?)
+}
+
+fn parse_value<T>(value: &Value) -> serde_json::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse(value.as_str().ok_or_else(|| exabgp_error(format_args!("{} isn't a string", value)))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use bytes::Bytes;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::*;
+
+    fn update() -> UpdateMessage {
+        let prefix = |s: &str| s.parse::<Ipv4Prefix>().unwrap();
+        let mut update = UpdateMessage::default();
+        update.withdrawn_routes.value = vec![prefix("198.51.100.0/24").with_path_id(7)];
+        update.path_attributes.value = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![
+                AsPathSegment::AsSequence(vec![65001, 4200000000]),
+                AsPathSegment::AsSet(vec![65010, 65011]),
+            ]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+            PathAttribute::MultiExitDisc(50),
+            PathAttribute::LocalPref(200),
+            PathAttribute::AtomicAggregate,
+            PathAttribute::Aggregator { asn: 65001, address: Ipv4Addr::new(192, 0, 2, 9) },
+            PathAttribute::Communities(vec![Community::new(65000, 100), Community::no_export()]),
+            PathAttribute::MpReachNlri(MpReachNlri::ipv6_unicast(
+                "2001:db8::1".parse().unwrap(),
+                Some("fe80::1".parse().unwrap()),
+                vec!["2001:db8:1::/48".parse().unwrap()],
+            )),
+            PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                afi: Afi::Ipv6,
+                safi: Safi::Unicast,
+                withdrawn: Nlri::Ipv6(vec!["2001:db8:2::/48".parse().unwrap()]),
+            }),
+            PathAttribute::ExtendedCommunities(vec![
+                ExtendedCommunity::route_target(65000, 1),
+                ExtendedCommunity::RouteOrigin(ExtendedAdmin::FourOctetAs { asn: 65000, local: 2 }),
+                ExtendedCommunity::from_octets([0x43, 0x0c, 0, 0, 0, 0, 0, 8]),
+            ]),
+            PathAttribute::LargeCommunities(vec![LargeCommunity::new(4200000000, 1, 2)]),
+            PathAttribute::Aigp(vec![AigpTlv::Metric(1000)]),
+            PathAttribute::PrefixSid(vec![PrefixSidTlv::label_index(42)]),
+            PathAttribute::Unknown { code: 99, flags: ATTR_OPTIONAL | ATTR_TRANSITIVE, data: Bytes::from_static(&[1]) },
+        ];
+        update.nlri = vec![prefix("203.0.113.0/24")];
+        update
+    }
+
+    #[test]
+    fn test_update_json() {
+        let message = BgpMessage::Update(update());
+        let text = to_json(&message).unwrap();
+        let value: Value = serde_json::from_str(&text).unwrap();
+        let update = &value["update"];
+        assert_eq!(update["withdrawn_routes"], json!([{ "prefix": "198.51.100.0/24", "path_id": 7 }]));
+        assert_eq!(update["nlri"], json!(["203.0.113.0/24"]));
+
+        let attrs = &update["path_attributes"];
+        assert_eq!(attrs[0], json!({ "origin": "igp" }));
+        let as_path = json!([{ "as_sequence": [65001, 4200000000u32] }, { "as_set": [65010, 65011] }]);
+        assert_eq!(attrs[1], json!({ "as_path": as_path }));
+        assert_eq!(attrs[2], json!({ "next_hop": "192.0.2.1" }));
+        assert_eq!(attrs[5], json!("atomic_aggregate"));
+        assert_eq!(attrs[7], json!({ "communities": ["65000:100", "65535:65281"] }));
+        assert_eq!(attrs[8]["mp_reach_nlri"], json!({
+            "afi": "ipv6",
+            "safi": "unicast",
+            "next_hop": { "ipv6_link_local": { "global": "2001:db8::1", "link_local": "fe80::1" } },
+            "nlri": { "ipv6": ["2001:db8:1::/48"] },
+        }));
+        let extended = json!(["target:65000:1", "origin:65000L:2", "0x430c000000000008"]);
+        assert_eq!(attrs[10], json!({ "extended_communities": extended }));
+        assert_eq!(attrs[11], json!({ "large_communities": ["4200000000:1:2"] }));
+
+        assert_eq!(from_json(&text).unwrap(), message);
+    }
+
+    #[test]
+    fn test_json_leaves_wire_alone() {
+        // The same message through the wire form and back out as JSON (no
+        // path identifiers, ADD-PATH isn't on)
+        let mut update = update();
+        update.withdrawn_routes.value = vec!["198.51.100.0/24".parse().unwrap()];
+        let message = BgpMessage::Update(update);
+        let wire = to_message_bytes(MessageType::Update, &message).unwrap();
+        let decoded = BgpMessage::from_bytes(&wire).unwrap();
+        assert_eq!(to_json(&decoded).unwrap(), to_json(&message).unwrap());
+
+        let open = OpenMessage::new(65001, 90, BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap());
+        assert_eq!(to_bytes(&open).unwrap()[5..9], [192, 0, 2, 1]);
+        match from_bytes::<BgpMessage>(&[0, 0, 0, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().contains("BgpMessage::from_bytes"), "{}", e),
+        }
+
+        // Wire-only wrappers are left out of the text form
+        let slot = (Padded::<u16, 4>(7), Counted::<u8, u8>::new(vec![1, 2]));
+        assert_eq!(serde_json::to_string(&slot).unwrap(), "[7,[1,2]]");
+        assert_eq!(serde_json::from_str::<(Padded<u16, 4>, Counted<u8, u8>)>("[7,[1,2]]").unwrap(), slot);
+    }
+
+    #[test]
+    fn test_open_json() {
+        let mut open = OpenMessage::new(23456, 90, BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap());
+        open.optional_parameters.value = vec![OptionalParameter::Capabilities(vec![
+            Capability::Multiprotocol { afi: Afi::Ipv6, safi: Safi::Unicast },
+            Capability::FourOctetAs(4200000000),
+            Capability::AddPath(vec![AddPathFamily {
+                afi: Afi::Ipv4,
+                safi: Safi::Unicast,
+                direction: AddPathDirection::Both,
+            }]),
+        ])];
+        let message = BgpMessage::Open(open);
+        let text = to_json(&message).unwrap();
+        let value: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["open"]["bgp_identifier"], json!("192.0.2.1"));
+        assert_eq!(value["open"]["optional_parameters"][0]["capabilities"][0], json!({
+            "multiprotocol": { "afi": "ipv6", "safi": "unicast" },
+        }));
+        assert_eq!(from_json(&text).unwrap(), message);
+
+        // Numbers for names and unknown code points
+        let refresh = RouteRefreshMessage::new(Afi::Unknown(99), Safi::Unknown(250));
+        let value = serde_json::to_value(BgpMessage::RouteRefresh(refresh)).unwrap();
+        assert_eq!((&value["route_refresh"]["afi"], &value["route_refresh"]["safi"]), (&json!(99), &json!(250)));
+        assert_eq!(serde_json::from_value::<Afi>(json!(2)).unwrap(), Afi::Ipv6);
+    }
+
+    #[test]
+    fn test_json_err() {
+        let update = r#"{"update": {"withdrawn_routes": [], "path_attributes": [{"communities": ["65536:1"]}],
+            "nlri": []}}"#;
+        match from_json(update) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().starts_with("Invalid community \"65536:1\""), "{}", e),
+        }
+        let open = r#"{"open": {"version": 4, "my_as": 1, "hold_time": 0, "bgp_identifier": "0.0.0.0",
+            "optional_parameters": []}}"#;
+        match from_json(open) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().starts_with("Invalid BGP Identifier 0.0.0.0"), "{}", e),
+        }
+        assert!(serde_json::from_str::<Ipv6Prefix>(r#""2001:db8::/129""#).is_err());
+        assert!(serde_json::from_str::<Safi>(r#""unicorn""#).is_err());
+    }
+
+    fn neighbor() -> ExaBgpNeighbor {
+        ExaBgpNeighbor {
+            local_address: "10.0.0.2".parse().unwrap(),
+            peer_address: "10.0.0.1".parse().unwrap(),
+            local_asn: 65001,
+            peer_asn: 65000,
+            received: true,
+        }
+    }
+
+    #[test]
+    fn test_exabgp_round_trip() {
+        let prefix = |s: &str| s.parse::<Ipv4Prefix>().unwrap();
+        let mut update = UpdateMessage::default();
+        update.withdrawn_routes.value = vec![prefix("198.51.100.0/24").with_path_id(7)];
+        update.path_attributes.value = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![
+                AsPathSegment::AsConfedSequence(vec![65100]),
+                AsPathSegment::AsSequence(vec![65001, 4200000000]),
+                AsPathSegment::AsSet(vec![65010, 65011]),
+            ]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+            PathAttribute::MultiExitDisc(50),
+            PathAttribute::LocalPref(200),
+            PathAttribute::AtomicAggregate,
+            PathAttribute::Aggregator { asn: 65001, address: Ipv4Addr::new(192, 0, 2, 9) },
+            PathAttribute::Communities(vec![Community::new(65000, 100)]),
+            PathAttribute::OriginatorId(BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 5)).unwrap()),
+            PathAttribute::ClusterList(vec![Ipv4Addr::new(192, 0, 2, 6)]),
+            PathAttribute::MpReachNlri(MpReachNlri::ipv6_unicast(
+                "2001:db8::1".parse().unwrap(),
+                None,
+                vec!["2001:db8:1::/48".parse().unwrap()],
+            )),
+            PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                afi: Afi::Ipv6,
+                safi: Safi::Unicast,
+                withdrawn: Nlri::Ipv6(vec!["2001:db8:2::/48".parse().unwrap()]),
+            }),
+            PathAttribute::ExtendedCommunities(vec![ExtendedCommunity::route_target(65000, 1)]),
+            PathAttribute::LargeCommunities(vec![LargeCommunity::new(4200000000, 1, 2)]),
+        ];
+        update.nlri = vec![prefix("203.0.113.0/24")];
+        let message = BgpMessage::Update(update);
+
+        let value = to_exabgp(&message, &neighbor()).unwrap();
+        assert_eq!(value["type"], json!("update"));
+        assert_eq!(value["neighbor"]["address"], json!({ "local": "10.0.0.2", "peer": "10.0.0.1" }));
+        let update = &value["neighbor"]["message"]["update"];
+        assert_eq!(update["attribute"]["as-path"], json!([65001, 4200000000u32, [65010, 65011]]));
+        assert_eq!(update["attribute"]["confederation-path"], json!([65100]));
+        assert_eq!(update["attribute"]["aggregator"], json!("65001:192.0.2.9"));
+        assert_eq!(update["attribute"]["community"], json!([[65000, 100]]));
+        assert_eq!(update["attribute"]["extended-community"][0]["string"], json!("target:65000:1"));
+        assert_eq!(update["announce"]["ipv4 unicast"], json!({ "192.0.2.1": [{ "nlri": "203.0.113.0/24" }] }));
+        assert_eq!(update["withdraw"]["ipv4 unicast"], json!([
+            { "nlri": "198.51.100.0/24", "path-information": "0.0.0.7" },
+        ]));
+        assert_eq!(update["announce"]["ipv6 unicast"], json!({ "2001:db8::1": [{ "nlri": "2001:db8:1::/48" }] }));
+        assert_eq!(from_exabgp(&value).unwrap(), (neighbor(), message));
+
+        let notification = BgpMessage::Notification(NotificationMessage {
+            error_code: 6,
+            error_subcode: 2,
+            data: vec![0xde, 0xad],
+        });
+        let value = to_exabgp(&notification, &neighbor()).unwrap();
+        assert_eq!(value["neighbor"]["notification"], json!({ "code": 6, "subcode": 2, "data": "dead" }));
+        assert_eq!(from_exabgp(&value).unwrap().1, notification);
+        let keepalive = BgpMessage::Keepalive(KeepaliveMessage);
+        assert_eq!(from_exabgp(&to_exabgp(&keepalive, &neighbor()).unwrap()).unwrap().1, keepalive);
+    }
+
+    #[test]
+    fn test_exabgp_input() {
+        let text = r#"{ "exabgp": "4.0.1", "time": 1561040455.0, "host": "collector", "pid": 1, "ppid": 1,
+            "counter": 3, "type": "update", "neighbor": { "address": { "local": "10.0.0.2", "peer": "10.0.0.1" },
+            "asn": { "local": 65001, "peer": 65000 }, "direction": "receive", "message": { "update": {
+            "attribute": { "origin": "igp", "as-path": [ 65000 ], "confederation-path": [], "med": 0,
+            "local-preference": 100 }, "announce": { "ipv4 unicast": { "10.0.0.1": [ { "nlri": "192.0.2.0/24" } ]
+            } } } } } }"#;
+        let (peer, message) = from_exabgp(&serde_json::from_str(text).unwrap()).unwrap();
+        assert_eq!(peer, neighbor());
+        let update = UpdateMessage {
+            path_attributes: LengthPrefixed::new(vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65000])]),
+                PathAttribute::NextHop(Ipv4Addr::new(10, 0, 0, 1)),
+                PathAttribute::MultiExitDisc(0),
+                PathAttribute::LocalPref(100),
+            ]),
+            nlri: vec!["192.0.2.0/24".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(message, BgpMessage::Update(update));
+    }
+
+    #[test]
+    fn test_exabgp_err() {
+        let open = OpenMessage::new(65001, 90, BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap());
+        match to_exabgp(&BgpMessage::Open(open), &neighbor()) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "ExaBGP has no Open message"),
+        }
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![PathAttribute::Aigp(vec![AigpTlv::Metric(1000)])];
+        match to_exabgp(&BgpMessage::Update(update), &neighbor()) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().starts_with("ExaBGP has no attribute for Aigp"), "{}", e),
+        }
+        let mut value = to_exabgp(&BgpMessage::Keepalive(KeepaliveMessage), &neighbor()).unwrap();
+        value["neighbor"]["direction"] = json!("sideways");
+        match from_exabgp(&value) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "ExaBGP direction \"sideways\" unknown"),
+        }
+
+        // IPv6 routes under two next hops would need two MP_REACH_NLRIs
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![PathAttribute::MpReachNlri(MpReachNlri::ipv6_unicast(
+            "2001:db8::1".parse().unwrap(),
+            None,
+            vec!["2001:db8:1::/48".parse().unwrap()],
+        ))];
+        let mut value = to_exabgp(&BgpMessage::Update(update), &neighbor()).unwrap();
+        let announce = &mut value["neighbor"]["message"]["update"]["announce"]["ipv6 unicast"];
+        announce["2001:db8::2"] = json!([{ "nlri": "2001:db8:2::/48" }]);
+        match from_exabgp(&value) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "ExaBGP IPv6 routes must share one next hop to fit in an UPDATE"),
+        }
+    }
+}
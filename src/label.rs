@@ -0,0 +1,351 @@
+// MPLS label stacks as carried in NLRI (RFC 3032 entries, RFC 8277 usage).
+//
+// Each entry is 3 octets: a 20 bit label, 3 bits of traffic class and the
+// bottom-of-stack bit. LabelStack sets that bit on its last entry when
+// serializing and stops reading at the first entry that has it. Withdrawals
+// may carry the 0x800000 placeholder instead of a stack; it decodes to an
+// empty stack, and an empty stack is written as that placeholder.
+//
+// Labeled unicast (SAFI 4) routes are a length in bits covering the stack
+// and the prefix, the stack, then the significant prefix octets.
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::Afi;
+use crate::prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+
+pub const MAX_LABEL: u32 = 0x0f_ffff;
+// Label stack entry sent in withdrawals instead of the route's labels.
+const WITHDRAWN_ENTRY: [u8; 3] = [0x80, 0, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Label {
+    // 20 bits
+    pub value: u32,
+    // Traffic class, 3 bits
+    pub tc: u8,
+    pub bottom: bool,
+}
+
+// Outermost label first. Stacks built with new, or decoded, have the bottom
+// flag set on exactly the last entry, so they compare equal after a round
+// trip.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LabelStack(pub Vec<Label>);
+
+// A labeled unicast route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LabeledPrefix {
+    pub labels: LabelStack,
+    pub prefix: IpPrefix,
+}
+
+impl Label {
+    pub fn new(value: u32) -> Option<Self> {
+        match value <= MAX_LABEL {
+            true => Some(Label { value, tc: 0, bottom: false }),
+            false => None,
+        }
+    }
+
+    pub fn with_tc(self, tc: u8) -> Self {
+        Label { tc, ..self }
+    }
+
+    pub fn octets(&self) -> [u8; 3] {
+        let entry = (self.value & MAX_LABEL) << 4 | (self.tc as u32 & 0x07) << 1 | self.bottom as u32;
+        let [_, a, b, c] = entry.to_be_bytes();
+        [a, b, c]
+    }
+
+    pub fn from_octets(octets: [u8; 3]) -> Self {
+        let entry = u32::from_be_bytes([0, octets[0], octets[1], octets[2]]);
+        Label { value: entry >> 4, tc: (entry >> 1 & 0x07) as u8, bottom: entry & 0x01 != 0 }
+    }
+}
+
+impl LabelStack {
+    pub fn new(mut labels: Vec<Label>) -> Self {
+        let n = labels.len();
+        for (i, label) in labels.iter_mut().enumerate() {
+            label.bottom = i + 1 == n;
+        }
+        LabelStack(labels)
+    }
+
+    // A stack of plain labels, traffic class zero.
+    pub fn from_values<I: IntoIterator<Item = u32>>(values: I) -> Option<Self> {
+        values.into_iter().map(Label::new).collect::<Option<_>>().map(LabelStack::new)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Entries on the wire, counting the withdrawal placeholder.
+    pub(crate) fn wire_len(&self) -> usize {
+        self.0.len().max(1)
+    }
+}
+
+impl From<Vec<Label>> for LabelStack {
+    fn from(labels: Vec<Label>) -> Self {
+        LabelStack::new(labels)
+    }
+}
+
+impl LabeledPrefix {
+    pub fn new(labels: LabelStack, prefix: IpPrefix) -> Self {
+        LabeledPrefix { labels, prefix }
+    }
+}
+
+// -- Wire form --
+
+impl Serialize for Label {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.value > MAX_LABEL {
+            return Err(ser::Error::custom(format!("MPLS label {} does not fit in 20 bits", self.value)));
+        }
+        if self.tc > 0x07 {
+            return Err(ser::Error::custom(format!("Traffic class {} does not fit in 3 bits", self.tc)));
+        }
+        self.octets().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Label {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        <[u8; 3]>::deserialize(deserializer).map(Label::from_octets)
+    }
+}
+
+impl Serialize for LabelStack {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(self.wire_len())?;
+        if self.0.is_empty() {
+            tup.serialize_element(&WITHDRAWN_ENTRY)?;
+        }
+        for (i, label) in self.0.iter().enumerate() {
+            tup.serialize_element(&Label { bottom: i + 1 == self.0.len(), ..*label })?;
+        }
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelStack {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct StackVisitor;
+
+        impl<'de> Visitor<'de> for StackVisitor {
+            type Value = LabelStack;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an MPLS label stack")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut labels = Vec::new();
+                loop {
+                    let octets: [u8; 3] = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(labels.len(), &self))?;
+                    if octets == WITHDRAWN_ENTRY && labels.is_empty() {
+                        return Ok(LabelStack(labels));
+                    }
+                    let label = Label::from_octets(octets);
+                    labels.push(label);
+                    if label.bottom {
+                        return Ok(LabelStack(labels));
+                    }
+                }
+            }
+        }
+
+        deserializer.deserialize_seq(StackVisitor)
+    }
+}
+
+impl Serialize for LabeledPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let len = self.prefix.prefix_len();
+        let bits = self.labels.wire_len() * 24 + len as usize;
+        let bits = u8::try_from(bits)
+            .map_err(|_| ser::Error::custom(format!("Labeled route is {} bits, at most 255 fit the length", bits)))?;
+        let n = (len as usize).div_ceil(8);
+        let mut tup = serializer.serialize_tuple(2 + n)?;
+        tup.serialize_element(&bits)?;
+        tup.serialize_element(&self.labels)?;
+        for b in &prefix_octets(&self.prefix)[..n] {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+pub(crate) fn prefix_octets(prefix: &IpPrefix) -> Vec<u8> {
+    match prefix.addr() {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    }
+}
+
+// Reads the significant octets of a `len` bit prefix of `afi`. `index` is
+// the element count so far, for errors.
+pub(crate) fn read_prefix<'de, A: SeqAccess<'de>>(
+    seq: &mut A,
+    afi: Afi,
+    len: usize,
+    index: usize,
+    exp: &dyn de::Expected,
+) -> std::result::Result<IpPrefix, A::Error> {
+    let max = match afi {
+        Afi::Ipv4 => 32,
+        _ => 128,
+    };
+    if len > max {
+        return Err(de::Error::custom(format!("Invalid prefix length {}, at most {} allowed", len, max)));
+    }
+    let mut octets = [0u8; 16];
+    for (i, b) in octets.iter_mut().take(len.div_ceil(8)).enumerate() {
+        *b = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(index + i, exp))?;
+    }
+    let prefix = match afi {
+        Afi::Ipv4 => Ipv4Prefix::new(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]), len as u8).map(IpPrefix::V4),
+        _ => Ipv6Prefix::new(Ipv6Addr::from(octets), len as u8).map(IpPrefix::V6),
+    };
+    // The length was checked above
+    prefix.ok_or_else(|| de::Error::custom(format!("Invalid prefix length {}", len)))
+}
+
+// Labeled unicast routes of `afi` up to the end of the NLRI.
+pub(crate) struct LabeledSeed {
+    pub afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for LabeledSeed {
+    type Value = Vec<LabeledPrefix>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LabeledSeed {
+    type Value = Vec<LabeledPrefix>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("labeled unicast NLRI")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut routes = Vec::new();
+        while let Some(route) = seq.next_element_seed(LabeledPrefixSeed { afi: self.afi })? {
+            routes.push(route);
+        }
+        Ok(routes)
+    }
+}
+
+struct LabeledPrefixSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for LabeledPrefixSeed {
+    type Value = LabeledPrefix;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        // Length, stack and up to 16 address octets
+        d.deserialize_tuple(2 + 16, self)
+    }
+}
+
+impl<'de> Visitor<'de> for LabeledPrefixSeed {
+    type Value = LabeledPrefix;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a labeled route")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let bits: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let labels: LabelStack = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let len = (bits as usize)
+            .checked_sub(labels.wire_len() * 24)
+            .ok_or_else(|| de::Error::custom(format!("Labeled route length {} is shorter than its label stack", bits)))?;
+        let prefix = read_prefix(&mut seq, self.afi, len, 2, &self)?;
+        Ok(LabeledPrefix { labels, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, MpReachNlri, MpUnreachNlri, NextHop, Nlri, Safi};
+
+    #[test]
+    fn test_label_wire() {
+        let label = Label::new(16).unwrap().with_tc(5);
+        assert_eq!(to_bytes(label).unwrap().as_ref(), &[0, 1, 0x0a]);
+        assert_eq!(Label::from_octets([0, 1, 0x0b]), Label { bottom: true, ..label });
+        assert_eq!(Label::new(MAX_LABEL + 1), None);
+        assert!(to_bytes(Label { value: MAX_LABEL + 1, ..label }).is_err());
+        assert!(to_bytes(Label { tc: 8, ..label }).is_err());
+
+        // Bottom of stack moves to the last entry whatever the input says
+        let stack = LabelStack(vec![Label { bottom: true, ..label }, Label::new(MAX_LABEL).unwrap()]);
+        let wire = to_bytes(&stack).unwrap();
+        assert_eq!(wire.as_ref(), &[0, 1, 0x0a, 0xff, 0xff, 0xf1]);
+        let decoded: LabelStack = from_bytes(&wire).unwrap();
+        assert_eq!(decoded, LabelStack::new(stack.0));
+        assert_eq!(decoded.0[0], label);
+        assert!(decoded.0[1].bottom);
+
+        assert_eq!(to_bytes(LabelStack::default()).unwrap().as_ref(), &WITHDRAWN_ENTRY);
+        assert_eq!(from_bytes::<LabelStack>(&WITHDRAWN_ENTRY).unwrap(), LabelStack::default());
+    }
+
+    #[test]
+    fn test_labeled_unicast_wire() {
+        let route = LabeledPrefix::new(LabelStack::from_values([100]).unwrap(), "10.1.0.0/16".parse().unwrap());
+        let reach = MpReachNlri {
+            afi: Afi::Ipv4,
+            safi: Safi::Mpls,
+            next_hop: NextHop::Ipv4(Ipv4Addr::new(192, 0, 2, 1)),
+            nlri: Nlri::Labeled(vec![route.clone()]),
+        };
+        let wire: &[u8] = &[0, 1, 4, 4, 192, 0, 2, 1, 0, 40, 0, 0x06, 0x41, 10, 1];
+        assert_eq!(to_bytes(&reach).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<MpReachNlri>(wire).unwrap(), reach);
+
+        let withdrawn = LabeledPrefix::new(LabelStack::default(), "2001:db8::/32".parse().unwrap());
+        let unreach = MpUnreachNlri { afi: Afi::Ipv6, safi: Safi::Mpls, withdrawn: Nlri::Labeled(vec![withdrawn]) };
+        let wire = to_bytes(&unreach).unwrap();
+        assert_eq!(&wire[3..], &[56, 0x80, 0, 0, 0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(from_bytes::<MpUnreachNlri>(&wire).unwrap(), unreach);
+    }
+
+    #[test]
+    fn test_labeled_unicast_err() {
+        // Length shorter than the stack
+        let wire = [0, 1, 4, 16, 0, 0x06, 0x41];
+        match from_bytes::<MpUnreachNlri>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Labeled route length 16 is shorter than its label stack"),
+        }
+        let wire = [0, 1, 4, 24 + 33, 0, 0x06, 0x41, 10, 0, 0, 0, 0];
+        match from_bytes::<MpUnreachNlri>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid prefix length 33, at most 32 allowed"),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+// Wire layout introspection.
+//
+// Runs a value through a tracing serializer that mirrors the wire Serializer
+// but, instead of emitting octets, records where every field lands: its path,
+// nesting depth, offset, width and kind. The result derives Serialize, so it
+// can be dumped through any serde format for documentation or for comparing
+// layouts against another implementation.
+use serde::{ser, Serialize};
+
+use crate::error::{SerializerError, Result};
+use crate::attributes::ATTR_EXTENDED_LENGTH;
+use crate::types::{
+    length_prefix_width, tag_width, ASN, PADDED, FOUR_OCTET_ASN, MULTICAST_NLRI, PATH_ATTRIBUTE, PATH_ID_IPV4, PATH_ID_IPV6,
+    TAKE_REST, TWO_OCTET_SESSION,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LayoutKind {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    // Raw octet string, width is whatever the value held.
+    Bytes,
+    // Length field written in front of a LengthPrefixed value.
+    Length,
+    // Sequences and tuples; elements are nested one level deeper.
+    Seq,
+    // Structs, tuple structs, newtypes and data carrying enum variants.
+    Struct,
+    // None, unit and unit variants; present in the model, absent on the wire.
+    Absent,
+    // Type code in front of a TaggedEnum variant.
+    Tag,
+    // Zero octets after a Padded value.
+    Padding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayoutEntry {
+    // Dotted path from the root, sequence elements are indexed as "[i]".
+    pub path: String,
+    // Type name (and variant, as "Type::Variant") for compound entries.
+    pub name: Option<String>,
+    pub depth: usize,
+    pub offset: usize,
+    pub width: usize,
+    pub kind: LayoutKind,
+}
+
+// Entries are returned in wire order, containers before their children.
+pub fn describe_layout<T: Serialize>(value: &T) -> Result<Vec<LayoutEntry>> {
+    let mut tracer = LayoutTracer {
+        offset: 0,
+        tag_width: None,
+        padded: Vec::new(),
+        path: Vec::new(),
+        open: Vec::new(),
+        entries: Vec::new(),
+    };
+    value.serialize(&mut tracer)?;
+    Ok(tracer.entries)
+}
+
+// A container that has been entered but not yet closed.
+struct OpenContainer {
+    entry: usize,
+    next_index: usize,
+    indexed: bool,
+}
+
+struct LayoutTracer {
+    offset: usize,
+    // Type code width for the enum right inside a TaggedEnum
+    tag_width: Option<usize>,
+    // Padded width of each open tuple struct that is a Padded.
+    padded: Vec<Option<usize>>,
+    path: Vec<String>,
+    open: Vec<OpenContainer>,
+    entries: Vec<LayoutEntry>,
+}
+
+impl LayoutTracer {
+    fn current_path(&self) -> String {
+        self.path.join(".")
+    }
+
+    fn record(&mut self, kind: LayoutKind, width: usize) {
+        self.entries.push(LayoutEntry {
+            path: self.current_path(),
+            name: None,
+            depth: self.open.len(),
+            offset: self.offset,
+            width,
+            kind,
+        });
+        self.offset += width;
+    }
+
+    fn open(&mut self, kind: LayoutKind, name: Option<String>, indexed: bool) {
+        // The root container has no field name to hang off of, use the type.
+        let root = self.path.is_empty() && self.open.is_empty();
+        if root {
+            if let Some(n) = &name {
+                self.path.push(n.clone());
+            }
+        }
+        self.entries.push(LayoutEntry {
+            path: self.current_path(),
+            name,
+            depth: self.open.len(),
+            offset: self.offset,
+            width: 0,
+            kind,
+        });
+        self.open.push(OpenContainer {
+            entry: self.entries.len() - 1,
+            next_index: 0,
+            indexed,
+        });
+    }
+
+    fn close(&mut self) {
+        if let Some(c) = self.open.pop() {
+            let entry = &mut self.entries[c.entry];
+            entry.width = self.offset - entry.offset;
+        }
+    }
+
+    // Serializes a nested value under the given path segment.
+    fn nested<T>(&mut self, segment: String, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.path.push(segment);
+        let res = value.serialize(&mut *self);
+        self.path.pop();
+        res
+    }
+
+    // Path attributes are traced with a 2 octet length, then shrunk to match
+    // the Serializer when the real encoding uses a 1 octet length.
+    fn trace_path_attribute<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let wire = crate::ser::to_bytes(value)?;
+        let short = wire[0] & ATTR_EXTENDED_LENGTH == 0 && wire.len() - 4 <= u8::MAX as usize;
+
+        let first = self.entries.len();
+        self.open(LayoutKind::Struct, Some(String::from("PathAttribute")), false);
+        let res = self.nested(String::from("0"), value);
+        let len_entry = self.entries[first..].iter().position(|e| e.kind == LayoutKind::Length);
+        if let (true, Some(i)) = (short, len_entry) {
+            let i = first + i;
+            let at = self.entries[i].offset;
+            self.entries[i].width = 1;
+            // Already closed containers around the length lose an octet,
+            // everything after it moves up by one.
+            for entry in &mut self.entries[first + 1..i] {
+                if entry.offset + entry.width > at {
+                    entry.width -= 1;
+                }
+            }
+            for entry in &mut self.entries[i + 1..] {
+                entry.offset -= 1;
+            }
+            self.offset -= 1;
+        }
+        self.close();
+        res
+    }
+
+    // The type code of a variant right inside a TaggedEnum, if this is one.
+    fn record_tag(&mut self) -> bool {
+        match self.tag_width.take() {
+            Some(width) => {
+                self.path.push(String::from("tag"));
+                self.record(LayoutKind::Tag, width);
+                self.path.pop();
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn next_segment(&mut self) -> String {
+        match self.open.last_mut() {
+            Some(c) => {
+                let i = c.next_index;
+                c.next_index += 1;
+                if c.indexed {
+                    format!("[{}]", i)
+                } else {
+                    i.to_string()
+                }
+            },
+            None => String::new(),
+        }
+    }
+
+    fn metadata(&self) -> Option<String> {
+        Some(format!("Path: \"{}\"", self.current_path()))
+    }
+}
+
+impl ser::Serializer for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    type SerializeMap = Self;
+    type SerializeSeq = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        self.record(LayoutKind::Bool, 1);
+        Ok(())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(self.metadata()))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(self.metadata()))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(self.metadata()))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(self.metadata()))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        self.record(LayoutKind::U8, 1);
+        Ok(())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        self.record(LayoutKind::U16, 2);
+        Ok(())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        self.record(LayoutKind::U32, 4);
+        Ok(())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        self.record(LayoutKind::U64, 8);
+        Ok(())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(SerializerError::UnsupportedFloat(self.metadata()))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(SerializerError::UnsupportedFloat(self.metadata()))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Err(SerializerError::UnsupportedText(self.metadata()))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Err(SerializerError::UnsupportedText(self.metadata()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.record(LayoutKind::Bytes, v.len());
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.record(LayoutKind::Absent, 0);
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.record(LayoutKind::Absent, 0);
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.record(LayoutKind::Absent, 0);
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        if !self.record_tag() {
+            self.record(LayoutKind::Absent, 0);
+        }
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        // LengthPrefixed shows up as its length field followed by the value.
+        if let Some(width) = length_prefix_width(name) {
+            let name = format!("LengthPrefixed<u{}>", width * 8);
+            self.open(LayoutKind::Struct, Some(name), false);
+            self.path.push(String::from("len"));
+            self.record(LayoutKind::Length, width);
+            self.path.pop();
+            let res = self.nested(String::from("value"), value);
+            self.close();
+            return res;
+        }
+        if name == PATH_ATTRIBUTE {
+            return self.trace_path_attribute(value);
+        }
+        // Traced at the default 4-octet width, where AS4_* companions are
+        // never written.
+        if name == ASN || name == FOUR_OCTET_ASN {
+            return value.serialize(self);
+        }
+        // Likewise without ADD-PATH, so no path identifiers.
+        if name == TWO_OCTET_SESSION || name == PATH_ID_IPV4 || name == PATH_ID_IPV6 {
+            return Ok(());
+        }
+        if name == MULTICAST_NLRI || name == TAKE_REST {
+            return value.serialize(self);
+        }
+        if let Some(width) = tag_width(name) {
+            let saved = self.tag_width;
+            self.tag_width = Some(width);
+            let res = value.serialize(&mut *self);
+            self.tag_width = saved;
+            return res;
+        }
+        self.open(LayoutKind::Struct, Some(name.to_string()), false);
+        let res = self.nested(String::from("0"), value);
+        self.close();
+        res
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.open(LayoutKind::Struct, Some(format!("{}::{}", name, variant)), false);
+        self.record_tag();
+        let res = self.nested(String::from("0"), value);
+        self.close();
+        res
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.open(LayoutKind::Seq, None, true);
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        self.open(LayoutKind::Seq, None, true);
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.padded.push((name == PADDED).then_some(len));
+        let name = if name == PADDED { format!("Padded<{}>", len) } else { name.to_string() };
+        self.open(LayoutKind::Struct, Some(name), false);
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.open(LayoutKind::Struct, Some(format!("{}::{}", name, variant)), false);
+        self.record_tag();
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(SerializerError::UnsupportedMap(self.metadata()))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct> {
+        self.open(LayoutKind::Struct, Some(name.to_string()), false);
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.open(LayoutKind::Struct, Some(format!("{}::{}", name, variant)), false);
+        self.record_tag();
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let segment = self.next_segment();
+        self.nested(segment, value)
+    }
+    fn end(self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let segment = self.next_segment();
+        self.nested(segment, value)
+    }
+    fn end(self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let segment = self.next_segment();
+        self.nested(segment, value)
+    }
+    fn end(self) -> Result<()> {
+        if let Some(Some(width)) = self.padded.pop() {
+            let used = self.offset - self.entries[self.open.last().map_or(0, |c| c.entry)].offset;
+            if used > width {
+                return Err(SerializerError::LengthOverflow(self.metadata()));
+            }
+            self.path.push(String::from("padding"));
+            self.record(LayoutKind::Padding, width - used);
+            self.path.pop();
+        }
+        self.close();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let segment = self.next_segment();
+        self.nested(segment, value)
+    }
+    fn end(self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.nested(String::from(key), value)
+    }
+    fn end(self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.nested(String::from(key), value)
+    }
+    fn end(self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+// Maps are unsupported on the wire, so they are unsupported here too.
+impl ser::SerializeMap for &mut LayoutTracer {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_key<T>(&mut self, _key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(SerializerError::UnsupportedMap(self.metadata()))
+    }
+    fn serialize_value<T>(&mut self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(SerializerError::UnsupportedMap(self.metadata()))
+    }
+    fn end(self) -> Result<()> {
+        Err(SerializerError::UnsupportedMap(self.metadata()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_bytes;
+
+    #[derive(Serialize)]
+    struct Header {
+        marker: [u8; 2],
+        length: u16,
+        msg_type: u8,
+    }
+
+    #[derive(Serialize)]
+    struct Wrapper {
+        header: Header,
+        body: Vec<u32>,
+        opt: Option<u8>,
+    }
+
+    #[test]
+    fn test_layout_offsets_and_paths() {
+        let value = Wrapper {
+            header: Header { marker: [0xff, 0xff], length: 11, msg_type: 4 },
+            body: vec![1, 2],
+            opt: None,
+        };
+        let layout = describe_layout(&value).unwrap();
+        let flat: Vec<(&str, usize, usize, usize, LayoutKind)> = layout
+            .iter()
+            .map(|e| (e.path.as_str(), e.depth, e.offset, e.width, e.kind))
+            .collect();
+
+        assert_eq!(flat, vec![
+            ("Wrapper", 0, 0, 13, LayoutKind::Struct),
+            ("Wrapper.header", 1, 0, 5, LayoutKind::Struct),
+            ("Wrapper.header.marker", 2, 0, 2, LayoutKind::Seq),
+            ("Wrapper.header.marker.[0]", 3, 0, 1, LayoutKind::U8),
+            ("Wrapper.header.marker.[1]", 3, 1, 1, LayoutKind::U8),
+            ("Wrapper.header.length", 2, 2, 2, LayoutKind::U16),
+            ("Wrapper.header.msg_type", 2, 4, 1, LayoutKind::U8),
+            ("Wrapper.body", 1, 5, 8, LayoutKind::Seq),
+            ("Wrapper.body.[0]", 2, 5, 4, LayoutKind::U32),
+            ("Wrapper.body.[1]", 2, 9, 4, LayoutKind::U32),
+            ("Wrapper.opt", 1, 13, 0, LayoutKind::Absent),
+        ]);
+        assert_eq!(layout[1].name.as_deref(), Some("Header"));
+
+        // The traced width must agree with what actually hits the wire.
+        assert_eq!(layout[0].width, to_bytes(&value).unwrap().len());
+    }
+
+    #[test]
+    fn test_layout_length_prefixed() {
+        #[derive(Serialize)]
+        struct Attr {
+            code: u8,
+            value: crate::LengthPrefixed<u16, Vec<u8>>,
+        }
+        let layout = describe_layout(&Attr { code: 2, value: vec![1, 2, 3].into() }).unwrap();
+        let flat: Vec<(&str, usize, usize, LayoutKind)> = layout
+            .iter()
+            .map(|e| (e.path.as_str(), e.offset, e.width, e.kind))
+            .collect();
+
+        assert_eq!(flat, vec![
+            ("Attr", 0, 6, LayoutKind::Struct),
+            ("Attr.code", 0, 1, LayoutKind::U8),
+            ("Attr.value", 1, 5, LayoutKind::Struct),
+            ("Attr.value.len", 1, 2, LayoutKind::Length),
+            ("Attr.value.value", 3, 3, LayoutKind::Seq),
+            ("Attr.value.value.[0]", 3, 1, LayoutKind::U8),
+            ("Attr.value.value.[1]", 4, 1, LayoutKind::U8),
+            ("Attr.value.value.[2]", 5, 1, LayoutKind::U8),
+        ]);
+    }
+
+    #[test]
+    fn test_layout_tagged_enum() {
+        #[derive(Serialize)]
+        enum Tlv {
+            #[serde(rename = "1")]
+            Value(u16),
+        }
+        #[derive(Serialize)]
+        struct Tlvs {
+            tlv: crate::TaggedEnum<u8, Tlv>,
+        }
+        let layout = describe_layout(&Tlvs { tlv: Tlv::Value(7).into() }).unwrap();
+        let flat: Vec<(&str, usize, usize, LayoutKind)> = layout
+            .iter()
+            .map(|e| (e.path.as_str(), e.offset, e.width, e.kind))
+            .collect();
+
+        assert_eq!(flat, vec![
+            ("Tlvs", 0, 3, LayoutKind::Struct),
+            ("Tlvs.tlv", 0, 3, LayoutKind::Struct),
+            ("Tlvs.tlv.tag", 0, 1, LayoutKind::Tag),
+            ("Tlvs.tlv.0", 1, 2, LayoutKind::U16),
+        ]);
+        assert_eq!(layout[1].name.as_deref(), Some("Tlv::1"));
+    }
+
+    #[test]
+    fn test_layout_padded() {
+        let layout = describe_layout(&crate::Padded::<u16, 4>(1)).unwrap();
+        let flat: Vec<(&str, usize, usize, LayoutKind)> = layout
+            .iter()
+            .map(|e| (e.path.as_str(), e.offset, e.width, e.kind))
+            .collect();
+
+        assert_eq!(flat, vec![
+            ("Padded<4>", 0, 4, LayoutKind::Struct),
+            ("Padded<4>.0", 0, 2, LayoutKind::U16),
+            ("Padded<4>.padding", 2, 2, LayoutKind::Padding),
+        ]);
+    }
+
+    #[test]
+    fn test_layout_err_path() {
+        #[derive(Serialize)]
+        struct Bad {
+            ok: u8,
+            bad: i8,
+        }
+        match describe_layout(&Bad { ok: 1, bad: -1 }) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.to_string(), "Serialization of signed ints unsupported. Error info - Path: \"Bad.bad\".")
+            },
+        }
+    }
+}
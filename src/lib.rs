@@ -1,12 +1,127 @@
 // BGP message serialization and deserialization using serde
 
+mod afi;
+mod aigp;
+#[cfg(feature = "bumpalo")]
+pub mod arena;
+mod as_path;
+#[cfg(feature = "futures-io")]
+pub mod async_futures;
+#[cfg(feature = "tokio")]
+pub mod async_tokio;
+mod attributes;
+mod bgp_ls;
+mod bgpsec;
+mod bmp;
+mod capabilities;
+#[cfg(feature = "tokio")]
+mod codec;
+mod communities;
 mod de;
 mod error;
+mod evpn;
+#[cfg(feature = "bitflags")]
+mod flags;
+mod flowspec;
+#[cfg(feature = "json")]
+pub mod json;
+mod label;
+mod layout;
+mod messages;
+#[cfg(feature = "mrt")]
+pub mod mrt;
+mod multiprotocol;
+mod notification;
+mod orf;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+mod prefix;
+mod prefix_sid;
+pub mod registry;
+mod routes;
 mod ser;
+mod size;
+mod stream;
+mod types;
+mod update_builder;
+mod validate;
+mod vpn;
 
-pub use de::Deserializer;
-pub use error::{SerializerError, Result};
-pub use ser::{to_bytes, Serializer};
+pub use afi::{AddPathFamilies, Afi, Safi};
+#[cfg(feature = "derive")]
+pub use bgp4_serde_derive::wire_codes;
+pub use aigp::{increment_aigp, increment_aigp_in_update, AigpTlv, AIGP_TLV, AIGP_TLV_LEN};
+pub use as_path::{
+    as_path_contains, as_path_len, neighbor_as, prepend_asn, strip_confederations, AsPathIter, AsPathSegment,
+    AsPathSegmentRef, AsnIter, AS_CONFED_SEQUENCE, AS_CONFED_SET, AS_SEQUENCE, AS_SET, AS_TRANS,
+};
+pub use attributes::{
+    reflect, reflection_loop, strip_elc, Origin, PathAttribute, PathAttributeRef, ATTR_EXTENDED_LENGTH, ATTR_OPTIONAL,
+    ATTR_PARTIAL, ATTR_RESERVED, ATTR_TRANSITIVE,
+};
+pub use bgp_ls::{
+    Bandwidth, LinkDescriptor, LinkStateAttribute, LinkStateNlri, NodeDescriptor, PrefixDescriptor, SidLabel, SrgbRange,
+};
+pub use bgpsec::{
+    AlgorithmSuite, BgpsecPath, SecurePathSegment, SignatureBlock, SignatureSegment, BGPSEC_CONFED_SEGMENT,
+};
+pub use bmp::{
+    BmpInformation, BmpMessage, BmpPeerHeader, BmpPeerUp, BmpStat, BmpTlv, PeerDownReason, BMP_FLAG_ADJ_RIB_OUT,
+    BMP_FLAG_LEGACY_AS_PATH, BMP_FLAG_POST_POLICY, BMP_HEADER_LEN, BMP_VERSION, BMP_VERSION_4,
+};
+pub use capabilities::{
+    AddPathDirection, AddPathFamily, Capability, CapabilityAction, CapabilityChange, ExtendedNextHopFamily,
+    GracefulRestart, GracefulRestartFamily, LlgrFamily, OptionalParameter, OrfFamily, OrfSupport, GR_FORWARDING_STATE,
+    GR_NOTIFICATION, GR_RESTART_STATE,
+};
+#[cfg(feature = "tokio")]
+pub use codec::BgpCodec;
+pub use communities::{is_llgr_stale, strip_llgr, Community, ExtendedAdmin, ExtendedCommunity, LargeCommunity};
+pub use de::{
+    from_bytes, from_bytes_with, from_message_bytes, from_message_bytes_with, Deserializer, DeserializerOptions,
+    OnUnknown, ReadCheckpoint, ReservedFlags, UnknownMessages,
+};
+pub use error::{DeserializeError, SerializerError, Result};
+pub use evpn::{
+    Esi, EvpnRoute, MacAddress, EVPN_ETHERNET_AUTO_DISCOVERY, EVPN_ETHERNET_SEGMENT, EVPN_INCLUSIVE_MULTICAST,
+    EVPN_IP_PREFIX, EVPN_MAC_IP_ADVERTISEMENT,
+};
+#[cfg(feature = "bitflags")]
+pub use flags::{LenientFlags, StrictFlags};
+pub use flowspec::{
+    BitmaskMatch, FlowSpecComponent, FlowSpecPrefix, FlowSpecRule, NumericMatch, FLOWSPEC_MAX_LEN, FRAGMENT_DONT,
+    FRAGMENT_FIRST, FRAGMENT_IS, FRAGMENT_LAST,
+};
+pub use label::{Label, LabelStack, LabeledPrefix, MAX_LABEL};
+pub use layout::{describe_layout, LayoutEntry, LayoutKind};
+pub use messages::{
+    negotiate_hold_time, negotiate_max_message_len, normalize, BgpMessage, CapabilityMessage, HoldTimers,
+    KeepaliveMessage, Message, MessageType, NotificationMessage, OpenMessage, RawMessage, ReceivedMessage,
+    RouteRefreshMessage,
+    RouteRefreshSubtype, UpdateMessage, UpdateMessageRef, EXTENDED_MAX_MESSAGE_LEN, HEADER_LEN, MARKER,
+    MAX_MESSAGE_LEN,
+};
+pub use multiprotocol::{MpReachNlri, MpUnreachNlri, NextHop, Nlri};
+pub use notification::{
+    CeaseSubcode, FsmError, MessageHeaderError, NotificationCode, OpenMessageError, UpdateMessageError,
+};
+pub use orf::{Orf, OrfAction, OrfEntry, OrfMatch, OrfPrefix, RouteRefreshOrf, WhenToRefresh};
+pub use prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+pub use prefix_sid::{PrefixSidTlv, SrgbBlock};
+pub use routes::{RouteAction, RouteEntry};
+pub use ser::{
+    to_bytes, to_bytes_in, to_bytes_in_with, to_bytes_with, to_message_bytes, to_message_bytes_with, to_writer,
+    to_writer_with, Checkpoint, Endianness, Serializer, SerializerBuilder, SerializerOptions,
+};
+pub use size::{serialized_size, serialized_size_with};
+pub use stream::{from_reader, recv_message, send_message, DecodeStats, MessageStream, Quarantine, QuarantinedFrame};
+pub use types::{
+    BgpIdentifier, Counted, LengthPrefixed, LengthType, OctetsToEnd, Padded, PaddedTo, Presence, SerializeIter,
+    SizePrefixed, TagType, TakeRest, TaggedEnum, Timestamped, U24,
+};
+pub use update_builder::{pack_routes, UpdateBuilder, UpdateMessages};
+pub use validate::{validate_header, validate_header_with, Validate, ValidationError};
+pub use vpn::{Rd, VpnPrefix};
 
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,916 @@
+// BGP message models and helpers that go along with them.
+use std::fmt;
+use std::ops::Range;
+
+use bytes::Bytes;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::attributes::{PathAttribute, ATTR_EXTENDED_LENGTH};
+use crate::capabilities::{Capability, CapabilityChange, OptionalParameter};
+use crate::communities::ExtendedCommunity;
+use crate::de::{from_bytes, from_bytes_with, message_header, DeserializerOptions, UnknownMessages};
+use crate::error::DeserializeError;
+use crate::multiprotocol::{MpReachNlri, MpUnreachNlri, Nlri};
+use crate::notification::{CeaseSubcode, NotificationCode};
+use crate::orf::{Orf, RouteRefreshOrf, RouteRefreshOrfSeed, WhenToRefresh};
+use crate::prefix::Ipv4Prefix;
+use crate::types::{BgpIdentifier, LengthPrefixed};
+
+// Every message starts with a 16 octet all-ones marker, a 2 octet total
+// length (header included) and a 1 octet type.
+pub const MARKER: [u8; 16] = [0xff; 16];
+pub const HEADER_LEN: usize = 19;
+pub const MAX_MESSAGE_LEN: usize = 4096;
+// RFC 8654, once both sides advertised the Extended Message capability.
+// KEEPALIVEs and OPENs still have to fit in MAX_MESSAGE_LEN.
+pub const EXTENDED_MAX_MESSAGE_LEN: usize = 65535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum MessageType {
+    Open,
+    Update,
+    Notification,
+    Keepalive,
+    RouteRefresh,
+    Capability,
+    Unknown(u8),
+}
+
+impl From<u8> for MessageType {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => MessageType::Open,
+            2 => MessageType::Update,
+            3 => MessageType::Notification,
+            4 => MessageType::Keepalive,
+            5 => MessageType::RouteRefresh,
+            6 => MessageType::Capability,
+            other => MessageType::Unknown(other),
+        }
+    }
+}
+
+impl From<MessageType> for u8 {
+    fn from(t: MessageType) -> Self {
+        match t {
+            MessageType::Open => 1,
+            MessageType::Update => 2,
+            MessageType::Notification => 3,
+            MessageType::Keepalive => 4,
+            MessageType::RouteRefresh => 5,
+            MessageType::Capability => 6,
+            MessageType::Unknown(other) => other,
+        }
+    }
+}
+
+impl Serialize for MessageType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(MessageType::from)
+    }
+}
+
+// Message bodies; the header is added by to_message_bytes and checked by
+// from_message_bytes using MESSAGE_TYPE.
+pub trait Message {
+    const MESSAGE_TYPE: MessageType;
+}
+
+// RFC 4271 4.2
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OpenMessage {
+    pub version: u8,
+    pub my_as: u16,
+    pub hold_time: u16,
+    pub bgp_identifier: BgpIdentifier,
+    pub optional_parameters: LengthPrefixed<u8, Vec<OptionalParameter>>,
+}
+
+// RFC 4271 4.3. Withdrawn routes and path attributes are each preceded by a
+// 2 octet length, the NLRI is whatever is left of the message.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct UpdateMessage {
+    pub withdrawn_routes: LengthPrefixed<u16, Vec<Ipv4Prefix>>,
+    #[serde(deserialize_with = "crate::attributes::deserialize_attribute_list")]
+    pub path_attributes: LengthPrefixed<u16, Vec<PathAttribute>>,
+    pub nlri: Vec<Ipv4Prefix>,
+}
+
+// An UPDATE with its three sections left as slices of the input, for
+// collectors that only look at part of each message. Nothing is copied;
+// sections can be decoded later with from_bytes, attributes one at a time
+// through PathAttributeRef.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateMessageRef<'a> {
+    #[serde(borrow)]
+    pub withdrawn_routes: LengthPrefixed<u16, &'a [u8]>,
+    #[serde(borrow)]
+    pub path_attributes: LengthPrefixed<u16, &'a [u8]>,
+    pub nlri: &'a [u8],
+}
+
+// RFC 4271 4.5. Data runs to the end of the message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct NotificationMessage {
+    pub error_code: u8,
+    pub error_subcode: u8,
+    pub data: Vec<u8>,
+}
+
+// RFC 2918, with the Reserved octet turned into a subtype by RFC 7313
+// (Enhanced Route Refresh) and optional ORFs after it (RFC 5291).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RouteRefreshMessage {
+    pub afi: Afi,
+    pub subtype: RouteRefreshSubtype,
+    pub safi: Safi,
+    pub orf: Option<RouteRefreshOrf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum RouteRefreshSubtype {
+    Request,
+    // Beginning / End of Route Refresh
+    BoRR,
+    EoRR,
+    Unknown(u8),
+}
+
+impl From<u8> for RouteRefreshSubtype {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => RouteRefreshSubtype::Request,
+            1 => RouteRefreshSubtype::BoRR,
+            2 => RouteRefreshSubtype::EoRR,
+            other => RouteRefreshSubtype::Unknown(other),
+        }
+    }
+}
+
+impl From<RouteRefreshSubtype> for u8 {
+    fn from(t: RouteRefreshSubtype) -> Self {
+        match t {
+            RouteRefreshSubtype::Request => 0,
+            RouteRefreshSubtype::BoRR => 1,
+            RouteRefreshSubtype::EoRR => 2,
+            RouteRefreshSubtype::Unknown(other) => other,
+        }
+    }
+}
+
+impl Serialize for RouteRefreshSubtype {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for RouteRefreshSubtype {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(RouteRefreshSubtype::from)
+    }
+}
+
+impl RouteRefreshMessage {
+    pub fn new(afi: Afi, safi: Safi) -> Self {
+        RouteRefreshMessage { afi, subtype: RouteRefreshSubtype::Request, safi, orf: None }
+    }
+
+    // A refresh request carrying ORFs, which only plain requests may.
+    pub fn with_orf(afi: Afi, safi: Safi, when_to_refresh: WhenToRefresh, orfs: Vec<Orf>) -> Self {
+        let orf = Some(RouteRefreshOrf { when_to_refresh, orfs });
+        RouteRefreshMessage { afi, subtype: RouteRefreshSubtype::Request, safi, orf }
+    }
+}
+
+// The ORF entries are prefixes of the message's AFI, so they are decoded
+// once the AFI is known.
+impl<'de> Deserialize<'de> for RouteRefreshMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RefreshVisitor;
+
+        impl<'de> Visitor<'de> for RefreshVisitor {
+            type Value = RouteRefreshMessage;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a ROUTE-REFRESH message")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let afi: Afi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let subtype = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let safi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                let orf = seq.next_element_seed(RouteRefreshOrfSeed { afi })?.flatten();
+                Ok(RouteRefreshMessage { afi, subtype, safi, orf })
+            }
+        }
+
+        deserializer.deserialize_tuple(4, RefreshVisitor)
+    }
+}
+
+// draft-ietf-idr-dynamic-cap. Only sent once both sides advertised the
+// Dynamic Capability; the changes run to the end of the message.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CapabilityMessage {
+    #[serde(deserialize_with = "crate::capabilities::deserialize_changes")]
+    pub changes: Vec<CapabilityChange>,
+}
+
+// RFC 4271 4.4. Header only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct KeepaliveMessage;
+
+impl Message for OpenMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::Open;
+}
+
+impl Message for UpdateMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::Update;
+}
+
+impl Message for UpdateMessageRef<'_> {
+    const MESSAGE_TYPE: MessageType = MessageType::Update;
+}
+
+impl Message for NotificationMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::Notification;
+}
+
+impl Message for KeepaliveMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::Keepalive;
+}
+
+impl Message for RouteRefreshMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::RouteRefresh;
+}
+
+impl Message for CapabilityMessage {
+    const MESSAGE_TYPE: MessageType = MessageType::Capability;
+}
+
+// Any one message, as produced by the stream decoder. Serializes as the body
+// of whichever message it holds, so it goes through to_message_bytes with
+// message_type(). Text formats get it as an enum, {"update": {...}}, which
+// is also the only form it can be deserialized from; on the wire the body
+// alone doesn't say which message it is, that's what from_bytes is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BgpMessage {
+    Open(OpenMessage),
+    Update(UpdateMessage),
+    Notification(NotificationMessage),
+    Keepalive(KeepaliveMessage),
+    RouteRefresh(RouteRefreshMessage),
+    Capability(CapabilityMessage),
+    // A type the crate doesn't know, kept under UnknownMessages::Preserve.
+    Unknown {
+        msg_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        payload: Bytes,
+    },
+}
+
+impl BgpMessage {
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            BgpMessage::Open(_) => MessageType::Open,
+            BgpMessage::Update(_) => MessageType::Update,
+            BgpMessage::Notification(_) => MessageType::Notification,
+            BgpMessage::Keepalive(_) => MessageType::Keepalive,
+            BgpMessage::RouteRefresh(_) => MessageType::RouteRefresh,
+            BgpMessage::Capability(_) => MessageType::Capability,
+            BgpMessage::Unknown { msg_type, .. } => MessageType::from(*msg_type),
+        }
+    }
+
+    // Decodes one complete message, header included, picking the body type
+    // from the header's type octet.
+    pub fn from_bytes(input: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        Self::from_bytes_with(input, DeserializerOptions::default())
+    }
+
+    pub fn from_bytes_with(input: &[u8], options: DeserializerOptions) -> std::result::Result<Self, DeserializeError> {
+        let msg_type = message_header(input)?;
+        Self::from_body(msg_type, &input[HEADER_LEN..], options)
+    }
+
+    fn from_body(
+        msg_type: MessageType,
+        body: &[u8],
+        options: DeserializerOptions,
+    ) -> std::result::Result<Self, DeserializeError> {
+        match msg_type {
+            MessageType::Open => from_bytes_with(body, options).map(BgpMessage::Open),
+            MessageType::Update => from_bytes_with(body, options).map(BgpMessage::Update),
+            MessageType::Notification => from_bytes_with(body, options).map(BgpMessage::Notification),
+            MessageType::Keepalive => from_bytes_with(body, options).map(BgpMessage::Keepalive),
+            MessageType::RouteRefresh => from_bytes_with(body, options).map(BgpMessage::RouteRefresh),
+            MessageType::Capability => from_bytes_with(body, options).map(BgpMessage::Capability),
+            MessageType::Unknown(msg_type) if options.unknown_messages == UnknownMessages::Preserve => {
+                Ok(BgpMessage::Unknown { msg_type, payload: Bytes::copy_from_slice(body) })
+            },
+            other => Err(DeserializeError::InvalidEnumDiscriminant {
+                type_name: "message type",
+                value: u8::from(other) as u64,
+                offset: HEADER_LEN - 1,
+            }),
+        }
+    }
+}
+
+// A message decoded only as far as its header, with the body left as opaque
+// octets. For proxies that forward most messages untouched and only parse
+// the ones they need to; serializing it through to_message_bytes with its
+// message_type gives back the original message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawMessage {
+    pub message_type: MessageType,
+    pub body: Bytes,
+}
+
+impl RawMessage {
+    pub fn from_bytes(input: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let message_type = message_header(input)?;
+        let body = from_bytes(&input[HEADER_LEN..])?;
+        Ok(RawMessage { message_type, body })
+    }
+
+    pub fn decode(&self) -> std::result::Result<BgpMessage, DeserializeError> {
+        self.decode_with(DeserializerOptions::default())
+    }
+
+    pub fn decode_with(&self, options: DeserializerOptions) -> std::result::Result<BgpMessage, DeserializeError> {
+        BgpMessage::from_body(self.message_type, &self.body, options)
+    }
+}
+
+impl Serialize for RawMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.body.serialize(serializer)
+    }
+}
+
+// A decoded message kept together with the octets it was received as,
+// header included, so the original can be logged, stored or sent on
+// verbatim after working with the typed form. For an UPDATE,
+// attribute_ranges has where each entry of path_attributes sits in `wire`,
+// flags through value; attributes dropped by OnUnknown::Skip have none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedMessage {
+    pub message: BgpMessage,
+    pub wire: Bytes,
+    pub attribute_ranges: Vec<Range<usize>>,
+}
+
+impl ReceivedMessage {
+    // Takes Bytes so a frame split off a receive buffer is kept without
+    // copying it.
+    pub fn from_bytes(input: Bytes) -> std::result::Result<Self, DeserializeError> {
+        Self::from_bytes_with(input, DeserializerOptions::default())
+    }
+
+    pub fn from_bytes_with(input: Bytes, options: DeserializerOptions) -> std::result::Result<Self, DeserializeError> {
+        let message = BgpMessage::from_bytes_with(&input, options)?;
+        let attribute_ranges = match &message {
+            BgpMessage::Update(update) => attribute_ranges(&input, &update.path_attributes.value),
+            _ => Vec::new(),
+        };
+        Ok(ReceivedMessage { message, wire: input, attribute_ranges })
+    }
+
+    // The octets the i-th path attribute was received as.
+    pub fn attribute_wire(&self, i: usize) -> Option<Bytes> {
+        self.attribute_ranges.get(i).map(|range| self.wire.slice(range.clone()))
+    }
+}
+
+// Walks the attribute list of an UPDATE that has already decoded, pairing
+// each attribute header with the next decoded attribute of the same code.
+// Only ones skipped as unknown go unpaired, and no decoded attribute has
+// their code.
+fn attribute_ranges(frame: &[u8], attributes: &[PathAttribute]) -> Vec<Range<usize>> {
+    let read_len = |at: usize, width: usize| {
+        frame[at..at + width].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize)
+    };
+    let start = HEADER_LEN + 2 + read_len(HEADER_LEN, 2) + 2;
+    let end = start + read_len(start - 2, 2);
+    let mut decoded = attributes.iter().peekable();
+    let mut ranges = Vec::with_capacity(attributes.len());
+    let mut pos = start;
+    while pos < end {
+        let width = if frame[pos] & ATTR_EXTENDED_LENGTH != 0 { 2 } else { 1 };
+        let next = pos + 2 + width + read_len(pos + 2, width);
+        if decoded.next_if(|attr| attr.type_code() == frame[pos + 1]).is_some() {
+            ranges.push(pos..next);
+        }
+        pos = next;
+    }
+    ranges
+}
+
+// ROUTE-REFRESH ORFs have no text form, so ROUTE-REFRESH messages can't be
+// read back.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "BgpMessage", rename_all = "snake_case")]
+enum BgpMessageText {
+    Open(OpenMessage),
+    Update(UpdateMessage),
+    Notification(NotificationMessage),
+    Keepalive(KeepaliveMessage),
+    #[serde(skip_deserializing)]
+    RouteRefresh(RouteRefreshMessage),
+    Capability(CapabilityMessage),
+    Unknown { msg_type: u8, payload: Bytes },
+}
+
+impl Serialize for BgpMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return BgpMessageText::serialize(self, serializer);
+        }
+        match self {
+            BgpMessage::Open(m) => m.serialize(serializer),
+            BgpMessage::Update(m) => m.serialize(serializer),
+            BgpMessage::Notification(m) => m.serialize(serializer),
+            BgpMessage::Keepalive(m) => m.serialize(serializer),
+            BgpMessage::RouteRefresh(m) => m.serialize(serializer),
+            BgpMessage::Capability(m) => m.serialize(serializer),
+            BgpMessage::Unknown { payload, .. } => payload.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BgpMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Err(de::Error::custom("A BgpMessage body doesn't say its type, use BgpMessage::from_bytes"));
+        }
+        BgpMessageText::deserialize(deserializer)
+    }
+}
+
+impl OpenMessage {
+    pub fn new(my_as: u16, hold_time: u16, bgp_identifier: BgpIdentifier) -> Self {
+        OpenMessage {
+            version: 4,
+            my_as,
+            hold_time,
+            bgp_identifier,
+            optional_parameters: LengthPrefixed::default(),
+        }
+    }
+}
+
+impl NotificationMessage {
+    pub fn new(code: NotificationCode, data: Vec<u8>) -> Self {
+        NotificationMessage {
+            error_code: code.code(),
+            error_subcode: code.subcode(),
+            data,
+        }
+    }
+
+    // None if the subcode isn't defined for the error code.
+    pub fn code(&self) -> Option<NotificationCode> {
+        NotificationCode::from_octets(self.error_code, self.error_subcode)
+    }
+
+    // RFC 8538: a Cease/Hard Reset carries the NOTIFICATION that caused it
+    // as its data, code and subcode octets first.
+    pub fn hard_reset(cause: &NotificationMessage) -> Self {
+        let mut data = Vec::with_capacity(2 + cause.data.len());
+        data.extend_from_slice(&[cause.error_code, cause.error_subcode]);
+        data.extend_from_slice(&cause.data);
+        NotificationMessage::new(NotificationCode::Cease(CeaseSubcode::HardReset), data)
+    }
+
+    // The NOTIFICATION wrapped in a Hard Reset, None for anything else or
+    // if the data is too short to hold one. The cause may itself be a Hard
+    // Reset.
+    pub fn hard_reset_cause(&self) -> Option<NotificationMessage> {
+        if self.code() != Some(NotificationCode::Cease(CeaseSubcode::HardReset)) || self.data.len() < 2 {
+            return None;
+        }
+        Some(NotificationMessage {
+            error_code: self.data[0],
+            error_subcode: self.data[1],
+            data: self.data[2..].to_vec(),
+        })
+    }
+}
+
+// Negotiated session timers, both in seconds. Zero means the hold timer and
+// KEEPALIVEs are disabled for the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoldTimers {
+    pub hold_time: u16,
+    pub keepalive: u16,
+}
+
+// RFC 4271 4.2/10: the session uses the smaller of the two hold times, and
+// KEEPALIVEs are sent at a third of that. Hold times of 1 and 2 seconds are
+// illegal; None is returned so the caller can send an OPEN Message Error /
+// Unacceptable Hold Time NOTIFICATION.
+pub fn negotiate_hold_time(local: u16, peer: u16) -> Option<HoldTimers> {
+    if matches!(local, 1 | 2) || matches!(peer, 1 | 2) {
+        return None;
+    }
+    let hold_time = local.min(peer);
+    Some(HoldTimers {
+        hold_time,
+        keepalive: hold_time / 3,
+    })
+}
+
+// RFC 8654: the larger limit only applies once both sides advertised the
+// Extended Message capability.
+pub fn negotiate_max_message_len(local: &[Capability], peer: &[Capability]) -> usize {
+    let extended = |caps: &[Capability]| caps.iter().any(|c| matches!(c, Capability::ExtendedMessage));
+    match extended(local) && extended(peer) {
+        true => EXTENDED_MAX_MESSAGE_LEN,
+        false => MAX_MESSAGE_LEN,
+    }
+}
+
+// Puts an UPDATE in canonical form, so two UPDATEs that mean the same thing
+// serialize to the same octets: attributes in type code order, the
+// community lists sorted, and IPv4/IPv6 prefixes (withdrawn, NLRI and in the
+// MP attributes) sorted. Other NLRI kinds keep their order.
+pub fn normalize(update: &mut UpdateMessage) {
+    update.withdrawn_routes.value.sort_unstable();
+    update.nlri.sort_unstable();
+    let attributes = &mut update.path_attributes.value;
+    attributes.sort_by_key(PathAttribute::type_code);
+    for attr in attributes {
+        match attr.attribute_mut() {
+            PathAttribute::Communities(c) => c.sort_unstable(),
+            PathAttribute::ExtendedCommunities(c) => c.sort_unstable_by_key(ExtendedCommunity::octets),
+            PathAttribute::LargeCommunities(c) => c.sort_unstable(),
+            PathAttribute::MpReachNlri(MpReachNlri { nlri, .. })
+            | PathAttribute::MpUnreachNlri(MpUnreachNlri { withdrawn: nlri, .. }) => match nlri {
+                Nlri::Ipv4(prefixes) => prefixes.sort_unstable(),
+                Nlri::Ipv6(prefixes) => prefixes.sort_unstable(),
+                _ => {},
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_bytes, from_message_bytes, from_message_bytes_with, to_message_bytes, to_message_bytes_with, AddPathFamilies,
+        Capability, CeaseSubcode, Origin, PathAttributeRef, SerializerOptions,
+    };
+    use std::net::Ipv4Addr;
+
+    fn round_trip<T>(msg: &T) -> T
+    where
+        T: Message + Serialize + for<'de> Deserialize<'de>,
+    {
+        let bytes = to_message_bytes(T::MESSAGE_TYPE, msg).unwrap();
+        from_message_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_open_wire() {
+        let id = BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        let mut open = OpenMessage::new(65001, 90, id);
+        open.optional_parameters.value = vec![OptionalParameter::Capabilities(vec![Capability::FourOctetAs(65001)])];
+
+        let bytes = to_message_bytes(MessageType::Open, &open).unwrap();
+        assert_eq!(&bytes[16..], &[
+            0, 37, 1,
+            4, 0xfd, 0xe9, 0, 90, 192, 0, 2, 1,
+            8, 2, 6, 65, 4, 0, 0, 0xfd, 0xe9,
+        ]);
+        assert_eq!(round_trip(&open), open);
+    }
+
+    #[test]
+    fn test_update_wire() {
+        let update = UpdateMessage {
+            withdrawn_routes: vec!["10.1.0.0/16".parse().unwrap()].into(),
+            path_attributes: vec![PathAttribute::Origin(Origin::Igp)].into(),
+            nlri: vec!["192.0.2.0/24".parse().unwrap()],
+        };
+        let bytes = to_message_bytes(MessageType::Update, &update).unwrap();
+        assert_eq!(&bytes[16..], &[
+            0, 34, 2,
+            0, 3, 16, 10, 1,
+            0, 4, 0x40, 1, 1, 0,
+            24, 192, 0, 2,
+        ]);
+        assert_eq!(round_trip(&update), update);
+        assert_eq!(round_trip(&UpdateMessage::default()), UpdateMessage::default());
+    }
+
+    #[test]
+    fn test_update_add_path() {
+        let add_path = AddPathFamilies::new().with(Afi::Ipv4, Safi::Unicast);
+        let ser_opts = SerializerOptions { add_path, ..Default::default() };
+        let de_opts = DeserializerOptions { add_path, ..Default::default() };
+        let prefix: Ipv4Prefix = "192.0.2.0/24".parse().unwrap();
+        let update = UpdateMessage {
+            nlri: vec![prefix.with_path_id(1), prefix.with_path_id(2)],
+            ..Default::default()
+        };
+
+        let bytes = to_message_bytes_with(MessageType::Update, &update, ser_opts).unwrap();
+        assert_eq!(&bytes[23..], &[0, 0, 0, 1, 24, 192, 0, 2, 0, 0, 0, 2, 24, 192, 0, 2]);
+        assert_eq!(from_message_bytes_with::<UpdateMessage>(&bytes, de_opts).unwrap(), update);
+
+        // The same octets read without ADD-PATH are a different (and here
+        // invalid) set of prefixes.
+        assert!(from_message_bytes::<UpdateMessage>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_update_ref_borrowed() {
+        let update = UpdateMessage {
+            withdrawn_routes: vec!["10.1.0.0/16".parse().unwrap()].into(),
+            path_attributes: vec![PathAttribute::Origin(Origin::Igp), PathAttribute::LocalPref(100)].into(),
+            nlri: vec!["192.0.2.0/24".parse().unwrap()],
+        };
+        let bytes = to_message_bytes(MessageType::Update, &update).unwrap();
+        let raw: UpdateMessageRef = from_message_bytes(&bytes).unwrap();
+
+        assert_eq!(raw.withdrawn_routes.value, &[16, 10, 1]);
+        assert_eq!(raw.nlri, &[24, 192, 0, 2]);
+        // Slices point into the input rather than a copy.
+        let input = bytes.as_ptr_range();
+        assert!(input.contains(&raw.path_attributes.value.as_ptr()));
+
+        let attrs: Vec<PathAttributeRef> = from_bytes(raw.path_attributes.value).unwrap();
+        assert_eq!(attrs[1], PathAttributeRef { flags: 0x40, code: 5, data: &[0, 0, 0, 100] });
+        assert!(input.contains(&attrs[1].data.as_ptr()));
+        assert_eq!(attrs[1].decode().unwrap(), PathAttribute::LocalPref(100));
+
+        assert_eq!(to_message_bytes(MessageType::Update, raw).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_received_message_wire() {
+        let update = UpdateMessage {
+            withdrawn_routes: vec!["10.1.0.0/16".parse().unwrap()].into(),
+            path_attributes: vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::Unknown { code: 250, flags: 0xc0, data: Bytes::from_static(&[1, 2]) },
+                PathAttribute::LocalPref(100),
+            ]
+            .into(),
+            nlri: vec!["192.0.2.0/24".parse().unwrap()],
+        };
+        let bytes = to_message_bytes(MessageType::Update, &update).unwrap().freeze();
+        let received = ReceivedMessage::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(received.message, BgpMessage::Update(update));
+        assert_eq!(received.wire, bytes);
+        assert_eq!(received.attribute_ranges, vec![26..30, 30..35, 35..42]);
+        assert_eq!(received.attribute_wire(1).unwrap().as_ref(), &[0xc0, 250, 2, 1, 2]);
+        assert_eq!(received.attribute_wire(3), None);
+
+        // The skipped attribute has no entry, the others keep theirs.
+        let opts = DeserializerOptions { on_unknown: crate::OnUnknown::Skip, ..Default::default() };
+        let received = ReceivedMessage::from_bytes_with(bytes.clone(), opts).unwrap();
+        assert_eq!(received.attribute_ranges, vec![26..30, 35..42]);
+        assert_eq!(received.attribute_wire(1).unwrap().as_ref(), &[0x40, 5, 4, 0, 0, 0, 100]);
+
+        let keepalive = to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap().freeze();
+        let received = ReceivedMessage::from_bytes(keepalive.clone()).unwrap();
+        assert_eq!((received.message, received.wire), (BgpMessage::Keepalive(KeepaliveMessage), keepalive));
+        assert!(received.attribute_ranges.is_empty());
+    }
+
+    #[test]
+    fn test_notification_keepalive_round_trip() {
+        let notification = NotificationMessage { error_code: 6, error_subcode: 2, data: vec![] };
+        assert_eq!(round_trip(&notification), notification);
+        let with_data = NotificationMessage { error_code: 2, error_subcode: 2, data: vec![0, 1] };
+        assert_eq!(round_trip(&with_data), with_data);
+        assert_eq!(round_trip(&KeepaliveMessage), KeepaliveMessage);
+    }
+
+    #[test]
+    fn test_notification_code() {
+        let notification = NotificationMessage::new(NotificationCode::Cease(CeaseSubcode::HardReset), vec![6, 2]);
+        let bytes = to_message_bytes(MessageType::Notification, &notification).unwrap();
+        assert_eq!(&bytes[19..], &[6, 9, 6, 2]);
+        assert_eq!(round_trip(&notification).code(), Some(NotificationCode::Cease(CeaseSubcode::HardReset)));
+        let cause = notification.hard_reset_cause().unwrap();
+        assert_eq!(cause.code(), Some(NotificationCode::Cease(CeaseSubcode::AdministrativeShutdown)));
+        assert_eq!(NotificationMessage::hard_reset(&cause), notification);
+
+        // A Hard Reset caused by a Hard Reset
+        let hold = NotificationMessage::new(NotificationCode::HoldTimerExpired, vec![]);
+        let nested = NotificationMessage::hard_reset(&NotificationMessage::hard_reset(&hold));
+        assert_eq!(nested.data, [6, 9, 4, 0]);
+        let nested = round_trip(&nested);
+        assert_eq!(nested.hard_reset_cause().and_then(|n| n.hard_reset_cause()), Some(hold.clone()));
+        assert_eq!(hold.hard_reset_cause(), None);
+        // Undefined subcodes still decode, they just don't map to a code.
+        let odd = NotificationMessage { error_code: 4, error_subcode: 3, data: vec![] };
+        assert_eq!(round_trip(&odd).code(), None);
+    }
+
+    #[test]
+    fn test_route_refresh() {
+        let refresh = RouteRefreshMessage::new(Afi::Ipv6, Safi::Unicast);
+        let bytes = to_message_bytes(MessageType::RouteRefresh, &refresh).unwrap();
+        assert_eq!(&bytes[16..], &[0, 23, 5, 0, 2, 0, 1]);
+        assert_eq!(BgpMessage::from_bytes(&bytes).unwrap(), BgpMessage::RouteRefresh(refresh.clone()));
+
+        let eorr = RouteRefreshMessage { subtype: RouteRefreshSubtype::EoRR, ..refresh };
+        let bytes = to_message_bytes(MessageType::RouteRefresh, &eorr).unwrap();
+        assert_eq!(bytes[20], 2);
+        assert_eq!(round_trip(&eorr), eorr);
+    }
+
+    #[test]
+    fn test_raw_message_passthrough() {
+        let update = UpdateMessage { nlri: vec!["192.0.2.0/24".parse().unwrap()], ..Default::default() };
+        let wire = to_message_bytes(MessageType::Update, &update).unwrap();
+
+        let raw = RawMessage::from_bytes(&wire).unwrap();
+        assert_eq!(raw.message_type, MessageType::Update);
+        assert_eq!(raw.body.as_ref(), &wire[HEADER_LEN..]);
+        assert_eq!(to_message_bytes(raw.message_type, &raw).unwrap(), wire);
+        assert_eq!(raw.decode().unwrap(), BgpMessage::Update(update));
+
+        // Types the crate can't decode still pass through.
+        let raw = RawMessage { message_type: MessageType::Unknown(7), body: Bytes::from_static(&[1, 2]) };
+        let wire = to_message_bytes(raw.message_type, &raw).unwrap();
+        assert_eq!(RawMessage::from_bytes(&wire).unwrap(), raw);
+        assert!(raw.decode().is_err());
+    }
+
+    #[test]
+    fn test_unknown_message_preserved() {
+        use crate::{Validate, ValidationError};
+
+        let raw = RawMessage { message_type: MessageType::Unknown(200), body: Bytes::from_static(&[1, 2, 3]) };
+        let wire = to_message_bytes(raw.message_type, &raw).unwrap();
+        assert!(BgpMessage::from_bytes(&wire).is_err());
+
+        let options = DeserializerOptions { unknown_messages: UnknownMessages::Preserve, ..Default::default() };
+        let message = BgpMessage::from_bytes_with(&wire, options).unwrap();
+        assert_eq!(message, BgpMessage::Unknown { msg_type: 200, payload: Bytes::from_static(&[1, 2, 3]) });
+        assert_eq!(raw.decode_with(options).unwrap(), message);
+        assert_eq!(to_message_bytes(message.message_type(), &message).unwrap(), wire);
+
+        // Still not a message a speaker can accept.
+        assert_eq!(message.validate(), Err(ValidationError::BadMessageType(200)));
+    }
+
+    #[test]
+    fn test_err_message_type_mismatch() {
+        let bytes = to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap();
+        match from_message_bytes::<UpdateMessage>(&bytes) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Expected Update message, header says Keepalive"),
+        }
+        let mut bad_marker = bytes.to_vec();
+        bad_marker[3] = 0;
+        assert!(matches!(from_message_bytes::<KeepaliveMessage>(&bad_marker), Err(DeserializeError::Custom(_))));
+        let mut bad_len = bytes.to_vec();
+        bad_len[17] = 20;
+        assert!(matches!(from_message_bytes::<KeepaliveMessage>(&bad_len), Err(DeserializeError::LengthMismatch { .. })));
+    }
+
+    #[test]
+    fn test_err_type_length() {
+        let mut keepalive = to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap().to_vec();
+        keepalive[17] = 20;
+        keepalive.push(0);
+        match BgpMessage::from_bytes(&keepalive) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e, DeserializeError::Invalid(crate::ValidationError::BadMessageLength(20)));
+                let n = e.to_notification(&keepalive).unwrap();
+                assert_eq!((n.error_code, n.error_subcode, n.data), (1, 2, vec![0, 20]));
+            },
+        }
+
+        // NOTIFICATION without its error subcode, OPEN without its fixed part
+        let notification = [&MARKER[..], &[0, 20, 3, 6]].concat();
+        assert!(matches!(BgpMessage::from_bytes(&notification), Err(DeserializeError::Invalid(_))));
+        let open = [&MARKER[..], &[0, 28, 1, 4, 0xfd, 0xe9, 0, 90, 192, 0, 2, 1]].concat();
+        assert!(matches!(RawMessage::from_bytes(&open), Err(DeserializeError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_negotiate_hold_time() {
+        assert_eq!(negotiate_hold_time(90, 180), Some(HoldTimers { hold_time: 90, keepalive: 30 }));
+        assert_eq!(negotiate_hold_time(240, 9), Some(HoldTimers { hold_time: 9, keepalive: 3 }));
+        // Either side at zero disables the timers
+        assert_eq!(negotiate_hold_time(0, 90), Some(HoldTimers { hold_time: 0, keepalive: 0 }));
+        assert_eq!(negotiate_hold_time(90, 0), Some(HoldTimers { hold_time: 0, keepalive: 0 }));
+        // Smallest legal value still gets a 1 second keepalive
+        assert_eq!(negotiate_hold_time(3, 3), Some(HoldTimers { hold_time: 3, keepalive: 1 }));
+    }
+
+    #[test]
+    fn test_negotiate_hold_time_unacceptable() {
+        assert_eq!(negotiate_hold_time(90, 1), None);
+        assert_eq!(negotiate_hold_time(90, 2), None);
+        assert_eq!(negotiate_hold_time(2, 90), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        use crate::{Community, Ipv6Prefix};
+
+        let prefix = |s: &str| s.parse::<Ipv4Prefix>().unwrap();
+        let unreach = |prefixes: &[&str]| {
+            let withdrawn = Nlri::Ipv6(prefixes.iter().map(|p| p.parse::<Ipv6Prefix>().unwrap()).collect());
+            PathAttribute::MpUnreachNlri(MpUnreachNlri { afi: Afi::Ipv6, safi: Safi::Unicast, withdrawn })
+        };
+        let mut update = UpdateMessage {
+            withdrawn_routes: vec![prefix("10.2.0.0/16"), prefix("10.1.0.0/16")].into(),
+            path_attributes: vec![
+                PathAttribute::ExtendedCommunities(vec![
+                    ExtendedCommunity::route_target(65001, 2),
+                    ExtendedCommunity::route_target(65001, 1),
+                ]),
+                unreach(&["2001:db8:2::/48", "2001:db8:1::/48"]),
+                PathAttribute::Communities(vec![Community::no_export(), Community::new(65001, 1)]),
+                PathAttribute::Origin(Origin::Igp),
+            ]
+            .into(),
+            nlri: vec![prefix("192.0.2.128/25"), prefix("192.0.2.0/25")],
+        };
+        let mut other = update.clone();
+        other.path_attributes.value.reverse();
+        other.nlri.reverse();
+        let bytes = |u: &UpdateMessage| to_message_bytes(MessageType::Update, u).unwrap();
+        assert_ne!(bytes(&update), bytes(&other));
+
+        normalize(&mut update);
+        normalize(&mut other);
+        assert_eq!(bytes(&update), bytes(&other));
+        assert_eq!(update.withdrawn_routes.value, [prefix("10.1.0.0/16"), prefix("10.2.0.0/16")]);
+        assert_eq!(update.nlri, [prefix("192.0.2.0/25"), prefix("192.0.2.128/25")]);
+        assert_eq!(update.path_attributes.value, [
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::Communities(vec![Community::new(65001, 1), Community::no_export()]),
+            unreach(&["2001:db8:1::/48", "2001:db8:2::/48"]),
+            PathAttribute::ExtendedCommunities(vec![
+                ExtendedCommunity::route_target(65001, 1),
+                ExtendedCommunity::route_target(65001, 2),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn test_capability_message() {
+        use crate::{CapabilityAction, DeserializerOptions, OnUnknown};
+
+        let msg = CapabilityMessage {
+            changes: vec![
+                CapabilityChange {
+                    action: CapabilityAction::Set,
+                    capability: Capability::Multiprotocol { afi: Afi::Ipv6, safi: Safi::Unicast },
+                },
+                CapabilityChange { action: CapabilityAction::Remove, capability: Capability::RouteRefresh },
+            ],
+        };
+        let bytes = to_message_bytes(MessageType::Capability, &msg).unwrap();
+        assert_eq!(&bytes[16..], &[0, 31, 6, 0, 1, 0, 4, 0, 2, 0, 1, 1, 2, 0, 0]);
+        assert_eq!(BgpMessage::from_bytes(&bytes).unwrap(), BgpMessage::Capability(msg.clone()));
+        assert_eq!(round_trip(&msg), msg);
+
+        // Unknown capabilities go the same way as in an OPEN
+        let mut wire = bytes.to_vec();
+        wire.extend_from_slice(&[0, 200, 0, 1, 0xaa]);
+        wire[17] += 5;
+        let opts = DeserializerOptions { on_unknown: OnUnknown::Skip, ..Default::default() };
+        assert_eq!(from_message_bytes_with::<CapabilityMessage>(&wire, opts).unwrap(), msg);
+        let opts = DeserializerOptions { on_unknown: OnUnknown::Error, ..Default::default() };
+        assert!(from_message_bytes_with::<CapabilityMessage>(&wire, opts).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_max_message_len() {
+        let both = [Capability::RouteRefresh, Capability::ExtendedMessage];
+        assert_eq!(negotiate_max_message_len(&both, &both), EXTENDED_MAX_MESSAGE_LEN);
+        assert_eq!(negotiate_max_message_len(&both, &[Capability::RouteRefresh]), MAX_MESSAGE_LEN);
+        assert_eq!(negotiate_max_message_len(&[], &both), MAX_MESSAGE_LEN);
+    }
+}
@@ -0,0 +1,873 @@
+// MRT routing information export format (RFC 6396, feature = "mrt").
+//
+// Reads and writes the records route collectors archive: TABLE_DUMP_V2 RIB
+// snapshots and BGP4MP message and state change logs. A record is a common
+// header (seconds, type, subtype and a 4 octet length) and a body; BGP4MP_ET
+// records start the body with the microseconds.
+//
+// BGP4MP messages are complete BGP messages, decoded with BgpMessage and the
+// AS number width the subtype gives. RIB entries carry path attributes as a
+// 4-octet AS speaker would send them, except MP_REACH_NLRI which is cut down
+// to the next hop length and next hop (RFC 6396 4.3.4); it decodes to an
+// MpReachNlri of the table's family with no NLRI. Other types and subtypes,
+// such as the original TABLE_DUMP and RIB_GENERIC, keep their raw body.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::{Bytes, BytesMut};
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, SerializeSeq, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::attributes::{Encoded, PathAttribute, PathAttributeRef};
+use crate::de::{from_bytes, from_bytes_seed, DeserializerOptions};
+use crate::error::{DeserializeError, Result, SerializerError};
+use crate::messages::BgpMessage;
+use crate::multiprotocol::{MpReachNlri, NextHop, Nlri};
+use crate::prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+use crate::registry::{mrt, path_attr};
+use crate::ser::{to_bytes_in, to_message_bytes_with, SerializerOptions};
+use crate::types::LengthPrefixed;
+
+pub const MRT_HEADER_LEN: usize = 12;
+
+// Peer Index Table peer type bits
+const PEER_IPV6: u8 = 0x01;
+const PEER_AS4: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MrtRecord {
+    pub seconds: u32,
+    // Only BGP4MP_ET records have them; setting them makes a BGP4MP record
+    // an ET one.
+    pub microseconds: Option<u32>,
+    pub body: MrtBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MrtBody {
+    PeerIndexTable(PeerIndexTable),
+    Rib(RibTable),
+    Bgp4mp(Bgp4mp),
+    Unknown { mrt_type: u16, subtype: u16, data: Bytes },
+}
+
+// The peers RIB entries refer to by index. Comes first in a TABLE_DUMP_V2
+// dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIndexTable {
+    pub collector_id: Ipv4Addr,
+    pub view_name: String,
+    pub peers: Vec<PeerEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerEntry {
+    pub bgp_id: Ipv4Addr,
+    pub address: IpAddr,
+    pub asn: u32,
+    pub four_octet_asn: bool,
+}
+
+// The paths to one prefix, from a RIB_IPV4/IPV6_UNICAST/MULTICAST record.
+// The subtype follows from the prefix and `safi`, which is Unicast or
+// Multicast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RibTable {
+    pub sequence: u32,
+    pub safi: Safi,
+    pub prefix: IpPrefix,
+    pub entries: Vec<RibEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RibEntry {
+    pub peer_index: u16,
+    // When the path was received, in seconds since the epoch
+    pub originated: u32,
+    pub attributes: Vec<PathAttribute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bgp4mp {
+    // Session FSM states, 1 (Idle) to 6 (Established)
+    StateChange { peers: Bgp4mpPeers, old_state: u16, new_state: u16 },
+    // `local` for the _LOCAL subtypes, messages the collector sent.
+    Message { peers: Bgp4mpPeers, local: bool, message: BgpMessage },
+}
+
+// The session a BGP4MP record is about. `four_octet_asn` picks the AS4
+// subtypes, which also means 4 octet AS numbers in the message's AS_PATH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bgp4mpPeers {
+    pub peer_asn: u32,
+    pub local_asn: u32,
+    pub interface: u16,
+    pub peer_address: IpAddr,
+    pub local_address: IpAddr,
+    pub four_octet_asn: bool,
+}
+
+impl MrtRecord {
+    pub fn new(seconds: u32, body: MrtBody) -> Self {
+        MrtRecord { seconds, microseconds: None, body }
+    }
+
+    pub fn mrt_type(&self) -> u16 {
+        match &self.body {
+            MrtBody::PeerIndexTable(_) | MrtBody::Rib(_) => mrt::TABLE_DUMP_V2,
+            MrtBody::Bgp4mp(_) if self.microseconds.is_some() => mrt::BGP4MP_ET,
+            MrtBody::Bgp4mp(_) => mrt::BGP4MP,
+            MrtBody::Unknown { mrt_type, .. } => *mrt_type,
+        }
+    }
+
+    pub fn subtype(&self) -> u16 {
+        match &self.body {
+            MrtBody::PeerIndexTable(_) => mrt::PEER_INDEX_TABLE,
+            MrtBody::Rib(rib) => match (rib.prefix, rib.safi) {
+                (IpPrefix::V4(_), Safi::Multicast) => mrt::RIB_IPV4_MULTICAST,
+                (IpPrefix::V4(_), _) => mrt::RIB_IPV4_UNICAST,
+                (IpPrefix::V6(_), Safi::Multicast) => mrt::RIB_IPV6_MULTICAST,
+                (IpPrefix::V6(_), _) => mrt::RIB_IPV6_UNICAST,
+            },
+            MrtBody::Bgp4mp(Bgp4mp::StateChange { peers, .. }) => match peers.four_octet_asn {
+                true => mrt::BGP4MP_STATE_CHANGE_AS4,
+                false => mrt::BGP4MP_STATE_CHANGE,
+            },
+            MrtBody::Bgp4mp(Bgp4mp::Message { peers, local, .. }) => match (peers.four_octet_asn, local) {
+                (false, false) => mrt::BGP4MP_MESSAGE,
+                (true, false) => mrt::BGP4MP_MESSAGE_AS4,
+                (false, true) => mrt::BGP4MP_MESSAGE_LOCAL,
+                (true, true) => mrt::BGP4MP_MESSAGE_AS4_LOCAL,
+            },
+            MrtBody::Unknown { subtype, .. } => *subtype,
+        }
+    }
+
+    // Decodes one complete record, header included.
+    pub fn from_bytes(input: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let header = input.get(..MRT_HEADER_LEN).ok_or(DeserializeError::UnexpectedEof {
+            needed: MRT_HEADER_LEN,
+            available: input.len(),
+            offset: 0,
+        })?;
+        let (seconds, mrt_type, subtype, len): (u32, u16, u16, u32) = from_bytes(header)?;
+        let mut body = &input[MRT_HEADER_LEN..];
+        if len as usize != body.len() {
+            return Err(DeserializeError::LengthMismatch { declared: len as usize, actual: body.len(), offset: 8 });
+        }
+        let mut microseconds = None;
+        if mrt_type == mrt::BGP4MP_ET && is_bgp4mp_subtype(subtype) {
+            let octets = body.get(..4).ok_or(DeserializeError::UnexpectedEof {
+                needed: 4,
+                available: body.len(),
+                offset: MRT_HEADER_LEN,
+            })?;
+            microseconds = Some(u32::from_be_bytes(octets.try_into().unwrap()));
+            body = &body[4..];
+        }
+        let body = MrtBody::from_bytes(mrt_type, subtype, body)?;
+        Ok(MrtRecord { seconds, microseconds, body })
+    }
+
+    pub fn to_bytes(&self) -> Result<BytesMut> {
+        let mut body = BytesMut::new();
+        if let Some(microseconds) = self.microseconds {
+            if !matches!(self.body, MrtBody::Bgp4mp(_)) {
+                return Err(SerializerError::CustomMsg(String::from("Only BGP4MP records have microseconds")));
+            }
+            to_bytes_in(microseconds, &mut body)?;
+        }
+        match &self.body {
+            MrtBody::PeerIndexTable(table) => to_bytes_in(table, &mut body)?,
+            MrtBody::Rib(rib) => to_bytes_in(rib, &mut body)?,
+            MrtBody::Bgp4mp(Bgp4mp::StateChange { peers, old_state, new_state }) => {
+                to_bytes_in((PeersWire(peers), old_state, new_state), &mut body)?
+            },
+            MrtBody::Bgp4mp(Bgp4mp::Message { peers, message, .. }) => {
+                to_bytes_in(PeersWire(peers), &mut body)?;
+                let options = SerializerOptions { four_octet_asn: peers.four_octet_asn, ..Default::default() };
+                body.extend_from_slice(&to_message_bytes_with(message.message_type(), message, options)?);
+            },
+            MrtBody::Unknown { data, .. } => body.extend_from_slice(data),
+        }
+        let len = u32::try_from(body.len()).map_err(|_| {
+            SerializerError::LengthOverflow(Some(format!("MRT record body is {} octets", body.len())))
+        })?;
+        let mut out = BytesMut::with_capacity(MRT_HEADER_LEN + body.len());
+        to_bytes_in((self.seconds, self.mrt_type(), self.subtype(), len), &mut out)?;
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+}
+
+fn is_bgp4mp_subtype(subtype: u16) -> bool {
+    matches!(
+        subtype,
+        mrt::BGP4MP_STATE_CHANGE
+            | mrt::BGP4MP_STATE_CHANGE_AS4
+            | mrt::BGP4MP_MESSAGE
+            | mrt::BGP4MP_MESSAGE_AS4
+            | mrt::BGP4MP_MESSAGE_LOCAL
+            | mrt::BGP4MP_MESSAGE_AS4_LOCAL
+    )
+}
+
+impl MrtBody {
+    fn from_bytes(mrt_type: u16, subtype: u16, data: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let bgp4mp = mrt_type == mrt::BGP4MP || mrt_type == mrt::BGP4MP_ET;
+        Ok(match subtype {
+            mrt::PEER_INDEX_TABLE if mrt_type == mrt::TABLE_DUMP_V2 => {
+                MrtBody::PeerIndexTable(PeerIndexTable::from_bytes(data)?)
+            },
+            mrt::RIB_IPV4_UNICAST | mrt::RIB_IPV4_MULTICAST if mrt_type == mrt::TABLE_DUMP_V2 => {
+                MrtBody::Rib(RibTable::from_raw(from_bytes::<RawRib<Ipv4Prefix>>(data)?, subtype)?)
+            },
+            mrt::RIB_IPV6_UNICAST | mrt::RIB_IPV6_MULTICAST if mrt_type == mrt::TABLE_DUMP_V2 => {
+                MrtBody::Rib(RibTable::from_raw(from_bytes::<RawRib<Ipv6Prefix>>(data)?, subtype)?)
+            },
+            mrt::BGP4MP_STATE_CHANGE | mrt::BGP4MP_STATE_CHANGE_AS4 if bgp4mp => {
+                let seed = PeersSeed { four_octet_asn: subtype == mrt::BGP4MP_STATE_CHANGE_AS4 };
+                let (peers, rest) = from_bytes_seed(data, DeserializerOptions::default(), seed)?;
+                let (old_state, new_state) = from_bytes(rest)?;
+                MrtBody::Bgp4mp(Bgp4mp::StateChange { peers, old_state, new_state })
+            },
+            mrt::BGP4MP_MESSAGE | mrt::BGP4MP_MESSAGE_AS4 | mrt::BGP4MP_MESSAGE_LOCAL | mrt::BGP4MP_MESSAGE_AS4_LOCAL
+                if bgp4mp =>
+            {
+                let four_octet_asn = subtype == mrt::BGP4MP_MESSAGE_AS4 || subtype == mrt::BGP4MP_MESSAGE_AS4_LOCAL;
+                let local = subtype == mrt::BGP4MP_MESSAGE_LOCAL || subtype == mrt::BGP4MP_MESSAGE_AS4_LOCAL;
+                let seed = PeersSeed { four_octet_asn };
+                let (peers, rest) = from_bytes_seed(data, DeserializerOptions::default(), seed)?;
+                let options = DeserializerOptions { four_octet_asn, ..Default::default() };
+                let message = BgpMessage::from_bytes_with(rest, options)?;
+                MrtBody::Bgp4mp(Bgp4mp::Message { peers, local, message })
+            },
+            _ => MrtBody::Unknown { mrt_type, subtype, data: Bytes::copy_from_slice(data) },
+        })
+    }
+}
+
+impl PeerIndexTable {
+    fn from_bytes(data: &[u8]) -> std::result::Result<Self, DeserializeError> {
+        let raw: RawPeerIndex = from_bytes(data)?;
+        if raw.count as usize != raw.peers.len() {
+            return Err(DeserializeError::Custom(format!(
+                "Peer Index Table says {} peers, found {}", raw.count, raw.peers.len()
+            )));
+        }
+        let view_name = String::from_utf8(raw.view_name.value.to_vec())
+            .map_err(|_| DeserializeError::Custom(String::from("Peer Index Table view name is not UTF-8")))?;
+        Ok(PeerIndexTable { collector_id: raw.collector_id, view_name, peers: raw.peers })
+    }
+}
+
+impl RibTable {
+    fn from_raw<P: Into<IpPrefix>>(raw: RawRib<P>, subtype: u16) -> std::result::Result<Self, DeserializeError> {
+        if raw.count as usize != raw.entries.len() {
+            return Err(DeserializeError::Custom(format!(
+                "RIB record says {} entries, found {}", raw.count, raw.entries.len()
+            )));
+        }
+        let (afi, safi) = match subtype {
+            mrt::RIB_IPV4_UNICAST => (Afi::Ipv4, Safi::Unicast),
+            mrt::RIB_IPV4_MULTICAST => (Afi::Ipv4, Safi::Multicast),
+            mrt::RIB_IPV6_UNICAST => (Afi::Ipv6, Safi::Unicast),
+            _ => (Afi::Ipv6, Safi::Multicast),
+        };
+        let entries = raw
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let attributes: Vec<PathAttributeRef> = from_bytes(entry.attributes.value)?;
+                let attributes = attributes
+                    .iter()
+                    .map(|attr| match attr.code {
+                        path_attr::MP_REACH_NLRI => rib_mp_reach(afi, safi, attr.data),
+                        _ => attr.decode(),
+                    })
+                    .collect::<std::result::Result<_, _>>()?;
+                Ok(RibEntry { peer_index: entry.peer_index, originated: entry.originated, attributes })
+            })
+            .collect::<std::result::Result<_, DeserializeError>>()?;
+        Ok(RibTable { sequence: raw.sequence, safi, prefix: raw.prefix.into(), entries })
+    }
+}
+
+// The MP_REACH_NLRI of a RIB entry, only the next hop.
+fn rib_mp_reach(afi: Afi, safi: Safi, data: &[u8]) -> std::result::Result<PathAttribute, DeserializeError> {
+    let next_hop: LengthPrefixed<u8, NextHop> = from_bytes(data)?;
+    let nlri = match afi {
+        Afi::Ipv4 => Nlri::Ipv4(Vec::new()),
+        _ => Nlri::Ipv6(Vec::new()),
+    };
+    Ok(PathAttribute::MpReachNlri(MpReachNlri { afi, safi, next_hop: next_hop.value, nlri }))
+}
+
+// -- Reading and writing --
+
+// Reads records one after another, as an iterator too. A record that fails
+// to decode doesn't stop the reader; the next call goes on with the record
+// after it.
+pub struct MrtReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> MrtReader<R> {
+    pub fn new(reader: R) -> Self {
+        MrtReader { reader }
+    }
+
+    // None at the end of the input, if it ends between records.
+    pub fn next_record(&mut self) -> Result<Option<MrtRecord>> {
+        let mut frame = vec![0u8; MRT_HEADER_LEN];
+        let mut filled = 0;
+        while filled < MRT_HEADER_LEN {
+            match self.reader.read(&mut frame[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let len = u32::from_be_bytes([frame[8], frame[9], frame[10], frame[11]]) as u64;
+        // Grown as the body comes in, not sized from the header up front, so
+        // a garbage length at the end of a cut off file doesn't allocate
+        // gigabytes.
+        if self.reader.by_ref().take(len).read_to_end(&mut frame)? as u64 != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(Some(MrtRecord::from_bytes(&frame)?))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for MrtReader<R> {
+    type Item = Result<MrtRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+pub struct MrtWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> MrtWriter<W> {
+    pub fn new(out: W) -> Self {
+        MrtWriter { out }
+    }
+
+    pub fn write_record(&mut self, record: &MrtRecord) -> Result<()> {
+        self.out.write_all(&record.to_bytes()?)?;
+        Ok(())
+    }
+
+    // A complete TABLE_DUMP_V2 snapshot of unicast routes: the Peer Index
+    // Table of every peer in `routes`, then a RIB record per prefix holding
+    // a path for each peer that has one. Everything is stamped with
+    // `seconds`, paths included, since the routes don't say when they were
+    // received.
+    pub fn write_rib_dump<I>(&mut self, seconds: u32, collector_id: Ipv4Addr, view_name: &str, routes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (PeerEntry, IpPrefix, Vec<PathAttribute>)>,
+    {
+        let mut peers = Vec::new();
+        let mut peer_index = HashMap::new();
+        let mut ribs: Vec<RibTable> = Vec::new();
+        let mut rib_index = HashMap::new();
+        for (peer, prefix, attributes) in routes {
+            let index = match peer_index.get(&peer) {
+                Some(&index) => index,
+                None => {
+                    let index = u16::try_from(peers.len())
+                        .map_err(|_| SerializerError::CustomMsg(String::from("More than 65535 peers in a RIB dump")))?;
+                    peers.push(peer);
+                    peer_index.insert(peer, index);
+                    index
+                },
+            };
+            let rib = *rib_index.entry(prefix).or_insert_with(|| {
+                let sequence = ribs.len() as u32;
+                ribs.push(RibTable { sequence, safi: Safi::Unicast, prefix, entries: Vec::new() });
+                ribs.len() - 1
+            });
+            ribs[rib].entries.push(RibEntry { peer_index: index, originated: seconds, attributes });
+        }
+
+        let view_name = String::from(view_name);
+        let index = PeerIndexTable { collector_id, view_name, peers };
+        self.write_record(&MrtRecord::new(seconds, MrtBody::PeerIndexTable(index)))?;
+        for rib in ribs {
+            self.write_record(&MrtRecord::new(seconds, MrtBody::Rib(rib)))?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// -- Wire form --
+
+struct Address<'a>(&'a IpAddr);
+
+impl Serialize for Address<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            IpAddr::V4(addr) => addr.serialize(serializer),
+            IpAddr::V6(addr) => addr.serialize(serializer),
+        }
+    }
+}
+
+struct ViewName<'a>(&'a str);
+
+impl Serialize for ViewName<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0.as_bytes())
+    }
+}
+
+fn count<E: ser::Error>(what: &str, n: usize) -> std::result::Result<u16, E> {
+    u16::try_from(n).map_err(|_| E::custom(format!("{} {} don't fit a 2 octet count", n, what)))
+}
+
+impl Serialize for PeerIndexTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let view_name: LengthPrefixed<u16, _> = LengthPrefixed::new(ViewName(&self.view_name));
+        let peers: u16 = count("peers", self.peers.len())?;
+        (self.collector_id, view_name, peers, &self.peers).serialize(serializer)
+    }
+}
+
+impl Serialize for PeerEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut peer_type = 0;
+        if self.address.is_ipv6() {
+            peer_type |= PEER_IPV6;
+        }
+        if self.four_octet_asn {
+            peer_type |= PEER_AS4;
+        }
+        let mut tup = serializer.serialize_tuple(4)?;
+        tup.serialize_element(&peer_type)?;
+        tup.serialize_element(&self.bgp_id)?;
+        tup.serialize_element(&Address(&self.address))?;
+        serialize_asn(&mut tup, self.asn, self.four_octet_asn)?;
+        tup.end()
+    }
+}
+
+fn serialize_asn<T: SerializeTuple>(tup: &mut T, asn: u32, four_octet_asn: bool) -> std::result::Result<(), T::Error> {
+    if four_octet_asn {
+        return tup.serialize_element(&asn);
+    }
+    let asn = u16::try_from(asn).map_err(|_| ser::Error::custom(format!("AS {} needs 4 octets", asn)))?;
+    tup.serialize_element(&asn)
+}
+
+impl Serialize for RibTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if !matches!(self.safi, Safi::Unicast | Safi::Multicast) {
+            return Err(ser::Error::custom(format!("RIB record SAFI {:?} isn't unicast or multicast", self.safi)));
+        }
+        let entries: u16 = count("RIB entries", self.entries.len())?;
+        let mut tup = serializer.serialize_tuple(4)?;
+        tup.serialize_element(&self.sequence)?;
+        tup.serialize_element(&self.prefix)?;
+        tup.serialize_element(&entries)?;
+        tup.serialize_element(&RibEntries(&self.entries))?;
+        tup.end()
+    }
+}
+
+struct RibEntries<'a>(&'a [RibEntry]);
+
+impl Serialize for RibEntries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for entry in self.0 {
+            let attributes: LengthPrefixed<u16, _> = LengthPrefixed::new(RibAttributes(&entry.attributes));
+            seq.serialize_element(&(entry.peer_index, entry.originated, attributes))?;
+        }
+        seq.end()
+    }
+}
+
+struct RibAttributes<'a>(&'a [PathAttribute]);
+
+impl Serialize for RibAttributes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for attr in self.0 {
+            match attr.attribute() {
+                PathAttribute::MpReachNlri(mp) => {
+                    let next_hop: LengthPrefixed<u8, _> = LengthPrefixed::new(&mp.next_hop);
+                    let flags = attr.flags();
+                    seq.serialize_element(&Encoded { flags, type_code: path_attr::MP_REACH_NLRI, value: next_hop })?
+                },
+                _ => seq.serialize_element(attr)?,
+            }
+        }
+        seq.end()
+    }
+}
+
+struct PeersWire<'a>(&'a Bgp4mpPeers);
+
+impl Serialize for PeersWire<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let peers = self.0;
+        let afi = match (peers.peer_address, peers.local_address) {
+            (IpAddr::V4(_), IpAddr::V4(_)) => Afi::Ipv4,
+            (IpAddr::V6(_), IpAddr::V6(_)) => Afi::Ipv6,
+            _ => return Err(ser::Error::custom("BGP4MP peer and local addresses must be the same family")),
+        };
+        let mut tup = serializer.serialize_tuple(6)?;
+        serialize_asn(&mut tup, peers.peer_asn, peers.four_octet_asn)?;
+        serialize_asn(&mut tup, peers.local_asn, peers.four_octet_asn)?;
+        tup.serialize_element(&peers.interface)?;
+        tup.serialize_element(&u16::from(afi))?;
+        tup.serialize_element(&Address(&peers.peer_address))?;
+        tup.serialize_element(&Address(&peers.local_address))?;
+        tup.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawPeerIndex<'a> {
+    collector_id: Ipv4Addr,
+    #[serde(borrow)]
+    view_name: LengthPrefixed<u16, &'a [u8]>,
+    count: u16,
+    peers: Vec<PeerEntry>,
+}
+
+#[derive(Deserialize)]
+struct RawRib<'a, P> {
+    sequence: u32,
+    prefix: P,
+    count: u16,
+    #[serde(borrow)]
+    entries: Vec<RawRibEntry<'a>>,
+}
+
+#[derive(Deserialize)]
+struct RawRibEntry<'a> {
+    peer_index: u16,
+    originated: u32,
+    #[serde(borrow)]
+    attributes: LengthPrefixed<u16, &'a [u8]>,
+}
+
+impl<'de> Deserialize<'de> for PeerEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PeerVisitor;
+
+        impl<'de> Visitor<'de> for PeerVisitor {
+            type Value = PeerEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a Peer Index Table peer entry")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let peer_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let bgp_id = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let ipv6 = peer_type & PEER_IPV6 != 0;
+                let address = next_address(&mut seq, ipv6, 2, &self)?;
+                let four_octet_asn = peer_type & PEER_AS4 != 0;
+                let asn = next_asn(&mut seq, four_octet_asn, 3, &self)?;
+                Ok(PeerEntry { bgp_id, address, asn, four_octet_asn })
+            }
+        }
+
+        deserializer.deserialize_tuple(4, PeerVisitor)
+    }
+}
+
+fn next_address<'de, A: SeqAccess<'de>>(
+    seq: &mut A,
+    ipv6: bool,
+    index: usize,
+    exp: &dyn de::Expected,
+) -> std::result::Result<IpAddr, A::Error> {
+    let addr = match ipv6 {
+        true => seq.next_element::<Ipv6Addr>()?.map(IpAddr::V6),
+        false => seq.next_element::<Ipv4Addr>()?.map(IpAddr::V4),
+    };
+    addr.ok_or_else(|| de::Error::invalid_length(index, exp))
+}
+
+fn next_asn<'de, A: SeqAccess<'de>>(
+    seq: &mut A,
+    four_octet_asn: bool,
+    index: usize,
+    exp: &dyn de::Expected,
+) -> std::result::Result<u32, A::Error> {
+    let asn = match four_octet_asn {
+        true => seq.next_element::<u32>()?,
+        false => seq.next_element::<u16>()?.map(u32::from),
+    };
+    asn.ok_or_else(|| de::Error::invalid_length(index, exp))
+}
+
+// The BGP4MP peer fields, then whatever follows them.
+struct PeersSeed {
+    four_octet_asn: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for PeersSeed {
+    type Value = (Bgp4mpPeers, &'de [u8]);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(7, self)
+    }
+}
+
+impl<'de> Visitor<'de> for PeersSeed {
+    type Value = (Bgp4mpPeers, &'de [u8]);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BGP4MP peers")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let peer_asn = next_asn(&mut seq, self.four_octet_asn, 0, &self)?;
+        let local_asn = next_asn(&mut seq, self.four_octet_asn, 1, &self)?;
+        let interface = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let afi: u16 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let ipv6 = match Afi::from(afi) {
+            Afi::Ipv4 => false,
+            Afi::Ipv6 => true,
+            _ => return Err(de::Error::custom(format!("Invalid BGP4MP address family {}", afi))),
+        };
+        let peer_address = next_address(&mut seq, ipv6, 4, &self)?;
+        let local_address = next_address(&mut seq, ipv6, 5, &self)?;
+        let rest = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(6, &self))?;
+        let peers = Bgp4mpPeers {
+            peer_asn,
+            local_asn,
+            interface,
+            peer_address,
+            local_address,
+            four_octet_asn: self.four_octet_asn,
+        };
+        Ok((peers, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsPathSegment, KeepaliveMessage, Origin, UpdateMessage};
+
+    fn peers(four_octet_asn: bool) -> Bgp4mpPeers {
+        Bgp4mpPeers {
+            peer_asn: 64500,
+            local_asn: 64501,
+            interface: 0,
+            peer_address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            local_address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            four_octet_asn,
+        }
+    }
+
+    #[test]
+    fn test_mrt_table_dump_v2() {
+        let index = MrtRecord::new(1_700_000_000, MrtBody::PeerIndexTable(PeerIndexTable {
+            collector_id: Ipv4Addr::new(192, 0, 2, 254),
+            view_name: String::from("rv"),
+            peers: vec![
+                PeerEntry {
+                    bgp_id: Ipv4Addr::new(192, 0, 2, 1),
+                    address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                    asn: 64500,
+                    four_octet_asn: false,
+                },
+                PeerEntry {
+                    bgp_id: Ipv4Addr::new(192, 0, 2, 2),
+                    address: "2001:db8::2".parse().unwrap(),
+                    asn: 4_200_000_000,
+                    four_octet_asn: true,
+                },
+            ],
+        }));
+        let rib = MrtRecord::new(1_700_000_000, MrtBody::Rib(RibTable {
+            sequence: 7,
+            safi: Safi::Unicast,
+            prefix: "2001:db8:1::/48".parse().unwrap(),
+            entries: vec![RibEntry {
+                peer_index: 1,
+                originated: 1_699_999_000,
+                attributes: vec![
+                    PathAttribute::Origin(Origin::Igp),
+                    PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![4_200_000_000, 64496])]),
+                    PathAttribute::MpReachNlri(MpReachNlri {
+                        afi: Afi::Ipv6,
+                        safi: Safi::Unicast,
+                        next_hop: NextHop::Ipv6("2001:db8::2".parse().unwrap()),
+                        nlri: Nlri::Ipv6(Vec::new()),
+                    }),
+                ],
+            }],
+        }));
+
+        let mut writer = MrtWriter::new(Vec::new());
+        writer.write_record(&index).unwrap();
+        writer.write_record(&rib).unwrap();
+        let out = writer.into_inner();
+
+        // Header, collector, view name, peer count, first peer
+        #[rustfmt::skip]
+        let head: &[u8] = &[
+            0x65, 0x53, 0xf1, 0x00, 0, 13, 0, 1, 0, 0, 0, 46,
+            192, 0, 2, 254, 0, 2, b'r', b'v', 0, 2,
+            0, 192, 0, 2, 1, 192, 0, 2, 1, 0xfb, 0xf4,
+        ];
+        assert_eq!(&out[..head.len()], head);
+        // The RIB entry's MP_REACH_NLRI is only the next hop
+        let rib_wire = rib.to_bytes().unwrap();
+        let next_hop: [u8; 20] = [0x80, 14, 17, 16, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+        assert_eq!(&rib_wire[rib_wire.len() - 20..], &next_hop);
+
+        let records: Vec<MrtRecord> = MrtReader::new(out.as_slice()).collect::<Result<_>>().unwrap();
+        assert_eq!(records, vec![index, rib]);
+    }
+
+    #[test]
+    fn test_mrt_rib_dump() {
+        let peer = |last: u8, asn: u32| PeerEntry {
+            bgp_id: Ipv4Addr::new(192, 0, 2, last),
+            address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, last)),
+            asn,
+            four_octet_asn: true,
+        };
+        let path = |asn: u32| vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![asn])]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+        ];
+        let a: IpPrefix = "10.0.0.0/8".parse().unwrap();
+        let b: IpPrefix = "10.1.0.0/16".parse().unwrap();
+        let routes = vec![
+            (peer(1, 64500), a, path(64500)),
+            (peer(2, 64501), b, path(64501)),
+            (peer(2, 64501), a, path(64501)),
+        ];
+
+        let mut writer = MrtWriter::new(Vec::new());
+        writer.write_rib_dump(1_700_000_000, Ipv4Addr::new(192, 0, 2, 254), "rv", routes).unwrap();
+        let records: Vec<MrtRecord> = MrtReader::new(writer.into_inner().as_slice()).collect::<Result<_>>().unwrap();
+        assert_eq!(records.len(), 3);
+        match &records[0].body {
+            MrtBody::PeerIndexTable(index) => assert_eq!(index.peers, [peer(1, 64500), peer(2, 64501)]),
+            other => panic!("Unexpected record {:?}", other),
+        }
+        match &records[1].body {
+            MrtBody::Rib(rib) => {
+                assert_eq!((rib.sequence, rib.prefix), (0, a));
+                assert_eq!(rib.entries.iter().map(|e| e.peer_index).collect::<Vec<_>>(), [0, 1]);
+                assert_eq!(rib.entries[1].attributes, path(64501));
+            },
+            other => panic!("Unexpected record {:?}", other),
+        }
+        match &records[2].body {
+            MrtBody::Rib(rib) => assert_eq!((rib.sequence, rib.prefix, rib.entries.len()), (1, b, 1)),
+            other => panic!("Unexpected record {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mrt_bgp4mp() {
+        let mut update = UpdateMessage::default();
+        update.path_attributes.value = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![64500, 64496])]),
+        ];
+        update.nlri = vec!["198.51.100.0/24".parse().unwrap()];
+        let mut message = MrtRecord::new(1_700_000_000, MrtBody::Bgp4mp(Bgp4mp::Message {
+            peers: peers(false),
+            local: false,
+            message: BgpMessage::Update(update),
+        }));
+        let wire = message.to_bytes().unwrap();
+        assert_eq!(&wire[4..8], &[0, 16, 0, 1]);
+        // 2 octet AS numbers both in the peers and the AS_PATH
+        assert_eq!(&wire[12..16], &[0xfb, 0xf4, 0xfb, 0xf5]);
+        assert_eq!(&wire[wire.len() - 13..wire.len() - 4], &[0x40, 2, 6, 2, 2, 0xfb, 0xf4, 0xfb, 0xf0]);
+        assert_eq!(MrtRecord::from_bytes(&wire).unwrap(), message);
+
+        message.microseconds = Some(500_000);
+        let wire = message.to_bytes().unwrap();
+        assert_eq!(&wire[4..16], &[0, 17, 0, 1, 0, 0, 0, 60, 0, 0x07, 0xa1, 0x20]);
+        assert_eq!(MrtRecord::from_bytes(&wire).unwrap(), message);
+
+        let records = [
+            MrtRecord::new(1, MrtBody::Bgp4mp(Bgp4mp::StateChange { peers: peers(true), old_state: 5, new_state: 6 })),
+            MrtRecord::new(2, MrtBody::Bgp4mp(Bgp4mp::Message {
+                peers: peers(true),
+                local: true,
+                message: BgpMessage::Keepalive(KeepaliveMessage),
+            })),
+            MrtRecord::new(3, MrtBody::Unknown { mrt_type: 12, subtype: 1, data: Bytes::from_static(&[1, 2]) }),
+        ];
+        for record in records {
+            let wire = record.to_bytes().unwrap();
+            assert_eq!(MrtRecord::from_bytes(&wire).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn test_mrt_err() {
+        // A 4 octet AS peer in a 2 octet AS subtype
+        let mut peers = peers(false);
+        peers.peer_asn = 4_200_000_000;
+        let record = MrtRecord::new(0, MrtBody::Bgp4mp(Bgp4mp::StateChange { peers, old_state: 1, new_state: 2 }));
+        assert!(record.to_bytes().is_err());
+
+        // Header says 4 octets, 3 follow
+        match MrtRecord::from_bytes(&[0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 4, 0, 0, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::LengthMismatch { declared: 4, actual: 3, offset: 8 }),
+        }
+
+        // Peer count past the peers there are
+        let wire = [0, 0, 0, 0, 0, 13, 0, 1, 0, 0, 0, 8, 192, 0, 2, 254, 0, 0, 0, 1];
+        match MrtRecord::from_bytes(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Peer Index Table says 1 peers, found 0"),
+        }
+
+        // Input ending inside a header
+        let mut reader = MrtReader::new(&[0u8, 0, 0][..]);
+        match reader.next_record() {
+            Err(SerializerError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("Expected Io error, got {:?}", other),
+        }
+        assert!(MrtReader::new(&[][..]).next_record().unwrap().is_none());
+
+        // Cut off after a header claiming 4 GiB
+        let mut reader = MrtReader::new(&[0u8, 0, 0, 0, 0, 16, 0, 4, 0xff, 0xff, 0xff, 0xff, 0, 0][..]);
+        match reader.next_record() {
+            Err(SerializerError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("Expected Io error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,618 @@
+// Multiprotocol reachability attributes (RFC 4760 3 and 4).
+//
+// MP_REACH_NLRI is AFI, SAFI, a 1 octet next hop length, the next hop, a
+// reserved octet and the NLRI up to the end of the attribute. MP_UNREACH_NLRI
+// is AFI, SAFI and the withdrawn routes. The NLRI encoding depends on the
+// AFI/SAFI pair, so decoding reads those first and picks the prefix type from
+// them; families the crate doesn't model keep their NLRI as raw octets.
+//
+// The next hop carries no type of its own, its length says what it is. That
+// holds for RFC 8950 IPv6 next hops of IPv4 (and VPN-IPv4) routes too; whether
+// the session allows one is up to the Extended Next Hop capability, which
+// NextHop::fits checks against.
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::bgp_ls::LinkStateNlri;
+use crate::capabilities::ExtendedNextHopFamily;
+use crate::evpn::EvpnRoute;
+use crate::flowspec::{FlowSpecRule, FlowSpecSeed};
+use crate::label::{LabeledPrefix, LabeledSeed};
+use crate::prefix::{Ipv4Prefix, Ipv6Prefix};
+use crate::types::{LengthPrefixed, LengthPrefixedSeed, MULTICAST_NLRI};
+use crate::vpn::{VpnPrefix, VpnSeed};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MpReachNlri {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub next_hop: NextHop,
+    pub nlri: Nlri,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MpUnreachNlri {
+    pub afi: Afi,
+    pub safi: Safi,
+    pub withdrawn: Nlri,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum NextHop {
+    // 4 octets
+    Ipv4(Ipv4Addr),
+    // 16 octets
+    Ipv6(Ipv6Addr),
+    // 32 octets, a global address followed by a link-local one (RFC 2545 3).
+    Ipv6LinkLocal { global: Ipv6Addr, link_local: Ipv6Addr },
+    // 12, 24 and 48 octets: the VPN forms, each address behind an RD of zero
+    // (RFC 4364 4.3.2, RFC 4659 3.2.1).
+    VpnIpv4(Ipv4Addr),
+    VpnIpv6(Ipv6Addr),
+    VpnIpv6LinkLocal { global: Ipv6Addr, link_local: Ipv6Addr },
+    // Any other length, or a VPN one with an RD that isn't zero.
+    Other(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))] Bytes),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Nlri {
+    Ipv4(Vec<Ipv4Prefix>),
+    Ipv6(Vec<Ipv6Prefix>),
+    // IPv4 or IPv6 FlowSpec (SAFI 133)
+    FlowSpec(Vec<FlowSpecRule>),
+    // L2VPN EVPN
+    Evpn(Vec<EvpnRoute>),
+    // BGP-LS (AFI 16388, SAFI 71)
+    LinkState(Vec<LinkStateNlri>),
+    // Labeled unicast (SAFI 4)
+    Labeled(Vec<LabeledPrefix>),
+    // VPN-IPv4 or VPN-IPv6 (SAFI 128)
+    Vpn(Vec<VpnPrefix>),
+    // NLRI of an AFI/SAFI the crate doesn't decode.
+    Unknown(#[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))] Bytes),
+}
+
+impl NextHop {
+    pub fn from_octets(octets: &[u8]) -> Self {
+        let ipv4 = |octets: &[u8]| Ipv4Addr::from(<[u8; 4]>::try_from(octets).unwrap());
+        let ipv6 = |octets: &[u8]| Ipv6Addr::from(<[u8; 16]>::try_from(octets).unwrap());
+        let zero_rd = |at: usize| octets[at..at + 8] == [0; 8];
+        match octets.len() {
+            4 => NextHop::Ipv4(ipv4(octets)),
+            16 => NextHop::Ipv6(ipv6(octets)),
+            32 => NextHop::Ipv6LinkLocal { global: ipv6(&octets[..16]), link_local: ipv6(&octets[16..]) },
+            12 if zero_rd(0) => NextHop::VpnIpv4(ipv4(&octets[8..])),
+            24 if zero_rd(0) => NextHop::VpnIpv6(ipv6(&octets[8..])),
+            48 if zero_rd(0) && zero_rd(24) => NextHop::VpnIpv6LinkLocal {
+                global: ipv6(&octets[8..24]),
+                link_local: ipv6(&octets[32..]),
+            },
+            _ => NextHop::Other(Bytes::copy_from_slice(octets)),
+        }
+    }
+
+    // The (global) address, None for Other.
+    pub fn address(&self) -> Option<IpAddr> {
+        match self {
+            NextHop::Ipv4(addr) | NextHop::VpnIpv4(addr) => Some(IpAddr::V4(*addr)),
+            NextHop::Ipv6(addr)
+            | NextHop::Ipv6LinkLocal { global: addr, .. }
+            | NextHop::VpnIpv6(addr)
+            | NextHop::VpnIpv6LinkLocal { global: addr, .. } => Some(IpAddr::V6(*addr)),
+            NextHop::Other(_) => None,
+        }
+    }
+
+    // Whether the next hop suits routes of afi/safi on a session where the
+    // peer's Extended Next Hop capability listed `extended`: the VPN forms
+    // for VPN families and plain addresses otherwise, of the family's own
+    // address type or IPv6 where `extended` allows it. Next hops of other
+    // AFIs, and Other, aren't checked.
+    pub fn fits(&self, afi: Afi, safi: Safi, extended: &[ExtendedNextHopFamily]) -> bool {
+        let vpn = matches!(safi, Safi::MplsVpn | Safi::MplsVpnMulticast);
+        let ipv6_allowed = || {
+            extended.iter().any(|f| f.nlri_afi == afi && f.nlri_safi == safi && f.next_hop_afi == Afi::Ipv6)
+        };
+        let (is_vpn, is_ipv6) = match self {
+            NextHop::Ipv4(_) => (false, false),
+            NextHop::Ipv6(_) | NextHop::Ipv6LinkLocal { .. } => (false, true),
+            NextHop::VpnIpv4(_) => (true, false),
+            NextHop::VpnIpv6(_) | NextHop::VpnIpv6LinkLocal { .. } => (true, true),
+            NextHop::Other(_) => return true,
+        };
+        match afi {
+            Afi::Ipv4 | Afi::Ipv6 => is_vpn == vpn && (is_ipv6 == (afi == Afi::Ipv6) || (is_ipv6 && ipv6_allowed())),
+            _ => true,
+        }
+    }
+}
+
+impl Nlri {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Nlri::Ipv4(prefixes) => prefixes.is_empty(),
+            Nlri::Ipv6(prefixes) => prefixes.is_empty(),
+            Nlri::FlowSpec(rules) => rules.is_empty(),
+            Nlri::Evpn(routes) => routes.is_empty(),
+            Nlri::LinkState(nlri) => nlri.is_empty(),
+            Nlri::Labeled(routes) => routes.is_empty(),
+            Nlri::Vpn(routes) => routes.is_empty(),
+            Nlri::Unknown(data) => data.is_empty(),
+        }
+    }
+}
+
+impl From<Ipv4Addr> for NextHop {
+    fn from(addr: Ipv4Addr) -> Self {
+        NextHop::Ipv4(addr)
+    }
+}
+
+impl From<Ipv6Addr> for NextHop {
+    fn from(addr: Ipv6Addr) -> Self {
+        NextHop::Ipv6(addr)
+    }
+}
+
+impl From<Vec<Ipv4Prefix>> for Nlri {
+    fn from(prefixes: Vec<Ipv4Prefix>) -> Self {
+        Nlri::Ipv4(prefixes)
+    }
+}
+
+impl From<Vec<Ipv6Prefix>> for Nlri {
+    fn from(prefixes: Vec<Ipv6Prefix>) -> Self {
+        Nlri::Ipv6(prefixes)
+    }
+}
+
+impl MpReachNlri {
+    // IPv6 unicast routes; the next hop gets the link-local address as well
+    // when one is given.
+    pub fn ipv6_unicast(global: Ipv6Addr, link_local: Option<Ipv6Addr>, nlri: Vec<Ipv6Prefix>) -> Self {
+        let next_hop = ipv6_next_hop(global, link_local);
+        MpReachNlri { afi: Afi::Ipv6, safi: Safi::Unicast, next_hop, nlri: Nlri::Ipv6(nlri) }
+    }
+
+    // RFC 8950. IPv4 unicast routes with an IPv6 next hop, for a peer whose
+    // Extended Next Hop capability lists IPv4 unicast.
+    pub fn ipv4_unicast_ipv6_next_hop(global: Ipv6Addr, link_local: Option<Ipv6Addr>, nlri: Vec<Ipv4Prefix>) -> Self {
+        let next_hop = ipv6_next_hop(global, link_local);
+        MpReachNlri { afi: Afi::Ipv4, safi: Safi::Unicast, next_hop, nlri: Nlri::Ipv4(nlri) }
+    }
+}
+
+fn ipv6_next_hop(global: Ipv6Addr, link_local: Option<Ipv6Addr>) -> NextHop {
+    match link_local {
+        Some(link_local) => NextHop::Ipv6LinkLocal { global, link_local },
+        None => NextHop::Ipv6(global),
+    }
+}
+
+// -- Text form --
+//
+// Text formats get MP_REACH_NLRI and MP_UNREACH_NLRI as plain structs and
+// the next hop and NLRI as enums, {"ipv6": ["2001:db8::/32"]}. NLRI of the
+// FlowSpec, EVPN, BGP-LS, labeled and VPN families is written in its wire
+// model and can't be read back.
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MpReachNlri")]
+struct MpReachNlriText {
+    afi: Afi,
+    safi: Safi,
+    #[serde(with = "NextHopText")]
+    next_hop: NextHop,
+    #[serde(with = "NlriText")]
+    nlri: Nlri,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MpUnreachNlri")]
+struct MpUnreachNlriText {
+    afi: Afi,
+    safi: Safi,
+    #[serde(with = "NlriText")]
+    withdrawn: Nlri,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "NextHop", rename_all = "snake_case")]
+enum NextHopText {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    Ipv6LinkLocal { global: Ipv6Addr, link_local: Ipv6Addr },
+    VpnIpv4(Ipv4Addr),
+    VpnIpv6(Ipv6Addr),
+    VpnIpv6LinkLocal { global: Ipv6Addr, link_local: Ipv6Addr },
+    Other(Bytes),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Nlri", rename_all = "snake_case")]
+enum NlriText {
+    Ipv4(Vec<Ipv4Prefix>),
+    Ipv6(Vec<Ipv6Prefix>),
+    #[serde(skip_deserializing)]
+    FlowSpec(Vec<FlowSpecRule>),
+    #[serde(skip_deserializing)]
+    Evpn(Vec<EvpnRoute>),
+    #[serde(skip_deserializing)]
+    LinkState(Vec<LinkStateNlri>),
+    #[serde(skip_deserializing)]
+    Labeled(Vec<LabeledPrefix>),
+    #[serde(skip_deserializing)]
+    Vpn(Vec<VpnPrefix>),
+    Unknown(Bytes),
+}
+
+// -- Wire form --
+
+impl Serialize for NextHop {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return NextHopText::serialize(self, serializer);
+        }
+        match self {
+            NextHop::Ipv4(addr) => addr.serialize(serializer),
+            NextHop::Ipv6(addr) => addr.serialize(serializer),
+            NextHop::Ipv6LinkLocal { global, link_local } => (global, link_local).serialize(serializer),
+            NextHop::VpnIpv4(addr) => ([0u8; 8], addr).serialize(serializer),
+            NextHop::VpnIpv6(addr) => ([0u8; 8], addr).serialize(serializer),
+            NextHop::VpnIpv6LinkLocal { global, link_local } => {
+                ([0u8; 8], global, [0u8; 8], link_local).serialize(serializer)
+            },
+            NextHop::Other(data) => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+// Takes whatever is left of the scope, which inside MP_REACH_NLRI is the
+// next hop length.
+impl<'de> Deserialize<'de> for NextHop {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return NextHopText::deserialize(deserializer);
+        }
+        Bytes::deserialize(deserializer).map(|data| NextHop::from_octets(&data))
+    }
+}
+
+impl Serialize for Nlri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return NlriText::serialize(self, serializer);
+        }
+        match self {
+            Nlri::Ipv4(prefixes) => prefixes.serialize(serializer),
+            Nlri::Ipv6(prefixes) => prefixes.serialize(serializer),
+            Nlri::FlowSpec(rules) => rules.serialize(serializer),
+            Nlri::Evpn(routes) => routes.serialize(serializer),
+            Nlri::LinkState(nlri) => nlri.serialize(serializer),
+            Nlri::Labeled(routes) => routes.serialize(serializer),
+            Nlri::Vpn(routes) => routes.serialize(serializer),
+            Nlri::Unknown(data) => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+// NLRI with the SAFI it was sent under. Multicast prefixes are wrapped in
+// MULTICAST_NLRI so ADD-PATH can be told apart from unicast.
+struct FamilyNlri<'a> {
+    safi: Safi,
+    nlri: &'a Nlri,
+}
+
+impl Serialize for FamilyNlri<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.safi {
+            Safi::Multicast => serializer.serialize_newtype_struct(MULTICAST_NLRI, self.nlri),
+            _ => self.nlri.serialize(serializer),
+        }
+    }
+}
+
+// NLRI of the given family, up to the end of the scope.
+struct NlriSeed {
+    afi: Afi,
+    safi: Safi,
+}
+
+impl<'de> DeserializeSeed<'de> for NlriSeed {
+    type Value = Nlri;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Nlri, D::Error> {
+        match (self.afi, self.safi) {
+            (_, Safi::Multicast) => d.deserialize_newtype_struct(MULTICAST_NLRI, self),
+            (Afi::Ipv4, Safi::Unicast) => Vec::deserialize(d).map(Nlri::Ipv4),
+            (Afi::Ipv6, Safi::Unicast) => Vec::deserialize(d).map(Nlri::Ipv6),
+            (afi @ (Afi::Ipv4 | Afi::Ipv6), Safi::FlowSpec) => FlowSpecSeed { afi }.deserialize(d).map(Nlri::FlowSpec),
+            (Afi::L2vpn, Safi::Evpn) => Vec::deserialize(d).map(Nlri::Evpn),
+            (Afi::BgpLs, Safi::BgpLs) => Vec::deserialize(d).map(Nlri::LinkState),
+            (afi @ (Afi::Ipv4 | Afi::Ipv6), Safi::Mpls) => LabeledSeed { afi }.deserialize(d).map(Nlri::Labeled),
+            (afi @ (Afi::Ipv4 | Afi::Ipv6), Safi::MplsVpn) => VpnSeed { afi }.deserialize(d).map(Nlri::Vpn),
+            _ => Bytes::deserialize(d).map(Nlri::Unknown),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for NlriSeed {
+    type Value = Nlri;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("multicast NLRI")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Nlri, D::Error> {
+        match self.afi {
+            Afi::Ipv4 => Vec::deserialize(d).map(Nlri::Ipv4),
+            Afi::Ipv6 => Vec::deserialize(d).map(Nlri::Ipv6),
+            _ => Bytes::deserialize(d).map(Nlri::Unknown),
+        }
+    }
+}
+
+impl Serialize for MpReachNlri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return MpReachNlriText::serialize(self, serializer);
+        }
+        let next_hop: LengthPrefixed<u8, _> = LengthPrefixed::new(&self.next_hop);
+        let mut tup = serializer.serialize_tuple(5)?;
+        tup.serialize_element(&self.afi)?;
+        tup.serialize_element(&self.safi)?;
+        tup.serialize_element(&next_hop)?;
+        // Reserved
+        tup.serialize_element(&0u8)?;
+        tup.serialize_element(&FamilyNlri { safi: self.safi, nlri: &self.nlri })?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for MpReachNlri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return MpReachNlriText::deserialize(deserializer);
+        }
+        struct ReachVisitor;
+
+        impl<'de> Visitor<'de> for ReachVisitor {
+            type Value = MpReachNlri;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an MP_REACH_NLRI value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let afi: Afi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let safi: Safi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let next_hop = seq.next_element_seed(LengthPrefixedSeed { width: 1, seed: PhantomData::<NextHop> })?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                // Reserved, ignored on receipt
+                let _: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                let nlri = seq.next_element_seed(NlriSeed { afi, safi })?
+                    .ok_or_else(|| de::Error::invalid_length(4, &self))?;
+                Ok(MpReachNlri { afi, safi, next_hop, nlri })
+            }
+        }
+
+        deserializer.deserialize_tuple(5, ReachVisitor)
+    }
+}
+
+impl Serialize for MpUnreachNlri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return MpUnreachNlriText::serialize(self, serializer);
+        }
+        let mut tup = serializer.serialize_tuple(3)?;
+        tup.serialize_element(&self.afi)?;
+        tup.serialize_element(&self.safi)?;
+        tup.serialize_element(&FamilyNlri { safi: self.safi, nlri: &self.withdrawn })?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for MpUnreachNlri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return MpUnreachNlriText::deserialize(deserializer);
+        }
+        struct UnreachVisitor;
+
+        impl<'de> Visitor<'de> for UnreachVisitor {
+            type Value = MpUnreachNlri;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an MP_UNREACH_NLRI value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let afi: Afi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let safi: Safi = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let withdrawn = seq.next_element_seed(NlriSeed { afi, safi })?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                Ok(MpUnreachNlri { afi, safi, withdrawn })
+            }
+        }
+
+        deserializer.deserialize_tuple(3, UnreachVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_bytes, from_bytes_with, to_bytes, to_bytes_with, AddPathFamilies, DeserializerOptions, PathAttribute,
+        SerializerOptions,
+    };
+
+    #[test]
+    fn test_mp_reach_wire() {
+        let reach = MpReachNlri {
+            afi: Afi::Ipv4,
+            safi: Safi::Unicast,
+            next_hop: NextHop::Ipv4(Ipv4Addr::new(192, 0, 2, 1)),
+            nlri: Nlri::Ipv4(vec!["198.51.100.0/24".parse().unwrap(), "10.0.0.0/8".parse().unwrap()]),
+        };
+        let attr = PathAttribute::MpReachNlri(reach);
+        let wire = [
+            0x80, 14, 15,
+            0, 1, 1,
+            4, 192, 0, 2, 1,
+            0,
+            24, 198, 51, 100, 8, 10,
+        ];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+    }
+
+    #[test]
+    fn test_mp_reach_next_hop_lengths() {
+        // 16 and 32 octet next hops in front of NLRI the crate keeps raw
+        for nh_len in [16u8, 32, 24] {
+            let mut wire = vec![0, 2, 129, nh_len];
+            wire.extend((0..nh_len).map(|i| i + 1));
+            wire.extend_from_slice(&[0, 0xaa, 0xbb]);
+            let reach: MpReachNlri = from_bytes(&wire).unwrap();
+            assert_eq!(reach.afi, Afi::Ipv6);
+            assert_eq!(reach.safi, Safi::MplsVpnMulticast);
+            match (&reach.next_hop, nh_len) {
+                (NextHop::Ipv6(addr), 16) => assert_eq!(addr.octets()[0], 1),
+                (NextHop::Ipv6LinkLocal { link_local, .. }, 32) => assert_eq!(link_local.octets()[0], 17),
+                (NextHop::Other(data), 24) => assert_eq!(data.len(), 24),
+                (other, _) => panic!("Unexpected next hop {:?}", other),
+            }
+            assert_eq!(reach.nlri, Nlri::Unknown(Bytes::from_static(&[0xaa, 0xbb])));
+            assert_eq!(to_bytes(&reach).unwrap().as_ref(), wire.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_mp_reach_ipv6() {
+        let global: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+        let nlri = vec!["2001:db8:1::/48".parse().unwrap(), Ipv6Prefix::from(global)];
+        let reach = MpReachNlri::ipv6_unicast(global, Some(link_local), nlri);
+
+        let bytes = to_bytes(&reach).unwrap();
+        assert_eq!(&bytes[..4], &[0, 2, 1, 32]);
+        assert_eq!(&bytes[4..20], &global.octets());
+        assert_eq!(&bytes[20..36], &link_local.octets());
+        assert_eq!(&bytes[36..], &[
+            0,
+            48, 0x20, 0x01, 0x0d, 0xb8, 0, 1,
+            128, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]);
+        assert_eq!(from_bytes::<MpReachNlri>(&bytes).unwrap(), reach);
+
+        // Global address only
+        let reach = MpReachNlri::ipv6_unicast(global, None, vec![]);
+        let bytes = to_bytes(&reach).unwrap();
+        assert_eq!(bytes[3], 16);
+        assert_eq!(from_bytes::<MpReachNlri>(&bytes).unwrap().next_hop, NextHop::Ipv6(global));
+
+        let unreach: MpUnreachNlri = from_bytes(&[0, 2, 1, 32, 0x20, 0x01, 0x0d, 0xb8]).unwrap();
+        assert_eq!(unreach.withdrawn, Nlri::Ipv6(vec!["2001:db8::/32".parse().unwrap()]));
+    }
+
+    #[test]
+    fn test_mp_reach_add_path() {
+        let families = AddPathFamilies::new().with(Afi::Ipv6, Safi::Multicast);
+        let ser_opts = SerializerOptions { add_path: families, ..Default::default() };
+        let de_opts = DeserializerOptions { add_path: families, ..Default::default() };
+        let prefix: Ipv6Prefix = "2001:db8::/32".parse().unwrap();
+        let mut reach = MpReachNlri::ipv6_unicast("2001:db8::1".parse().unwrap(), None, vec![prefix.with_path_id(7)]);
+
+        // Unicast wasn't negotiated, no path identifier.
+        let bytes = to_bytes_with(&reach, ser_opts).unwrap();
+        assert_eq!(&bytes[21..], &[32, 0x20, 0x01, 0x0d, 0xb8]);
+        let decoded: MpReachNlri = from_bytes_with(&bytes, de_opts).unwrap();
+        assert_eq!(decoded.nlri, Nlri::Ipv6(vec![prefix]));
+
+        reach.safi = Safi::Multicast;
+        let bytes = to_bytes_with(&reach, ser_opts).unwrap();
+        assert_eq!(&bytes[21..], &[0, 0, 0, 7, 32, 0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(from_bytes_with::<MpReachNlri>(&bytes, de_opts).unwrap(), reach);
+    }
+
+    #[test]
+    fn test_mp_reach_extended_next_hop() {
+        // RFC 8950 8 example, 192.0.2.0/24 behind 2001:db8::1
+        let global: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let reach = MpReachNlri::ipv4_unicast_ipv6_next_hop(global, None, vec!["192.0.2.0/24".parse().unwrap()]);
+        let bytes = to_bytes(&reach).unwrap();
+        assert_eq!(&bytes[..4], &[0, 1, 1, 16]);
+        assert_eq!(&bytes[20..], &[0, 24, 192, 0, 2]);
+        assert_eq!(from_bytes::<MpReachNlri>(&bytes).unwrap(), reach);
+        assert_eq!(reach.next_hop.address(), Some(IpAddr::V6(global)));
+
+        let ipv4_unicast =
+            ExtendedNextHopFamily { nlri_afi: Afi::Ipv4, nlri_safi: Safi::Unicast, next_hop_afi: Afi::Ipv6 };
+        assert!(reach.next_hop.fits(Afi::Ipv4, Safi::Unicast, &[ipv4_unicast]));
+        assert!(!reach.next_hop.fits(Afi::Ipv4, Safi::Unicast, &[]));
+        assert!(!reach.next_hop.fits(Afi::Ipv4, Safi::MplsVpn, &[ipv4_unicast]));
+        assert!(reach.next_hop.fits(Afi::Ipv6, Safi::Unicast, &[]));
+        assert!(!NextHop::Ipv4(Ipv4Addr::new(192, 0, 2, 1)).fits(Afi::Ipv6, Safi::Unicast, &[ipv4_unicast]));
+
+        // VPN next hops by length, each behind a zero RD
+        let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+        let vpn = [
+            NextHop::VpnIpv4(Ipv4Addr::new(192, 0, 2, 1)),
+            NextHop::VpnIpv6(global),
+            NextHop::VpnIpv6LinkLocal { global, link_local },
+        ];
+        for (next_hop, len) in vpn.into_iter().zip([12, 24, 48]) {
+            let bytes = to_bytes(&next_hop).unwrap();
+            assert_eq!(bytes.len(), len);
+            assert_eq!(&bytes[..8], &[0; 8]);
+            assert_eq!(NextHop::from_octets(&bytes), next_hop);
+        }
+        let vpnv4_over_ipv6 =
+            ExtendedNextHopFamily { nlri_afi: Afi::Ipv4, nlri_safi: Safi::MplsVpn, next_hop_afi: Afi::Ipv6 };
+        assert!(NextHop::VpnIpv6(global).fits(Afi::Ipv4, Safi::MplsVpn, &[vpnv4_over_ipv6]));
+        assert!(!NextHop::VpnIpv6(global).fits(Afi::Ipv4, Safi::Unicast, &[vpnv4_over_ipv6]));
+        assert!(!NextHop::VpnIpv4(Ipv4Addr::new(192, 0, 2, 1)).fits(Afi::Ipv4, Safi::Unicast, &[]));
+    }
+
+    #[test]
+    fn test_mp_unreach_wire() {
+        let unreach = MpUnreachNlri {
+            afi: Afi::Ipv4,
+            safi: Safi::Unicast,
+            withdrawn: Nlri::Ipv4(vec!["192.0.2.0/24".parse().unwrap()]),
+        };
+        let attr = PathAttribute::MpUnreachNlri(unreach);
+        let wire = [0x80, 15, 7, 0, 1, 1, 24, 192, 0, 2];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<PathAttribute>(&wire).unwrap(), attr);
+
+        // End-of-RIB for a family is an empty MP_UNREACH_NLRI.
+        let eor: MpUnreachNlri = from_bytes(&[0, 1, 1]).unwrap();
+        assert!(eor.withdrawn.is_empty());
+    }
+
+    #[test]
+    fn test_mp_reach_err() {
+        // Next hop length runs past the attribute
+        assert!(from_bytes::<MpReachNlri>(&[0, 1, 1, 4, 192, 0]).is_err());
+        // Prefix in the NLRI is cut short
+        match from_bytes::<MpReachNlri>(&[0, 1, 1, 4, 192, 0, 2, 1, 0, 24, 198]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(e.to_string().starts_with("Invalid network field"), "{}", e),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+// NOTIFICATION error codes and subcodes (RFC 4271 4.5, with the FSM Error
+// subcodes from RFC 6608 and the Cease subcodes from RFC 4486 and RFC 8538).
+//
+// On the wire this is just the error code octet and the subcode octet.
+// Decoding rejects subcodes that aren't defined for a known code; codes the
+// crate doesn't know keep both octets in Unknown. NotificationMessage keeps
+// the raw octets so an odd NOTIFICATION still decodes, NotificationCode is
+// what it looks like once checked.
+use std::fmt;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationCode {
+    MessageHeader(MessageHeaderError),
+    OpenMessage(OpenMessageError),
+    UpdateMessage(UpdateMessageError),
+    HoldTimerExpired,
+    Fsm(FsmError),
+    Cease(CeaseSubcode),
+    Unknown { code: u8, subcode: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageHeaderError {
+    Unspecific,
+    ConnectionNotSynchronized,
+    BadMessageLength,
+    BadMessageType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpenMessageError {
+    Unspecific,
+    UnsupportedVersionNumber,
+    BadPeerAs,
+    BadBgpIdentifier,
+    UnsupportedOptionalParameter,
+    UnacceptableHoldTime,
+    UnsupportedCapability,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpdateMessageError {
+    Unspecific,
+    MalformedAttributeList,
+    UnrecognizedWellKnownAttribute,
+    MissingWellKnownAttribute,
+    AttributeFlagsError,
+    AttributeLengthError,
+    InvalidOriginAttribute,
+    InvalidNextHopAttribute,
+    OptionalAttributeError,
+    InvalidNetworkField,
+    MalformedAsPath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FsmError {
+    Unspecified,
+    UnexpectedMessageInOpenSent,
+    UnexpectedMessageInOpenConfirm,
+    UnexpectedMessageInEstablished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CeaseSubcode {
+    Unspecific,
+    MaximumPrefixesReached,
+    AdministrativeShutdown,
+    PeerDeconfigured,
+    AdministrativeReset,
+    ConnectionRejected,
+    OtherConfigurationChange,
+    ConnectionCollisionResolution,
+    OutOfResources,
+    HardReset,
+}
+
+// Subcode tables, value first. Kept as slices so both directions come from
+// the same list.
+const MESSAGE_HEADER: &[(u8, MessageHeaderError)] = &[
+    (0, MessageHeaderError::Unspecific),
+    (1, MessageHeaderError::ConnectionNotSynchronized),
+    (2, MessageHeaderError::BadMessageLength),
+    (3, MessageHeaderError::BadMessageType),
+];
+
+const OPEN_MESSAGE: &[(u8, OpenMessageError)] = &[
+    (0, OpenMessageError::Unspecific),
+    (1, OpenMessageError::UnsupportedVersionNumber),
+    (2, OpenMessageError::BadPeerAs),
+    (3, OpenMessageError::BadBgpIdentifier),
+    (4, OpenMessageError::UnsupportedOptionalParameter),
+    (6, OpenMessageError::UnacceptableHoldTime),
+    (7, OpenMessageError::UnsupportedCapability),
+];
+
+const UPDATE_MESSAGE: &[(u8, UpdateMessageError)] = &[
+    (0, UpdateMessageError::Unspecific),
+    (1, UpdateMessageError::MalformedAttributeList),
+    (2, UpdateMessageError::UnrecognizedWellKnownAttribute),
+    (3, UpdateMessageError::MissingWellKnownAttribute),
+    (4, UpdateMessageError::AttributeFlagsError),
+    (5, UpdateMessageError::AttributeLengthError),
+    (6, UpdateMessageError::InvalidOriginAttribute),
+    (8, UpdateMessageError::InvalidNextHopAttribute),
+    (9, UpdateMessageError::OptionalAttributeError),
+    (10, UpdateMessageError::InvalidNetworkField),
+    (11, UpdateMessageError::MalformedAsPath),
+];
+
+const FSM: &[(u8, FsmError)] = &[
+    (0, FsmError::Unspecified),
+    (1, FsmError::UnexpectedMessageInOpenSent),
+    (2, FsmError::UnexpectedMessageInOpenConfirm),
+    (3, FsmError::UnexpectedMessageInEstablished),
+];
+
+const CEASE: &[(u8, CeaseSubcode)] = &[
+    (0, CeaseSubcode::Unspecific),
+    (1, CeaseSubcode::MaximumPrefixesReached),
+    (2, CeaseSubcode::AdministrativeShutdown),
+    (3, CeaseSubcode::PeerDeconfigured),
+    (4, CeaseSubcode::AdministrativeReset),
+    (5, CeaseSubcode::ConnectionRejected),
+    (6, CeaseSubcode::OtherConfigurationChange),
+    (7, CeaseSubcode::ConnectionCollisionResolution),
+    (8, CeaseSubcode::OutOfResources),
+    (9, CeaseSubcode::HardReset),
+];
+
+fn to_wire<T: PartialEq>(table: &[(u8, T)], sub: &T) -> u8 {
+    table.iter().find(|(_, s)| s == sub).map(|(v, _)| *v).unwrap_or(0)
+}
+
+fn from_wire<T: Copy>(table: &[(u8, T)], v: u8) -> Option<T> {
+    table.iter().find(|(w, _)| *w == v).map(|(_, s)| *s)
+}
+
+impl NotificationCode {
+    pub fn code(&self) -> u8 {
+        match self {
+            NotificationCode::MessageHeader(_) => 1,
+            NotificationCode::OpenMessage(_) => 2,
+            NotificationCode::UpdateMessage(_) => 3,
+            NotificationCode::HoldTimerExpired => 4,
+            NotificationCode::Fsm(_) => 5,
+            NotificationCode::Cease(_) => 6,
+            NotificationCode::Unknown { code, .. } => *code,
+        }
+    }
+
+    pub fn subcode(&self) -> u8 {
+        match self {
+            NotificationCode::MessageHeader(s) => to_wire(MESSAGE_HEADER, s),
+            NotificationCode::OpenMessage(s) => to_wire(OPEN_MESSAGE, s),
+            NotificationCode::UpdateMessage(s) => to_wire(UPDATE_MESSAGE, s),
+            NotificationCode::HoldTimerExpired => 0,
+            NotificationCode::Fsm(s) => to_wire(FSM, s),
+            NotificationCode::Cease(s) => to_wire(CEASE, s),
+            NotificationCode::Unknown { subcode, .. } => *subcode,
+        }
+    }
+
+    // None if the subcode isn't defined for a known error code.
+    pub fn from_octets(code: u8, subcode: u8) -> Option<Self> {
+        match code {
+            1 => from_wire(MESSAGE_HEADER, subcode).map(NotificationCode::MessageHeader),
+            2 => from_wire(OPEN_MESSAGE, subcode).map(NotificationCode::OpenMessage),
+            3 => from_wire(UPDATE_MESSAGE, subcode).map(NotificationCode::UpdateMessage),
+            4 => (subcode == 0).then_some(NotificationCode::HoldTimerExpired),
+            5 => from_wire(FSM, subcode).map(NotificationCode::Fsm),
+            6 => from_wire(CEASE, subcode).map(NotificationCode::Cease),
+            code => Some(NotificationCode::Unknown { code, subcode }),
+        }
+    }
+}
+
+impl Serialize for NotificationCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(2)?;
+        tup.serialize_element(&self.code())?;
+        tup.serialize_element(&self.subcode())?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for NotificationCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct CodeVisitor;
+
+        impl<'de> Visitor<'de> for CodeVisitor {
+            type Value = NotificationCode;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an error code and subcode")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let code: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let subcode: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                NotificationCode::from_octets(code, subcode).ok_or_else(|| {
+                    de::Error::custom(format!("Undefined subcode {} for error code {}", subcode, code))
+                })
+            }
+        }
+
+        deserializer.deserialize_tuple(2, CodeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes};
+
+    #[test]
+    fn test_notification_code_wire() {
+        let cases = [
+            (NotificationCode::MessageHeader(MessageHeaderError::BadMessageLength), [1, 2]),
+            (NotificationCode::OpenMessage(OpenMessageError::UnacceptableHoldTime), [2, 6]),
+            (NotificationCode::UpdateMessage(UpdateMessageError::MalformedAsPath), [3, 11]),
+            (NotificationCode::HoldTimerExpired, [4, 0]),
+            (NotificationCode::Fsm(FsmError::UnexpectedMessageInEstablished), [5, 3]),
+            (NotificationCode::Cease(CeaseSubcode::AdministrativeShutdown), [6, 2]),
+            (NotificationCode::Cease(CeaseSubcode::HardReset), [6, 9]),
+            (NotificationCode::Unknown { code: 7, subcode: 1 }, [7, 1]),
+        ];
+        for (code, wire) in cases {
+            assert_eq!(to_bytes(code).unwrap().as_ref(), &wire, "{:?}", code);
+            assert_eq!(from_bytes::<NotificationCode>(&wire).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_notification_code_err() {
+        // 5 and 7 are deprecated OPEN/UPDATE subcodes
+        for wire in [[2, 5], [3, 7], [4, 1], [6, 200]] {
+            match from_bytes::<NotificationCode>(&wire) {
+                Ok(_) => panic!("Expected Err, got Ok"),
+                Err(e) => assert_eq!(
+                    e.to_string(),
+                    format!("Undefined subcode {} for error code {}", wire[1], wire[0])
+                ),
+            }
+        }
+    }
+}
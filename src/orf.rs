@@ -0,0 +1,469 @@
+// Outbound Route Filtering in ROUTE-REFRESH (RFC 5291), with the Address
+// Prefix ORF entries of RFC 5292.
+//
+// After the AFI/SAFI a ROUTE-REFRESH may carry a when-to-refresh octet and
+// one or more ORFs, each a 1 octet type, a 2 octet length and the entries.
+// Every entry starts with an octet holding the action and match bits; the
+// rest depends on the ORF type, and is left out entirely for REMOVE-ALL.
+// Address Prefix entries hold a prefix of the message's AFI, so decoding
+// needs the AFI read first; ORF types (or AFIs) the crate doesn't model keep
+// their entries as raw octets.
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::Afi;
+use crate::prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+use crate::registry::orf;
+use crate::types::{LengthPrefixed, LengthPrefixedSeed};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RouteRefreshOrf {
+    pub when_to_refresh: WhenToRefresh,
+    pub orfs: Vec<Orf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum WhenToRefresh {
+    Immediate,
+    // Hold off until a later ROUTE-REFRESH asks for IMMEDIATE.
+    Defer,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Orf {
+    AddressPrefix(Vec<OrfEntry>),
+    Unknown {
+        orf_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// One Address Prefix ORF entry. `prefix` is None exactly when the action
+// is RemoveAll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OrfEntry {
+    pub action: OrfAction,
+    pub match_type: OrfMatch,
+    pub prefix: Option<OrfPrefix>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OrfAction {
+    Add,
+    Remove,
+    RemoveAll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OrfMatch {
+    Permit,
+    Deny,
+}
+
+// Matches routes covered by `prefix` whose length is within
+// min_len..=max_len (0 meaning unset). Entries are applied in sequence
+// order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct OrfPrefix {
+    pub sequence: u32,
+    pub min_len: u8,
+    pub max_len: u8,
+    pub prefix: IpPrefix,
+}
+
+impl Orf {
+    pub fn orf_type(&self) -> u8 {
+        match self {
+            Orf::AddressPrefix(_) => orf::ADDRESS_PREFIX,
+            Orf::Unknown { orf_type, .. } => *orf_type,
+        }
+    }
+}
+
+impl OrfEntry {
+    pub fn add(match_type: OrfMatch, prefix: OrfPrefix) -> Self {
+        OrfEntry { action: OrfAction::Add, match_type, prefix: Some(prefix) }
+    }
+
+    pub fn remove(match_type: OrfMatch, prefix: OrfPrefix) -> Self {
+        OrfEntry { action: OrfAction::Remove, match_type, prefix: Some(prefix) }
+    }
+
+    pub fn remove_all() -> Self {
+        OrfEntry { action: OrfAction::RemoveAll, match_type: OrfMatch::Permit, prefix: None }
+    }
+}
+
+impl From<u8> for WhenToRefresh {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => WhenToRefresh::Immediate,
+            2 => WhenToRefresh::Defer,
+            other => WhenToRefresh::Unknown(other),
+        }
+    }
+}
+
+impl From<WhenToRefresh> for u8 {
+    fn from(w: WhenToRefresh) -> Self {
+        match w {
+            WhenToRefresh::Immediate => 1,
+            WhenToRefresh::Defer => 2,
+            WhenToRefresh::Unknown(other) => other,
+        }
+    }
+}
+
+impl Serialize for WhenToRefresh {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for WhenToRefresh {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(WhenToRefresh::from)
+    }
+}
+
+// -- Wire form --
+
+impl Serialize for RouteRefreshOrf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (self.when_to_refresh, &self.orfs).serialize(serializer)
+    }
+}
+
+impl Serialize for Orf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(2)?;
+        tup.serialize_element(&self.orf_type())?;
+        match self {
+            Orf::AddressPrefix(entries) => tup.serialize_element(&LengthPrefixed::<u16, _>::new(entries))?,
+            Orf::Unknown { data, .. } => tup.serialize_element(&LengthPrefixed::<u16, _>::new(data))?,
+        }
+        tup.end()
+    }
+}
+
+impl Serialize for OrfEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let action: u8 = match self.action {
+            OrfAction::Add => 0,
+            OrfAction::Remove => 1,
+            OrfAction::RemoveAll => 2,
+        };
+        let deny = (self.match_type == OrfMatch::Deny) as u8;
+        ((action << 6) | (deny << 5), &self.prefix).serialize(serializer)
+    }
+}
+
+impl Serialize for OrfPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        // Same length + significant octets as NLRI, without a path
+        // identifier even on ADD-PATH sessions.
+        let octets = match self.prefix.addr() {
+            IpAddr::V4(a) => a.octets().to_vec(),
+            IpAddr::V6(a) => a.octets().to_vec(),
+        };
+        let len = self.prefix.prefix_len();
+        let n = (len as usize).div_ceil(8);
+        let mut tup = serializer.serialize_tuple(4 + n)?;
+        tup.serialize_element(&self.sequence)?;
+        tup.serialize_element(&self.min_len)?;
+        tup.serialize_element(&self.max_len)?;
+        tup.serialize_element(&len)?;
+        for b in &octets[..n] {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+// The optional ORF part of a ROUTE-REFRESH, None if the message ends after
+// the AFI/SAFI.
+pub(crate) struct RouteRefreshOrfSeed {
+    pub afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for RouteRefreshOrfSeed {
+    type Value = Option<RouteRefreshOrf>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RouteRefreshOrfSeed {
+    type Value = Option<RouteRefreshOrf>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ROUTE-REFRESH ORFs")
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_tuple(2, OrfsVisitor { afi: self.afi }).map(Some)
+    }
+}
+
+struct OrfsVisitor {
+    afi: Afi,
+}
+
+impl<'de> Visitor<'de> for OrfsVisitor {
+    type Value = RouteRefreshOrf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a when-to-refresh octet and ORFs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let when_to_refresh = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let orfs = seq
+            .next_element_seed(OrfListSeed { afi: self.afi })?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(RouteRefreshOrf { when_to_refresh, orfs })
+    }
+}
+
+// ORFs up to the end of the message.
+struct OrfListSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for OrfListSeed {
+    type Value = Vec<Orf>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OrfListSeed {
+    type Value = Vec<Orf>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of ORFs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut orfs = Vec::new();
+        while let Some(orf) = seq.next_element_seed(OrfSeed { afi: self.afi })? {
+            orfs.push(orf);
+        }
+        Ok(orfs)
+    }
+}
+
+struct OrfSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for OrfSeed {
+    type Value = Orf;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de> Visitor<'de> for OrfSeed {
+    type Value = Orf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ORF")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let orf_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let seed = LengthPrefixedSeed { width: 2, seed: OrfValueSeed { orf_type, afi: self.afi } };
+        seq.next_element_seed(seed)?.ok_or_else(|| de::Error::invalid_length(1, &self))
+    }
+}
+
+struct OrfValueSeed {
+    orf_type: u8,
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for OrfValueSeed {
+    type Value = Orf;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        match (self.orf_type, self.afi) {
+            (orf::ADDRESS_PREFIX, Afi::Ipv4 | Afi::Ipv6) => d.deserialize_seq(self),
+            (orf_type, _) => Bytes::deserialize(d).map(|data| Orf::Unknown { orf_type, data }),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for OrfValueSeed {
+    type Value = Orf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Address Prefix ORF entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = seq.next_element_seed(OrfEntrySeed { afi: self.afi })? {
+            entries.push(entry);
+        }
+        Ok(Orf::AddressPrefix(entries))
+    }
+}
+
+struct OrfEntrySeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for OrfEntrySeed {
+    type Value = OrfEntry;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        // Common octet, sequence, min/max length, prefix length and at
+        // most 16 address octets.
+        d.deserialize_tuple(5 + 16, self)
+    }
+}
+
+impl<'de> Visitor<'de> for OrfEntrySeed {
+    type Value = OrfEntry;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an Address Prefix ORF entry")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let common: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let action = match common >> 6 {
+            0 => OrfAction::Add,
+            1 => OrfAction::Remove,
+            2 => OrfAction::RemoveAll,
+            other => return Err(de::Error::custom(format!("Invalid ORF action {}", other))),
+        };
+        let match_type = match common & 0x20 {
+            0 => OrfMatch::Permit,
+            _ => OrfMatch::Deny,
+        };
+        if action == OrfAction::RemoveAll {
+            return Ok(OrfEntry { action, match_type, prefix: None });
+        }
+
+        let sequence: u32 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let min_len: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let max_len: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let len: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?;
+        let max = match self.afi {
+            Afi::Ipv4 => 32,
+            _ => 128,
+        };
+        if len > max {
+            return Err(de::Error::custom(format!("Invalid prefix length {}, at most {} allowed", len, max)));
+        }
+        let mut octets = [0u8; 16];
+        for (i, b) in octets.iter_mut().take((len as usize).div_ceil(8)).enumerate() {
+            *b = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i + 5, &self))?;
+        }
+        let prefix = match self.afi {
+            Afi::Ipv4 => {
+                let addr = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
+                Ipv4Prefix::new(addr, len).map(IpPrefix::V4)
+            },
+            _ => Ipv6Prefix::new(Ipv6Addr::from(octets), len).map(IpPrefix::V6),
+        };
+        // The length was checked above
+        let prefix = prefix.ok_or_else(|| de::Error::custom("Invalid ORF prefix"))?;
+        Ok(OrfEntry { action, match_type, prefix: Some(OrfPrefix { sequence, min_len, max_len, prefix }) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_message_bytes, to_message_bytes, BgpMessage, DeserializeError, MessageType, RouteRefreshMessage, Safi,
+    };
+
+    fn orf_prefix(sequence: u32, prefix: &str, min_len: u8, max_len: u8) -> OrfPrefix {
+        OrfPrefix { sequence, min_len, max_len, prefix: prefix.parse().unwrap() }
+    }
+
+    #[test]
+    fn test_orf_wire() {
+        let refresh = RouteRefreshMessage::with_orf(
+            Afi::Ipv4,
+            Safi::Unicast,
+            WhenToRefresh::Immediate,
+            vec![Orf::AddressPrefix(vec![
+                OrfEntry::remove_all(),
+                OrfEntry::add(OrfMatch::Permit, orf_prefix(10, "10.0.0.0/8", 16, 24)),
+                OrfEntry::add(OrfMatch::Deny, orf_prefix(20, "0.0.0.0/0", 0, 0)),
+            ])],
+        );
+        let bytes = to_message_bytes(MessageType::RouteRefresh, &refresh).unwrap();
+        #[rustfmt::skip]
+        let body: &[u8] = &[
+            0, 1, 0, 1,
+            1,
+            64, 0, 18,
+            0x80,
+            0x00, 0, 0, 0, 10, 16, 24, 8, 10,
+            0x20, 0, 0, 0, 20, 0, 0, 0,
+        ];
+        assert_eq!(&bytes[19..], body);
+        assert_eq!(BgpMessage::from_bytes(&bytes).unwrap(), BgpMessage::RouteRefresh(refresh));
+
+        // IPv6 prefixes, and ORF types the crate doesn't model.
+        let mut refresh = RouteRefreshMessage::new(Afi::Ipv6, Safi::Unicast);
+        refresh.orf = Some(RouteRefreshOrf {
+            when_to_refresh: WhenToRefresh::Defer,
+            orfs: vec![
+                Orf::AddressPrefix(vec![OrfEntry::remove(OrfMatch::Permit, orf_prefix(1, "2001:db8::/32", 48, 64))]),
+                Orf::Unknown { orf_type: 65, data: Bytes::from_static(&[1, 2, 3]) },
+            ],
+        });
+        let bytes = to_message_bytes(MessageType::RouteRefresh, &refresh).unwrap();
+        assert_eq!(bytes[23], 2);
+        assert_eq!(from_message_bytes::<RouteRefreshMessage>(&bytes).unwrap(), refresh);
+    }
+
+    #[test]
+    fn test_orf_err() {
+        let mut wire = to_message_bytes(MessageType::RouteRefresh, RouteRefreshMessage::new(Afi::Ipv4, Safi::Unicast))
+            .unwrap()
+            .to_vec();
+        // Action 3 is undefined
+        wire.extend_from_slice(&[1, 64, 0, 1, 0xc0]);
+        wire[17] = wire.len() as u8;
+        match from_message_bytes::<RouteRefreshMessage>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner(), &DeserializeError::Custom(String::from("Invalid ORF action 3"))),
+        }
+
+        // The ORF length runs past the end of the message
+        let len = wire.len();
+        wire[len - 3..len - 1].copy_from_slice(&[0, 9]);
+        wire[len - 1] = 0x80;
+        assert!(from_message_bytes::<RouteRefreshMessage>(&wire).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+// IP prefixes in NLRI encoding (RFC 4271 4.3): a length octet in bits
+// followed by only as many address octets as the length needs. On ADD-PATH
+// sessions (RFC 7911) a 4 octet path identifier comes first; whether it is
+// there depends on what was negotiated, so it's the add_path option that
+// decides, not the type.
+//
+// The address family is never on the wire, it comes from context (plain
+// UPDATE NLRI is IPv4, MP_REACH/MP_UNREACH say so in their AFI), so each
+// family has its own type. IpPrefix holds either for code that doesn't care,
+// but can only be serialized.
+use std::fmt::{self, Display};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::SerializerError;
+use crate::types::{PATH_ID_IPV4, PATH_ID_IPV6, PREFIX_LEN_IPV4, PREFIX_LEN_IPV6};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ipv4Prefix {
+    addr: Ipv4Addr,
+    len: u8,
+    path_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ipv6Prefix {
+    addr: Ipv6Addr,
+    len: u8,
+    path_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum IpPrefix {
+    V4(Ipv4Prefix),
+    V6(Ipv6Prefix),
+}
+
+// Clears the bits past the prefix length.
+fn mask<const N: usize>(mut octets: [u8; N], len: u8) -> [u8; N] {
+    for (i, b) in octets.iter_mut().enumerate() {
+        let keep = (len as usize).saturating_sub(i * 8).min(8);
+        *b &= !(0xffu8.checked_shr(keep as u32).unwrap_or(0));
+    }
+    octets
+}
+
+impl Ipv4Prefix {
+    // None if the length is over 32. Host bits are cleared.
+    pub fn new(addr: Ipv4Addr, len: u8) -> Option<Self> {
+        if len > 32 {
+            return None;
+        }
+        Some(Ipv4Prefix { addr: Ipv4Addr::from(mask(addr.octets(), len)), len, path_id: None })
+    }
+
+    pub fn addr(&self) -> Ipv4Addr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+
+    // Set when decoded from an ADD-PATH session. Written as 0 on ADD-PATH
+    // sessions if unset, and not at all elsewhere.
+    pub fn path_id(&self) -> Option<u32> {
+        self.path_id
+    }
+
+    pub fn with_path_id(self, path_id: u32) -> Self {
+        Ipv4Prefix { path_id: Some(path_id), ..self }
+    }
+}
+
+impl Ipv6Prefix {
+    // None if the length is over 128. Host bits are cleared.
+    pub fn new(addr: Ipv6Addr, len: u8) -> Option<Self> {
+        if len > 128 {
+            return None;
+        }
+        Some(Ipv6Prefix { addr: Ipv6Addr::from(mask(addr.octets(), len)), len, path_id: None })
+    }
+
+    pub fn addr(&self) -> Ipv6Addr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+
+    pub fn path_id(&self) -> Option<u32> {
+        self.path_id
+    }
+
+    pub fn with_path_id(self, path_id: u32) -> Self {
+        Ipv6Prefix { path_id: Some(path_id), ..self }
+    }
+}
+
+impl IpPrefix {
+    pub fn new(addr: IpAddr, len: u8) -> Option<Self> {
+        match addr {
+            IpAddr::V4(a) => Ipv4Prefix::new(a, len).map(IpPrefix::V4),
+            IpAddr::V6(a) => Ipv6Prefix::new(a, len).map(IpPrefix::V6),
+        }
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        match self {
+            IpPrefix::V4(p) => IpAddr::V4(p.addr),
+            IpPrefix::V6(p) => IpAddr::V6(p.addr),
+        }
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        match self {
+            IpPrefix::V4(p) => p.len,
+            IpPrefix::V6(p) => p.len,
+        }
+    }
+
+    pub fn path_id(&self) -> Option<u32> {
+        match self {
+            IpPrefix::V4(p) => p.path_id,
+            IpPrefix::V6(p) => p.path_id,
+        }
+    }
+}
+
+// Host routes, /32 and /128.
+impl From<Ipv4Addr> for Ipv4Prefix {
+    fn from(addr: Ipv4Addr) -> Self {
+        Ipv4Prefix { addr, len: 32, path_id: None }
+    }
+}
+
+impl From<Ipv6Addr> for Ipv6Prefix {
+    fn from(addr: Ipv6Addr) -> Self {
+        Ipv6Prefix { addr, len: 128, path_id: None }
+    }
+}
+
+impl From<IpAddr> for IpPrefix {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(a) => IpPrefix::V4(a.into()),
+            IpAddr::V6(a) => IpPrefix::V6(a.into()),
+        }
+    }
+}
+
+impl TryFrom<(Ipv6Addr, u8)> for Ipv6Prefix {
+    type Error = SerializerError;
+
+    fn try_from((addr, len): (Ipv6Addr, u8)) -> Result<Self, Self::Error> {
+        Ipv6Prefix::new(addr, len)
+            .ok_or_else(|| SerializerError::CustomMsg(format!("Invalid prefix length {}, at most 128 allowed", len)))
+    }
+}
+
+impl TryFrom<(Ipv4Addr, u8)> for Ipv4Prefix {
+    type Error = SerializerError;
+
+    fn try_from((addr, len): (Ipv4Addr, u8)) -> Result<Self, Self::Error> {
+        Ipv4Prefix::new(addr, len)
+            .ok_or_else(|| SerializerError::CustomMsg(format!("Invalid prefix length {}, at most 32 allowed", len)))
+    }
+}
+
+// Lengths in range and host bits clear, as new makes them.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ipv4Prefix {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let prefix = Ipv4Prefix::new(u.arbitrary()?, u.int_in_range(0..=32)?).unwrap();
+        Ok(Ipv4Prefix { path_id: u.arbitrary()?, ..prefix })
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ipv6Prefix {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let prefix = Ipv6Prefix::new(u.arbitrary()?, u.int_in_range(0..=128)?).unwrap();
+        Ok(Ipv6Prefix { path_id: u.arbitrary()?, ..prefix })
+    }
+}
+
+impl From<Ipv4Prefix> for IpPrefix {
+    fn from(p: Ipv4Prefix) -> Self {
+        IpPrefix::V4(p)
+    }
+}
+
+impl From<Ipv6Prefix> for IpPrefix {
+    fn from(p: Ipv6Prefix) -> Self {
+        IpPrefix::V6(p)
+    }
+}
+
+// -- Text form, "192.0.2.0/24" --
+
+impl Display for Ipv4Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl Display for Ipv6Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpPrefix::V4(p) => p.fmt(f),
+            IpPrefix::V6(p) => p.fmt(f),
+        }
+    }
+}
+
+fn split_prefix(s: &str) -> Result<(&str, u8), SerializerError> {
+    let invalid = || SerializerError::CustomMsg(format!("Invalid prefix \"{}\"", s));
+    let (addr, len) = s.split_once('/').ok_or_else(invalid)?;
+    Ok((addr, len.parse().map_err(|_| invalid())?))
+}
+
+impl FromStr for Ipv4Prefix {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = split_prefix(s)?;
+        addr.parse()
+            .ok()
+            .and_then(|a| Ipv4Prefix::new(a, len))
+            .ok_or_else(|| SerializerError::CustomMsg(format!("Invalid prefix \"{}\"", s)))
+    }
+}
+
+impl FromStr for Ipv6Prefix {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = split_prefix(s)?;
+        addr.parse()
+            .ok()
+            .and_then(|a| Ipv6Prefix::new(a, len))
+            .ok_or_else(|| SerializerError::CustomMsg(format!("Invalid prefix \"{}\"", s)))
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = split_prefix(s)?;
+        addr.parse()
+            .ok()
+            .and_then(|a| IpPrefix::new(a, len))
+            .ok_or_else(|| SerializerError::CustomMsg(format!("Invalid prefix \"{}\"", s)))
+    }
+}
+
+// With a path identifier the text form is {"prefix": "192.0.2.0/24",
+// "path_id": 1} instead.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PrefixText {
+    Plain(String),
+    WithPathId { prefix: String, path_id: u32 },
+}
+
+fn serialize_text<S, P>(serializer: S, prefix: &P, path_id: Option<u32>) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    P: Display,
+{
+    match path_id {
+        None => serializer.collect_str(prefix),
+        Some(path_id) => PrefixText::WithPathId { prefix: prefix.to_string(), path_id }.serialize(serializer),
+    }
+}
+
+fn deserialize_text<'de, D: Deserializer<'de>, P: FromStr<Err = SerializerError>>(
+    deserializer: D,
+) -> Result<(P, Option<u32>), D::Error> {
+    let (prefix, path_id) = match PrefixText::deserialize(deserializer)? {
+        PrefixText::Plain(prefix) => (prefix, None),
+        PrefixText::WithPathId { prefix, path_id } => (prefix, Some(path_id)),
+    };
+    Ok((prefix.parse().map_err(de::Error::custom)?, path_id))
+}
+
+// -- Wire form --
+
+// The path identifier, wrapped in the reserved name for the prefix's AFI.
+struct PathId {
+    name: &'static str,
+    id: Option<u32>,
+}
+
+impl Serialize for PathId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(self.name, &self.id.unwrap_or(0))
+    }
+}
+
+struct PathIdSeed(&'static str);
+
+impl<'de> DeserializeSeed<'de> for PathIdSeed {
+    type Value = Option<u32>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Option<u32>, D::Error> {
+        d.deserialize_newtype_struct(self.0, self)
+    }
+}
+
+impl<'de> Visitor<'de> for PathIdSeed {
+    type Value = Option<u32>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a path identifier")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        u32::deserialize(d).map(Some)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+}
+
+fn serialize_prefix<S: Serializer>(serializer: S, path_id: PathId, len: u8, octets: &[u8]) -> Result<S::Ok, S::Error> {
+    let n = (len as usize).div_ceil(8);
+    let mut tup = serializer.serialize_tuple(2 + n)?;
+    tup.serialize_element(&path_id)?;
+    tup.serialize_element(&len)?;
+    for b in &octets[..n] {
+        tup.serialize_element(b)?;
+    }
+    tup.end()
+}
+
+// The length octet, under the reserved name for the prefix's AFI.
+struct PrefixLenSeed(&'static str);
+
+impl<'de> DeserializeSeed<'de> for PrefixLenSeed {
+    type Value = u8;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<u8, D::Error> {
+        d.deserialize_newtype_struct(self.0, self)
+    }
+}
+
+impl<'de> Visitor<'de> for PrefixLenSeed {
+    type Value = u8;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a prefix length")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        u8::deserialize(d)
+    }
+}
+
+// Reads the path identifier (if there is one), the length octet, then only
+// the address octets the length calls for. The names are the PATH_ID_* and
+// PREFIX_LEN_* ones for the AFI.
+struct PrefixVisitor<const N: usize>(&'static str, &'static str);
+
+impl<'de, const N: usize> Visitor<'de> for PrefixVisitor<N> {
+    type Value = (Option<u32>, u8, [u8; N]);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a prefix of at most {} bits", N * 8)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let path_id = seq.next_element_seed(PathIdSeed(self.0))?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let len = seq.next_element_seed(PrefixLenSeed(self.1))?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        if len as usize > N * 8 {
+            return Err(de::Error::custom(format!(
+                "Invalid prefix length {}, at most {} allowed", len, N * 8
+            )));
+        }
+        let mut octets = [0u8; N];
+        for (i, b) in octets.iter_mut().take((len as usize).div_ceil(8)).enumerate() {
+            *b = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i + 2, &self))?;
+        }
+        Ok((path_id, len, mask(octets, len)))
+    }
+}
+
+impl Serialize for Ipv4Prefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serialize_text(serializer, self, self.path_id);
+        }
+        let path_id = PathId { name: PATH_ID_IPV4, id: self.path_id };
+        serialize_prefix(serializer, path_id, self.len, &self.addr.octets())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ipv4Prefix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let (prefix, path_id): (Self, _) = deserialize_text(deserializer)?;
+            return Ok(Ipv4Prefix { path_id, ..prefix });
+        }
+        let (path_id, len, octets) = deserializer.deserialize_tuple(2 + 4, PrefixVisitor::<4>(PATH_ID_IPV4, PREFIX_LEN_IPV4))?;
+        Ok(Ipv4Prefix { addr: Ipv4Addr::from(octets), len, path_id })
+    }
+}
+
+impl Serialize for Ipv6Prefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serialize_text(serializer, self, self.path_id);
+        }
+        let path_id = PathId { name: PATH_ID_IPV6, id: self.path_id };
+        serialize_prefix(serializer, path_id, self.len, &self.addr.octets())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ipv6Prefix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let (prefix, path_id): (Self, _) = deserialize_text(deserializer)?;
+            return Ok(Ipv6Prefix { path_id, ..prefix });
+        }
+        let (path_id, len, octets) = deserializer.deserialize_tuple(2 + 16, PrefixVisitor::<16>(PATH_ID_IPV6, PREFIX_LEN_IPV6))?;
+        Ok(Ipv6Prefix { addr: Ipv6Addr::from(octets), len, path_id })
+    }
+}
+
+impl Serialize for IpPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IpPrefix::V4(p) => p.serialize(serializer),
+            IpPrefix::V6(p) => p.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, DeserializeError, ValidationError};
+
+    #[test]
+    fn test_prefix_ser() {
+        let cases: [(&str, &[u8]); 6] = [
+            ("0.0.0.0/0", &[0]),
+            ("10.0.0.0/8", &[8, 10]),
+            ("192.0.2.0/24", &[24, 192, 0, 2]),
+            ("172.16.128.0/17", &[17, 172, 16, 128]),
+            ("192.0.2.1/32", &[32, 192, 0, 2, 1]),
+            ("2001:db8::/32", &[32, 0x20, 0x01, 0x0d, 0xb8]),
+        ];
+        for (text, wire) in cases {
+            let prefix: IpPrefix = text.parse().unwrap();
+            assert_eq!(to_bytes(prefix).unwrap().as_ref(), wire, "{}", text);
+            assert_eq!(prefix.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_prefix_de() {
+        let v4: Vec<Ipv4Prefix> = from_bytes(&[24, 192, 0, 2, 0, 9, 10, 128]).unwrap();
+        assert_eq!(v4, vec![
+            "192.0.2.0/24".parse().unwrap(),
+            "0.0.0.0/0".parse().unwrap(),
+            "10.128.0.0/9".parse().unwrap(),
+        ]);
+
+        let v6: Ipv6Prefix = from_bytes(&[64, 0x20, 0x01, 0x0d, 0xb8, 0, 1, 0, 2]).unwrap();
+        assert_eq!(v6.to_string(), "2001:db8:1:2::/64");
+        let full: Ipv6Prefix = from_bytes(&[128, 0x20, 1, 0xd, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]).unwrap();
+        assert_eq!(full.to_string(), "2001:db8::1/128");
+    }
+
+    #[test]
+    fn test_prefix_host_bits_cleared() {
+        let p = Ipv4Prefix::new(Ipv4Addr::new(10, 1, 2, 3), 12).unwrap();
+        assert_eq!(p.to_string(), "10.0.0.0/12");
+        // Trailing bits in the last octet are ignored on decode too.
+        let p: Ipv4Prefix = from_bytes(&[12, 10, 0x1f]).unwrap();
+        assert_eq!(p.to_string(), "10.16.0.0/12");
+    }
+
+    #[test]
+    fn test_prefix_conversions() {
+        let addr: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(Ipv6Prefix::from(addr).to_string(), "2001:db8::1/128");
+        assert_eq!(IpPrefix::from(IpAddr::V6(addr)).prefix_len(), 128);
+        let p = Ipv6Prefix::try_from((addr, 64)).unwrap();
+        assert_eq!(p.to_string(), "2001:db8::/64");
+        match Ipv6Prefix::try_from((addr, 129)) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Invalid prefix length 129, at most 128 allowed"),
+        }
+        assert_eq!(Ipv4Prefix::from(Ipv4Addr::new(192, 0, 2, 1)).to_string(), "192.0.2.1/32");
+    }
+
+    #[test]
+    fn test_prefix_err() {
+        assert!(Ipv4Prefix::new(Ipv4Addr::UNSPECIFIED, 33).is_none());
+        assert!("10.0.0.0".parse::<Ipv4Prefix>().is_err());
+        assert!("2001:db8::/129".parse::<IpPrefix>().is_err());
+
+        match from_bytes::<Ipv4Prefix>(&[33, 1, 2, 3, 4, 5]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner(), &DeserializeError::Invalid(ValidationError::InvalidNetworkField(33))),
+        }
+        assert!(from_bytes::<Ipv6Prefix>(&[129; 18]).is_err());
+        // Length calls for 3 octets, only 2 present
+        match from_bytes::<Vec<Ipv4Prefix>>(&[8, 10, 24, 192, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.inner(), &DeserializeError::Invalid(ValidationError::InvalidNetworkField(24)));
+                assert_eq!(e.to_string(), "Invalid network field, prefix length 24. (at offset 2 while decoding [1].1)");
+            },
+        }
+    }
+}
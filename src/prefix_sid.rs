@@ -0,0 +1,200 @@
+// BGP Prefix-SID attribute (RFC 8669).
+//
+// A list of TLVs with a 1 octet type and a 2 octet length. Label-Index
+// carries the prefix's index into the SR Global Block, Originator SRGB the
+// blocks the originator uses (a 3 octet first label and a 3 octet size
+// each). Other TLVs, such as the SRv6 service ones, keep their raw value.
+use std::fmt;
+
+use bytes::Bytes;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::registry::prefix_sid;
+use crate::types::{LengthPrefixed, LengthPrefixedSeed, U24};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PrefixSidTlv {
+    LabelIndex { flags: u16, index: u32 },
+    OriginatorSrgb { flags: u16, blocks: Vec<SrgbBlock> },
+    Unknown {
+        tlv_type: u8,
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::types::arbitrary_bytes))]
+        data: Bytes,
+    },
+}
+
+// `size` labels starting at `first`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SrgbBlock {
+    pub first: U24,
+    pub size: U24,
+}
+
+impl PrefixSidTlv {
+    pub fn label_index(index: u32) -> Self {
+        PrefixSidTlv::LabelIndex { flags: 0, index }
+    }
+
+    pub fn tlv_type(&self) -> u8 {
+        match self {
+            PrefixSidTlv::LabelIndex { .. } => prefix_sid::LABEL_INDEX,
+            PrefixSidTlv::OriginatorSrgb { .. } => prefix_sid::ORIGINATOR_SRGB,
+            PrefixSidTlv::Unknown { tlv_type, .. } => *tlv_type,
+        }
+    }
+}
+
+impl SrgbBlock {
+    pub fn new(first: U24, size: U24) -> Self {
+        SrgbBlock { first, size }
+    }
+}
+
+// In text formats TLVs and blocks are plain enums and structs.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PrefixSidTlv", rename_all = "snake_case")]
+enum PrefixSidTlvText {
+    LabelIndex { flags: u16, index: u32 },
+    OriginatorSrgb { flags: u16, blocks: Vec<SrgbBlock> },
+    Unknown { tlv_type: u8, data: Bytes },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SrgbBlock")]
+struct SrgbBlockText {
+    first: U24,
+    size: U24,
+}
+
+// -- Wire form --
+
+struct TlvValue<'a>(&'a PrefixSidTlv);
+
+impl Serialize for TlvValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            PrefixSidTlv::LabelIndex { flags, index } => (0u8, flags, index).serialize(serializer),
+            PrefixSidTlv::OriginatorSrgb { flags, blocks } => (flags, blocks).serialize(serializer),
+            PrefixSidTlv::Unknown { data, .. } => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl Serialize for PrefixSidTlv {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return PrefixSidTlvText::serialize(self, serializer);
+        }
+        let value: LengthPrefixed<u16, _> = LengthPrefixed::new(TlvValue(self));
+        (self.tlv_type(), value).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrefixSidTlv {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return PrefixSidTlvText::deserialize(deserializer);
+        }
+        struct TlvVisitor;
+
+        impl<'de> Visitor<'de> for TlvVisitor {
+            type Value = PrefixSidTlv;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a Prefix-SID TLV")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let tlv_type: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let seed = LengthPrefixedSeed { width: 2, seed: TlvSeed(tlv_type) };
+                seq.next_element_seed(seed)?.ok_or_else(|| de::Error::invalid_length(1, &self))
+            }
+        }
+
+        deserializer.deserialize_tuple(2, TlvVisitor)
+    }
+}
+
+struct TlvSeed(u8);
+
+impl<'de> DeserializeSeed<'de> for TlvSeed {
+    type Value = PrefixSidTlv;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<PrefixSidTlv, D::Error> {
+        Ok(match self.0 {
+            prefix_sid::LABEL_INDEX => {
+                let (_reserved, flags, index): (u8, _, _) = Deserialize::deserialize(d)?;
+                PrefixSidTlv::LabelIndex { flags, index }
+            },
+            prefix_sid::ORIGINATOR_SRGB => {
+                let (flags, blocks) = Deserialize::deserialize(d)?;
+                PrefixSidTlv::OriginatorSrgb { flags, blocks }
+            },
+            tlv_type => PrefixSidTlv::Unknown { tlv_type, data: Bytes::deserialize(d)? },
+        })
+    }
+}
+
+impl Serialize for SrgbBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return SrgbBlockText::serialize(self, serializer);
+        }
+        (self.first, self.size).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SrgbBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return SrgbBlockText::deserialize(deserializer);
+        }
+        let (first, size) = Deserialize::deserialize(deserializer)?;
+        Ok(SrgbBlock { first, size })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, DeserializeError, PathAttribute};
+
+    #[test]
+    fn test_prefix_sid_wire() {
+        let blocks = vec![
+            SrgbBlock::new(U24::new(16000).unwrap(), U24::new(8000).unwrap()),
+            SrgbBlock::new(U24::new(100_000).unwrap(), U24::new(1000).unwrap()),
+        ];
+        let attr = PathAttribute::PrefixSid(vec![
+            PrefixSidTlv::label_index(42),
+            PrefixSidTlv::OriginatorSrgb { flags: 0, blocks },
+            PrefixSidTlv::Unknown { tlv_type: 5, data: Bytes::from_static(&[0, 1]) },
+        ]);
+        #[rustfmt::skip]
+        let wire: &[u8] = &[
+            0xc0, 40, 32,
+            1, 0, 7, 0, 0, 0, 0, 0, 0, 42,
+            3, 0, 14, 0, 0,
+            0, 0x3e, 0x80, 0, 0x1f, 0x40,
+            0x01, 0x86, 0xa0, 0, 0x03, 0xe8,
+            5, 0, 2, 0, 1,
+        ];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<PathAttribute>(wire).unwrap(), attr);
+    }
+
+    #[test]
+    fn test_prefix_sid_err() {
+        // Label-Index one octet short
+        match from_bytes::<PrefixSidTlv>(&[1, 0, 6, 0, 0, 0, 0, 0, 42]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e.inner(), DeserializeError::UnexpectedEof { .. }), "{:?}", e),
+        }
+        // SRGB block cut off
+        assert!(from_bytes::<PrefixSidTlv>(&[3, 0, 5, 0, 0, 0, 0x3e, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+// IANA registry values used on the wire.
+//
+// Grouped by registry so call sites read as `registry::path_attr::AS_PATH`
+// rather than a bare number. Only the codes the crate or its users are likely
+// to touch are listed; anything else is still just a u8/u16.
+
+// BGP Path Attributes
+pub mod path_attr {
+    pub const ORIGIN: u8 = 1;
+    pub const AS_PATH: u8 = 2;
+    pub const NEXT_HOP: u8 = 3;
+    pub const MULTI_EXIT_DISC: u8 = 4;
+    pub const LOCAL_PREF: u8 = 5;
+    pub const ATOMIC_AGGREGATE: u8 = 6;
+    pub const AGGREGATOR: u8 = 7;
+    pub const COMMUNITIES: u8 = 8;
+    pub const ORIGINATOR_ID: u8 = 9;
+    pub const CLUSTER_LIST: u8 = 10;
+    pub const MP_REACH_NLRI: u8 = 14;
+    pub const MP_UNREACH_NLRI: u8 = 15;
+    pub const EXTENDED_COMMUNITIES: u8 = 16;
+    pub const AS4_PATH: u8 = 17;
+    pub const AS4_AGGREGATOR: u8 = 18;
+    pub const PMSI_TUNNEL: u8 = 22;
+    pub const TUNNEL_ENCAPSULATION: u8 = 23;
+    pub const AIGP: u8 = 26;
+    // RFC 6790, deprecated by RFC 7447
+    pub const ENTROPY_LABEL_CAPABILITY: u8 = 28;
+    pub const BGP_LS: u8 = 29;
+    pub const LARGE_COMMUNITIES: u8 = 32;
+    pub const BGPSEC_PATH: u8 = 33;
+    pub const ONLY_TO_CUSTOMER: u8 = 35;
+    pub const PREFIX_SID: u8 = 40;
+    pub const ATTR_SET: u8 = 128;
+}
+
+// OPEN Optional Parameter Types
+pub mod optional_param {
+    pub const CAPABILITIES: u8 = 2;
+    // RFC 9072
+    pub const EXTENDED_LENGTH: u8 = 255;
+}
+
+// Capability Codes
+pub mod capability {
+    pub const MULTIPROTOCOL: u8 = 1;
+    pub const ROUTE_REFRESH: u8 = 2;
+    pub const OUTBOUND_ROUTE_FILTERING: u8 = 3;
+    pub const EXTENDED_NEXT_HOP: u8 = 5;
+    pub const EXTENDED_MESSAGE: u8 = 6;
+    pub const BGP_ROLE: u8 = 9;
+    pub const GRACEFUL_RESTART: u8 = 64;
+    pub const FOUR_OCTET_AS: u8 = 65;
+    pub const DYNAMIC: u8 = 67;
+    pub const ADD_PATH: u8 = 69;
+    pub const ENHANCED_ROUTE_REFRESH: u8 = 70;
+    pub const LONG_LIVED_GRACEFUL_RESTART: u8 = 71;
+    pub const FQDN: u8 = 73;
+}
+
+// Outbound Route Filtering types (RFC 5291)
+pub mod orf {
+    pub const ADDRESS_PREFIX: u8 = 64;
+    pub const CP_ORF: u8 = 65;
+}
+
+// Flow Spec Component Types (RFC 8955, RFC 8956)
+pub mod flowspec {
+    pub const DESTINATION_PREFIX: u8 = 1;
+    pub const SOURCE_PREFIX: u8 = 2;
+    pub const IP_PROTOCOL: u8 = 3;
+    pub const PORT: u8 = 4;
+    pub const DESTINATION_PORT: u8 = 5;
+    pub const SOURCE_PORT: u8 = 6;
+    pub const ICMP_TYPE: u8 = 7;
+    pub const ICMP_CODE: u8 = 8;
+    pub const TCP_FLAGS: u8 = 9;
+    pub const PACKET_LENGTH: u8 = 10;
+    pub const DSCP: u8 = 11;
+    pub const FRAGMENT: u8 = 12;
+    // IPv6 only
+    pub const FLOW_LABEL: u8 = 13;
+}
+
+// BGP-LS NLRI Types, Protocol-IDs and TLV code points (RFC 7752, RFC 7308,
+// RFC 9085)
+pub mod bgp_ls {
+    pub const NLRI_NODE: u16 = 1;
+    pub const NLRI_LINK: u16 = 2;
+    pub const NLRI_IPV4_PREFIX: u16 = 3;
+    pub const NLRI_IPV6_PREFIX: u16 = 4;
+
+    pub const PROTOCOL_ISIS_L1: u8 = 1;
+    pub const PROTOCOL_ISIS_L2: u8 = 2;
+    pub const PROTOCOL_OSPFV2: u8 = 3;
+    pub const PROTOCOL_DIRECT: u8 = 4;
+    pub const PROTOCOL_STATIC: u8 = 5;
+    pub const PROTOCOL_OSPFV3: u8 = 6;
+
+    // NLRI descriptors
+    pub const LOCAL_NODE_DESCRIPTORS: u16 = 256;
+    pub const REMOTE_NODE_DESCRIPTORS: u16 = 257;
+    pub const LINK_IDENTIFIERS: u16 = 258;
+    pub const IPV4_INTERFACE_ADDRESS: u16 = 259;
+    pub const IPV4_NEIGHBOR_ADDRESS: u16 = 260;
+    pub const IPV6_INTERFACE_ADDRESS: u16 = 261;
+    pub const IPV6_NEIGHBOR_ADDRESS: u16 = 262;
+    pub const MULTI_TOPOLOGY_ID: u16 = 263;
+    pub const OSPF_ROUTE_TYPE: u16 = 264;
+    pub const IP_REACHABILITY: u16 = 265;
+    pub const AUTONOMOUS_SYSTEM: u16 = 512;
+    pub const BGP_LS_IDENTIFIER: u16 = 513;
+    pub const OSPF_AREA_ID: u16 = 514;
+    pub const IGP_ROUTER_ID: u16 = 515;
+
+    // BGP-LS attribute
+    pub const NODE_FLAGS: u16 = 1024;
+    pub const NODE_NAME: u16 = 1026;
+    pub const ISIS_AREA_ID: u16 = 1027;
+    pub const IPV4_ROUTER_ID_LOCAL: u16 = 1028;
+    pub const IPV6_ROUTER_ID_LOCAL: u16 = 1029;
+    pub const SR_CAPABILITIES: u16 = 1034;
+    pub const ADMIN_GROUP: u16 = 1088;
+    pub const MAX_LINK_BANDWIDTH: u16 = 1089;
+    pub const MAX_RESERVABLE_BANDWIDTH: u16 = 1090;
+    pub const UNRESERVED_BANDWIDTH: u16 = 1091;
+    pub const TE_DEFAULT_METRIC: u16 = 1092;
+    pub const IGP_METRIC: u16 = 1095;
+    pub const SRLG: u16 = 1096;
+    pub const LINK_NAME: u16 = 1098;
+    pub const IGP_FLAGS: u16 = 1152;
+    pub const PREFIX_METRIC: u16 = 1155;
+    pub const OSPF_FORWARDING_ADDRESS: u16 = 1156;
+    pub const SID_LABEL: u16 = 1161;
+    pub const EXTENDED_ADMIN_GROUP: u16 = 1173;
+}
+
+// BGP Prefix-SID TLV Types (RFC 8669, RFC 9252)
+pub mod prefix_sid {
+    pub const LABEL_INDEX: u8 = 1;
+    pub const ORIGINATOR_SRGB: u8 = 3;
+    pub const SRV6_L3_SERVICE: u8 = 5;
+    pub const SRV6_L2_SERVICE: u8 = 6;
+}
+
+// BMP Message Types (RFC 7854)
+pub mod bmp {
+    pub const ROUTE_MONITORING: u8 = 0;
+    pub const STATISTICS_REPORT: u8 = 1;
+    pub const PEER_DOWN_NOTIFICATION: u8 = 2;
+    pub const PEER_UP_NOTIFICATION: u8 = 3;
+    pub const INITIATION: u8 = 4;
+    pub const TERMINATION: u8 = 5;
+    pub const ROUTE_MIRRORING: u8 = 6;
+
+    // Peer types (RFC 9069 adds Loc-RIB)
+    pub const PEER_GLOBAL: u8 = 0;
+    pub const PEER_RD: u8 = 1;
+    pub const PEER_LOCAL: u8 = 2;
+    pub const PEER_LOC_RIB: u8 = 3;
+
+    // Peer Down reasons
+    pub const LOCAL_NOTIFICATION: u8 = 1;
+    pub const LOCAL_NO_NOTIFICATION: u8 = 2;
+    pub const REMOTE_NOTIFICATION: u8 = 3;
+    pub const REMOTE_NO_DATA: u8 = 4;
+    pub const PEER_DECONFIGURED: u8 = 5;
+
+    // Initiation and Peer Up information TLV types
+    pub const INFO_STRING: u16 = 0;
+    pub const INFO_SYS_DESCR: u16 = 1;
+    pub const INFO_SYS_NAME: u16 = 2;
+
+    // Termination TLV types
+    pub const TERM_STRING: u16 = 0;
+    pub const TERM_REASON: u16 = 1;
+
+    // BMPv4 message TLV types (draft-ietf-grow-bmp-tlv, provisional)
+    pub const TLV_GROUP: u16 = 0;
+    pub const TLV_BGP_PDU: u16 = 1;
+}
+
+// MRT Types and Subtypes (RFC 6396)
+pub mod mrt {
+    pub const TABLE_DUMP_V2: u16 = 13;
+    pub const BGP4MP: u16 = 16;
+    pub const BGP4MP_ET: u16 = 17;
+
+    // TABLE_DUMP_V2 subtypes
+    pub const PEER_INDEX_TABLE: u16 = 1;
+    pub const RIB_IPV4_UNICAST: u16 = 2;
+    pub const RIB_IPV4_MULTICAST: u16 = 3;
+    pub const RIB_IPV6_UNICAST: u16 = 4;
+    pub const RIB_IPV6_MULTICAST: u16 = 5;
+    pub const RIB_GENERIC: u16 = 6;
+
+    // BGP4MP and BGP4MP_ET subtypes
+    pub const BGP4MP_STATE_CHANGE: u16 = 0;
+    pub const BGP4MP_MESSAGE: u16 = 1;
+    pub const BGP4MP_MESSAGE_AS4: u16 = 4;
+    pub const BGP4MP_STATE_CHANGE_AS4: u16 = 5;
+    pub const BGP4MP_MESSAGE_LOCAL: u16 = 6;
+    pub const BGP4MP_MESSAGE_AS4_LOCAL: u16 = 7;
+}
@@ -0,0 +1,136 @@
+// One record per prefix of an UPDATE.
+//
+// An UPDATE carries routes in up to four places: the withdrawn routes and
+// NLRI for IPv4 unicast, MP_UNREACH_NLRI and MP_REACH_NLRI for the other
+// families. UpdateMessage::routes walks all of them and hands back each
+// prefix on its own, with whether it is announced or withdrawn and the
+// attributes that go with it, which is the shape a database row or an
+// analytics record wants.
+use crate::afi::Safi;
+use crate::attributes::PathAttribute;
+use crate::messages::UpdateMessage;
+use crate::multiprotocol::{MpReachNlri, MpUnreachNlri, Nlri};
+use crate::prefix::{IpPrefix, Ipv4Prefix, Ipv6Prefix};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteAction {
+    Announce,
+    Withdraw,
+}
+
+// The prefix has no path identifier of its own, that is `path_id`.
+// Announcements get every attribute of the UPDATE, MP_REACH_NLRI included
+// for its next hop; withdrawals have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteEntry<'a> {
+    pub prefix: IpPrefix,
+    pub path_id: Option<u32>,
+    pub safi: Safi,
+    pub action: RouteAction,
+    pub attributes: &'a [PathAttribute],
+}
+
+impl<'a> RouteEntry<'a> {
+    fn new(prefix: IpPrefix, safi: Safi, action: RouteAction, attributes: &'a [PathAttribute]) -> Self {
+        let path_id = prefix.path_id();
+        let prefix = IpPrefix::new(prefix.addr(), prefix.prefix_len()).unwrap();
+        RouteEntry { prefix, path_id, safi, action, attributes }
+    }
+}
+
+impl UpdateMessage {
+    // Withdrawals first, IPv4 unicast then MP_UNREACH_NLRI, then
+    // announcements in the same order. Only IPv4 and IPv6 prefixes are
+    // flattened; MP_REACH/MP_UNREACH_NLRI of other families (FlowSpec, VPN,
+    // EVPN and so on) are passed over.
+    pub fn routes(&self) -> impl Iterator<Item = RouteEntry<'_>> {
+        let attributes = self.path_attributes.value.as_slice();
+        let reach = attributes.iter().filter_map(|attr| match attr.attribute() {
+            PathAttribute::MpReachNlri(MpReachNlri { safi, nlri, .. }) => Some((*safi, nlri)),
+            _ => None,
+        });
+        let unreach = attributes.iter().filter_map(|attr| match attr.attribute() {
+            PathAttribute::MpUnreachNlri(MpUnreachNlri { safi, withdrawn, .. }) => Some((*safi, withdrawn)),
+            _ => None,
+        });
+        let withdrawn = self.withdrawn_routes.value.iter().map(|p| IpPrefix::V4(*p));
+        let announced = self.nlri.iter().map(|p| IpPrefix::V4(*p));
+
+        entries(withdrawn, Safi::Unicast, RouteAction::Withdraw, &[])
+            .chain(unreach.flat_map(|(safi, nlri)| nlri_entries(nlri, safi, RouteAction::Withdraw, &[])))
+            .chain(entries(announced, Safi::Unicast, RouteAction::Announce, attributes))
+            .chain(reach.flat_map(move |(safi, nlri)| nlri_entries(nlri, safi, RouteAction::Announce, attributes)))
+    }
+}
+
+fn nlri_entries<'a>(
+    nlri: &'a Nlri,
+    safi: Safi,
+    action: RouteAction,
+    attributes: &'a [PathAttribute],
+) -> impl Iterator<Item = RouteEntry<'a>> {
+    let (ipv4, ipv6): (&[Ipv4Prefix], &[Ipv6Prefix]) = match nlri {
+        Nlri::Ipv4(prefixes) => (prefixes, &[]),
+        Nlri::Ipv6(prefixes) => (&[], prefixes),
+        _ => (&[], &[]),
+    };
+    let ipv4 = ipv4.iter().map(|p| IpPrefix::V4(*p));
+    let ipv6 = ipv6.iter().map(|p| IpPrefix::V6(*p));
+    entries(ipv4.chain(ipv6), safi, action, attributes)
+}
+
+fn entries<'a>(
+    prefixes: impl Iterator<Item = IpPrefix> + 'a,
+    safi: Safi,
+    action: RouteAction,
+    attributes: &'a [PathAttribute],
+) -> impl Iterator<Item = RouteEntry<'a>> + 'a {
+    prefixes.map(move |prefix| RouteEntry::new(prefix, safi, action, attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Afi, NextHop, Origin};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_update_routes() {
+        let mp_nlri: Ipv6Prefix = "2001:db8::/32".parse().unwrap();
+        let attributes = vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::MpUnreachNlri(MpUnreachNlri {
+                afi: Afi::Ipv6,
+                safi: Safi::Unicast,
+                withdrawn: Nlri::Ipv6(vec!["2001:db8:1::/48".parse().unwrap()]),
+            }),
+            PathAttribute::MpReachNlri(MpReachNlri {
+                afi: Afi::Ipv6,
+                safi: Safi::Multicast,
+                next_hop: NextHop::Ipv6("2001:db8::1".parse().unwrap()),
+                nlri: Nlri::Ipv6(vec![mp_nlri.with_path_id(7)]),
+            }),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+        ];
+        let update = UpdateMessage {
+            withdrawn_routes: vec!["10.1.0.0/16".parse().unwrap()].into(),
+            path_attributes: attributes.clone().into(),
+            nlri: vec!["192.0.2.0/24".parse().unwrap(), "198.51.100.0/24".parse().unwrap()],
+        };
+
+        let routes: Vec<_> = update.routes().collect();
+        let summary: Vec<_> = routes.iter().map(|r| (r.prefix.to_string(), r.path_id, r.safi, r.action)).collect();
+        assert_eq!(summary, vec![
+            (String::from("10.1.0.0/16"), None, Safi::Unicast, RouteAction::Withdraw),
+            (String::from("2001:db8:1::/48"), None, Safi::Unicast, RouteAction::Withdraw),
+            (String::from("192.0.2.0/24"), None, Safi::Unicast, RouteAction::Announce),
+            (String::from("198.51.100.0/24"), None, Safi::Unicast, RouteAction::Announce),
+            (String::from("2001:db8::/32"), Some(7), Safi::Multicast, RouteAction::Announce),
+        ]);
+        assert_eq!(routes[4].prefix, IpPrefix::V6(mp_nlri));
+        assert!(routes[..2].iter().all(|r| r.attributes.is_empty()));
+        assert!(routes[2..].iter().all(|r| r.attributes == attributes.as_slice()));
+
+        assert_eq!(UpdateMessage::default().routes().count(), 0);
+    }
+}
@@ -1,39 +1,382 @@
 // Definition of custom Serializer
 use bytes::{BytesMut, BufMut};
 use serde::{ser, Serialize};
+use std::io::Write;
 
+use crate::afi::AddPathFamilies;
+use crate::attributes::ATTR_EXTENDED_LENGTH;
 use crate::error::{SerializerError, Result};
+use crate::messages::{MessageType, HEADER_LEN, MARKER, MAX_MESSAGE_LEN};
+use crate::as_path::AS_TRANS;
+use crate::types::{
+    length_prefix_width, path_id_family, tag_width, variant_code, ASN, FOUR_OCTET_ASN, MULTICAST_NLRI, PADDED,
+    PATH_ATTRIBUTE, TWO_OCTET_SESSION,
+};
 
-// Since the serialization is basic (just to bytes), will only have one public
-// method; to_bytes
+// Since the serialization is basic (just to bytes), the main entry point is
+// to_bytes; a Serializer can also be held directly when the caller needs
+// checkpoints.
 // the err_metadata field is used for holding metadata for returning useful
 // error messages, based on the wrapper type that had a field fail serialization.
 pub struct Serializer {
     output: BytesMut,
+    options: SerializerOptions,
+    endianness: Endianness,
+    // Inside MULTICAST_NLRI
+    multicast_nlri: bool,
+    // Type code width for the enum right inside a TaggedEnum
+    tag_width: Option<usize>,
+    // One entry per open tuple struct: for a Padded one, where it started
+    // and the width to pad it to.
+    padded: Vec<Option<(usize, usize)>>,
     _err_type_metadata: String,
     _err_variant_metadata: String,
     _err_field_metadata: String
 }
 
 
+// Settings negotiated with the peer that change how the same value is
+// encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerializerOptions {
+    // RFC 6793. Without it AS numbers in AS_PATH and AGGREGATOR are 2 octets,
+    // AS numbers that don't fit become AS_TRANS and the real path goes in an
+    // AS4_PATH (AS4_AGGREGATOR) attribute after it.
+    pub four_octet_asn: bool,
+    // RFC 7911. Families whose prefixes get a path identifier.
+    pub add_path: AddPathFamilies,
+    // Largest message to_message_bytes will produce, header included.
+    // MAX_MESSAGE_LEN unless Extended Messages (RFC 8654) were negotiated,
+    // see negotiate_max_message_len.
+    pub max_message_len: usize,
+}
+
+// Byte order of integers and length fields. BGP is always Big; the others
+// are for adjacent formats that reuse the engine (some MRT sub-records, test
+// harnesses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+    // Whatever the host uses.
+    Native,
+}
+
+impl Endianness {
+    fn is_little(self) -> bool {
+        match self {
+            Endianness::Big => false,
+            Endianness::Little => true,
+            Endianness::Native => cfg!(target_endian = "little"),
+        }
+    }
+}
+
+impl Default for SerializerOptions {
+    fn default() -> Self {
+        SerializerOptions { four_octet_asn: true, add_path: AddPathFamilies::new(), max_message_len: MAX_MESSAGE_LEN }
+    }
+}
+
 pub fn to_bytes<T: Serialize>(in_type: T) -> Result<BytesMut> {
+        to_bytes_with(in_type, SerializerOptions::default())
+}
+
+pub fn to_bytes_with<T: Serialize>(in_type: T, options: SerializerOptions) -> Result<BytesMut> {
         // Construct a new instance of Self
-        let mut serializer = Serializer {
+        let mut serializer = Serializer::with_options(options);
+
+// Try to serialize the type and return the result
+        in_type.serialize(&mut serializer)?;
+        Ok(serializer.output)
+}
+
+// Appends the value to `buffer` instead of allocating a new one, so a hot
+// path can keep reusing the same buffer. On error the buffer is left as it
+// was.
+pub fn to_bytes_in<T: Serialize>(in_type: T, buffer: &mut BytesMut) -> Result<()> {
+        to_bytes_in_with(in_type, buffer, SerializerOptions::default())
+}
+
+pub fn to_bytes_in_with<T: Serialize>(in_type: T, buffer: &mut BytesMut, options: SerializerOptions) -> Result<()> {
+        let start = buffer.len();
+        let mut serializer = Serializer::with_buffer(options, std::mem::take(buffer));
+        let res = in_type.serialize(&mut serializer);
+        *buffer = serializer.output;
+        if res.is_err() {
+            buffer.truncate(start);
+        }
+        res
+}
+
+// Serializes the value and writes it out in one go. Lengths are patched in
+// after the value they cover, so nothing reaches the writer until the whole
+// value is serialized.
+pub fn to_writer<T: Serialize, W: Write>(in_type: T, writer: W) -> Result<()> {
+        to_writer_with(in_type, writer, SerializerOptions::default())
+}
+
+pub fn to_writer_with<T: Serialize, W: Write>(in_type: T, mut writer: W, options: SerializerOptions) -> Result<()> {
+        let output = to_bytes_with(in_type, options)?;
+        writer.write_all(&output)?;
+        Ok(())
+}
+
+// Serializes a complete message: marker, total length and type octet,
+// followed by the body. The length is filled in once the body is written.
+pub fn to_message_bytes<T: Serialize>(msg_type: MessageType, body: T) -> Result<BytesMut> {
+        to_message_bytes_with(msg_type, body, SerializerOptions::default())
+}
+
+pub fn to_message_bytes_with<T: Serialize>(
+    msg_type: MessageType,
+    body: T,
+    options: SerializerOptions,
+) -> Result<BytesMut> {
+        let mut serializer = Serializer::with_options(options);
+        serializer.output.put_slice(&MARKER);
+        serializer.output.put_u16(0);
+        serializer.output.put_u8(msg_type.into());
+
+        body.serialize(&mut serializer)?;
+
+        let len = serializer.output.len();
+        let limit = match msg_type {
+            MessageType::Open | MessageType::Keepalive => options.max_message_len.min(MAX_MESSAGE_LEN),
+            _ => options.max_message_len,
+        };
+        if len > limit {
+            return Err(SerializerError::MessageTooLarge { limit, attempted: len });
+        }
+        match u16::try_from(len) {
+            Ok(len) => serializer.output[16..18].copy_from_slice(&len.to_be_bytes()),
+            Err(_) => {
+                return Err(SerializerError::LengthOverflow(Some(format!(
+                    "message is {} octets, {} of them body", len, len - HEADER_LEN
+                ))))
+            }
+        }
+        Ok(serializer.output)
+}
+
+// Marks a position in the Serializer output that can be rolled back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    len: usize
+}
+
+// Serializer::builder(). Options default to a BGP session with 4-octet AS
+// numbers and nothing else negotiated, in network byte order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerializerBuilder {
+    options: SerializerOptions,
+    endianness: Endianness,
+}
+
+impl SerializerBuilder {
+    pub fn options(mut self, options: SerializerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn four_octet_asn(mut self, four_octet_asn: bool) -> Self {
+        self.options.four_octet_asn = four_octet_asn;
+        self
+    }
+
+    pub fn add_path(mut self, add_path: AddPathFamilies) -> Self {
+        self.options.add_path = add_path;
+        self
+    }
+
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    pub fn build(self) -> Serializer {
+        Serializer::from_builder(self)
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer {
+    // For callers that want to drive serialization themselves (speculative
+    // serialization with checkpoints, several values into one buffer).
+    // Values are serialized with `value.serialize(&mut serializer)`.
+    pub fn new() -> Self {
+        Self::with_options(SerializerOptions::default())
+    }
+
+    pub fn with_options(options: SerializerOptions) -> Self {
+        Self::builder().options(options).build()
+    }
+
+    // Serializes after whatever `buffer` already holds, reusing its
+    // capacity.
+    pub fn with_buffer(options: SerializerOptions, buffer: BytesMut) -> Self {
+        Serializer { output: buffer, ..Self::with_options(options) }
+    }
+
+    pub fn builder() -> SerializerBuilder {
+        SerializerBuilder::default()
+    }
+
+    fn from_builder(builder: SerializerBuilder) -> Self {
+        Serializer {
             // Max message size is 4096 octets. BytesMut is smart,
             // giving max capacity does not mean the message is guaranteed
             // to be that long!
             output: BytesMut::with_capacity(4096),
+            options: builder.options,
+            endianness: builder.endianness,
+            multicast_nlri: false,
+            tag_width: None,
+            padded: Vec::new(),
             _err_type_metadata: String::new(),
             _err_variant_metadata: String::new(),
             _err_field_metadata: String::new(),
-    };
+        }
+    }
 
-// Try to serialize the type and return the result
-        in_type.serialize(&mut serializer)?;
-        Ok(serializer.output)
-}
+    // Number of octets serialized so far.
+    pub fn len(&self) -> usize {
+        self.output.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.output.is_empty()
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn into_inner(self) -> BytesMut {
+        self.output
+    }
+
+    // Moves everything serialized so far into any BufMut and starts over,
+    // keeping the capacity for the next value.
+    pub fn drain_into<B: BufMut>(&mut self, buf: &mut B) {
+        buf.put_slice(&self.output);
+        self.output.clear();
+    }
+
+    pub fn options(&self) -> SerializerOptions {
+        self.options
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { len: self.output.len() }
+    }
+
+    // Discards everything serialized after the checkpoint was taken, e.g. the
+    // NLRI that pushed a message over the size limit. Rolling back to a
+    // checkpoint that lies past the current end (one taken before an earlier
+    // rollback) leaves the output untouched.
+    pub fn rollback(&mut self, cp: Checkpoint) {
+        self.output.truncate(cp.len);
+    }
+
+    fn serialize_length_prefixed<T>(&mut self, width: usize, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize
+    {
+        let start = self.output.len();
+        self.output.put_bytes(0, width);
+        value.serialize(&mut *self)?;
+
+        let len = self.output.len() - start - width;
+        if width < 8 && len >> (width * 8) != 0 {
+            return Err(SerializerError::LengthOverflow(self.format_metadata()));
+        }
+        let mut len_bytes = (len as u64).to_be_bytes();
+        let field = &mut len_bytes[8 - width..];
+        if self.endianness.is_little() {
+            field.reverse();
+        }
+        self.output[start..start + width].copy_from_slice(field);
+        Ok(())
+    }
+
+    // Writes the type code of a variant inside a TaggedEnum. Anywhere else
+    // variants aren't on the wire. Taken so an enum inside the variant
+    // doesn't get one too.
+    fn serialize_tag(&mut self, index: u32, variant: &'static str) -> Result<()> {
+        let width = match self.tag_width.take() {
+            Some(width) => width,
+            None => return Ok(()),
+        };
+        let code = variant_code(variant, index);
+        if code >> (width * 8) != 0 {
+            return Err(SerializerError::CustomMsg(format!(
+                "Type code {} of variant {} does not fit in {} octet(s)", code, variant, width
+            )));
+        }
+        let mut code_bytes = code.to_be_bytes();
+        let field = &mut code_bytes[8 - width..];
+        if self.endianness.is_little() {
+            field.reverse();
+        }
+        self.output.put_slice(field);
+        Ok(())
+    }
+
+    fn serialize_asn<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize
+    {
+        let start = self.output.len();
+        value.serialize(&mut *self)?;
+        if !self.options.four_octet_asn {
+            let octets: [u8; 4] = self.output[start..].try_into().map_err(|_| {
+                SerializerError::CustomMsg(String::from("AS number must serialize as a u32"))
+            })?;
+            let asn = match self.endianness.is_little() {
+                true => u32::from_le_bytes(octets),
+                false => u32::from_be_bytes(octets),
+            };
+            self.output.truncate(start);
+            ser::Serializer::serialize_u16(&mut *self, u16::try_from(asn).unwrap_or(AS_TRANS as u16))?;
+        }
+        Ok(())
+    }
+
+    // Flags, type code, 2 octet length and value are written as usual, then
+    // the length is cut down to one octet unless the value needs two or the
+    // attribute asked for the extended length flag itself.
+    fn serialize_path_attribute<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize
+    {
+        let start = self.output.len();
+        value.serialize(&mut *self)?;
+
+        let len = self.output.len() - start - 4;
+        if self.output[start] & ATTR_EXTENDED_LENGTH != 0 {
+            return Ok(());
+        }
+        if len > u8::MAX as usize {
+            self.output[start] |= ATTR_EXTENDED_LENGTH;
+        } else {
+            self.output[start + 3..].copy_within(1.., 0);
+            self.output.truncate(self.output.len() - 1);
+            self.output[start + 2] = len as u8;
+        }
+        Ok(())
+    }
 
-impl Serializer {
     // Function to format the metadata to use for errors.
     fn format_metadata(&self) -> Option<String> {
         let t = &self._err_type_metadata;
@@ -61,7 +404,7 @@ impl Serializer {
     }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl ser::Serializer for &mut Serializer {
     
     // Will be mutating the internal buffer, no need to return any intermediate results
     // to the caller
@@ -79,6 +422,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
+
+    // Keeps types like Ipv4Addr in their binary form instead of text.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
     
 
     fn serialize_bool(self, v: bool) -> Result<()> {
@@ -113,17 +461,29 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // BytesMut put_x methods store multi-byte
     // values in network byte order by default.
     fn serialize_u16(self, v: u16) -> Result<()> {
-       self.output.put_u16(v);
+       match self.endianness {
+           Endianness::Big => self.output.put_u16(v),
+           Endianness::Little => self.output.put_u16_le(v),
+           Endianness::Native => self.output.put_u16_ne(v),
+       }
        Ok(())
     }
     
     fn serialize_u32(self, v: u32) -> Result<()> {
-        self.output.put_u32(v);
+        match self.endianness {
+            Endianness::Big => self.output.put_u32(v),
+            Endianness::Little => self.output.put_u32_le(v),
+            Endianness::Native => self.output.put_u32_ne(v),
+        }
         Ok(())
     }
     
     fn serialize_u64(self, v: u64) -> Result<()> {
-        self.output.put_u64(v);
+        match self.endianness {
+            Endianness::Big => self.output.put_u64(v),
+            Endianness::Little => self.output.put_u64_le(v),
+            Endianness::Native => self.output.put_u64_ne(v),
+        }
         Ok(())
     }
     
@@ -174,11 +534,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
+        variant_index: u32,
+        variant: &'static str,
     ) -> Result<()> {
-        // Do nothing with these, no need to error.
-        Ok(())
+        // Only the type code, if there is one.
+        self.serialize_tag(variant_index, variant)
     }
     
     fn serialize_newtype_struct<T>(
@@ -188,6 +548,50 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     ) -> Result<()> 
     where
         T: ?Sized + ser::Serialize {
+            // LengthPrefixed: reserve the length octets, serialize the value
+            // and patch the real length in afterwards.
+            if let Some(width) = length_prefix_width(name) {
+                return self.serialize_length_prefixed(width, value);
+            }
+            if name == ASN {
+                return self.serialize_asn(value);
+            }
+            if name == FOUR_OCTET_ASN {
+                let saved = self.options.four_octet_asn;
+                self.options.four_octet_asn = true;
+                let res = value.serialize(&mut *self);
+                self.options.four_octet_asn = saved;
+                return res;
+            }
+            if name == TWO_OCTET_SESSION {
+                return match self.options.four_octet_asn {
+                    true => Ok(()),
+                    false => value.serialize(self),
+                };
+            }
+            if name == PATH_ATTRIBUTE {
+                return self.serialize_path_attribute(value);
+            }
+            if let Some((afi, safi)) = path_id_family(name, self.multicast_nlri) {
+                return match self.options.add_path.contains(afi, safi) {
+                    true => value.serialize(self),
+                    false => Ok(()),
+                };
+            }
+            if let Some(width) = tag_width(name) {
+                let saved = self.tag_width;
+                self.tag_width = Some(width);
+                let res = value.serialize(&mut *self);
+                self.tag_width = saved;
+                return res;
+            }
+            if name == MULTICAST_NLRI {
+                let saved = self.multicast_nlri;
+                self.multicast_nlri = true;
+                let res = value.serialize(&mut *self);
+                self.multicast_nlri = saved;
+                return res;
+            }
             self._err_type_metadata = String::from(name);
             self._err_field_metadata.clear();
             self._err_variant_metadata.clear();
@@ -197,13 +601,14 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_newtype_variant<T>(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         value: &T,
     ) -> Result<()> 
     where
         T: ?Sized + ser::Serialize {
 
+        self.serialize_tag(variant_index, variant)?;
         self._err_type_metadata = String::from(name);
         self._err_variant_metadata = String::from(variant);
         self._err_field_metadata.clear();
@@ -224,8 +629,10 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_tuple_struct(
         self,
         name: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
+        // For Padded the length is the padded width.
+        self.padded.push((name == PADDED).then_some((self.output.len(), len)));
         self._err_type_metadata = String::from(name);
         self._err_field_metadata.clear();
         self._err_variant_metadata.clear();
@@ -235,11 +642,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_tuple_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
         
+        self.serialize_tag(variant_index, variant)?;
         self._err_type_metadata = String::from(name);
         self._err_variant_metadata = String::from(variant);
         self._err_field_metadata.clear();
@@ -265,10 +673,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_struct_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
+        self.serialize_tag(variant_index, variant)?;
         self._err_type_metadata = String::from(name);
         self._err_variant_metadata = String::from(variant);
         self._err_field_metadata.clear();
@@ -280,7 +689,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 // Now to define the impls that handle compound types.
 // The structure of the message types are pre-defined
 // and are self-describing. Most of these will be identical.
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -299,7 +708,7 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -316,7 +725,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -331,7 +740,7 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl ser::SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -346,7 +755,7 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl ser::SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -356,11 +765,20 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
                 value.serialize(&mut **self)
     }
     fn end(self) -> Result<()> {
+        if let Some(Some((start, width))) = self.padded.pop() {
+            let used = self.output.len() - start;
+            if used > width {
+                return Err(SerializerError::LengthOverflow(Some(format!(
+                    "value is {} octets, padded width is {}", used, width
+                ))));
+            }
+            self.output.put_bytes(0, width - used);
+        }
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -376,7 +794,7 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
 }
 
 // Map is unsupported in the format (for now)
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl ser::SerializeMap for &mut Serializer {
     type Ok = ();
     type Error = SerializerError;
 
@@ -406,8 +824,10 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
 }
 
 #[cfg(test)]
+#[allow(clippy::enum_variant_names, clippy::approx_constant)]
 mod tests {
     use super::*;
+    use crate::{LengthPrefixed, EXTENDED_MAX_MESSAGE_LEN};
     use std::collections::HashMap;
     
     // Types used for testing error conditions
@@ -475,6 +895,93 @@ mod tests {
         field: i64
     }
 
+    #[test]
+    fn test_message_bytes() {
+        let keepalive = to_message_bytes(MessageType::Keepalive, ()).unwrap();
+        assert_eq!(keepalive.len(), HEADER_LEN);
+        assert_eq!(&keepalive[..16], &MARKER);
+        assert_eq!(&keepalive[16..], &[0, 19, 4]);
+
+        let refresh = to_message_bytes(MessageType::RouteRefresh, (1u16, 0u8, 1u8)).unwrap();
+        assert_eq!(&refresh[16..], &[0, 23, 5, 0, 1, 0, 1]);
+
+        let oversized = to_message_bytes(MessageType::Update, vec![0u8; 4078]);
+        assert!(matches!(oversized, Err(SerializerError::MessageTooLarge { limit: 4096, attempted: 4097 })));
+
+        let extended = SerializerOptions { max_message_len: EXTENDED_MAX_MESSAGE_LEN, ..Default::default() };
+        assert_eq!(to_message_bytes_with(MessageType::Update, vec![0u8; 4078], extended).unwrap().len(), 4097);
+        let open = to_message_bytes_with(MessageType::Open, vec![0u8; 4078], extended);
+        assert!(matches!(open, Err(SerializerError::MessageTooLarge { limit: 4096, .. })));
+        let oversized = to_message_bytes_with(MessageType::Update, vec![0u8; 65535], extended);
+        assert!(matches!(oversized, Err(SerializerError::MessageTooLarge { limit: 65535, attempted: 65554 })));
+
+        // A limit past what the length field can hold still stops at 65535.
+        let unlimited = SerializerOptions { max_message_len: usize::MAX, ..Default::default() };
+        let oversized = to_message_bytes_with(MessageType::Update, vec![0u8; 65535], unlimited);
+        assert!(matches!(oversized, Err(SerializerError::LengthOverflow(_))));
+    }
+
+    #[test]
+    fn test_checkpoint_rollback() {
+        let mut serializer = Serializer::new();
+        let mut last_good = serializer.checkpoint();
+
+        // Keep adding 4-octet "prefixes" until the 10 octet budget is blown.
+        for prefix in [0x0a000000u32, 0x0a010000, 0x0a020000] {
+            prefix.serialize(&mut serializer).unwrap();
+            if serializer.len() > 10 {
+                serializer.rollback(last_good);
+                break;
+            }
+            last_good = serializer.checkpoint();
+        }
+        assert_eq!(serializer.len(), 8);
+        assert_eq!(serializer.into_inner().as_ref(), &[10, 0, 0, 0, 10, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_to_bytes_in() {
+        let mut buffer = BytesMut::with_capacity(64);
+        buffer.put_u8(0xff);
+        to_bytes_in(LengthPrefixed::<u8, _>::new(vec![1u8, 2]), &mut buffer).unwrap();
+        to_bytes_in(0x0304u16, &mut buffer).unwrap();
+        assert_eq!(buffer.as_ref(), &[0xff, 2, 1, 2, 3, 4]);
+
+        // A failed value leaves nothing behind.
+        assert!(to_bytes_in((5u8, -1i8), &mut buffer).is_err());
+        assert_eq!(buffer.len(), 6);
+
+        let mut writer = Vec::new();
+        to_writer((1u8, 0x0203u16), &mut writer).unwrap();
+        assert_eq!(writer, [1, 2, 3]);
+
+        let mut serializer = Serializer::new();
+        let mut out = Vec::new();
+        for v in [7u8, 8] {
+            v.serialize(&mut serializer).unwrap();
+            serializer.drain_into(&mut out);
+            assert!(serializer.is_empty());
+        }
+        assert_eq!(out, [7, 8]);
+    }
+
+    #[test]
+    fn test_builder_endianness() {
+        let value = (0x0102u16, 0x03040506u32, LengthPrefixed::<u16, _>::new(vec![7u8; 3]));
+
+        let mut serializer = Serializer::builder().endianness(Endianness::Little).build();
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(serializer.into_inner().as_ref(), &[2, 1, 6, 5, 4, 3, 3, 0, 7, 7, 7]);
+
+        let mut serializer = Serializer::builder().build();
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(serializer.into_inner().as_ref(), &[1, 2, 3, 4, 5, 6, 0, 3, 7, 7, 7]);
+
+        let serializer = Serializer::builder().four_octet_asn(false).endianness(Endianness::Native).build();
+        assert!(!serializer.options().four_octet_asn);
+        assert_eq!(serializer.endianness(), Endianness::Native);
+    }
+
     #[test]
     fn test_err_enum_hash() {
         let test_ntype = EnumHashTest::NewTypeVariant(HashMap::new());
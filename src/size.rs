@@ -0,0 +1,462 @@
+// Exact encoded size without encoding.
+//
+// A counting serializer that mirrors the wire Serializer octet for octet but
+// only keeps a running total, so nothing is allocated. It follows the same
+// options (2-octet AS numbers, ADD-PATH) and the same special cases (length
+// prefixes, the one-octet path attribute length, padding, type codes), which
+// makes it usable to fill in length fields or to check that an UPDATE fits
+// in a message before building it.
+use serde::{ser, Serialize};
+
+use crate::attributes::ATTR_EXTENDED_LENGTH;
+use crate::error::{Result, SerializerError};
+use crate::ser::SerializerOptions;
+use crate::types::{
+    length_prefix_width, path_id_family, tag_width, variant_code, ASN, FOUR_OCTET_ASN, MULTICAST_NLRI, PADDED,
+    PATH_ATTRIBUTE, TWO_OCTET_SESSION,
+};
+
+// Number of octets to_bytes would produce for the value.
+pub fn serialized_size<T: ?Sized + Serialize>(value: &T) -> Result<usize> {
+    serialized_size_with(value, SerializerOptions::default())
+}
+
+pub fn serialized_size_with<T: ?Sized + Serialize>(value: &T, options: SerializerOptions) -> Result<usize> {
+    let mut counter = SizeCounter {
+        count: 0,
+        options,
+        multicast_nlri: false,
+        tag_width: None,
+        padded: Vec::new(),
+        flags: None,
+    };
+    value.serialize(&mut counter)?;
+    Ok(counter.count)
+}
+
+struct SizeCounter {
+    count: usize,
+    options: SerializerOptions,
+    // Inside MULTICAST_NLRI
+    multicast_nlri: bool,
+    // Type code width for the enum right inside a TaggedEnum
+    tag_width: Option<usize>,
+    // Start and width of each open tuple struct that is a Padded.
+    padded: Vec<Option<(usize, usize)>>,
+    // Flags octet of the path attribute being counted, once it has been
+    // seen. Some(None) while waiting for it.
+    flags: Option<Option<u8>>,
+}
+
+impl SizeCounter {
+    fn add(&mut self, n: usize) {
+        self.count += n;
+    }
+
+    fn count_tag(&mut self, index: u32, variant: &'static str) -> Result<()> {
+        if let Some(width) = self.tag_width.take() {
+            let code = variant_code(variant, index);
+            if code >> (width * 8) != 0 {
+                return Err(SerializerError::CustomMsg(format!(
+                    "Type code {} of variant {} does not fit in {} octet(s)", code, variant, width
+                )));
+            }
+            self.add(width);
+        }
+        Ok(())
+    }
+
+    // Counted with its 2-octet length, which drops to one octet unless the
+    // Extended Length flag is set or the value needs it.
+    fn count_path_attribute<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let saved = self.flags.replace(None);
+        let start = self.count;
+        let res = value.serialize(&mut *self);
+        let flags = std::mem::replace(&mut self.flags, saved).flatten();
+        res?;
+        let extended = flags.is_some_and(|f| f & ATTR_EXTENDED_LENGTH != 0);
+        if !extended && self.count - start - 4 <= u8::MAX as usize {
+            self.count -= 1;
+        }
+        Ok(())
+    }
+}
+
+impl ser::Serializer for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    type SerializeMap = ser::Impossible<(), SerializerError>;
+    type SerializeSeq = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        self.add(1);
+        Ok(())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(None))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(None))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(None))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Err(SerializerError::UnsupportedSignedInt(None))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        if let Some(flags @ None) = &mut self.flags {
+            *flags = Some(v);
+        }
+        self.add(1);
+        Ok(())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        self.add(2);
+        Ok(())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        self.add(4);
+        Ok(())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        self.add(8);
+        Ok(())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(SerializerError::UnsupportedFloat(None))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(SerializerError::UnsupportedFloat(None))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Err(SerializerError::UnsupportedText(None))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Err(SerializerError::UnsupportedText(None))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.add(v.len());
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, variant_index: u32, variant: &'static str) -> Result<()> {
+        self.count_tag(variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(width) = length_prefix_width(name) {
+            let start = self.count;
+            value.serialize(&mut *self)?;
+            if width < 8 && (self.count - start) >> (width * 8) != 0 {
+                return Err(SerializerError::LengthOverflow(None));
+            }
+            self.add(width);
+            return Ok(());
+        }
+        if name == ASN {
+            let start = self.count;
+            value.serialize(&mut *self)?;
+            if !self.options.four_octet_asn {
+                if self.count - start != 4 {
+                    return Err(SerializerError::CustomMsg(String::from("AS number must serialize as a u32")));
+                }
+                self.count = start + 2;
+            }
+            return Ok(());
+        }
+        if name == FOUR_OCTET_ASN {
+            let saved = std::mem::replace(&mut self.options.four_octet_asn, true);
+            let res = value.serialize(&mut *self);
+            self.options.four_octet_asn = saved;
+            return res;
+        }
+        if name == TWO_OCTET_SESSION {
+            return match self.options.four_octet_asn {
+                true => Ok(()),
+                false => value.serialize(self),
+            };
+        }
+        if name == PATH_ATTRIBUTE {
+            return self.count_path_attribute(value);
+        }
+        if let Some((afi, safi)) = path_id_family(name, self.multicast_nlri) {
+            return match self.options.add_path.contains(afi, safi) {
+                true => value.serialize(self),
+                false => Ok(()),
+            };
+        }
+        if let Some(width) = tag_width(name) {
+            let saved = self.tag_width.replace(width);
+            let res = value.serialize(&mut *self);
+            self.tag_width = saved;
+            return res;
+        }
+        if name == MULTICAST_NLRI {
+            let saved = std::mem::replace(&mut self.multicast_nlri, true);
+            let res = value.serialize(&mut *self);
+            self.multicast_nlri = saved;
+            return res;
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.count_tag(variant_index, variant)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct> {
+        self.padded.push((name == PADDED).then_some((self.count, len)));
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.count_tag(variant_index, variant)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(SerializerError::UnsupportedMap(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.count_tag(variant_index, variant)?;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        if let Some(Some((start, width))) = self.padded.pop() {
+            if self.count - start > width {
+                return Err(SerializerError::LengthOverflow(None));
+            }
+            self.count = start + width;
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = SerializerError;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        to_bytes, to_bytes_with, AddPathFamilies, Afi, AsPathSegment, Community, Ipv4Prefix, Origin, Padded,
+        PathAttribute, Safi, TaggedEnum, UpdateMessage,
+    };
+    use std::net::Ipv4Addr;
+
+    fn update() -> UpdateMessage {
+        let prefix = |s: &str| s.parse::<Ipv4Prefix>().unwrap();
+        UpdateMessage {
+            withdrawn_routes: vec![prefix("198.51.100.0/24")].into(),
+            path_attributes: vec![
+                PathAttribute::Origin(Origin::Igp),
+                PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001, 4_200_000_000])]),
+                PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+                // Needs the extended length
+                PathAttribute::Communities(vec![Community::new(65001, 1); 100]),
+            ]
+            .into(),
+            nlri: vec![prefix("192.0.2.0/24"), prefix("10.0.0.0/8").with_path_id(7)],
+        }
+    }
+
+    #[test]
+    fn test_serialized_size_matches() {
+        let update = update();
+        assert_eq!(serialized_size(&update).unwrap(), to_bytes(&update).unwrap().len());
+
+        let two_octet = SerializerOptions {
+            four_octet_asn: false,
+            add_path: AddPathFamilies::new().with(Afi::Ipv4, Safi::Unicast),
+            ..Default::default()
+        };
+        assert_eq!(
+            serialized_size_with(&update, two_octet).unwrap(),
+            to_bytes_with(&update, two_octet).unwrap().len()
+        );
+
+        #[derive(Serialize)]
+        enum Tlv {
+            #[serde(rename = "0x10")]
+            Value(u16),
+        }
+        let value = (Padded::<u16, 5>(1), TaggedEnum::<u16, _>::new(Tlv::Value(3)));
+        assert_eq!(serialized_size(&value).unwrap(), 9);
+        assert_eq!(to_bytes(&value).unwrap().len(), 9);
+    }
+
+    #[test]
+    fn test_serialized_size_err() {
+        assert!(matches!(serialized_size(&-1i32), Err(SerializerError::UnsupportedSignedInt(_))));
+        let too_long: crate::LengthPrefixed<u8, Vec<u8>> = vec![0; 256].into();
+        assert!(matches!(serialized_size(&too_long), Err(SerializerError::LengthOverflow(_))));
+    }
+}
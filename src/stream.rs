@@ -0,0 +1,557 @@
+// Decoding messages out of a byte stream.
+//
+// TCP hands over whatever arrived, which may be part of a message or several
+// at once. MessageStream buffers fed data until a full header and the length
+// it declares are available, then decodes that frame and keeps the rest.
+//
+// send_message and recv_message are the blocking equivalents for a
+// TcpStream (or any Read/Write), one message per call.
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+
+use bytes::{Buf, Bytes, BytesMut};
+use serde::Serialize;
+
+use crate::de::DeserializerOptions;
+use crate::error::{DeserializeError, Result};
+use crate::messages::{BgpMessage, Message, MessageType, ReceivedMessage, HEADER_LEN, MARKER, MAX_MESSAGE_LEN};
+use crate::notification::{MessageHeaderError, NotificationCode};
+use crate::ser::{to_message_bytes_with, SerializerOptions};
+use crate::validate::{check_type_length, ValidationError};
+
+pub struct MessageStream {
+    buf: BytesMut,
+    options: DeserializerOptions,
+    stats: DecodeStats,
+    quarantine: Option<Quarantine>,
+}
+
+// What a MessageStream or BgpCodec has decoded so far, for monitoring a
+// peer without wrapping every call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    // Complete frames by type code, index 0 for codes the crate doesn't
+    // know. Frames that failed to decode are counted too.
+    by_type: [u64; 7],
+    pub octets: u64,
+    // Bad marker or length. The stream can't recover from these, and every
+    // call that returns one counts it again.
+    pub header_errors: u64,
+    // A complete frame that didn't decode.
+    pub message_errors: u64,
+    pub max_message_len: usize,
+    // Header and message errors by the NOTIFICATION they call for.
+    by_error: HashMap<NotificationCode, u64>,
+}
+
+impl DecodeStats {
+    pub fn messages(&self, msg_type: MessageType) -> u64 {
+        self.by_type[Self::index(msg_type)]
+    }
+
+    pub fn total_messages(&self) -> u64 {
+        self.by_type.iter().sum()
+    }
+
+    fn index(msg_type: MessageType) -> usize {
+        match u8::from(msg_type) {
+            code @ 1..=6 => code as usize,
+            _ => 0,
+        }
+    }
+
+    // Errors that call for the NOTIFICATION `code`. A NOTIFICATION that
+    // fails to decode isn't answered, it counts as Message Header Error /
+    // Unspecific.
+    pub fn errors(&self, code: NotificationCode) -> u64 {
+        self.by_error.get(&code).copied().unwrap_or(0)
+    }
+
+    // Every code errors() would give a count for, in no particular order.
+    pub fn error_counts(&self) -> impl Iterator<Item = (NotificationCode, u64)> + '_ {
+        self.by_error.iter().map(|(code, count)| (*code, *count))
+    }
+
+    fn record(&mut self, frame: &[u8], max_len: usize, error: Option<&DeserializeError>) {
+        self.by_type[Self::index(MessageType::from(frame[18]))] += 1;
+        self.octets += frame.len() as u64;
+        self.max_message_len = self.max_message_len.max(frame.len());
+        if let Some(error) = error {
+            self.message_errors += 1;
+            self.record_error(frame, max_len, error);
+        }
+    }
+
+    fn record_header_error(&mut self, buf: &[u8], max_len: usize, error: &DeserializeError) {
+        self.header_errors += 1;
+        self.record_error(buf, max_len, error);
+    }
+
+    fn record_error(&mut self, frame: &[u8], max_len: usize, error: &DeserializeError) {
+        let code = match error.to_notification_with(frame, max_len) {
+            Some(notification) => notification.code().unwrap_or(NotificationCode::Unknown {
+                code: notification.error_code,
+                subcode: notification.error_subcode,
+            }),
+            None => NotificationCode::MessageHeader(MessageHeaderError::Unspecific),
+        };
+        *self.by_error.entry(code).or_insert(0) += 1;
+    }
+}
+
+// Frames that failed to decode, kept with their error so the evidence is
+// there to file an interop bug with. Holds at most `limit` octets of frames;
+// the oldest are dropped to make room, and a frame over the limit on its own
+// isn't kept at all. A header error is kept once, though the stream returns
+// it on every call after.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quarantine {
+    limit: usize,
+    octets: usize,
+    entries: VecDeque<QuarantinedFrame>,
+    header_error: bool,
+    // Frames dropped or never kept because of the limit.
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedFrame {
+    // The whole message, header included. For a header error, as much of
+    // the declared length as was buffered, and at least the header.
+    pub frame: Bytes,
+    pub error: DeserializeError,
+}
+
+impl Quarantine {
+    pub fn new(limit: usize) -> Self {
+        Quarantine { limit, ..Default::default() }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // Octets of frames currently held.
+    pub fn octets(&self) -> usize {
+        self.octets
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &QuarantinedFrame> {
+        self.entries.iter()
+    }
+
+    // Hands over everything held so far, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = QuarantinedFrame> + '_ {
+        self.octets = 0;
+        self.entries.drain(..)
+    }
+
+    pub(crate) fn record(&mut self, frame: &[u8], error: &DeserializeError) {
+        if frame.len() > self.limit {
+            self.dropped += 1;
+            return;
+        }
+        while self.octets + frame.len() > self.limit {
+            if let Some(old) = self.entries.pop_front() {
+                self.octets -= old.frame.len();
+                self.dropped += 1;
+            }
+        }
+        self.octets += frame.len();
+        self.entries.push_back(QuarantinedFrame { frame: Bytes::copy_from_slice(frame), error: error.clone() });
+    }
+
+    // `buf` starts with a header that failed frame_len.
+    fn record_header(&mut self, buf: &[u8], error: &DeserializeError) {
+        if self.header_error {
+            return;
+        }
+        self.header_error = true;
+        let declared = u16::from_be_bytes([buf[16], buf[17]]) as usize;
+        self.record(&buf[..declared.clamp(HEADER_LEN, buf.len())], error);
+    }
+}
+
+impl Default for MessageStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageStream {
+    pub fn new() -> Self {
+        Self::with_options(DeserializerOptions::default())
+    }
+
+    // Options can change mid-session once the OPENs have been exchanged.
+    pub fn with_options(options: DeserializerOptions) -> Self {
+        MessageStream {
+            buf: BytesMut::with_capacity(options.max_message_len),
+            options,
+            stats: DecodeStats::default(),
+            quarantine: None,
+        }
+    }
+
+    pub fn set_options(&mut self, options: DeserializerOptions) {
+        self.options = options;
+    }
+
+    pub fn options(&self) -> DeserializerOptions {
+        self.options
+    }
+
+    // Once Extended Messages are negotiated, without touching the other
+    // options.
+    pub fn set_max_message_len(&mut self, max_message_len: usize) {
+        self.options.max_message_len = max_message_len;
+    }
+
+    // Keeps frames that fail to decode from here on, or stops keeping them
+    // with None. Off by default.
+    pub fn set_quarantine(&mut self, quarantine: Option<Quarantine>) {
+        self.quarantine = quarantine;
+    }
+
+    pub fn quarantine(&self) -> Option<&Quarantine> {
+        self.quarantine.as_ref()
+    }
+
+    pub fn quarantine_mut(&mut self) -> Option<&mut Quarantine> {
+        self.quarantine.as_mut()
+    }
+
+    // Buffers the data and returns the first complete message, if there is
+    // one. A single read can complete more than one message, so callers
+    // should keep calling next_message until it returns None.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Option<BgpMessage>> {
+        self.buf.extend_from_slice(data);
+        self.next_message()
+    }
+
+    // Decodes the next buffered message without feeding more data.
+    // Header errors (bad marker, impossible length) mean the stream is out of
+    // sync; they are returned again on every call since there is no way to
+    // find the next message boundary.
+    pub fn next_message(&mut self) -> Result<Option<BgpMessage>> {
+        let options = self.options;
+        self.next_frame(|frame| BgpMessage::from_bytes_with(&frame, options))
+    }
+
+    // next_message keeping the frame each message was decoded from, along
+    // with where its path attributes are in it.
+    pub fn next_received(&mut self) -> Result<Option<ReceivedMessage>> {
+        let options = self.options;
+        self.next_frame(|frame| ReceivedMessage::from_bytes_with(frame, options))
+    }
+
+    fn next_frame<T, F>(&mut self, decode: F) -> Result<Option<T>>
+    where
+        F: FnOnce(Bytes) -> std::result::Result<T, DeserializeError>,
+    {
+        let max_len = self.options.max_message_len;
+        Ok(decode_frame(&mut self.buf, max_len, &mut self.stats, self.quarantine.as_mut(), decode)?)
+    }
+
+    // Octets received but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn stats(&self) -> &DecodeStats {
+        &self.stats
+    }
+}
+
+// Blocking read of exactly one message.
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<BgpMessage> {
+    recv_message(reader, DeserializerOptions::default())
+}
+
+// Blocking read of the next message, decoded with the session's options.
+// The header is checked before the body is read, so a bad length fails
+// without waiting on (or allocating for) a body that can't be decoded.
+pub fn recv_message<R: Read>(reader: &mut R, options: DeserializerOptions) -> Result<BgpMessage> {
+    let mut frame = vec![0u8; HEADER_LEN];
+    reader.read_exact(&mut frame)?;
+    let len = frame_len(&frame, options.max_message_len)?.unwrap_or(HEADER_LEN);
+    frame.resize(len, 0);
+    reader.read_exact(&mut frame[HEADER_LEN..])?;
+    Ok(BgpMessage::from_bytes_with(&frame, options)?)
+}
+
+// Blocking write of one message. Encoding errors (e.g. over the size
+// limit) are returned before anything is written.
+pub fn send_message<W, T>(writer: &mut W, message: &T, options: SerializerOptions) -> Result<()>
+where
+    W: Write,
+    T: Message + Serialize,
+{
+    writer.write_all(&to_message_bytes_with(T::MESSAGE_TYPE, message, options)?)?;
+    Ok(())
+}
+
+// The framing MessageStream and BgpCodec share: decodes the first frame in
+// `buf` once all of it is there, keeping the stats and quarantine. Until
+// then `buf` gets room reserved for the rest of the frame, so a reader
+// sizing its next read from it asks for the whole message.
+pub(crate) fn decode_frame<T, F>(
+    buf: &mut BytesMut,
+    max_len: usize,
+    stats: &mut DecodeStats,
+    quarantine: Option<&mut Quarantine>,
+    decode: F,
+) -> std::result::Result<Option<T>, DeserializeError>
+where
+    F: FnOnce(Bytes) -> std::result::Result<T, DeserializeError>,
+{
+    let len = match frame_len(buf, max_len) {
+        Ok(Some(len)) => len,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            stats.record_header_error(buf, max_len, &e);
+            if let Some(quarantine) = quarantine {
+                quarantine.record_header(buf, &e);
+            }
+            return Err(e);
+        },
+    };
+    if buf.len() < len {
+        buf.reserve(len - buf.len());
+        return Ok(None);
+    }
+    let frame = buf.split_to(len).freeze();
+    let message = decode(frame.clone());
+    stats.record(&frame, max_len, message.as_ref().err());
+    if let (Err(e), Some(quarantine)) = (&message, quarantine) {
+        quarantine.record(&frame, e);
+    }
+    message.map(Some)
+}
+
+// Total message length from the header, once a whole header is available.
+// A length over `max_len` (MAX_MESSAGE_LEN for OPENs and KEEPALIVEs, RFC
+// 8654 doesn't raise theirs) or one the message type can't have is rejected
+// here too, before waiting for the rest of a frame that can't be decoded
+// anyway.
+pub(crate) fn frame_len(buf: &[u8], max_len: usize) -> std::result::Result<Option<usize>, DeserializeError> {
+    if buf.len() < HEADER_LEN {
+        return Ok(None);
+    }
+    if buf[..16] != MARKER {
+        return Err(DeserializeError::Invalid(ValidationError::BadMarker));
+    }
+    let len = (&buf[16..18]).get_u16() as usize;
+    let msg_type = MessageType::from(buf[18]);
+    let max_len = match msg_type {
+        MessageType::Open | MessageType::Keepalive => max_len.min(MAX_MESSAGE_LEN),
+        _ => max_len,
+    };
+    if !(HEADER_LEN..=max_len).contains(&len) {
+        return Err(DeserializeError::Invalid(ValidationError::BadMessageLength(len as u16)));
+    }
+    check_type_length(msg_type, len).map_err(DeserializeError::Invalid)?;
+    Ok(Some(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{KeepaliveMessage, NotificationMessage, UpdateMessage, EXTENDED_MAX_MESSAGE_LEN};
+    use crate::error::SerializerError;
+    use crate::to_message_bytes;
+
+    fn wire() -> Vec<u8> {
+        let update = UpdateMessage {
+            nlri: vec!["192.0.2.0/24".parse().unwrap()],
+            ..Default::default()
+        };
+        let mut out = to_message_bytes(MessageType::Update, &update).unwrap().to_vec();
+        out.extend_from_slice(&to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap());
+        out
+    }
+
+    #[test]
+    fn test_stream_fragmented() {
+        let wire = wire();
+        let mut stream = MessageStream::new();
+        let mut decoded = Vec::new();
+
+        // One octet at a time is the worst case fragmentation.
+        for b in &wire {
+            if let Some(msg) = stream.feed(std::slice::from_ref(b)).unwrap() {
+                decoded.push(msg);
+            }
+        }
+        assert_eq!(decoded.len(), 2);
+        assert!(matches!(&decoded[0], BgpMessage::Update(u) if u.nlri[0].to_string() == "192.0.2.0/24"));
+        assert_eq!(decoded[1], BgpMessage::Keepalive(KeepaliveMessage));
+        assert_eq!(stream.buffered(), 0);
+    }
+
+    #[test]
+    fn test_stream_coalesced() {
+        let wire = wire();
+        let mut stream = MessageStream::new();
+        // Both messages plus the start of a third in one read.
+        let mut data = wire.clone();
+        data.extend_from_slice(&MARKER[..4]);
+
+        assert!(matches!(stream.feed(&data).unwrap(), Some(BgpMessage::Update(_))));
+        assert!(matches!(stream.next_message().unwrap(), Some(BgpMessage::Keepalive(_))));
+        assert!(stream.next_message().unwrap().is_none());
+        assert_eq!(stream.buffered(), 4);
+    }
+
+    #[test]
+    fn test_stream_bad_header() {
+        let mut stream = MessageStream::new();
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0x10, 0x01, 2]);
+        match stream.feed(&data) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Bad message length 4097."),
+        }
+
+        // Fine once Extended Messages are negotiated, but not for a KEEPALIVE
+        stream.set_max_message_len(EXTENDED_MAX_MESSAGE_LEN);
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0x10, 0x01, 2, 0, 0, 0, 0]);
+        data.resize(4097, 0);
+        // 4074 default routes
+        let update = MessageStream::with_options(stream.options()).feed(&data).unwrap();
+        assert!(matches!(update, Some(BgpMessage::Update(u)) if u.nlri.len() == 4074));
+        data[18] = 4;
+        assert!(MessageStream::with_options(stream.options()).feed(&data).is_err());
+    }
+
+    #[test]
+    fn test_stream_stats() {
+        let mut stream = MessageStream::new();
+        let mut data = wire();
+        // A NOTIFICATION too short to hold its code and subcode
+        data.extend_from_slice(&MARKER);
+        data.extend_from_slice(&[0, 19, 3]);
+        assert!(stream.feed(&data).unwrap().is_some());
+        assert!(stream.next_message().unwrap().is_some());
+        assert!(stream.next_message().is_err());
+
+        let stats = stream.stats();
+        assert_eq!(stats.messages(MessageType::Update), 1);
+        assert_eq!(stats.messages(MessageType::Keepalive), 1);
+        assert_eq!(stats.messages(MessageType::Open), 0);
+        assert_eq!(stats.total_messages(), 2);
+        assert_eq!(stats.octets, data.len() as u64 - 19);
+        assert_eq!(stats.max_message_len, data.len() - 19 - 19);
+        assert_eq!((stats.header_errors, stats.message_errors), (1, 0));
+        assert_eq!(stats.errors(NotificationCode::MessageHeader(MessageHeaderError::BadMessageLength)), 1);
+        assert_eq!(stats.error_counts().count(), 1);
+    }
+
+    #[test]
+    fn test_stream_quarantine() {
+        // UPDATEs whose withdrawn routes run past the end of the message
+        let bad = |fill: u8| {
+            let mut frame = MARKER.to_vec();
+            frame.extend_from_slice(&[0, 23, 2, 0, 9, fill, 0]);
+            frame
+        };
+        let mut stream = MessageStream::new();
+        assert!(stream.feed(&bad(1)).is_err());
+        assert!(stream.quarantine().is_none());
+
+        // Room for two of them.
+        stream.set_quarantine(Some(Quarantine::new(50)));
+        for fill in 2..=4 {
+            assert!(stream.feed(&bad(fill)).is_err());
+        }
+        assert!(stream.feed(&wire()).unwrap().is_some());
+        let quarantine = stream.quarantine().unwrap();
+        assert_eq!((quarantine.len(), quarantine.octets(), quarantine.dropped), (2, 46, 1));
+        let kept: Vec<_> = quarantine.iter().map(|q| q.frame.to_vec()).collect();
+        assert_eq!(kept, vec![bad(3), bad(4)]);
+
+        let drained: Vec<_> = stream.quarantine_mut().unwrap().drain().collect();
+        assert_eq!(drained.len(), 2);
+        assert!(stream.quarantine().unwrap().is_empty());
+        assert_eq!(stream.quarantine().unwrap().octets(), 0);
+    }
+
+    #[test]
+    fn test_stream_quarantine_header() {
+        // A KEEPALIVE with a body
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0, 20, 4, 0]);
+        let mut stream = MessageStream::new();
+        stream.set_quarantine(Some(Quarantine::new(4096)));
+        assert!(stream.feed(&data).is_err());
+        assert!(stream.next_message().is_err());
+        let kept: Vec<_> = stream.quarantine().unwrap().iter().cloned().collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].frame, data);
+        assert_eq!(kept[0].error, DeserializeError::Invalid(ValidationError::BadMessageLength(20)));
+
+        // A length no message can have keeps what was buffered
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0xff, 0xff, 2, 0, 0, 0, 0]);
+        let mut stream = MessageStream::new();
+        stream.set_quarantine(Some(Quarantine::new(4096)));
+        assert!(stream.feed(&data).is_err());
+        assert_eq!(stream.quarantine().unwrap().iter().next().unwrap().frame, data);
+    }
+
+    #[test]
+    fn test_stream_bad_type_length() {
+        // A KEEPALIVE with a body is rejected as soon as its header is in.
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0, 20, 4]);
+        match MessageStream::new().feed(&data) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Bad message length 20."),
+        }
+
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&[0, 20, 3, 6]);
+        assert!(MessageStream::new().feed(&data).is_err());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let notification = NotificationMessage { error_code: 4, error_subcode: 0, data: vec![] };
+        let mut wire = to_message_bytes(MessageType::Notification, &notification).unwrap().to_vec();
+        wire.extend_from_slice(&to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap());
+
+        let mut reader = wire.as_slice();
+        assert_eq!(from_reader(&mut reader).unwrap(), BgpMessage::Notification(notification));
+        assert_eq!(from_reader(&mut reader).unwrap(), BgpMessage::Keepalive(KeepaliveMessage));
+        assert!(matches!(from_reader(&mut reader), Err(SerializerError::Io(_))));
+    }
+
+    #[test]
+    fn test_send_recv_tcp() {
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let update = UpdateMessage { nlri: vec!["192.0.2.0/24".parse().unwrap()], ..Default::default() };
+        send_message(&mut client, &update, SerializerOptions::default()).unwrap();
+        send_message(&mut client, &KeepaliveMessage, SerializerOptions::default()).unwrap();
+        let options = DeserializerOptions::default();
+        assert_eq!(recv_message(&mut server, options).unwrap(), BgpMessage::Update(update));
+        assert_eq!(recv_message(&mut server, options).unwrap(), BgpMessage::Keepalive(KeepaliveMessage));
+
+        drop(client);
+        assert!(matches!(recv_message(&mut server, options), Err(SerializerError::Io(_))));
+    }
+}
@@ -0,0 +1,1218 @@
+// Wrapper and helper types with BGP-specific wire encodings.
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use serde::ser::{SerializeTuple, SerializeTupleStruct};
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::error::SerializerError;
+
+// Reserved newtype struct names. The crate's Serializer and Deserializer
+// recognize these and handle the length octets themselves (back-patching the
+// length on the way out, scoping the value to it on the way in). Any other
+// serde format only sees the wrapped value.
+pub(crate) const LENGTH_PREFIXED_U8: &str = "$bgp4_serde::LengthPrefixed<u8>";
+pub(crate) const LENGTH_PREFIXED_U16: &str = "$bgp4_serde::LengthPrefixed<u16>";
+pub(crate) const LENGTH_PREFIXED_U32: &str = "$bgp4_serde::LengthPrefixed<u32>";
+
+// Wraps (flags, code, LengthPrefixed<u16, value>); the Serializer drops the
+// length to one octet when the extended length flag isn't set and the value
+// fits, and sets the flag when it doesn't.
+pub(crate) const PATH_ATTRIBUTE: &str = "$bgp4_serde::PathAttribute";
+
+// Wraps a u32 AS number. Written and read as 4 octets when the session uses
+// 4-octet AS numbers, as 2 otherwise (with AS_TRANS standing in for AS
+// numbers that don't fit).
+pub(crate) const ASN: &str = "$bgp4_serde::Asn";
+
+// The wrapped value always uses 4-octet AS numbers (AS4_PATH,
+// AS4_AGGREGATOR), whatever the session does.
+pub(crate) const FOUR_OCTET_ASN: &str = "$bgp4_serde::FourOctetAsn";
+
+// The wrapped value is only written on sessions without 4-octet AS numbers.
+pub(crate) const TWO_OCTET_SESSION: &str = "$bgp4_serde::TwoOctetSession";
+
+// Wraps the raw value of a TLV with an unrecognized type code; the
+// Deserializer keeps, skips or rejects it as DeserializerOptions::on_unknown
+// says.
+pub(crate) const UNKNOWN_VALUE: &str = "$bgp4_serde::UnknownValue";
+
+// Wraps the u32 path identifier in front of a prefix (RFC 7911). It is only
+// written and read when DeserializerOptions::add_path (SerializerOptions on
+// the way out) lists the prefix's family: the AFI is in the name, the SAFI is
+// unicast unless inside MULTICAST_NLRI.
+pub(crate) const PATH_ID_IPV4: &str = "$bgp4_serde::PathId<Ipv4>";
+pub(crate) const PATH_ID_IPV6: &str = "$bgp4_serde::PathId<Ipv6>";
+
+// Wraps the length octet of a prefix. The Deserializer checks it against the
+// family's address width and the octets left before decoding the prefix, so
+// a bad one is an Invalid Network Field.
+pub(crate) const PREFIX_LEN_IPV4: &str = "$bgp4_serde::PrefixLen<Ipv4>";
+pub(crate) const PREFIX_LEN_IPV6: &str = "$bgp4_serde::PrefixLen<Ipv6>";
+
+// Prefixes in the wrapped value are multicast NLRI.
+pub(crate) const MULTICAST_NLRI: &str = "$bgp4_serde::MulticastNlri";
+
+// Wraps the UPDATE's length prefixed attribute list. The Deserializer walks
+// the attribute headers before decoding any of them, so lengths that don't
+// add up are a Malformed Attribute List rather than an error out of
+// whichever attribute happens to be misread.
+pub(crate) const ATTRIBUTE_LIST: &str = "$bgp4_serde::AttributeList";
+
+// Wraps an attribute flags octet. The Deserializer rejects one with any of
+// the reserved low four bits set unless DeserializerOptions::reserved_flags
+// says to keep them.
+pub(crate) const ATTRIBUTE_FLAGS: &str = "$bgp4_serde::AttributeFlags";
+
+// Wraps an enum whose variant is picked by a type code of the given width
+// written in front of the variant's value.
+pub(crate) const TAGGED_ENUM_U8: &str = "$bgp4_serde::TaggedEnum<u8>";
+pub(crate) const TAGGED_ENUM_U16: &str = "$bgp4_serde::TaggedEnum<u16>";
+
+// The wrapped value takes everything left of the current length scope.
+pub(crate) const TAKE_REST: &str = "$bgp4_serde::TakeRest";
+
+// A tuple struct name rather than a newtype one, so the padded width can
+// ride along as the tuple length.
+pub(crate) const PADDED: &str = "$bgp4_serde::Padded";
+
+// Family of the prefixes behind a PATH_ID_* name.
+pub(crate) fn path_id_family(name: &str, multicast: bool) -> Option<(Afi, Safi)> {
+    let safi = if multicast { Safi::Multicast } else { Safi::Unicast };
+    match name {
+        PATH_ID_IPV4 => Some((Afi::Ipv4, safi)),
+        PATH_ID_IPV6 => Some((Afi::Ipv6, safi)),
+        _ => None,
+    }
+}
+
+// Longest prefix, in bits, behind a PREFIX_LEN_* name.
+pub(crate) fn max_prefix_len(name: &str) -> Option<u8> {
+    match name {
+        PREFIX_LEN_IPV4 => Some(32),
+        PREFIX_LEN_IPV6 => Some(128),
+        _ => None,
+    }
+}
+
+// Width of the length prefix for one of the reserved names above.
+pub(crate) fn length_prefix_width(name: &str) -> Option<usize> {
+    match name {
+        LENGTH_PREFIXED_U8 => Some(1),
+        LENGTH_PREFIXED_U16 => Some(2),
+        LENGTH_PREFIXED_U32 => Some(4),
+        _ => None,
+    }
+}
+
+// Width of the type code for one of the TAGGED_ENUM_* names.
+pub(crate) fn tag_width(name: &str) -> Option<usize> {
+    match name {
+        TAGGED_ENUM_U8 => Some(1),
+        TAGGED_ENUM_U16 => Some(2),
+        _ => None,
+    }
+}
+
+// Type code of an enum variant inside a TaggedEnum: the variant's name if it
+// is a number ("2", "0x0a"), its index otherwise.
+pub(crate) fn variant_code(variant: &str, index: u32) -> u64 {
+    let parsed = match variant.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => variant.parse(),
+    };
+    parsed.unwrap_or(index as u64)
+}
+
+pub(crate) fn length_prefix_name(width: usize) -> &'static str {
+    match width {
+        1 => LENGTH_PREFIXED_U8,
+        2 => LENGTH_PREFIXED_U16,
+        _ => LENGTH_PREFIXED_U32,
+    }
+}
+
+// The DeserializeSeed side of LengthPrefixed, for hand-written impls that
+// only know what the value is at runtime (TLVs keyed by a type code).
+pub(crate) struct LengthPrefixedSeed<S> {
+    pub width: usize,
+    pub seed: S,
+}
+
+impl<'de, S: de::DeserializeSeed<'de>> de::DeserializeSeed<'de> for LengthPrefixedSeed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<S::Value, D::Error> {
+        struct SeedVisitor<S>(S);
+
+        impl<'de, S: de::DeserializeSeed<'de>> de::Visitor<'de> for SeedVisitor<S> {
+            type Value = S::Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a length prefixed value")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                self.0.deserialize(d)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(length_prefix_name(self.width), SeedVisitor(self.seed))
+    }
+}
+
+// Decodes the raw value of an unrecognized TLV, None if it was skipped.
+// `what` and `code` name it in the error when unknown codes are rejected.
+pub(crate) struct UnknownValueSeed {
+    pub what: &'static str,
+    pub code: u8,
+}
+
+impl<'de> de::DeserializeSeed<'de> for UnknownValueSeed {
+    type Value = Option<Bytes>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Option<Bytes>, D::Error> {
+        deserializer.deserialize_newtype_struct(UNKNOWN_VALUE, self)
+    }
+}
+
+impl<'de> de::Visitor<'de> for UnknownValueSeed {
+    type Value = Option<Bytes>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.what, self.code)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        Bytes::deserialize(d).map(Some)
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+}
+
+// A list element that may have been skipped, and the list that drops those.
+pub(crate) struct Skippable<T>(pub Option<T>);
+
+pub(crate) struct SkippingList<T>(pub Vec<T>);
+
+impl<'de, T> Deserialize<'de> for SkippingList<T>
+where
+    Skippable<T>: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let items: Vec<Skippable<T>> = Deserialize::deserialize(deserializer)?;
+        Ok(SkippingList(items.into_iter().filter_map(|item| item.0).collect()))
+    }
+}
+
+// What text formats (is_human_readable) get for a code point: its name, or
+// the plain number when it has none. Numbers are taken for names too when
+// reading back, so hand-written input can use either.
+pub(crate) enum TextOrNumber {
+    Text(String),
+    Number(u64),
+}
+
+impl<'de> Deserialize<'de> for TextOrNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TextVisitor;
+
+        impl<'de> de::Visitor<'de> for TextVisitor {
+            type Value = TextOrNumber;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a name or a number")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Self::Value, E> {
+                Ok(TextOrNumber::Number(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                Ok(TextOrNumber::Text(v.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(TextVisitor)
+    }
+}
+
+impl TextOrNumber {
+    pub(crate) fn decode<T, E: de::Error>(
+        self,
+        what: &str,
+        from_text: impl FnOnce(&str) -> Option<T>,
+        from_number: impl FnOnce(u64) -> Option<T>,
+    ) -> std::result::Result<T, E> {
+        match self {
+            TextOrNumber::Text(s) => from_text(&s).ok_or_else(|| E::custom(format!("Invalid {} \"{}\"", what, s))),
+            TextOrNumber::Number(v) => from_number(v).ok_or_else(|| E::custom(format!("Invalid {} {}", what, v))),
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+// Integer types usable as a LengthPrefixed length field or a Counted count.
+pub trait LengthType:
+    private::Sealed + Copy + Serialize + for<'de> Deserialize<'de> + TryFrom<usize> + Into<u64>
+{
+    #[doc(hidden)]
+    const NAME: &'static str;
+}
+
+impl LengthType for u8 {
+    const NAME: &'static str = LENGTH_PREFIXED_U8;
+}
+
+impl LengthType for u16 {
+    const NAME: &'static str = LENGTH_PREFIXED_U16;
+}
+
+// For MRT and BMP, whose record lengths are 4 octets.
+impl LengthType for u32 {
+    const NAME: &'static str = LENGTH_PREFIXED_U32;
+}
+
+// A value preceded on the wire by its length in octets, as an L. Covers the
+// "length octet(s) followed by value" pattern (attribute length, optional
+// parameters length, withdrawn routes length, ...) without the caller having
+// to compute the length. On decode the value is limited to exactly that many
+// octets, so a trailing sequence inside it stops where the length says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LengthPrefixed<L, T> {
+    pub value: T,
+    len: PhantomData<L>,
+}
+
+// The name the wrapper also goes by: the prefix is a size in octets, unlike
+// Counted's element count.
+pub type SizePrefixed<L, T> = LengthPrefixed<L, T>;
+
+impl<L, T> LengthPrefixed<L, T> {
+    pub fn new(value: T) -> Self {
+        LengthPrefixed { value, len: PhantomData }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<L, T> From<T> for LengthPrefixed<L, T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<L: LengthType, T: Serialize> Serialize for LengthPrefixed<L, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(L::NAME, &self.value)
+    }
+}
+
+impl<'de, L: LengthType, T: Deserialize<'de>> Deserialize<'de> for LengthPrefixed<L, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PrefixedVisitor<L, T>(PhantomData<(L, T)>);
+
+        impl<'de, L, T: Deserialize<'de>> de::Visitor<'de> for PrefixedVisitor<L, T> {
+            type Value = LengthPrefixed<L, T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a length prefixed value")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                T::deserialize(d).map(LengthPrefixed::new)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(L::NAME, PrefixedVisitor(PhantomData))
+    }
+}
+
+// A list preceded on the wire by its number of elements, as an L, rather
+// than by its size in octets. On decode exactly that many elements are
+// read, wherever the scope ends. Text formats get the plain list.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Counted<L, T> {
+    pub value: Vec<T>,
+    len: PhantomData<L>,
+}
+
+impl<L, T> Counted<L, T> {
+    pub fn new(value: Vec<T>) -> Self {
+        Counted { value, len: PhantomData }
+    }
+
+    pub fn into_inner(self) -> Vec<T> {
+        self.value
+    }
+}
+
+impl<L, T> From<Vec<T>> for Counted<L, T> {
+    fn from(value: Vec<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+// A Counted over a borrowed slice, for Serialize impls of types that keep
+// the list themselves.
+pub(crate) struct CountedRef<'a, L, T>(pub &'a [T], pub PhantomData<L>);
+
+impl<L: LengthType, T: Serialize> Serialize for CountedRef<'_, L, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+        let count = L::try_from(self.0.len())
+            .map_err(|_| ser::Error::custom(format!("{} elements do not fit the count", self.0.len())))?;
+        let mut tup = serializer.serialize_tuple(1 + self.0.len())?;
+        tup.serialize_element(&count)?;
+        for item in self.0 {
+            tup.serialize_element(item)?;
+        }
+        tup.end()
+    }
+}
+
+impl<L: LengthType, T: Serialize> Serialize for Counted<L, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        CountedRef::<L, _>(&self.value, PhantomData).serialize(serializer)
+    }
+}
+
+impl<'de, L: LengthType, T: Deserialize<'de>> Deserialize<'de> for Counted<L, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return Vec::deserialize(deserializer).map(Counted::new);
+        }
+
+        struct CountedVisitor<L, T>(PhantomData<(L, T)>);
+
+        impl<'de, L: LengthType, T: Deserialize<'de>> de::Visitor<'de> for CountedVisitor<L, T> {
+            type Value = Counted<L, T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a counted list")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let count: L = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let count = count.into() as usize;
+                // Not trusting the count with the allocation
+                let mut value = Vec::with_capacity(count.min(seq.size_hint().unwrap_or(0)));
+                for i in 0..count {
+                    value.push(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1 + i, &self))?);
+                }
+                Ok(Counted::new(value))
+            }
+        }
+
+        // The count, then as many elements as it can say
+        deserializer.deserialize_tuple(usize::MAX, CountedVisitor(PhantomData))
+    }
+}
+
+// Integer types usable as a TaggedEnum type code.
+pub trait TagType: private::Sealed {
+    #[doc(hidden)]
+    const NAME: &'static str;
+}
+
+impl TagType for u8 {
+    const NAME: &'static str = TAGGED_ENUM_U8;
+}
+
+impl TagType for u16 {
+    const NAME: &'static str = TAGGED_ENUM_U16;
+}
+
+// An enum whose variant is selected by a type code on the wire, as a Tag,
+// rather than by anything serde writes. Variants are named after their code
+// with #[serde(rename = "2")] or #[serde(rename = "0x0a")] (or, with the
+// derive feature, #[bgp_wire(code = 2)] under #[wire_codes]); a variant whose
+// name isn't a number has its index as the code. The variant's value follows
+// the code, and unit variants are the code alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TaggedEnum<Tag, T> {
+    pub value: T,
+    tag: PhantomData<Tag>,
+}
+
+impl<Tag, T> TaggedEnum<Tag, T> {
+    pub fn new(value: T) -> Self {
+        TaggedEnum { value, tag: PhantomData }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<Tag, T> From<T> for TaggedEnum<Tag, T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<Tag: TagType, T: Serialize> Serialize for TaggedEnum<Tag, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(Tag::NAME, &self.value)
+    }
+}
+
+impl<'de, Tag: TagType, T: Deserialize<'de>> Deserialize<'de> for TaggedEnum<Tag, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TaggedVisitor<Tag, T>(PhantomData<(Tag, T)>);
+
+        impl<'de, Tag, T: Deserialize<'de>> de::Visitor<'de> for TaggedVisitor<Tag, T> {
+            type Value = TaggedEnum<Tag, T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an enum selected by its type code")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                T::deserialize(d).map(TaggedEnum::new)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(Tag::NAME, TaggedVisitor(PhantomData))
+    }
+}
+
+// A value followed by zero octets up to N octets in all, for reserved fields
+// and fixed-width slots a shorter value may sit in. Serializing a value wider
+// than N is an error; on decode the value is limited to the N octets and the
+// padding after it has to be all zero. A value that runs to the end of its
+// scope, like a Vec, would take the padding in with it, so T should have a
+// fixed width. Text formats get the value alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Padded<T, const N: usize>(pub T);
+
+// The name fixed-width slots (MRT and vendor TLV fields) go by.
+pub type PaddedTo<T, const N: usize> = Padded<T, N>;
+
+impl<T: Serialize, const N: usize> Serialize for Padded<T, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+        let mut tup = serializer.serialize_tuple_struct(PADDED, N)?;
+        tup.serialize_field(&self.0)?;
+        tup.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for Padded<T, N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return T::deserialize(deserializer).map(Padded);
+        }
+
+        struct PaddedVisitor<T, const N: usize>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>, const N: usize> de::Visitor<'de> for PaddedVisitor<T, N> {
+            type Value = Padded<T, N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a value padded to {} octets", N)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let value = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                Ok(Padded(value))
+            }
+        }
+
+        deserializer.deserialize_tuple_struct(PADDED, N, PaddedVisitor(PhantomData))
+    }
+}
+
+// A value that makes up the rest of its length scope, for "whatever is left
+// of the attribute is the value" layouts. Written as is; on decode T gets
+// the octets left before the end of the scope and has to use all of them,
+// so a value that stops short is an error rather than trailing octets for
+// whatever comes next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TakeRest<T>(pub T);
+
+// The raw octets to the end of the scope.
+pub type OctetsToEnd = TakeRest<Vec<u8>>;
+
+impl<T: Serialize> Serialize for TakeRest<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(TAKE_REST, &self.0)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for TakeRest<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RestVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> de::Visitor<'de> for RestVisitor<T> {
+            type Value = TakeRest<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("the rest of the scope")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+                T::deserialize(d).map(TakeRest)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(TAKE_REST, RestVisitor(PhantomData))
+    }
+}
+
+// An Option with its presence on the wire: one octet, 1 followed by the
+// value for Some, 0 alone for None. A plain Option writes nothing for None
+// and decodes as None once its scope is used up, which only works for a
+// trailing section; Presence works at any position. For the "length of zero
+// means absent" form use LengthPrefixed<L, Option<T>>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Presence<T>(pub Option<T>);
+
+impl<T> From<Option<T>> for Presence<T> {
+    fn from(value: Option<T>) -> Self {
+        Presence(value)
+    }
+}
+
+impl<T: Serialize> Serialize for Presence<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (self.0.is_some() as u8, &self.0).serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Presence<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PresenceVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> de::Visitor<'de> for PresenceVisitor<T> {
+            type Value = Presence<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a presence octet and an optional value")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let flag: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                match flag {
+                    0 => Ok(Presence(None)),
+                    1 => {
+                        let value = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                        Ok(Presence(Some(value)))
+                    },
+                    other => Err(de::Error::custom(format!("Invalid presence octet {}", other))),
+                }
+            }
+        }
+
+        deserializer.deserialize_tuple(2, PresenceVisitor(PhantomData))
+    }
+}
+
+// A value tagged with the time it was seen. Encodes as 4 octets of seconds
+// and 4 octets of microseconds since the UNIX epoch followed by the value,
+// which matches the MRT _ET and BMP per-peer header timestamp layouts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timestamped<T> {
+    pub seconds: u32,
+    pub microseconds: u32,
+    pub inner: T,
+}
+
+impl<T> Timestamped<T> {
+    pub fn new(seconds: u32, microseconds: u32, inner: T) -> Self {
+        Timestamped { seconds, microseconds, inner }
+    }
+
+    pub fn now(inner: T) -> Self {
+        Self::at(SystemTime::now(), inner)
+    }
+
+    // Times before the epoch clamp to zero, times past 2106 clamp to u32::MAX
+    // seconds since neither can be represented on the wire.
+    pub fn at(time: SystemTime, inner: T) -> Self {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        Timestamped {
+            seconds: u32::try_from(since.as_secs()).unwrap_or(u32::MAX),
+            microseconds: since.subsec_micros(),
+            inner,
+        }
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::new(self.seconds as u64, 0) + Duration::from_micros(self.microseconds as u64)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+// Serializes the items of an iterator as a sequence without collecting them
+// first, so prefixes/communities can go straight from a RIB iterator into the
+// output buffer. The iterator is consumed by the first serialization, a second
+// attempt is an error.
+pub struct SerializeIter<I>(RefCell<Option<I>>);
+
+impl<I> SerializeIter<I> {
+    pub fn new(iter: I) -> Self {
+        SerializeIter(RefCell::new(Some(iter)))
+    }
+}
+
+impl<I> Serialize for SerializeIter<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0.borrow_mut().take() {
+            Some(iter) => serializer.collect_seq(iter),
+            None => Err(ser::Error::custom("SerializeIter can only be serialized once")),
+        }
+    }
+}
+
+// The 4-octet BGP Identifier of a speaker (OPEN, ORIGINATOR_ID, BMP peer
+// headers). Zero, multicast (224/4) and reserved (240/4, which includes the
+// limited broadcast address) values are rejected both at construction and on
+// decode; real routers never use them as router IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BgpIdentifier(u32);
+
+impl BgpIdentifier {
+    pub fn new(id: u32) -> Option<Self> {
+        let addr = Ipv4Addr::from(id);
+        if id == 0 || addr.is_multicast() || addr.octets()[0] >= 240 {
+            None
+        } else {
+            Some(BgpIdentifier(id))
+        }
+    }
+
+    pub fn from_ipv4(addr: Ipv4Addr) -> Option<Self> {
+        Self::new(u32::from(addr))
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
+    pub fn as_ipv4(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.0)
+    }
+}
+
+impl From<BgpIdentifier> for u32 {
+    fn from(id: BgpIdentifier) -> Self {
+        id.0
+    }
+}
+
+impl From<BgpIdentifier> for Ipv4Addr {
+    fn from(id: BgpIdentifier) -> Self {
+        id.as_ipv4()
+    }
+}
+
+impl Display for BgpIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ipv4().fmt(f)
+    }
+}
+
+// Dotted quad in text formats.
+impl Serialize for BgpIdentifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(self);
+        }
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for BgpIdentifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let id = if deserializer.is_human_readable() {
+            TextOrNumber::deserialize(deserializer)?.decode(
+                "BGP Identifier",
+                |s| s.parse::<Ipv4Addr>().ok().map(u32::from),
+                |v| u32::try_from(v).ok(),
+            )?
+        } else {
+            u32::deserialize(deserializer)?
+        };
+        BgpIdentifier::new(id)
+            .ok_or_else(|| de::Error::custom(format!("Invalid BGP Identifier {}", Ipv4Addr::from(id))))
+    }
+}
+
+// Only values new accepts: 0.0.0.1 up to 223.255.255.255.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for BgpIdentifier {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(BgpIdentifier(u.int_in_range(1..=0xdfff_ffff)?))
+    }
+}
+
+// A 3-octet unsigned integer (LLGR stale time, some BMP and MRT fields).
+// Out of range values are rejected at construction, so encoding can't fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct U24(u32);
+
+impl U24 {
+    pub const MAX: U24 = U24(0x00ff_ffff);
+
+    pub fn new(v: u32) -> Option<Self> {
+        (v <= Self::MAX.0).then_some(U24(v))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u16> for U24 {
+    fn from(v: u16) -> Self {
+        U24(v as u32)
+    }
+}
+
+impl TryFrom<u32> for U24 {
+    type Error = SerializerError;
+
+    fn try_from(v: u32) -> std::result::Result<Self, Self::Error> {
+        U24::new(v).ok_or_else(|| {
+            SerializerError::LengthOverflow(Some(format!("{} does not fit in 3 octets", v)))
+        })
+    }
+}
+
+impl From<U24> for u32 {
+    fn from(v: U24) -> Self {
+        v.0
+    }
+}
+
+impl Display for U24 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// A plain number in text formats.
+impl Serialize for U24 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_u32(self.0);
+        }
+        let [_, a, b, c] = self.0.to_be_bytes();
+        [a, b, c].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for U24 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let v = u32::deserialize(deserializer)?;
+            return U24::new(v).ok_or_else(|| de::Error::custom(format!("{} does not fit in 3 octets", v)));
+        }
+        let [a, b, c] = <[u8; 3]>::deserialize(deserializer)?;
+        Ok(U24(u32::from_be_bytes([0, a, b, c])))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for U24 {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(U24(u.int_in_range(0..=Self::MAX.0)?))
+    }
+}
+
+// For Bytes fields of types deriving Arbitrary, bytes has no impl of its own.
+#[cfg(feature = "arbitrary")]
+pub(crate) fn arbitrary_bytes(u: &mut arbitrary::Unstructured) -> arbitrary::Result<Bytes> {
+    Ok(Bytes::copy_from_slice(u.arbitrary()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, DeserializeError, SerializerError};
+    use serde::de::IntoDeserializer;
+
+    #[test]
+    fn test_timestamped_ser() {
+        let stamped = Timestamped::new(0x5f5e_1000, 250_000, [4u8; 2]);
+        assert_eq!(
+            to_bytes(&stamped).unwrap().as_ref(),
+            &[0x5f, 0x5e, 0x10, 0x00, 0x00, 0x03, 0xd0, 0x90, 4, 4]
+        );
+    }
+
+    #[test]
+    fn test_timestamped_system_time() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_000);
+        let stamped = Timestamped::at(time, ());
+        assert_eq!(stamped.seconds, 1_700_000_000);
+        assert_eq!(stamped.microseconds, 123_456);
+        assert_eq!(stamped.time(), time);
+    }
+
+    #[test]
+    fn test_serialize_iter() {
+        #[derive(Serialize)]
+        #[serde(bound = "")]
+        struct Body<I: Iterator<Item = u16>> {
+            count: u8,
+            items: SerializeIter<I>,
+        }
+        let body = Body {
+            count: 3,
+            items: SerializeIter::new((1..=3u16).map(|i| i * 0x101)),
+        };
+        assert_eq!(
+            to_bytes(&body).unwrap().as_ref(),
+            &[3, 0x01, 0x01, 0x02, 0x02, 0x03, 0x03]
+        );
+
+        // Already drained by the first call.
+        match to_bytes(&body) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "SerializeIter can only be serialized once"),
+        }
+    }
+
+    #[test]
+    fn test_bgp_identifier() {
+        let id = BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        assert_eq!(id.to_string(), "192.0.2.1");
+        assert_eq!(to_bytes(id).unwrap().as_ref(), &[192, 0, 2, 1]);
+
+        assert!(BgpIdentifier::new(0).is_none());
+        assert!(BgpIdentifier::from_ipv4(Ipv4Addr::new(224, 0, 0, 5)).is_none());
+        assert!(BgpIdentifier::from_ipv4(Ipv4Addr::new(240, 0, 0, 1)).is_none());
+        assert!(BgpIdentifier::from_ipv4(Ipv4Addr::BROADCAST).is_none());
+        assert!(BgpIdentifier::from_ipv4(Ipv4Addr::new(10, 255, 255, 255)).is_some());
+    }
+
+    #[test]
+    fn test_bgp_identifier_de() {
+        let de = |v: u32| BgpIdentifier::deserialize(IntoDeserializer::<SerializerError>::into_deserializer(v));
+        assert_eq!(de(0x0a000001).unwrap().as_ipv4(), Ipv4Addr::new(10, 0, 0, 1));
+        match de(0) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Invalid BGP Identifier 0.0.0.0"),
+        }
+    }
+
+    #[test]
+    fn test_u24() {
+        let v = U24::new(0x012345).unwrap();
+        assert_eq!(to_bytes(v).unwrap().as_ref(), &[0x01, 0x23, 0x45]);
+        assert_eq!(from_bytes::<U24>(&[0x01, 0x23, 0x45]).unwrap(), v);
+        assert_eq!(from_bytes::<U24>(&[0xff, 0xff, 0xff]).unwrap(), U24::MAX);
+        assert!(U24::new(0x0100_0000).is_none());
+        match U24::try_from(0x0100_0000u32) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e, SerializerError::LengthOverflow(_)), "{}", e),
+        }
+        assert!(matches!(from_bytes::<U24>(&[1, 2]).unwrap_err().inner(), DeserializeError::UnexpectedEof { .. }));
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_in_range() {
+        // Whatever the input, only values the constructors would give
+        let data: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        let mut u = arbitrary::Unstructured::new(&data);
+        for _ in 0..64 {
+            let id: BgpIdentifier = u.arbitrary().unwrap();
+            assert_eq!(BgpIdentifier::new(id.as_u32()), Some(id));
+            assert!(u.arbitrary::<U24>().unwrap() <= U24::MAX);
+        }
+        let prefix: crate::Ipv4Prefix = arbitrary::Unstructured::new(&[0xff; 16]).arbitrary().unwrap();
+        assert_eq!(crate::Ipv4Prefix::new(prefix.addr(), prefix.prefix_len()).unwrap().addr(), prefix.addr());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Update {
+        withdrawn: LengthPrefixed<u16, Vec<u32>>,
+        attrs: LengthPrefixed<u16, Vec<u8>>,
+        nlri: Vec<u8>,
+    }
+
+    #[test]
+    fn test_length_prefixed_ser() {
+        let short: LengthPrefixed<u8, _> = LengthPrefixed::new((1u16, 2u8));
+        assert_eq!(to_bytes(short).unwrap().as_ref(), &[3, 0, 1, 2]);
+
+        let update = Update {
+            withdrawn: vec![0x0a000000].into(),
+            attrs: vec![].into(),
+            nlri: vec![8, 10],
+        };
+        assert_eq!(to_bytes(&update).unwrap().as_ref(), &[0, 4, 10, 0, 0, 0, 0, 0, 8, 10]);
+    }
+
+    #[test]
+    fn test_length_prefixed_de() {
+        // The prefixed sequences stop at their lengths, not at the end of input.
+        let update: Update = from_bytes(&[0, 8, 1, 1, 1, 1, 2, 2, 2, 2, 0, 1, 7, 24, 1, 2, 3]).unwrap();
+        assert_eq!(update.withdrawn.value, vec![0x01010101, 0x02020202]);
+        assert_eq!(update.attrs.value, vec![7]);
+        assert_eq!(update.nlri, vec![24, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_length_prefixed_u32() {
+        let record: SizePrefixed<u32, (u16, Vec<u8>)> = SizePrefixed::new((13, vec![1, 2]));
+        let wire = [0, 0, 0, 4, 0, 13, 1, 2];
+        assert_eq!(to_bytes(&record).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<SizePrefixed<u32, (u16, Vec<u8>)>>(&wire).unwrap(), record);
+        assert!(matches!(
+            from_bytes::<SizePrefixed<u32, Vec<u8>>>(&[0, 1, 0, 0, 1]),
+            Err(DeserializeError::LengthMismatch { declared: 65536, actual: 1, offset: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_counted() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Segment {
+            hops: Counted<u8, u16>,
+            tail: Vec<u8>,
+        }
+        let segment = Segment { hops: vec![1, 2].into(), tail: vec![9] };
+        let wire = [2, 0, 1, 0, 2, 9];
+        assert_eq!(to_bytes(&segment).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Segment>(&wire).unwrap(), segment);
+
+        let wide: Counted<u16, u8> = vec![7].into();
+        assert_eq!(to_bytes(&wide).unwrap().as_ref(), &[0, 1, 7]);
+        assert!(to_bytes(Counted::<u8, u8>::new(vec![0; 256])).is_err());
+
+        // More elements counted than there are
+        assert!(matches!(
+            from_bytes::<Counted<u8, u16>>(&[2, 0, 1]).unwrap_err().inner(),
+            DeserializeError::UnexpectedEof { .. }
+        ));
+    }
+
+    #[test]
+    fn test_length_prefixed_err() {
+        let too_long: LengthPrefixed<u8, Vec<u8>> = LengthPrefixed::new(vec![0; 256]);
+        assert!(matches!(to_bytes(&too_long), Err(SerializerError::LengthOverflow(_))));
+
+        // Length runs past the input
+        match from_bytes::<LengthPrefixed<u8, Vec<u8>>>(&[4, 1, 2]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.to_string(), "Length field does not match the value. Error info - length says 4 octets for the value at offset 1, found 2.")
+            },
+        }
+        // Value doesn't use all of its length
+        match from_bytes::<LengthPrefixed<u8, u16>>(&[3, 1, 2, 3]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => {
+                assert_eq!(e.to_string(), "Length field does not match the value. Error info - length says 3 octets for the value at offset 1, found 2.")
+            },
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Tlv {
+        #[serde(rename = "1")]
+        Value(u16),
+        #[serde(rename = "0x10")]
+        Pair { a: u8, b: u8 },
+        #[serde(rename = "2")]
+        Flag,
+        // Index 3
+        Fallback(u8),
+    }
+
+    #[test]
+    fn test_tagged_enum_de() {
+        let cases = [
+            (&[1u8, 0, 5][..], Tlv::Value(5)),
+            (&[0x10, 1, 2][..], Tlv::Pair { a: 1, b: 2 }),
+            (&[2][..], Tlv::Flag),
+            (&[3, 9][..], Tlv::Fallback(9)),
+        ];
+        for (wire, tlv) in cases {
+            assert_eq!(from_bytes::<TaggedEnum<u8, Tlv>>(wire).unwrap().into_inner(), tlv);
+        }
+        let list: LengthPrefixed<u8, Vec<TaggedEnum<u16, Tlv>>> = from_bytes(&[6, 0, 2, 0, 1, 1, 0]).unwrap();
+        assert_eq!(list.value, vec![TaggedEnum::new(Tlv::Flag), TaggedEnum::new(Tlv::Value(0x100))]);
+
+        match from_bytes::<TaggedEnum<u8, Tlv>>(&[7, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::InvalidEnumDiscriminant { type_name: "Tlv", value: 7, offset: 0 }),
+        }
+        // Without the wrapper there is no code to go by
+        assert!(matches!(from_bytes::<Tlv>(&[2]), Err(DeserializeError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_tagged_enum_ser() {
+        let cases = [
+            (Tlv::Value(5), &[1u8, 0, 5][..]),
+            (Tlv::Pair { a: 1, b: 2 }, &[0x10, 1, 2][..]),
+            (Tlv::Flag, &[2][..]),
+            (Tlv::Fallback(9), &[3, 9][..]),
+        ];
+        for (tlv, wire) in cases {
+            let tagged: TaggedEnum<u8, Tlv> = TaggedEnum::new(tlv);
+            assert_eq!(to_bytes(&tagged).unwrap().as_ref(), wire);
+            assert_eq!(from_bytes::<TaggedEnum<u8, Tlv>>(wire).unwrap(), tagged);
+        }
+        let wide: TaggedEnum<u16, Tlv> = TaggedEnum::new(Tlv::Flag);
+        assert_eq!(to_bytes(&wide).unwrap().as_ref(), &[0, 2]);
+        // Without the wrapper only the value is written
+        assert_eq!(to_bytes(Tlv::Value(5)).unwrap().as_ref(), &[0, 5]);
+
+        #[derive(Serialize)]
+        enum Wide {
+            #[serde(rename = "0x100")]
+            Code,
+        }
+        match to_bytes(TaggedEnum::<u8, _>::new(Wide::Code)) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "Type code 256 of variant 0x100 does not fit in 1 octet(s)"),
+        }
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_wire_codes() {
+        #[crate::wire_codes]
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Attr {
+            #[bgp_wire(code = 14)]
+            Reach(u16),
+            #[bgp_wire(code = 0x10)]
+            Extended { high: u8, low: u8 },
+            #[bgp_wire(code = 1_0)]
+            Flag,
+            // Index 3, as without the macro
+            Fallback(u8),
+        }
+        let cases = [
+            (Attr::Reach(5), &[14u8, 0, 5][..]),
+            (Attr::Extended { high: 1, low: 2 }, &[0x10, 1, 2][..]),
+            (Attr::Flag, &[10][..]),
+            (Attr::Fallback(9), &[3, 9][..]),
+        ];
+        for (attr, wire) in cases {
+            let tagged: TaggedEnum<u8, Attr> = TaggedEnum::new(attr);
+            assert_eq!(to_bytes(&tagged).unwrap().as_ref(), wire);
+            assert_eq!(from_bytes::<TaggedEnum<u8, Attr>>(wire).unwrap(), tagged);
+        }
+    }
+
+    #[test]
+    fn test_padded() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Header {
+            marker: [u8; 16],
+            reserved: Padded<(), 2>,
+            digest: Padded<[u8; 2], 4>,
+        }
+        let header = Header { marker: [0xff; 16], reserved: Padded(()), digest: Padded([0xab, 0xcd]) };
+        let bytes = to_bytes(&header).unwrap();
+        assert_eq!(&bytes[..16], &[0xff; 16]);
+        assert_eq!(&bytes[16..], &[0, 0, 0xab, 0xcd, 0, 0]);
+        assert_eq!(from_bytes::<Header>(&bytes).unwrap(), header);
+
+        assert_eq!(to_bytes(Padded::<u16, 4>(0x0102)).unwrap().as_ref(), &[1, 2, 0, 0]);
+        assert!(matches!(to_bytes(Padded::<u32, 2>(1)), Err(SerializerError::LengthOverflow(_))));
+
+        match from_bytes::<Padded<u8, 3>>(&[1, 0, 5]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::NonZeroPadding { offset: 2 }),
+        }
+        assert!(matches!(
+            from_bytes::<Padded<u8, 3>>(&[1, 0]),
+            Err(DeserializeError::UnexpectedEof { needed: 3, available: 2, offset: 0 })
+        ));
+
+        // A vendor TLV with an 8 octet slot for a 3 octet value
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Slot {
+            code: u8,
+            value: PaddedTo<(u16, u8), 8>,
+            next: u8,
+        }
+        let slot = Slot { code: 1, value: Padded((0x0203, 4)), next: 5 };
+        let wire = [1, 2, 3, 4, 0, 0, 0, 0, 0, 5];
+        assert_eq!(to_bytes(&slot).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Slot>(&wire).unwrap(), slot);
+    }
+
+    #[test]
+    fn test_take_rest() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Attr {
+            code: u8,
+            value: LengthPrefixed<u8, TakeRest<(u16, u16)>>,
+            raw: OctetsToEnd,
+        }
+        let attr = Attr { code: 1, value: TakeRest((1, 2)).into(), raw: TakeRest(vec![7, 8]) };
+        let wire = [1, 4, 0, 1, 0, 2, 7, 8];
+        assert_eq!(to_bytes(&attr).unwrap().as_ref(), &wire);
+        assert_eq!(from_bytes::<Attr>(&wire).unwrap(), attr);
+        assert_eq!(from_bytes::<OctetsToEnd>(&[]).unwrap(), TakeRest(vec![]));
+
+        // Octets left over in the scope
+        match from_bytes::<LengthPrefixed<u8, TakeRest<u16>>>(&[3, 0, 1, 2]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e, DeserializeError::LengthMismatch { declared: 3, actual: 2, offset: 1 }),
+        }
+    }
+
+    #[test]
+    fn test_presence() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Fields {
+            first: Presence<u16>,
+            second: u8,
+            tail: LengthPrefixed<u8, Option<u16>>,
+        }
+        let some = Fields { first: Presence(Some(0x0102)), second: 3, tail: Some(4).into() };
+        let none = Fields { first: Presence(None), second: 3, tail: None.into() };
+        assert_eq!(to_bytes(&some).unwrap().as_ref(), &[1, 1, 2, 3, 2, 0, 4]);
+        assert_eq!(to_bytes(&none).unwrap().as_ref(), &[0, 3, 0]);
+        assert_eq!(from_bytes::<Fields>(&[1, 1, 2, 3, 2, 0, 4]).unwrap(), some);
+        assert_eq!(from_bytes::<Fields>(&[0, 3, 0]).unwrap(), none);
+
+        match from_bytes::<Presence<u8>>(&[2, 0]) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "Invalid presence octet 2"),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// Packing routes into UPDATE messages.
+//
+// UpdateBuilder takes any number of withdrawn and announced prefixes sharing
+// one set of path attributes and hands back as many UPDATEs as it takes to
+// carry them, each within the session's message size limit. Withdrawals go
+// out first; announcements follow, in order, with the attributes repeated
+// in every message that carries NLRI.
+//
+// pack_routes does the same for routes that each come with their own
+// attributes, grouping the prefixes whose attributes are the same.
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::attributes::PathAttribute;
+use crate::error::{Result, SerializerError};
+use crate::messages::{UpdateMessage, HEADER_LEN};
+use crate::prefix::Ipv4Prefix;
+use crate::ser::SerializerOptions;
+use crate::ser::to_bytes_with;
+use crate::size::serialized_size_with;
+
+// Withdrawn routes length and total path attribute length.
+const SECTION_LENGTHS: usize = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct UpdateBuilder {
+    options: SerializerOptions,
+    withdrawn: Vec<Ipv4Prefix>,
+    attributes: Vec<PathAttribute>,
+    nlri: Vec<Ipv4Prefix>,
+}
+
+impl UpdateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The options the messages will be serialized with. They decide both
+    // the size limit and how large each prefix and attribute is.
+    pub fn with_options(options: SerializerOptions) -> Self {
+        UpdateBuilder { options, ..Self::default() }
+    }
+
+    pub fn withdraw<I: IntoIterator<Item = Ipv4Prefix>>(mut self, prefixes: I) -> Self {
+        self.withdrawn.extend(prefixes);
+        self
+    }
+
+    pub fn attributes<I: IntoIterator<Item = PathAttribute>>(mut self, attributes: I) -> Self {
+        self.attributes.extend(attributes);
+        self
+    }
+
+    pub fn announce<I: IntoIterator<Item = Ipv4Prefix>>(mut self, prefixes: I) -> Self {
+        self.nlri.extend(prefixes);
+        self
+    }
+
+    // Fails if the attributes leave no room for even one prefix.
+    pub fn build(self) -> Result<UpdateMessages> {
+        let options = self.options;
+        let sized = |prefixes: Vec<Ipv4Prefix>| -> Result<Vec<(Ipv4Prefix, usize)>> {
+            prefixes.into_iter().map(|p| Ok((p, serialized_size_with(&p, options)?))).collect()
+        };
+        let withdrawn = sized(self.withdrawn)?;
+        let nlri = sized(self.nlri)?;
+        let attributes_len = serialized_size_with(&self.attributes, options)?;
+
+        let room = options.max_message_len.saturating_sub(HEADER_LEN + SECTION_LENGTHS);
+        let largest = nlri.iter().chain(&withdrawn).map(|&(_, len)| len).max().unwrap_or(0);
+        let needed = match nlri.is_empty() {
+            true => attributes_len.max(largest),
+            false => attributes_len + largest,
+        };
+        if needed > room {
+            return Err(SerializerError::MessageTooLarge {
+                limit: options.max_message_len,
+                attempted: HEADER_LEN + SECTION_LENGTHS + needed,
+            });
+        }
+
+        Ok(UpdateMessages {
+            attributes_pending: !self.attributes.is_empty(),
+            withdrawn: withdrawn.into_iter().peekable(),
+            nlri: nlri.into_iter().peekable(),
+            attributes: self.attributes,
+            attributes_len,
+            room,
+        })
+    }
+}
+
+// The UPDATEs from UpdateBuilder::build, ready for to_message_bytes_with
+// using the same options.
+#[derive(Debug, Clone)]
+pub struct UpdateMessages {
+    withdrawn: Peekable<IntoIter<(Ipv4Prefix, usize)>>,
+    nlri: Peekable<IntoIter<(Ipv4Prefix, usize)>>,
+    attributes: Vec<PathAttribute>,
+    attributes_len: usize,
+    // Octets left for the three sections once the header and the two
+    // section lengths are counted.
+    room: usize,
+    // Attributes that haven't gone out in any message yet. Without NLRI
+    // they still get one message (e.g. carrying MP_REACH_NLRI).
+    attributes_pending: bool,
+}
+
+impl Iterator for UpdateMessages {
+    type Item = UpdateMessage;
+
+    fn next(&mut self) -> Option<UpdateMessage> {
+        let mut update = UpdateMessage::default();
+        let mut room = self.room;
+
+        while let Some(&(prefix, len)) = self.withdrawn.peek() {
+            if len > room {
+                break;
+            }
+            room -= len;
+            update.withdrawn_routes.value.push(prefix);
+            self.withdrawn.next();
+        }
+
+        let announce = self.withdrawn.peek().is_none()
+            && (self.nlri.peek().is_some() || self.attributes_pending)
+            && self.attributes_len + self.nlri.peek().map_or(0, |&(_, len)| len) <= room;
+        if announce {
+            room -= self.attributes_len;
+            update.path_attributes.value = self.attributes.clone();
+            self.attributes_pending = false;
+            while let Some(&(prefix, len)) = self.nlri.peek() {
+                if len > room {
+                    break;
+                }
+                room -= len;
+                update.nlri.push(prefix);
+                self.nlri.next();
+            }
+        }
+
+        match update.withdrawn_routes.value.is_empty() && !announce {
+            true => None,
+            false => Some(update),
+        }
+    }
+}
+
+// The fewest UPDATEs carrying every route: prefixes whose attributes
+// encode to the same octets share messages. Groups go out in the order
+// their attributes were first seen, prefixes in the order given.
+pub fn pack_routes<I>(routes: I, options: SerializerOptions) -> Result<Vec<UpdateMessage>>
+where
+    I: IntoIterator<Item = (Ipv4Prefix, Vec<PathAttribute>)>,
+{
+    let mut groups: Vec<(Vec<PathAttribute>, Vec<Ipv4Prefix>)> = Vec::new();
+    let mut index = HashMap::new();
+    for (prefix, attributes) in routes {
+        let key = to_bytes_with(&attributes, options)?;
+        let i = *index.entry(key).or_insert_with(|| {
+            groups.push((attributes, Vec::new()));
+            groups.len() - 1
+        });
+        groups[i].1.push(prefix);
+    }
+    let mut updates = Vec::new();
+    for (attributes, nlri) in groups {
+        updates.extend(UpdateBuilder::with_options(options).attributes(attributes).announce(nlri).build()?);
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_message_bytes_with, to_message_bytes_with, AddPathFamilies, Afi, AsPathSegment, DeserializerOptions,
+        MessageType, Origin, Safi, EXTENDED_MAX_MESSAGE_LEN, MAX_MESSAGE_LEN,
+    };
+    use std::net::Ipv4Addr;
+
+    fn prefixes(count: u32, len: u8) -> Vec<Ipv4Prefix> {
+        (0..count)
+            .map(|i| Ipv4Prefix::new(Ipv4Addr::from(0x0a00_0000 + (i << (32 - len))), len).unwrap())
+            .collect()
+    }
+
+    fn attributes() -> Vec<PathAttribute> {
+        vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001])]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+        ]
+    }
+
+    // Serializes every message, checking it is within the limit, and
+    // returns them decoded again.
+    fn wire(updates: UpdateMessages, options: SerializerOptions) -> Vec<UpdateMessage> {
+        let de_options = DeserializerOptions { add_path: options.add_path, ..Default::default() };
+        updates
+            .map(|update| {
+                let bytes = to_message_bytes_with(MessageType::Update, &update, options).unwrap();
+                assert!(bytes.len() <= options.max_message_len);
+                let decoded: UpdateMessage = from_message_bytes_with(&bytes, de_options).unwrap();
+                assert_eq!(decoded, update);
+                decoded
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_update_builder_split() {
+        let withdrawn = prefixes(1500, 24);
+        let nlri = prefixes(2000, 22);
+        let updates = UpdateBuilder::new()
+            .withdraw(withdrawn.clone())
+            .attributes(attributes())
+            .announce(nlri.clone())
+            .build()
+            .unwrap();
+        let updates = wire(updates, SerializerOptions::default());
+
+        // 1500 * 4 octets of withdrawals need two messages; the second has
+        // room left over for the first announcements.
+        assert_eq!(updates.len(), 4);
+        assert!(updates[0].path_attributes.value.is_empty() && updates[0].nlri.is_empty());
+        assert!(!updates[1].nlri.is_empty());
+        let all_withdrawn: Vec<_> = updates.iter().flat_map(|u| u.withdrawn_routes.value.clone()).collect();
+        let all_nlri: Vec<_> = updates.iter().flat_map(|u| u.nlri.clone()).collect();
+        assert_eq!(all_withdrawn, withdrawn);
+        assert_eq!(all_nlri, nlri);
+        assert!(updates.iter().filter(|u| !u.nlri.is_empty()).all(|u| u.path_attributes.value == attributes()));
+
+        let extended = SerializerOptions { max_message_len: EXTENDED_MAX_MESSAGE_LEN, ..Default::default() };
+        let updates = UpdateBuilder::with_options(extended).withdraw(withdrawn).announce(nlri).build().unwrap();
+        assert_eq!(wire(updates, extended).len(), 1);
+    }
+
+    #[test]
+    fn test_update_builder_add_path() {
+        let options = SerializerOptions {
+            add_path: AddPathFamilies::new().with(Afi::Ipv4, Safi::Unicast),
+            ..Default::default()
+        };
+        let nlri: Vec<_> = prefixes(1000, 24).into_iter().map(|p| p.with_path_id(1)).collect();
+        let updates = UpdateBuilder::with_options(options).attributes(attributes()).announce(nlri).build().unwrap();
+        // 8 octets a prefix with the path identifier.
+        let updates = wire(updates, options);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].nlri.len(), (MAX_MESSAGE_LEN - HEADER_LEN - 4 - 21) / 8);
+    }
+
+    #[test]
+    fn test_pack_routes() {
+        let mut other = attributes();
+        other.push(PathAttribute::LocalPref(200));
+        // Alternating attribute sets, 1200 routes each
+        let routes = prefixes(2400, 24)
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (p, if i % 2 == 0 { attributes() } else { other.clone() }));
+        let updates = pack_routes(routes, SerializerOptions::default()).unwrap();
+        // (4096 - 23 - 21) / 4 = 1013 prefixes per message with the first set,
+        // 1011 with the second
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[0].path_attributes.value, attributes());
+        assert_eq!(updates[0].nlri.len(), 1013);
+        assert_eq!(updates[1].path_attributes.value, attributes());
+        assert_eq!(updates[2].path_attributes.value, other);
+        assert_eq!(updates[2].nlri.len(), 1011);
+        assert_eq!(updates.iter().map(|u| u.nlri.len()).sum::<usize>(), 2400);
+        for update in &updates {
+            let len = to_message_bytes_with(MessageType::Update, update, SerializerOptions::default()).unwrap().len();
+            assert!(len <= MAX_MESSAGE_LEN);
+        }
+
+        assert!(pack_routes([], SerializerOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_builder_edges() {
+        assert_eq!(UpdateBuilder::new().build().unwrap().count(), 0);
+
+        // Attributes alone still make a message.
+        let updates: Vec<_> = UpdateBuilder::new().attributes(attributes()).build().unwrap().collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].path_attributes.value, attributes());
+
+        let huge = PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001; 255]); 5]);
+        let res = UpdateBuilder::new().attributes([huge]).announce(prefixes(1, 24)).build();
+        match res {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert!(matches!(e, SerializerError::MessageTooLarge { limit: 4096, .. })),
+        }
+    }
+}
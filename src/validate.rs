@@ -0,0 +1,466 @@
+// Semantic checks on decoded messages (RFC 4271 6).
+//
+// Decoding only makes sure the octets fit the types; a message can decode
+// fine and still be one a speaker has to reject, like an UPDATE with NLRI
+// but no NEXT_HOP or an OPEN with a hold time of 1. Each ValidationError
+// corresponds to one NOTIFICATION error code and subcode.
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::net::Ipv4Addr;
+
+use crate::attributes::{Origin, PathAttribute};
+use crate::ser::to_bytes;
+use crate::messages::{
+    BgpMessage, KeepaliveMessage, MessageType, NotificationMessage, OpenMessage, RawMessage, RouteRefreshMessage,
+    UpdateMessage, HEADER_LEN, MARKER, MAX_MESSAGE_LEN,
+};
+use crate::notification::{MessageHeaderError, NotificationCode, OpenMessageError, UpdateMessageError};
+use crate::registry::path_attr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    BadMarker,
+    // Total message length, header included.
+    BadMessageLength(u16),
+    BadMessageType(u8),
+    UnsupportedVersion(u8),
+    BadPeerAs(u16),
+    UnacceptableHoldTime(u16),
+    // An attribute code appears more than once.
+    DuplicateAttribute(u8),
+    // The attribute lengths don't add up to the Total Path Attribute Length,
+    // or that runs past the end of the message.
+    MalformedAttributeList,
+    // Flags octet with reserved bits set
+    AttributeFlags(u8),
+    MissingWellKnownAttribute(u8),
+    InvalidOrigin(u8),
+    InvalidNextHop(Ipv4Addr),
+    // A prefix length longer than the address, or than what is left of the
+    // NLRI.
+    InvalidNetworkField(u8),
+}
+
+impl ValidationError {
+    pub fn notification_code(&self) -> NotificationCode {
+        match self {
+            ValidationError::BadMarker => NotificationCode::MessageHeader(MessageHeaderError::ConnectionNotSynchronized),
+            ValidationError::BadMessageLength(_) => NotificationCode::MessageHeader(MessageHeaderError::BadMessageLength),
+            ValidationError::BadMessageType(_) => NotificationCode::MessageHeader(MessageHeaderError::BadMessageType),
+            ValidationError::UnsupportedVersion(_) => {
+                NotificationCode::OpenMessage(OpenMessageError::UnsupportedVersionNumber)
+            },
+            ValidationError::BadPeerAs(_) => NotificationCode::OpenMessage(OpenMessageError::BadPeerAs),
+            ValidationError::UnacceptableHoldTime(_) => {
+                NotificationCode::OpenMessage(OpenMessageError::UnacceptableHoldTime)
+            },
+            ValidationError::DuplicateAttribute(_) | ValidationError::MalformedAttributeList => {
+                NotificationCode::UpdateMessage(UpdateMessageError::MalformedAttributeList)
+            },
+            ValidationError::MissingWellKnownAttribute(_) => {
+                NotificationCode::UpdateMessage(UpdateMessageError::MissingWellKnownAttribute)
+            },
+            ValidationError::AttributeFlags(_) => NotificationCode::UpdateMessage(UpdateMessageError::AttributeFlagsError),
+            ValidationError::InvalidOrigin(_) => NotificationCode::UpdateMessage(UpdateMessageError::InvalidOriginAttribute),
+            ValidationError::InvalidNextHop(_) => {
+                NotificationCode::UpdateMessage(UpdateMessageError::InvalidNextHopAttribute)
+            },
+            ValidationError::InvalidNetworkField(_) => {
+                NotificationCode::UpdateMessage(UpdateMessageError::InvalidNetworkField)
+            },
+        }
+    }
+
+    // The NOTIFICATION to send for this error, with the Data field filled in
+    // as RFC 4271 6.1-6.3 asks: the offending length or type for header
+    // errors, the supported version for a version mismatch, the missing
+    // attribute's type code, or the whole offending attribute.
+    pub fn to_notification(&self) -> NotificationMessage {
+        let data = match self {
+            ValidationError::BadMessageLength(len) => len.to_be_bytes().to_vec(),
+            ValidationError::BadMessageType(t) => vec![*t],
+            ValidationError::UnsupportedVersion(_) => SUPPORTED_VERSION.to_be_bytes().to_vec(),
+            ValidationError::MissingWellKnownAttribute(code) => vec![*code],
+            ValidationError::InvalidOrigin(v) => attribute_data(PathAttribute::Origin(Origin::Unknown(*v))),
+            ValidationError::InvalidNextHop(addr) => attribute_data(PathAttribute::NextHop(*addr)),
+            ValidationError::BadMarker
+            | ValidationError::BadPeerAs(_)
+            | ValidationError::UnacceptableHoldTime(_)
+            | ValidationError::DuplicateAttribute(_)
+            | ValidationError::MalformedAttributeList
+            | ValidationError::AttributeFlags(_)
+            | ValidationError::InvalidNetworkField(_) => Vec::new(),
+        };
+        NotificationMessage::new(self.notification_code(), data)
+    }
+}
+
+// Largest BGP version this crate speaks, as the 2-octet Data of an
+// Unsupported Version Number NOTIFICATION.
+const SUPPORTED_VERSION: u16 = 4;
+
+// The attribute as it was on the wire: flags, type code, length and value.
+fn attribute_data(attr: PathAttribute) -> Vec<u8> {
+    to_bytes(attr).map(|b| b.to_vec()).unwrap_or_default()
+}
+
+impl From<ValidationError> for NotificationMessage {
+    fn from(e: ValidationError) -> Self {
+        e.to_notification()
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::BadMarker => f.write_str("Message header marker is not all ones"),
+            ValidationError::BadMessageLength(len) => write!(f, "Bad message length {}", len),
+            ValidationError::BadMessageType(t) => write!(f, "Bad message type {}", t),
+            ValidationError::UnsupportedVersion(v) => write!(f, "Unsupported BGP version {}", v),
+            ValidationError::BadPeerAs(asn) => write!(f, "Bad peer AS {}", asn),
+            ValidationError::UnacceptableHoldTime(t) => write!(f, "Unacceptable hold time {}", t),
+            ValidationError::DuplicateAttribute(code) => write!(f, "Path attribute {} appears more than once", code),
+            ValidationError::MalformedAttributeList => f.write_str("Malformed path attribute list"),
+            ValidationError::AttributeFlags(flags) => write!(f, "Reserved attribute flag bits set in {:#04x}", flags),
+            ValidationError::MissingWellKnownAttribute(code) => {
+                write!(f, "Missing well-known path attribute {}", code)
+            },
+            ValidationError::InvalidOrigin(v) => write!(f, "Invalid ORIGIN {}", v),
+            ValidationError::InvalidNextHop(addr) => write!(f, "Invalid NEXT_HOP {}", addr),
+            ValidationError::InvalidNetworkField(len) => write!(f, "Invalid network field, prefix length {}", len),
+        }
+    }
+}
+
+pub trait Validate {
+    // The first problem found, if any.
+    fn validate(&self) -> std::result::Result<(), ValidationError>;
+}
+
+// Smallest legal total length of each message type.
+fn min_len(msg_type: MessageType) -> Option<usize> {
+    match msg_type {
+        MessageType::Open => Some(29),
+        MessageType::Update => Some(23),
+        MessageType::Notification => Some(21),
+        MessageType::Keepalive => Some(HEADER_LEN),
+        MessageType::RouteRefresh => Some(23),
+        // At least one change
+        MessageType::Capability => Some(23),
+        MessageType::Unknown(_) => None,
+    }
+}
+
+// `max_len` is the session's, MAX_MESSAGE_LEN unless Extended Messages were
+// negotiated; OPENs and KEEPALIVEs are held to MAX_MESSAGE_LEN either way.
+fn check_length(msg_type: MessageType, len: usize, max_len: usize) -> std::result::Result<(), ValidationError> {
+    if min_len(msg_type).is_none() {
+        return Err(ValidationError::BadMessageType(u8::from(msg_type)));
+    }
+    let max_len = match msg_type {
+        MessageType::Open | MessageType::Keepalive => max_len.min(MAX_MESSAGE_LEN),
+        _ => max_len,
+    };
+    if len > max_len {
+        return Err(ValidationError::BadMessageLength(len.min(u16::MAX as usize) as u16));
+    }
+    check_type_length(msg_type, len)
+}
+
+// The per-type part of check_length, for the decoders: KEEPALIVEs are
+// exactly a header, the other types have a fixed part they can't be shorter
+// than. Unknown types pass, whether they are acceptable is up to the caller.
+pub(crate) fn check_type_length(msg_type: MessageType, len: usize) -> std::result::Result<(), ValidationError> {
+    let min = match min_len(msg_type) {
+        Some(min) => min,
+        None => return Ok(()),
+    };
+    if len < min || (msg_type == MessageType::Keepalive && len != HEADER_LEN) {
+        return Err(ValidationError::BadMessageLength(len.min(u16::MAX as usize) as u16));
+    }
+    Ok(())
+}
+
+// Header checks (RFC 4271 6.1) on a complete message as received: marker,
+// a length within 19..=4096 that suits the message type, and a known type.
+pub fn validate_header(input: &[u8]) -> std::result::Result<(), ValidationError> {
+    validate_header_with(input, MAX_MESSAGE_LEN)
+}
+
+// validate_header with the session's largest message in place of 4096.
+pub fn validate_header_with(input: &[u8], max_message_len: usize) -> std::result::Result<(), ValidationError> {
+    if input.len() < HEADER_LEN {
+        return Err(ValidationError::BadMessageLength(input.len() as u16));
+    }
+    if input[..16] != MARKER {
+        return Err(ValidationError::BadMarker);
+    }
+    let len = u16::from_be_bytes([input[16], input[17]]);
+    check_length(MessageType::from(input[18]), len as usize, max_message_len).map_err(|e| match e {
+        ValidationError::BadMessageLength(_) => ValidationError::BadMessageLength(len),
+        other => other,
+    })
+}
+
+impl Validate for RawMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        self.validate_with(MAX_MESSAGE_LEN)
+    }
+}
+
+impl RawMessage {
+    // validate for a session that negotiated Extended Messages, see
+    // negotiate_max_message_len.
+    pub fn validate_with(&self, max_message_len: usize) -> std::result::Result<(), ValidationError> {
+        check_length(self.message_type, HEADER_LEN + self.body.len(), max_message_len)
+    }
+}
+
+impl Validate for OpenMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        if self.version != 4 {
+            return Err(ValidationError::UnsupportedVersion(self.version));
+        }
+        // RFC 7607
+        if self.my_as == 0 {
+            return Err(ValidationError::BadPeerAs(self.my_as));
+        }
+        if matches!(self.hold_time, 1 | 2) {
+            return Err(ValidationError::UnacceptableHoldTime(self.hold_time));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for UpdateMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        let attrs = &self.path_attributes.value;
+        let mut seen = HashSet::new();
+        for attr in attrs {
+            if !seen.insert(attr.type_code()) {
+                return Err(ValidationError::DuplicateAttribute(attr.type_code()));
+            }
+            match attr.attribute() {
+                PathAttribute::Origin(Origin::Unknown(v)) => return Err(ValidationError::InvalidOrigin(*v)),
+                PathAttribute::NextHop(addr) if !is_host_address(*addr) => {
+                    return Err(ValidationError::InvalidNextHop(*addr))
+                },
+                _ => {},
+            }
+        }
+
+        // ORIGIN and AS_PATH go with any reachable routes, NEXT_HOP only
+        // with the IPv4 ones in the NLRI field (MP_REACH_NLRI has its own).
+        let mut required = Vec::new();
+        if !self.nlri.is_empty() || seen.contains(&path_attr::MP_REACH_NLRI) {
+            required.extend([path_attr::ORIGIN, path_attr::AS_PATH]);
+        }
+        if !self.nlri.is_empty() {
+            required.push(path_attr::NEXT_HOP);
+        }
+        match required.into_iter().find(|code| !seen.contains(code)) {
+            Some(code) => Err(ValidationError::MissingWellKnownAttribute(code)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_host_address(addr: Ipv4Addr) -> bool {
+    !(addr.is_multicast() || addr.is_unspecified() || addr.is_broadcast())
+}
+
+impl Validate for NotificationMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+impl Validate for KeepaliveMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+impl Validate for RouteRefreshMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+impl Validate for BgpMessage {
+    fn validate(&self) -> std::result::Result<(), ValidationError> {
+        match self {
+            BgpMessage::Open(m) => m.validate(),
+            BgpMessage::Update(m) => m.validate(),
+            BgpMessage::Notification(m) => m.validate(),
+            BgpMessage::Keepalive(m) => m.validate(),
+            BgpMessage::RouteRefresh(m) => m.validate(),
+            BgpMessage::Capability(_) => Ok(()),
+            BgpMessage::Unknown { msg_type, .. } => Err(ValidationError::BadMessageType(*msg_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::{to_message_bytes, AsPathSegment, SerializerError, BgpIdentifier, Ipv4Prefix, EXTENDED_MAX_MESSAGE_LEN};
+
+    fn update(attrs: Vec<PathAttribute>) -> UpdateMessage {
+        UpdateMessage {
+            path_attributes: attrs.into(),
+            nlri: vec!["192.0.2.0/24".parse::<Ipv4Prefix>().unwrap()],
+            ..Default::default()
+        }
+    }
+
+    fn mandatory() -> Vec<PathAttribute> {
+        vec![
+            PathAttribute::Origin(Origin::Igp),
+            PathAttribute::AsPath(vec![AsPathSegment::AsSequence(vec![65001])]),
+            PathAttribute::NextHop(Ipv4Addr::new(192, 0, 2, 1)),
+        ]
+    }
+
+    #[test]
+    fn test_validate_header() {
+        let keepalive = to_message_bytes(MessageType::Keepalive, KeepaliveMessage).unwrap();
+        assert_eq!(validate_header(&keepalive), Ok(()));
+
+        let mut bad = keepalive.to_vec();
+        bad[3] = 0;
+        assert_eq!(validate_header(&bad), Err(ValidationError::BadMarker));
+
+        let mut bad = keepalive.to_vec();
+        bad[17] = 20;
+        assert_eq!(validate_header(&bad), Err(ValidationError::BadMessageLength(20)));
+        bad[16..18].copy_from_slice(&5000u16.to_be_bytes());
+        bad[18] = 2;
+        assert_eq!(validate_header(&bad), Err(ValidationError::BadMessageLength(5000)));
+        assert_eq!(validate_header_with(&bad, EXTENDED_MAX_MESSAGE_LEN), Ok(()));
+
+        let mut bad = keepalive.to_vec();
+        bad[18] = 9;
+        assert_eq!(validate_header(&bad), Err(ValidationError::BadMessageType(9)));
+    }
+
+    #[test]
+    fn test_validate_raw_extended() {
+        let raw = RawMessage { message_type: MessageType::Update, body: Bytes::from(vec![0; 5000 - HEADER_LEN]) };
+        assert_eq!(raw.validate(), Err(ValidationError::BadMessageLength(5000)));
+        assert_eq!(raw.validate_with(EXTENDED_MAX_MESSAGE_LEN), Ok(()));
+
+        let raw = RawMessage { message_type: MessageType::Open, body: raw.body };
+        assert_eq!(raw.validate_with(EXTENDED_MAX_MESSAGE_LEN), Err(ValidationError::BadMessageLength(5000)));
+    }
+
+    #[test]
+    fn test_validate_open() {
+        let id = BgpIdentifier::from_ipv4(Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        assert_eq!(OpenMessage::new(65001, 90, id).validate(), Ok(()));
+        assert_eq!(OpenMessage::new(65001, 0, id).validate(), Ok(()));
+        assert_eq!(OpenMessage::new(65001, 2, id).validate(), Err(ValidationError::UnacceptableHoldTime(2)));
+        assert_eq!(OpenMessage::new(0, 90, id).validate(), Err(ValidationError::BadPeerAs(0)));
+
+        let mut open = OpenMessage::new(65001, 90, id);
+        open.version = 3;
+        let err = open.validate().unwrap_err();
+        assert_eq!(err.notification_code(), NotificationCode::OpenMessage(OpenMessageError::UnsupportedVersionNumber));
+    }
+
+    #[test]
+    fn test_validate_update() {
+        assert_eq!(update(mandatory()).validate(), Ok(()));
+        // Withdrawal only, nothing is mandatory
+        assert_eq!(UpdateMessage::default().validate(), Ok(()));
+
+        let mut attrs = mandatory();
+        attrs.remove(2);
+        assert_eq!(update(attrs).validate(), Err(ValidationError::MissingWellKnownAttribute(3)));
+
+        let mut attrs = mandatory();
+        attrs[2] = PathAttribute::NextHop(Ipv4Addr::new(224, 0, 0, 5));
+        let err = update(attrs).validate().unwrap_err();
+        assert_eq!(err, ValidationError::InvalidNextHop(Ipv4Addr::new(224, 0, 0, 5)));
+        assert_eq!(err.notification_code().subcode(), 8);
+
+        let mut attrs = mandatory();
+        attrs.push(PathAttribute::Origin(Origin::Egp));
+        assert_eq!(update(attrs).validate(), Err(ValidationError::DuplicateAttribute(1)));
+
+        let mut attrs = mandatory();
+        attrs[0] = PathAttribute::Origin(Origin::Unknown(5));
+        assert_eq!(update(attrs).validate().unwrap_err().to_string(), "Invalid ORIGIN 5");
+    }
+
+    #[test]
+    fn test_to_notification() {
+        let n = ValidationError::BadMessageLength(5000).to_notification();
+        assert_eq!((n.error_code, n.error_subcode, n.data), (1, 2, vec![0x13, 0x88]));
+        let n = ValidationError::UnsupportedVersion(5).to_notification();
+        assert_eq!((n.error_code, n.error_subcode, n.data), (2, 1, vec![0, 4]));
+        let n = ValidationError::MissingWellKnownAttribute(3).to_notification();
+        assert_eq!((n.error_code, n.error_subcode, n.data), (3, 3, vec![3]));
+        let n = NotificationMessage::from(ValidationError::InvalidNextHop(Ipv4Addr::new(224, 0, 0, 5)));
+        assert_eq!((n.error_code, n.error_subcode, n.data), (3, 8, vec![0x40, 3, 4, 224, 0, 0, 5]));
+        let n = ValidationError::UnacceptableHoldTime(1).to_notification();
+        assert_eq!((n.error_code, n.error_subcode, n.data), (2, 6, vec![]));
+    }
+
+    #[test]
+    fn test_decode_error_notification() {
+        let msg = to_message_bytes(MessageType::Update, update(mandatory())).unwrap();
+        let mut bad = msg.to_vec();
+        // Total Path Attribute Length runs past the message
+        bad[22] = 0xff;
+        let err = BgpMessage::from_bytes(&bad).unwrap_err();
+        let n = err.to_notification(&bad).unwrap();
+        assert_eq!((n.error_code, n.error_subcode), (3, 1));
+
+        // NLRI prefix longer than 32 bits
+        let mut bad = msg.to_vec();
+        let at = bad.len() - 4;
+        bad[at] = 33;
+        let n = BgpMessage::from_bytes(&bad).unwrap_err().to_notification(&bad).unwrap();
+        assert_eq!((n.error_code, n.error_subcode), (3, 10));
+
+        let mut bad = msg.to_vec();
+        bad[0] = 0;
+        let n = BgpMessage::from_bytes(&bad).unwrap_err().to_notification(&bad).unwrap();
+        assert_eq!((n.error_code, n.error_subcode), (1, 1));
+
+        let hold_expired = NotificationMessage::new(NotificationCode::HoldTimerExpired, Vec::new());
+        let notification = to_message_bytes(MessageType::Notification, hold_expired).unwrap();
+        let err = SerializerError::CustomMsg(String::from("bad"));
+        assert!(err.to_notification(&notification).is_none());
+    }
+
+    #[test]
+    fn test_extended_decode_error_notification() {
+        use crate::{to_message_bytes_with, DeserializerOptions, MessageStream, SerializerOptions};
+
+        // An UPDATE over 4096 octets whose last prefix is longer than 32 bits
+        let mut big = update(mandatory());
+        big.nlri = (0..1250u32).map(|i| Ipv4Prefix::new(Ipv4Addr::from(i << 8), 24).unwrap()).collect();
+        let ser_opts = SerializerOptions { max_message_len: EXTENDED_MAX_MESSAGE_LEN, ..Default::default() };
+        let mut bad = to_message_bytes_with(MessageType::Update, &big, ser_opts).unwrap().to_vec();
+        assert!(bad.len() > MAX_MESSAGE_LEN);
+        let at = bad.len() - 4;
+        bad[at] = 33;
+
+        let de_opts = DeserializerOptions { max_message_len: EXTENDED_MAX_MESSAGE_LEN, ..Default::default() };
+        let err = BgpMessage::from_bytes_with(&bad, de_opts).unwrap_err();
+        let n = err.to_notification(&bad).unwrap();
+        assert_eq!((n.error_code, n.error_subcode), (1, 2));
+        let n = err.to_notification_with(&bad, EXTENDED_MAX_MESSAGE_LEN).unwrap();
+        assert_eq!((n.error_code, n.error_subcode), (3, 10));
+
+        let mut stream = MessageStream::with_options(de_opts);
+        assert!(stream.feed(&bad).is_err());
+        let code = NotificationCode::UpdateMessage(UpdateMessageError::InvalidNetworkField);
+        assert_eq!(stream.stats().errors(code), 1);
+    }
+}
@@ -0,0 +1,312 @@
+// Route Distinguishers and VPN-IPv4/VPN-IPv6 NLRI (RFC 4364, RFC 4659).
+//
+// The RD is 8 octets: a 2 octet type and an administrator/assigned number
+// pair laid out like the extended community ones (type 0: 2 octet AS and 4
+// octet number, type 1: IPv4 address and 2 octet number, type 2: 4 octet AS
+// and 2 octet number). A VPN route is a length in bits covering everything
+// after it, the MPLS label stack, the RD and the significant prefix octets.
+use std::fmt::{self, Display};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::afi::{Afi, Safi};
+use crate::error::SerializerError;
+use crate::label::{prefix_octets, read_prefix, LabelStack};
+use crate::multiprotocol::{MpReachNlri, NextHop, Nlri};
+use crate::prefix::IpPrefix;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Rd {
+    TwoOctetAs { asn: u16, local: u32 },
+    Ipv4 { address: Ipv4Addr, local: u16 },
+    FourOctetAs { asn: u32, local: u16 },
+    // Types not defined by RFC 4364, kept whole.
+    Unknown([u8; 8]),
+}
+
+// One VPN route. An empty label stack is a withdrawal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct VpnPrefix {
+    pub labels: LabelStack,
+    pub rd: Rd,
+    pub prefix: IpPrefix,
+}
+
+impl Rd {
+    // Type 0 when the AS number fits in 2 octets, type 2 otherwise.
+    pub fn for_asn(asn: u32, local: u16) -> Self {
+        match u16::try_from(asn) {
+            Ok(asn) => Rd::TwoOctetAs { asn, local: local as u32 },
+            Err(_) => Rd::FourOctetAs { asn, local },
+        }
+    }
+
+    pub fn rd_type(&self) -> u16 {
+        u16::from_be_bytes([self.octets()[0], self.octets()[1]])
+    }
+
+    pub fn octets(&self) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        match self {
+            Rd::TwoOctetAs { asn, local } => {
+                out[2..4].copy_from_slice(&asn.to_be_bytes());
+                out[4..].copy_from_slice(&local.to_be_bytes());
+            },
+            Rd::Ipv4 { address, local } => {
+                out[1] = 1;
+                out[2..6].copy_from_slice(&address.octets());
+                out[6..].copy_from_slice(&local.to_be_bytes());
+            },
+            Rd::FourOctetAs { asn, local } => {
+                out[1] = 2;
+                out[2..6].copy_from_slice(&asn.to_be_bytes());
+                out[6..].copy_from_slice(&local.to_be_bytes());
+            },
+            Rd::Unknown(octets) => return *octets,
+        }
+        out
+    }
+
+    pub fn from_octets(octets: [u8; 8]) -> Self {
+        let u16_at = |i: usize| u16::from_be_bytes([octets[i], octets[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([octets[i], octets[i + 1], octets[i + 2], octets[i + 3]]);
+        match u16_at(0) {
+            0 => Rd::TwoOctetAs { asn: u16_at(2), local: u32_at(4) },
+            1 => Rd::Ipv4 { address: Ipv4Addr::from(u32_at(2)), local: u16_at(6) },
+            2 => Rd::FourOctetAs { asn: u32_at(2), local: u16_at(6) },
+            _ => Rd::Unknown(octets),
+        }
+    }
+}
+
+impl Default for Rd {
+    fn default() -> Self {
+        Rd::TwoOctetAs { asn: 0, local: 0 }
+    }
+}
+
+impl Display for Rd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rd::TwoOctetAs { asn, local } => write!(f, "{}:{}", asn, local),
+            Rd::Ipv4 { address, local } => write!(f, "{}:{}", address, local),
+            Rd::FourOctetAs { asn, local } => write!(f, "{}:{}", asn, local),
+            Rd::Unknown(octets) => {
+                f.write_str("0x")?;
+                octets.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            },
+        }
+    }
+}
+
+// "65001:100", "4200000000:100" or "192.0.2.1:100". An AS number that fits
+// in 2 octets gives a type 0 RD, which also allows the larger number.
+impl FromStr for Rd {
+    type Err = SerializerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SerializerError::CustomMsg(format!("Invalid route distinguisher \"{}\"", s));
+        let (admin, local) = s.rsplit_once(':').ok_or_else(invalid)?;
+        if let Ok(address) = admin.parse::<Ipv4Addr>() {
+            return Ok(Rd::Ipv4 { address, local: local.parse().map_err(|_| invalid())? });
+        }
+        match admin.parse::<u32>().map_err(|_| invalid())? {
+            asn @ 0..=0xffff => Ok(Rd::TwoOctetAs { asn: asn as u16, local: local.parse().map_err(|_| invalid())? }),
+            asn => Ok(Rd::FourOctetAs { asn, local: local.parse().map_err(|_| invalid())? }),
+        }
+    }
+}
+
+impl Serialize for Rd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.octets().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        <[u8; 8]>::deserialize(deserializer).map(Rd::from_octets)
+    }
+}
+
+impl VpnPrefix {
+    pub fn new(labels: LabelStack, rd: Rd, prefix: IpPrefix) -> Self {
+        VpnPrefix { labels, rd, prefix }
+    }
+}
+
+impl MpReachNlri {
+    // VPN-IPv4 routes. The next hop is an RD of zero and the address.
+    pub fn vpnv4(next_hop: Ipv4Addr, nlri: Vec<VpnPrefix>) -> Self {
+        MpReachNlri { afi: Afi::Ipv4, safi: Safi::MplsVpn, next_hop: NextHop::VpnIpv4(next_hop), nlri: Nlri::Vpn(nlri) }
+    }
+
+    pub fn vpnv6(next_hop: Ipv6Addr, nlri: Vec<VpnPrefix>) -> Self {
+        MpReachNlri { afi: Afi::Ipv6, safi: Safi::MplsVpn, next_hop: NextHop::VpnIpv6(next_hop), nlri: Nlri::Vpn(nlri) }
+    }
+}
+
+// -- Wire form --
+
+impl Serialize for VpnPrefix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let len = self.prefix.prefix_len();
+        let bits = self.labels.wire_len() * 24 + 64 + len as usize;
+        let bits = u8::try_from(bits)
+            .map_err(|_| ser::Error::custom(format!("VPN route is {} bits, at most 255 fit the length", bits)))?;
+
+        let n = (len as usize).div_ceil(8);
+        let mut tup = serializer.serialize_tuple(3 + n)?;
+        tup.serialize_element(&bits)?;
+        tup.serialize_element(&self.labels)?;
+        tup.serialize_element(&self.rd)?;
+        for b in &prefix_octets(&self.prefix)[..n] {
+            tup.serialize_element(b)?;
+        }
+        tup.end()
+    }
+}
+
+// VPN routes of `afi` up to the end of the NLRI.
+pub(crate) struct VpnSeed {
+    pub afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for VpnSeed {
+    type Value = Vec<VpnPrefix>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for VpnSeed {
+    type Value = Vec<VpnPrefix>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("VPN NLRI")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut routes = Vec::new();
+        while let Some(route) = seq.next_element_seed(VpnPrefixSeed { afi: self.afi })? {
+            routes.push(route);
+        }
+        Ok(routes)
+    }
+}
+
+struct VpnPrefixSeed {
+    afi: Afi,
+}
+
+impl<'de> DeserializeSeed<'de> for VpnPrefixSeed {
+    type Value = VpnPrefix;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        // Length, stack, RD and up to 16 address octets
+        d.deserialize_tuple(3 + 16, self)
+    }
+}
+
+impl<'de> Visitor<'de> for VpnPrefixSeed {
+    type Value = VpnPrefix;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a VPN route")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let bits: u8 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let labels: LabelStack = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let len = (bits as usize)
+            .checked_sub(labels.wire_len() * 24 + 64)
+            .ok_or_else(|| de::Error::custom(format!("VPN route length {} leaves no room for the RD", bits)))?;
+        let rd: Rd = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let prefix = read_prefix(&mut seq, self.afi, len, 3, &self)?;
+        Ok(VpnPrefix { labels, rd, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes, MpUnreachNlri};
+
+    #[test]
+    fn test_rd_text() {
+        let cases = [
+            ("65001:100", Rd::TwoOctetAs { asn: 65001, local: 100 }, [0, 0, 0xfd, 0xe9, 0, 0, 0, 100]),
+            ("192.0.2.1:7", Rd::Ipv4 { address: Ipv4Addr::new(192, 0, 2, 1), local: 7 }, [0, 1, 192, 0, 2, 1, 0, 7]),
+            ("4200000000:5", Rd::FourOctetAs { asn: 4_200_000_000, local: 5 }, [0, 2, 0xfa, 0x56, 0xea, 0, 0, 5]),
+        ];
+        for (text, rd, octets) in cases {
+            assert_eq!(text.parse::<Rd>().unwrap(), rd);
+            assert_eq!(rd.to_string(), text);
+            assert_eq!(rd.octets(), octets);
+            assert_eq!(Rd::from_octets(octets), rd);
+        }
+        assert_eq!(Rd::for_asn(4_200_000_000, 5).rd_type(), 2);
+        assert_eq!(Rd::from_octets([0, 9, 1, 2, 3, 4, 5, 6]).to_string(), "0x0009010203040506");
+        for bad in ["65001", "4200000000:70000", "a:1", "192.0.2.1:70000"] {
+            assert!(bad.parse::<Rd>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_vpnv4_wire() {
+        let route = VpnPrefix::new(LabelStack::from_values([100, 200]).unwrap(), "65001:1".parse().unwrap(), "10.1.0.0/16".parse().unwrap());
+        let reach = MpReachNlri::vpnv4(Ipv4Addr::new(192, 0, 2, 1), vec![route.clone()]);
+        #[rustfmt::skip]
+        let wire: &[u8] = &[
+            0, 1, 128,
+            12, 0, 0, 0, 0, 0, 0, 0, 0, 192, 0, 2, 1,
+            0,
+            128,
+            0, 0x06, 0x40, 0, 0x0c, 0x81,
+            0, 0, 0xfd, 0xe9, 0, 0, 0, 1,
+            10, 1,
+        ];
+        assert_eq!(to_bytes(&reach).unwrap().as_ref(), wire);
+        assert_eq!(from_bytes::<MpReachNlri>(wire).unwrap(), reach);
+
+        // Withdrawn with the placeholder label
+        let withdrawn = VpnPrefix { labels: LabelStack::default(), ..route };
+        let unreach = MpUnreachNlri { afi: Afi::Ipv4, safi: Safi::MplsVpn, withdrawn: Nlri::Vpn(vec![withdrawn]) };
+        let wire = to_bytes(&unreach).unwrap();
+        assert_eq!(&wire[3..7], &[104, 0x80, 0, 0]);
+        assert_eq!(from_bytes::<MpUnreachNlri>(&wire).unwrap(), unreach);
+    }
+
+    #[test]
+    fn test_vpnv6_wire() {
+        let route = VpnPrefix::new(LabelStack::from_values([16]).unwrap(), "192.0.2.1:7".parse().unwrap(), "2001:db8:1::/48".parse().unwrap());
+        let reach = MpReachNlri::vpnv6("2001:db8::1".parse().unwrap(), vec![route]);
+        let wire = to_bytes(&reach).unwrap();
+        assert_eq!(wire[3], 24);
+        assert_eq!(&wire[29..33], &[24 + 64 + 48, 0, 1, 1]);
+        assert_eq!(from_bytes::<MpReachNlri>(&wire).unwrap(), reach);
+    }
+
+    #[test]
+    fn test_vpn_err() {
+        let too_long = VpnPrefix::new(LabelStack::from_values(0..8).unwrap(), Rd::default(), "10.0.0.0/8".parse().unwrap());
+        match to_bytes(&too_long) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.to_string(), "VPN route is 264 bits, at most 255 fit the length"),
+        }
+
+        // Two labels but only room for one
+        let wire = [0, 1, 128, 0, 0, 24 + 64, 0, 0, 0x10, 0, 0, 0x21, 0, 0, 0, 0, 0, 0, 0, 0];
+        match from_bytes::<MpReachNlri>(&wire) {
+            Ok(_) => panic!("Expected Err, got Ok"),
+            Err(e) => assert_eq!(e.inner().to_string(), "VPN route length 88 leaves no room for the RD"),
+        }
+    }
+}
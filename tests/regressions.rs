@@ -0,0 +1,119 @@
+// Replays inputs that once crashed a fuzz target. Each directory under
+// tests/regressions/ is named after a target in fuzz/fuzz_targets/ and holds
+// inputs in that target's format, so a crash file from fuzz/artifacts/<target>/
+// can be copied in as it is once the bug is fixed. Every input has to come
+// back as an error: a panic (an out of bounds read included) or a successful
+// decode of garbage fails the test, naming the file.
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use bgp4_serde::{BgpMessage, BmpMessage, DeserializerOptions, MessageStream, MARKER};
+
+// Same framing as fuzz/fuzz_targets/decode_message.rs: the first octet picks
+// the message type and options, the rest is the body.
+fn decode_message(data: &[u8]) -> bool {
+    let Some((&flags, body)) = data.split_first() else { return true };
+    let mut message = MARKER.to_vec();
+    message.extend_from_slice(&(19 + body.len() as u16).to_be_bytes());
+    message.push(1 + flags % 6);
+    message.extend_from_slice(body);
+
+    let mut options = DeserializerOptions { four_octet_asn: flags & 0x08 != 0, ..Default::default() };
+    if flags & 0x10 != 0 {
+        options.add_path.insert(bgp4_serde::Afi::Ipv4, bgp4_serde::Safi::Unicast);
+        options.add_path.insert(bgp4_serde::Afi::Ipv6, bgp4_serde::Safi::Unicast);
+    }
+    if flags & 0x20 != 0 {
+        options.on_unknown = bgp4_serde::OnUnknown::Skip;
+    }
+    BgpMessage::from_bytes_with(&message, options).is_err()
+}
+
+// The first octet is the piece size, as in the fuzz target.
+fn message_stream(data: &[u8]) -> bool {
+    let Some((&size, data)) = data.split_first() else { return true };
+    let mut stream = MessageStream::new();
+    for piece in data.chunks(size as usize + 1) {
+        let mut next = stream.feed(piece);
+        loop {
+            match next {
+                Ok(Some(_)) => next = stream.next_message(),
+                Ok(None) => break,
+                Err(_) => return true,
+            }
+        }
+    }
+    false
+}
+
+fn bmp(data: &[u8]) -> bool {
+    BmpMessage::from_bytes(data).is_err()
+}
+
+#[cfg(feature = "mrt")]
+fn mrt(data: &[u8]) -> bool {
+    bgp4_serde::mrt::MrtReader::new(data).any(|record| record.is_err())
+}
+
+// The first octet picks the file header, as in the fuzz target.
+#[cfg(feature = "pcap")]
+fn pcap(data: &[u8]) -> bool {
+    const PCAP_HEADER: [u8; 24] = [
+        0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 228, 0, 0, 0,
+    ];
+    const PCAPNG_SECTION_HEADER: [u8; 28] = [
+        0x0a, 0x0d, 0x0d, 0x0a, 28, 0, 0, 0, 0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 28, 0, 0, 0,
+    ];
+    let Some((&format, rest)) = data.split_first() else { return true };
+    let header: &[u8] = match format % 3 {
+        0 => &PCAP_HEADER,
+        1 => &PCAPNG_SECTION_HEADER,
+        _ => &[],
+    };
+    let input = [header, rest].concat();
+    bgp4_serde::pcap::PcapReader::new(input.as_slice()).any(|message| message.is_err())
+}
+
+fn replay(target: &str, fails: fn(&[u8]) -> bool) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions").join(target);
+    let mut failures = Vec::new();
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = fs::read(&path).unwrap();
+        match panic::catch_unwind(AssertUnwindSafe(|| fails(&data))) {
+            Ok(true) => {},
+            Ok(false) => failures.push(format!("{}: decoded without an error", path.display())),
+            Err(_) => failures.push(format!("{}: panicked", path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_regressions_decode_message() {
+    replay("decode_message", decode_message);
+}
+
+#[test]
+fn test_regressions_message_stream() {
+    replay("message_stream", message_stream);
+}
+
+#[test]
+fn test_regressions_bmp() {
+    replay("bmp", bmp);
+}
+
+#[cfg(feature = "mrt")]
+#[test]
+fn test_regressions_mrt() {
+    replay("mrt", mrt);
+}
+
+#[cfg(feature = "pcap")]
+#[test]
+fn test_regressions_pcap() {
+    replay("pcap", pcap);
+}